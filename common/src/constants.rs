@@ -125,6 +125,11 @@ pub const USER_STACK_BASE_ADDR: usize = 0xc0000000;
  */
 pub const USER_STACK_TOP_ADDR: usize = USER_STACK_BASE_ADDR - 0x1000;
 
+/**
+ * 用户进程栈的最大空间。1MB（栈空间按需分配物理页）
+ */
+pub const USER_STACK_MAX_SIZE: usize = 0x100000;
+
 /**
  * 系统调用的函数数量
  */
@@ -8,6 +8,7 @@ pub mod vga;
 pub mod sd;
 pub mod gdt;
 pub mod reg_cr0;
+pub mod reg_cr2;
 pub mod reg_cr3;
 pub mod selector;
 pub mod instruction;
@@ -172,6 +172,36 @@ impl <'a> Iterator for PageTableEntryIterator<'a> {
     pub fn get_data(&self) -> u32 {
         self.data
     }
+
+    /**
+     * 该页表项指向的页，是否可写
+     */
+    pub fn writable(&self) -> bool {
+        self.data & 0x00000002 == 0x2
+    }
+
+    pub fn set_writable(&mut self, writable: bool) {
+        if writable {
+            self.data |= 0x2;
+        } else {
+            self.data &= !0x2;
+        }
+    }
+
+    /**
+     * 该页表项指向的页，是否是写时复制的页（第11位，是留给操作系统自己使用的位）
+     */
+    pub fn is_cow(&self) -> bool {
+        self.data & 0x00000800 == 0x800
+    }
+
+    pub fn set_cow(&mut self, cow: bool) {
+        if cow {
+            self.data |= 0x800;
+        } else {
+            self.data &= !0x800;
+        }
+    }
     
     /**
      * 获取物理地址（页表项的高20位就是物理地址）
@@ -0,0 +1,24 @@
+use core::arch::asm;
+
+/**
+ * 本文件是对cr2寄存器的直接操作
+ * 发生缺页异常（Page Fault）的时候，CPU会把引发异常的虚拟地址放到cr2寄存器中
+ * <https://wiki.osdev.org/CPU_Registers_x86#CR2>
+ */
+
+/**
+ * 读取cr2寄存器，得到引发缺页异常的虚拟地址
+ */
+#[cfg(all(not(test), target_arch = "x86"))]
+pub fn get_fault_addr() -> u32 {
+    let fault_addr: u32;
+    unsafe {
+        asm!("mov {:e}, cr2", out(reg) fault_addr, options(nomem, nostack, preserves_flags));
+    }
+    fault_addr
+}
+
+#[cfg(any(test, not(target_arch = "x86")))]
+pub fn get_fault_addr() -> u32 {
+    todo!()
+}
@@ -1,3 +1,5 @@
 pub mod exec_dto;
 pub mod cwd_dto;
-pub mod open_file_dto;
\ No newline at end of file
+pub mod open_file_dto;
+pub mod path_pair_dto;pub mod read_link_dto;
+pub mod mount_dto;
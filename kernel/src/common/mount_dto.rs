@@ -0,0 +1,15 @@
+/**
+ * 挂载的请求参数：要挂载的分区名称，挂载点目录的路径
+ */
+#[derive(Debug)]
+pub struct MountDto<'a> {
+    pub part_name: &'a str,
+    pub path: &'a str,
+}
+
+impl <'a> MountDto<'a> {
+    #[inline(never)]
+    pub fn new(part_name: &'a str, path: &'a str) -> Self {
+        Self { part_name, path }
+    }
+}
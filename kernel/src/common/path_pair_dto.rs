@@ -0,0 +1,15 @@
+/**
+ * 两个路径的请求参数。比如：重命名（旧路径、新路径），创建链接（已存在的路径、链接的路径）
+ */
+#[derive(Debug)]
+pub struct PathPairDto<'a> {
+    pub src_path: &'a str,
+    pub dst_path: &'a str,
+}
+
+impl <'a> PathPairDto<'a> {
+    #[inline(never)]
+    pub fn new(src_path: &'a str, dst_path: &'a str) -> Self {
+        Self { src_path, dst_path }
+    }
+}
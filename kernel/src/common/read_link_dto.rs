@@ -0,0 +1,15 @@
+/**
+ * 读取符号链接的请求参数：符号链接的路径，存放目标路径的缓冲区
+ */
+#[derive(Debug)]
+pub struct ReadLinkDto<'a> {
+    pub link_path: &'a str,
+    pub buff: &'a mut [u8],
+}
+
+impl <'a> ReadLinkDto<'a> {
+    #[inline(never)]
+    pub fn new(link_path: &'a str, buff: &'a mut [u8]) -> Self {
+        Self { link_path, buff }
+    }
+}
@@ -131,6 +131,11 @@ pub struct Disk {
      */
     pub primary: bool,
 
+    /**
+     * 硬盘可用的扇区数量。identify之后才有
+     */
+    pub sec_cnt: u32,
+
     /**
      * 一个硬盘最多4个主分区
      */
@@ -149,6 +154,7 @@ impl Disk {
             name:  [0; constants::DISK_NAME_LEN],
             from_channel: ptr::null_mut(),
             primary: false,
+            sec_cnt: 0,
             primary_parts: [ARRAY_REPEAT_VALUE; 4],
             logical_parts: [ARRAY_REPEAT_VALUE; constants::DISK_LOGICAL_PARTITION_CNT],
         }
@@ -163,6 +169,7 @@ impl Disk {
             name: name_buf,
             from_channel: from_channel,
             primary: primary,
+            sec_cnt: 0,
             primary_parts: [ARRAY_REPEAT_VALUE; constants::DISK_PRIMARY_PARTITION_CNT],
             logical_parts: [ARRAY_REPEAT_VALUE; constants::DISK_LOGICAL_PARTITION_CNT],
         }
@@ -206,6 +213,7 @@ impl Disk {
         // printkln!("disk info: {},  sn: {}", disk_name, sn_name);
         // printkln!("module: {}", module_name);
         // printkln!("disk sector count: {}", identify_res.sec_cnt as u32);
+        self.sec_cnt = identify_res.sec_cnt;
         memory::sys_free(buf.as_ptr() as usize);
     }
     
@@ -471,6 +479,11 @@ pub struct Partition {
      * 以C字符串的格式存储
      */
     name: [u8; constants::DISK_NAME_LEN],
+    /**
+     * 分区表项中的分区类型
+     * @see PartitionType
+     */
+    pub part_type: u8,
     /**
      * 该分区位于硬盘的起始扇区数
      */
@@ -499,6 +512,7 @@ impl Partition {
     pub const fn empty() -> Self {
         Self {
             name: [0; constants::DISK_NAME_LEN],
+            part_type: 0,
             lba_start: LbaAddr::empty(),
             sec_cnt: 0,
             from_disk: ptr::null_mut(),
@@ -506,12 +520,13 @@ impl Partition {
         }
     }
 
-    pub fn new(name: &[u8], lba_start: LbaAddr, sec_cnt: u32, from_disk: *mut Disk) -> Self {
+    pub fn new(name: &[u8], part_type: u8, lba_start: LbaAddr, sec_cnt: u32, from_disk: *mut Disk) -> Self {
         ASSERT!(name.len() >= constants::DISK_NAME_LEN);
         let mut name_buf = [0; constants::DISK_NAME_LEN];
         name_buf.copy_from_slice(&name[0 .. constants::DISK_NAME_LEN]);
         Self {
             name: name_buf,
+            part_type: part_type,
             lba_start: lba_start,
             sec_cnt: sec_cnt,
             from_disk: from_disk,
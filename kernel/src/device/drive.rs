@@ -93,8 +93,29 @@ impl PartitionTableEntry {
 
 
 pub enum PartitionType {
+    // FAT16（小于32MiB）
+    Fat16Small = 0x4,
     // 扩展类型
     Extended = 0x5,
+    // FAT16
+    Fat16 = 0x6,
+    // FAT32（CHS寻址）
+    Fat32 = 0xb,
+    // FAT32（LBA寻址）
+    Fat32Lba = 0xc,
+    // FAT16（LBA寻址）
+    Fat16Lba = 0xe,
+    // Linux（ext2等）
+    Linux = 0x83,
+}
+
+impl PartitionType {
+    /**
+     * 分区表项中的类型，是不是FAT16或者FAT32
+     */
+    pub fn is_fat(part_type: u8) -> bool {
+        [Self::Fat16Small, Self::Fat16, Self::Fat32, Self::Fat32Lba, Self::Fat16Lba].into_iter().any(|fat_type| fat_type as u8 == part_type)
+    }
 }
 
 
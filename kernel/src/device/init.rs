@@ -1,4 +1,4 @@
-use core::mem::size_of;
+use core::{mem::size_of, ptr};
 
 use os_in_rust_common::{constants, cstr_write, cstring_utils, domain::LbaAddr, linked_list::LinkedList, racy_cell::RacyCell, utils, ASSERT};
 use os_in_rust_common::array_deque::ArrayDeque;
@@ -22,6 +22,17 @@ pub static mut ALL_ATA_CHANNELS: RacyCell<[Option<ATAChannel>; constants::ATA_CH
  */
 pub static PARTITION_LIST: RacyCell<LinkedList> = RacyCell::new(LinkedList::new());
 
+/**
+ * 所有的硬盘
+ */
+#[inline(never)]
+pub fn get_all_disk() -> impl Iterator<Item = &'static mut Disk> {
+    unsafe { (*ptr::addr_of_mut!(ALL_ATA_CHANNELS)).get_mut() }.iter_mut()
+        .filter_map(|channel| channel.as_mut())
+        .flat_map(|channel| channel.disks.iter_mut())
+        .filter_map(|disk| disk.as_mut())
+}
+
 /**
  * 总扩展分区的LBA起始地址
  */
@@ -135,7 +146,7 @@ pub fn main_part_init(disk: &mut Disk) {
 
             // 填充该主分区的信息
             let primary_part = &mut disk.primary_parts[idx];
-            *primary_part = Option::Some(Partition::new(buf, part_entry.start_lba, part_entry.sec_cnt, disk_ptr));
+            *primary_part = Option::Some(Partition::new(buf, part_entry.part_type, part_entry.start_lba, part_entry.sec_cnt, disk_ptr));
             
             // 放到队列中
             get_all_partition().append(&mut primary_part.as_mut().unwrap().tag);
@@ -205,7 +216,7 @@ pub fn extended_part_init(disk: &mut Disk, main_ext_lba: LbaAddr) {
                 let mut logical_part = &mut disk.logical_parts[part_no];
                 part_no += 1;
 
-                *logical_part = Option::Some(Partition::new(part_name_buf, extend_part_lba + part_entry.start_lba, part_entry.sec_cnt, disk_ptr));
+                *logical_part = Option::Some(Partition::new(part_name_buf, part_entry.part_type, extend_part_lba + part_entry.start_lba, part_entry.sec_cnt, disk_ptr));
 
                 let part = logical_part.as_mut().unwrap();
                 // 把该逻辑分区加入队列
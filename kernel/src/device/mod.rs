@@ -23,6 +23,7 @@ pub fn beep() {
 }
 
 pub use init::get_all_partition;
+pub use init::get_all_disk;
 pub use init::ata_init;
 pub use init::get_ata_channel;
 
@@ -32,5 +33,9 @@ pub use ata::ChannelIrqNoEnum;
 pub use ata::ChannelPortBaseEnum;
 pub use ata::Disk;
 
+pub use drive::BootSector;
+pub use drive::PartitionTableEntry;
+pub use drive::PartitionType;
+
 
 pub use pio::StatusRegister;
\ No newline at end of file
@@ -0,0 +1,182 @@
+use core::mem::size_of;
+
+/**
+ * ELF32可执行文件的格式：<https://wiki.osdev.org/ELF>
+ * 这里只解析exec需要用到的部分：ELF头 + 程序头表
+ */
+
+/**
+ * ELF文件的魔数：0x7f 'E' 'L' 'F'
+ */
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+
+/**
+ * e_ident[4]：32位
+ */
+const ELF_CLASS_32: u8 = 1;
+/**
+ * e_ident[5]：小端
+ */
+const ELF_DATA_LSB: u8 = 1;
+/**
+ * e_type：可执行文件
+ */
+const ELF_TYPE_EXEC: u16 = 2;
+/**
+ * e_machine：Intel 80386
+ */
+const ELF_MACHINE_386: u16 = 3;
+
+/**
+ * 程序头表项的数量上限
+ */
+pub const ELF_MAX_PROGRAM_HEADER_CNT: usize = 16;
+
+/**
+ * 段的类型：需要加载的段
+ */
+pub const PT_LOAD: u32 = 1;
+/**
+ * 段的权限：可写
+ */
+pub const PF_W: u32 = 0x2;
+
+/**
+ * ELF32文件头。52字节
+ */
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug)]
+pub struct Elf32Header {
+    pub e_ident: [u8; 16],
+    pub e_type: u16,
+    pub e_machine: u16,
+    pub e_version: u32,
+    /**
+     * 程序的入口地址
+     */
+    pub e_entry: u32,
+    /**
+     * 程序头表在文件中的偏移
+     */
+    pub e_phoff: u32,
+    pub e_shoff: u32,
+    pub e_flags: u32,
+    pub e_ehsize: u16,
+    /**
+     * 程序头表每一项的大小
+     */
+    pub e_phentsize: u16,
+    /**
+     * 程序头表项的数量
+     */
+    pub e_phnum: u16,
+    pub e_shentsize: u16,
+    pub e_shnum: u16,
+    pub e_shstrndx: u16,
+}
+
+impl Elf32Header {
+    pub const fn empty() -> Self {
+        Self {
+            e_ident: [0; 16],
+            e_type: 0,
+            e_machine: 0,
+            e_version: 0,
+            e_entry: 0,
+            e_phoff: 0,
+            e_shoff: 0,
+            e_flags: 0,
+            e_ehsize: 0,
+            e_phentsize: 0,
+            e_phnum: 0,
+            e_shentsize: 0,
+            e_shnum: 0,
+            e_shstrndx: 0,
+        }
+    }
+
+    /**
+     * 校验ELF头：必须是x86的32位小端可执行文件
+     */
+    #[inline(never)]
+    pub fn is_valid(&self) -> bool {
+        if self.e_ident[..4] != ELF_MAGIC {
+            return false;
+        }
+        if self.e_ident[4] != ELF_CLASS_32 || self.e_ident[5] != ELF_DATA_LSB {
+            return false;
+        }
+        let e_type = self.e_type;
+        let e_machine = self.e_machine;
+        if e_type != ELF_TYPE_EXEC || e_machine != ELF_MACHINE_386 {
+            return false;
+        }
+        let e_phentsize = self.e_phentsize;
+        let e_phnum = self.e_phnum;
+        // 程序头表项的大小必须一致，数量不能为0，也不能太多
+        e_phentsize as usize == size_of::<Elf32ProgramHeader>() && e_phnum > 0 && e_phnum as usize <= ELF_MAX_PROGRAM_HEADER_CNT
+    }
+}
+
+/**
+ * ELF32程序头表项（描述一个段）。32字节
+ */
+#[repr(C, packed)]
+#[derive(Clone, Copy, Debug)]
+pub struct Elf32ProgramHeader {
+    /**
+     * 段的类型
+     */
+    pub p_type: u32,
+    /**
+     * 段在文件中的偏移
+     */
+    pub p_offset: u32,
+    /**
+     * 段要加载到的虚拟地址
+     */
+    pub p_vaddr: u32,
+    pub p_paddr: u32,
+    /**
+     * 段在文件中的大小
+     */
+    pub p_filesz: u32,
+    /**
+     * 段在内存中的大小（大于文件中的大小的部分是.bss，需要清零）
+     */
+    pub p_memsz: u32,
+    /**
+     * 段的权限
+     */
+    pub p_flags: u32,
+    pub p_align: u32,
+}
+
+impl Elf32ProgramHeader {
+    pub const fn empty() -> Self {
+        Self {
+            p_type: 0,
+            p_offset: 0,
+            p_vaddr: 0,
+            p_paddr: 0,
+            p_filesz: 0,
+            p_memsz: 0,
+            p_flags: 0,
+            p_align: 0,
+        }
+    }
+
+    /**
+     * 是否是需要加载的段
+     */
+    pub fn is_load(&self) -> bool {
+        self.p_type == PT_LOAD
+    }
+
+    /**
+     * 段是否可写
+     */
+    pub fn writable(&self) -> bool {
+        self.p_flags & PF_W == PF_W
+    }
+}
@@ -1,29 +1,31 @@
-use core::arch::asm;
+use core::mem::size_of;
 
-use os_in_rust_common::{constants, cstr_write, instruction, utils, ASSERT};
+use os_in_rust_common::{constants, cstr_write, instruction, utils};
 
-use crate::{common::exec_dto::ExecParam, filesystem::{self}, interrupt, memory, thread};
+use crate::{common::exec_dto::ExecParam, elf::{self, Elf32Header, Elf32ProgramHeader}, filesystem::{self, SeekFrom}, interrupt, memory::{self, page_util, VmRegion, VmRegionType}, thread};
 
 #[derive(Debug)]
 pub enum ExecError {
     Init,
     OpenFileError(filesystem::FileError),
+    /**
+     * 不是合法的ELF32可执行文件（格式错误、架构不对、段的地址非法等）
+     */
+    BadImage,
 }
 
-const USER_PROC_ENTRY_ADDR: usize = 0xc048000;
-
 #[inline(never)]
 pub fn execv(param: &ExecParam) -> Result<(), ExecError> {
     
-    // 把这个文件，加载到0xc048000
-    self::load(param.get_file_path(), USER_PROC_ENTRY_ADDR)?;
+    // 解析ELF文件，把每个需要加载的段加载到指定的地址，得到程序的入口
+    let entry = self::load(param.get_file_path())?;
 
     let cur_pcb = thread::current_thread();
     cstr_write!(cur_pcb.task_struct.get_name_mut(), "{}", param.get_file_path());
 
     let intr_stack = &mut cur_pcb.interrupt_stack;
-    // 这个文件的起始地址，就是执行入口
-    intr_stack.init_exec(USER_PROC_ENTRY_ADDR.try_into().unwrap(), param.get_args());
+    // ELF头中的入口地址，就是执行入口
+    intr_stack.init_exec(entry, param.get_args());
 
     let intr_stack_addr = intr_stack as *const _ as u32;
     cur_pcb.task_struct.kernel_stack = intr_stack_addr;
@@ -36,35 +38,179 @@ pub fn execv(param: &ExecParam) -> Result<(), ExecError> {
     return Result::Ok(());
 }
 
+/**
+ * 加载ELF可执行文件。返回程序的入口地址
+ */
 #[inline(never)]
-fn load(file_path: &str, addr: usize) -> Result<(), ExecError> {
+fn load(file_path: &str) -> Result<u32, ExecError> {
     // 打开文件
     let exec_file = filesystem::File::open(file_path);
     if exec_file.is_err() {
         return Result::Err(ExecError::OpenFileError(exec_file.unwrap_err()));
     }
-    let exec_file = exec_file.unwrap();
-    
-    let cur_pcb = thread::current_thread();
-    
-    let file_size = exec_file.get_size().unwrap();
-    let page_cnt = utils::div_ceil(file_size as u32, constants::PAGE_SIZE) as usize;
-    
-    for page_idx in 0..page_cnt {
-
-        let cur_addr = addr + page_idx * constants::PAGE_SIZE as usize;
-        
-        // 申请1页内存
-        memory::malloc_user_page_by_vaddr(&mut cur_pcb.task_struct.vaddr_pool, cur_addr);
-        
-        // 把这个页空间转成一个buffer
-        let buff =  unsafe { core::slice::from_raw_parts_mut(cur_addr as *mut u8, constants::PAGE_SIZE as usize) };
-        
-        // 读取文件到指定的内存空间中
-        let read_res = exec_file.read(buff);
-        if read_res.is_err() {
-            return Result::Err(ExecError::OpenFileError(read_res.unwrap_err()));
+    let mut exec_file = exec_file.unwrap();
+    let file_size = exec_file.get_size().map_err(|e| ExecError::OpenFileError(e))?;
+
+    /**** 1. 读取ELF头，并且校验。文件比ELF头还小，读取整个文件 */
+    let mut header_buf = [0u8; size_of::<Elf32Header>()];
+    let header_len = header_buf.len().min(file_size);
+    self::read_at(&mut exec_file, 0, &mut header_buf[..header_len])?;
+    let elf_header = self::parse_header(&header_buf[..header_len])?;
+
+    /**** 2. 读取程序头表，然后加载每个段 */
+    // 程序头表放在内核空间，加载完就释放
+    let program_headers: &mut [Elf32ProgramHeader; elf::ELF_MAX_PROGRAM_HEADER_CNT] = memory::malloc_system(size_of::<[Elf32ProgramHeader; elf::ELF_MAX_PROGRAM_HEADER_CNT]>());
+    let load_res = self::load_segments(&mut exec_file, file_size, &elf_header, &mut program_headers[..elf_header.e_phnum as usize]);
+    memory::free_system(program_headers as *const _);
+    load_res?;
+
+    return Result::Ok(elf_header.e_entry);
+}
+
+/**
+ * 解析文件开头的ELF头
+ *  - 数据不够一个ELF头（文件被截断了），或者不是x86的32位可执行文件，返回BadImage
+ */
+#[inline(never)]
+pub fn parse_header(buf: &[u8]) -> Result<Elf32Header, ExecError> {
+    if buf.len() < size_of::<Elf32Header>() {
+        return Result::Err(ExecError::BadImage);
+    }
+    let elf_header = unsafe { (buf.as_ptr() as *const Elf32Header).read_unaligned() };
+    if !elf_header.is_valid() {
+        return Result::Err(ExecError::BadImage);
+    }
+    return Result::Ok(elf_header);
+}
+
+/**
+ * 读取程序头表，校验并且加载所有PT_LOAD段
+ */
+#[inline(never)]
+fn load_segments(exec_file: &mut filesystem::File, file_size: usize, elf_header: &Elf32Header, program_headers: &mut [Elf32ProgramHeader]) -> Result<(), ExecError> {
+    // 读取程序头表
+    let ph_buf = unsafe { core::slice::from_raw_parts_mut(program_headers.as_mut_ptr() as *mut u8, program_headers.len() * size_of::<Elf32ProgramHeader>()) };
+    self::read_at(exec_file, elf_header.e_phoff, ph_buf)?;
+
+    /**** 1. 校验所有的段。入口地址必须在某个段内 */
+    let mut entry_found = false;
+    for ph in program_headers.iter().filter(|ph| ph.is_load()) {
+        self::check_segment(ph, file_size)?;
+        let entry = elf_header.e_entry;
+        if entry >= ph.p_vaddr && entry < ph.p_vaddr + ph.p_memsz {
+            entry_found = true;
+        }
+    }
+    if !entry_found {
+        return Result::Err(ExecError::BadImage);
+    }
+
+    /**** 2. 程序映像的区域。不需要提前申请物理页，写入的时候在缺页异常中按需分配 */
+    let vm_regions = &mut thread::current_thread().task_struct.vm_regions;
+    vm_regions.remove_by_type(VmRegionType::Image);
+    for ph in program_headers.iter().filter(|ph| ph.is_load()) {
+        let (start, end) = self::segment_page_range(ph);
+        if !vm_regions.add(VmRegion::new(start, end, VmRegionType::Image)) {
+            return Result::Err(ExecError::BadImage);
+        }
+    }
+
+    /**** 3. 加载每个段。文件中的部分读取到内存，剩下的部分（.bss）清零 */
+    for ph in program_headers.iter().filter(|ph| ph.is_load()) {
+        // 之前加载的只读段，要先改成可写
+        self::set_segment_writable(ph, program_headers, true);
+
+        let file_sz = ph.p_filesz as usize;
+        let mem_sz = ph.p_memsz as usize;
+        if file_sz > 0 {
+            let seg_buf = unsafe { core::slice::from_raw_parts_mut(ph.p_vaddr as *mut u8, file_sz) };
+            self::read_at(exec_file, ph.p_offset, seg_buf)?;
         }
+        unsafe { ((ph.p_vaddr as usize + file_sz) as *mut u8).write_bytes(0, mem_sz - file_sz) };
+    }
+
+    /**** 4. 不可写的段，页表项设置为只读 */
+    for ph in program_headers.iter().filter(|ph| ph.is_load() && !ph.writable()) {
+        self::set_segment_writable(ph, program_headers, false);
     }
     return Result::Ok(());
-}
\ No newline at end of file
+}
+
+/**
+ * 校验一个需要加载的段：地址必须在用户空间（栈以下），文件中的部分不能超过文件大小
+ */
+#[inline(never)]
+pub fn check_segment(ph: &Elf32ProgramHeader, file_size: usize) -> Result<(), ExecError> {
+    let vaddr = ph.p_vaddr;
+    let mem_sz = ph.p_memsz;
+    let file_sz = ph.p_filesz;
+    let offset = ph.p_offset;
+    if file_sz > mem_sz || mem_sz == 0 {
+        return Result::Err(ExecError::BadImage);
+    }
+    // 按照32位计算结束地址，跟ELF32中字段的宽度一致（在宿主机上测试的时候，usize是64位，不会溢出）
+    let seg_end = vaddr.checked_add(mem_sz);
+    if (vaddr as usize) < constants::USER_PROCESS_ADDR_START || seg_end.is_none() || seg_end.unwrap() as usize > constants::USER_STACK_BASE_ADDR - constants::USER_STACK_MAX_SIZE {
+        return Result::Err(ExecError::BadImage);
+    }
+    let file_end = offset.checked_add(file_sz);
+    if file_end.is_none() || file_end.unwrap() as usize > file_size {
+        return Result::Err(ExecError::BadImage);
+    }
+    return Result::Ok(());
+}
+
+/**
+ * 段占用的页的范围 [start, end)
+ */
+#[inline(never)]
+fn segment_page_range(ph: &Elf32ProgramHeader) -> (usize, usize) {
+    let page_size = constants::PAGE_SIZE as usize;
+    let start = ph.p_vaddr as usize / page_size * page_size;
+    let end = utils::div_ceil(ph.p_vaddr + ph.p_memsz, constants::PAGE_SIZE) as usize * page_size;
+    (start, end)
+}
+
+/**
+ * 设置某个段所在的页是否可写（已经映射了的页）
+ *   - 设置为只读的时候，跟可写段共用的页，保持可写
+ *   - 设置为可写的时候，写时复制的页不处理（写入的时候会复制）
+ */
+#[inline(never)]
+fn set_segment_writable(ph: &Elf32ProgramHeader, program_headers: &[Elf32ProgramHeader], writable: bool) {
+    let (start, end) = self::segment_page_range(ph);
+    for vaddr in (start..end).step_by(constants::PAGE_SIZE as usize) {
+        if !page_util::is_mapped(vaddr) {
+            continue;
+        }
+        let pte = page_util::addr_to_pte(vaddr);
+        if writable && pte.is_cow() {
+            continue;
+        }
+        if !writable {
+            // 这一页，还被某个可写的段使用
+            let shared_with_writable = program_headers.iter()
+                .filter(|other| other.is_load() && other.writable())
+                .map(|other| self::segment_page_range(other))
+                .any(|(other_start, other_end)| vaddr >= other_start && vaddr < other_end);
+            if shared_with_writable {
+                continue;
+            }
+        }
+        pte.set_writable(writable);
+        instruction::invalidate_page(vaddr);
+    }
+}
+
+/**
+ * 从文件的off偏移处，读满buf
+ */
+#[inline(never)]
+fn read_at(exec_file: &mut filesystem::File, off: u32, buf: &mut [u8]) -> Result<(), ExecError> {
+    exec_file.seek(SeekFrom::Start(off)).map_err(|e| ExecError::OpenFileError(e))?;
+    let read_bytes = exec_file.read(buf).map_err(|e| ExecError::OpenFileError(e))?;
+    if read_bytes != buf.len() {
+        return Result::Err(ExecError::BadImage);
+    }
+    return Result::Ok(());
+}
@@ -0,0 +1,285 @@
+use core::{mem::size_of, slice};
+
+use os_in_rust_common::{constants, domain::LbaAddr, racy_cell::RacyCell};
+
+use crate::{device::Disk, memory, sync::Lock};
+
+use super::constant;
+
+/**
+ * 扇区的缓冲区。位于文件系统和ATA硬盘之间
+ *  - 以（硬盘, LBA地址）作为key，每一项缓存一个扇区的数据
+ *  - 读：命中直接从缓存复制；未命中从硬盘读取到缓存中
+ *  - 写：只写入缓存，并且标记为脏。被淘汰的时候，或者主动同步的时候，才写回到硬盘
+ *  - 缓存满了，淘汰最久没有被访问的那一项（LRU）
+ *  - 日志事务中修改的元数据扇区会被钉住（pinned）：事务提交之前，不会被淘汰，也不会写回到硬盘
+ */
+
+/**
+ * 全局的扇区缓冲区
+ */
+static BUFFER_CACHE: RacyCell<BufferCache> = RacyCell::new(BufferCache::new());
+
+/**
+ * 缓存的一个扇区
+ */
+struct CachedSector {
+    /**
+     * 扇区所在的硬盘
+     */
+    disk: *mut Disk,
+    /**
+     * 扇区的LBA地址
+     */
+    lba: LbaAddr,
+    /**
+     * 这一项是否在使用中
+     */
+    valid: bool,
+    /**
+     * 数据被修改过，还没有写回到硬盘
+     */
+    dirty: bool,
+    /**
+     * 被日志事务钉住。钉住的扇区不能被淘汰，也不能写回到硬盘
+     */
+    pinned: bool,
+    /**
+     * 最近一次访问的时间（访问计数）。淘汰的时候，淘汰距离现在最久的
+     */
+    last_access: u32,
+    /**
+     * 扇区的数据
+     */
+    data: [u8; constants::DISK_SECTOR_SIZE],
+}
+
+impl CachedSector {
+    /**
+     * 把脏数据写回到硬盘。钉住的扇区，要等日志事务提交之后才能写回
+     */
+    #[inline(never)]
+    fn write_back(&mut self) {
+        if !self.valid || !self.dirty || self.pinned {
+            return;
+        }
+        let disk = unsafe { &mut *self.disk };
+        disk.write_sector(&self.data, self.lba, 1);
+        self.dirty = false;
+    }
+}
+
+unsafe impl Send for CachedSector {}
+unsafe impl Sync for CachedSector {}
+
+struct BufferCache {
+    /**
+     * 缓存项。第一次使用的时候，从内核的内存池中申请
+     */
+    sectors: Option<&'static mut [CachedSector]>,
+    /**
+     * 访问计数。每访问一次加1
+     */
+    access_tick: u32,
+    /**
+     * 缓冲区的锁。读写硬盘会阻塞，多个任务可能同时操作缓冲区
+     */
+    lock: Lock,
+}
+
+impl BufferCache {
+    const fn new() -> Self {
+        Self {
+            sectors: Option::None,
+            access_tick: 0,
+            lock: Lock::new(),
+        }
+    }
+
+    #[inline(never)]
+    fn get_sectors(&mut self) -> &mut [CachedSector] {
+        if self.sectors.is_none() {
+            let bytes = size_of::<CachedSector>() * constant::BUFFER_CACHE_SECS;
+            let sectors_ptr: &mut CachedSector = memory::malloc_system(bytes);
+            let sectors = unsafe { slice::from_raw_parts_mut(sectors_ptr as *mut CachedSector, constant::BUFFER_CACHE_SECS) };
+            for sector in sectors.iter_mut() {
+                sector.valid = false;
+                sector.dirty = false;
+                sector.pinned = false;
+            }
+            self.sectors = Option::Some(sectors);
+        }
+        self.sectors.as_mut().unwrap()
+    }
+
+    /**
+     * 找到（disk, lba）对应的缓存项。没有缓存的话，淘汰一项给它使用
+     *  - need_load: 没有缓存的时候，是否需要从硬盘读取数据（整个扇区都要被覆盖的话，不需要读取）
+     */
+    #[inline(never)]
+    fn get_sector(&mut self, disk: *mut Disk, lba: LbaAddr, need_load: bool) -> &mut CachedSector {
+        self.access_tick = self.access_tick.wrapping_add(1);
+        let access_tick = self.access_tick;
+        let sectors = self.get_sectors();
+
+        // 命中缓存
+        let hit = sectors.iter().position(|sector| sector.valid && sector.disk == disk && sector.lba.get_lba() == lba.get_lba());
+        if hit.is_some() {
+            let sector = &mut sectors[hit.unwrap()];
+            sector.last_access = access_tick;
+            return sector;
+        }
+
+        // 没有命中，优先使用空闲的项，否则淘汰最久没有访问的项（钉住的项不能淘汰）
+        let victim_idx = sectors.iter().position(|sector| !sector.valid).unwrap_or_else(|| {
+            sectors.iter().enumerate()
+                .filter(|(_, sector)| !sector.pinned)
+                .max_by_key(|(_, sector)| access_tick.wrapping_sub(sector.last_access))
+                .map(|(idx, _)| idx)
+                .unwrap()
+        });
+        let victim = &mut sectors[victim_idx];
+        // 被淘汰的是脏数据，先写回到硬盘
+        victim.write_back();
+
+        victim.disk = disk;
+        victim.lba = lba;
+        victim.valid = true;
+        victim.dirty = false;
+        victim.pinned = false;
+        victim.last_access = access_tick;
+        if need_load {
+            let disk = unsafe { &mut *disk };
+            disk.read_sectors(lba, 1, &mut victim.data);
+        }
+        victim
+    }
+}
+
+/**
+ * 从lba_start开始，读取连续sec_cnt个扇区的数据，到buf中。经过缓冲区
+ */
+#[inline(never)]
+pub fn read_sectors(disk: &mut Disk, lba_start: LbaAddr, sec_cnt: usize, buf: &mut [u8]) {
+    let cache = unsafe { BUFFER_CACHE.get_mut() };
+    cache.lock.lock();
+    for sec_idx in 0..sec_cnt {
+        let sector = cache.get_sector(disk as *mut Disk, lba_start.add(sec_idx as u32), true);
+        let buf_off = sec_idx * constants::DISK_SECTOR_SIZE;
+        // 缓冲区不够一个扇区的，只复制前面的部分
+        let copy_len = buf.len().saturating_sub(buf_off).min(constants::DISK_SECTOR_SIZE);
+        buf[buf_off..buf_off + copy_len].copy_from_slice(&sector.data[..copy_len]);
+    }
+    cache.lock.unlock();
+}
+
+/**
+ * 把buf的数据，写入到lba_start开始的连续sec_cnt个扇区中。只写入缓冲区，标记为脏数据
+ */
+#[inline(never)]
+pub fn write_sector(disk: &mut Disk, buf: &[u8], lba_start: LbaAddr, sec_cnt: usize) {
+    self::do_write_sector(disk, buf, lba_start, sec_cnt, false);
+}
+
+/**
+ * 把buf的数据，写入到lba_start开始的连续sec_cnt个扇区中，并且钉住这些扇区
+ * 日志事务提交之前，这些扇区都不会写回到硬盘。提交之后，调用unpin_sectors解除
+ */
+#[inline(never)]
+pub fn write_sector_pinned(disk: &mut Disk, buf: &[u8], lba_start: LbaAddr, sec_cnt: usize) {
+    self::do_write_sector(disk, buf, lba_start, sec_cnt, true);
+}
+
+#[inline(never)]
+fn do_write_sector(disk: &mut Disk, buf: &[u8], lba_start: LbaAddr, sec_cnt: usize, pin: bool) {
+    let cache = unsafe { BUFFER_CACHE.get_mut() };
+    cache.lock.lock();
+    for sec_idx in 0..sec_cnt {
+        let buf_off = sec_idx * constants::DISK_SECTOR_SIZE;
+        let copy_len = buf.len().saturating_sub(buf_off).min(constants::DISK_SECTOR_SIZE);
+        // 整个扇区都会被覆盖，不需要从硬盘读取
+        let sector = cache.get_sector(disk as *mut Disk, lba_start.add(sec_idx as u32), copy_len < constants::DISK_SECTOR_SIZE);
+        sector.data[..copy_len].copy_from_slice(&buf[buf_off..buf_off + copy_len]);
+        sector.dirty = true;
+        sector.pinned |= pin;
+    }
+    cache.lock.unlock();
+}
+
+/**
+ * 解除某个硬盘中，[lba_start, lba_start + sec_cnt)范围内扇区的钉住状态
+ */
+#[inline(never)]
+pub fn unpin_sectors(disk: &mut Disk, lba_start: LbaAddr, sec_cnt: usize) {
+    let disk = disk as *mut Disk;
+    let lba_start = lba_start.get_lba();
+    let lba_end = lba_start + sec_cnt as u32;
+    let cache = unsafe { BUFFER_CACHE.get_mut() };
+    cache.lock.lock();
+    cache.get_sectors().iter_mut()
+        .filter(|sector| sector.disk == disk && sector.lba.get_lba() >= lba_start && sector.lba.get_lba() < lba_end)
+        .for_each(|sector| sector.pinned = false);
+    cache.lock.unlock();
+}
+
+/**
+ * 把某个硬盘的所有脏数据，写回到硬盘
+ */
+#[inline(never)]
+pub fn sync_disk(disk: &mut Disk) {
+    let disk = disk as *mut Disk;
+    let cache = unsafe { BUFFER_CACHE.get_mut() };
+    cache.lock.lock();
+    cache.get_sectors().iter_mut()
+        .filter(|sector| sector.disk == disk)
+        .for_each(|sector| sector.write_back());
+    cache.lock.unlock();
+}
+
+/**
+ * 把某个硬盘中，[lba_start, lba_start + sec_cnt)范围内的脏数据，写回到硬盘
+ */
+#[inline(never)]
+pub fn sync_sectors(disk: &mut Disk, lba_start: LbaAddr, sec_cnt: usize) {
+    let disk = disk as *mut Disk;
+    let lba_start = lba_start.get_lba();
+    let lba_end = lba_start + sec_cnt as u32;
+    let cache = unsafe { BUFFER_CACHE.get_mut() };
+    cache.lock.lock();
+    cache.get_sectors().iter_mut()
+        .filter(|sector| sector.disk == disk && sector.lba.get_lba() >= lba_start && sector.lba.get_lba() < lba_end)
+        .for_each(|sector| sector.write_back());
+    cache.lock.unlock();
+}
+
+/**
+ * 把所有的脏数据，写回到硬盘
+ */
+#[inline(never)]
+pub fn sync_all() {
+    let cache = unsafe { BUFFER_CACHE.get_mut() };
+    cache.lock.lock();
+    cache.get_sectors().iter_mut().for_each(|sector| sector.write_back());
+    cache.lock.unlock();
+}
+
+/**
+ * 丢弃某个硬盘中，[lba_start, lba_start + sec_cnt)范围内的缓存（不写回）
+ * 绕过缓冲区直接写硬盘之后（比如安装文件系统），需要调用，避免缓存中是旧的数据
+ */
+#[inline(never)]
+pub fn invalidate(disk: &mut Disk, lba_start: LbaAddr, sec_cnt: usize) {
+    let disk = disk as *mut Disk;
+    let lba_start = lba_start.get_lba();
+    let lba_end = lba_start + sec_cnt as u32;
+    let cache = unsafe { BUFFER_CACHE.get_mut() };
+    cache.lock.lock();
+    cache.get_sectors().iter_mut()
+        .filter(|sector| sector.disk == disk && sector.lba.get_lba() >= lba_start && sector.lba.get_lba() < lba_end)
+        .for_each(|sector| {
+            sector.valid = false;
+            sector.dirty = false;
+            sector.pinned = false;
+        });
+    cache.lock.unlock();
+}
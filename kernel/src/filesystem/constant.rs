@@ -1,8 +1,8 @@
 use core::mem::size_of;
 
-use os_in_rust_common::{constants, domain::InodeNo};
+use os_in_rust_common::{constants, domain::{InodeNo, LbaAddr}};
 
-use super::dir_entry::DirEntry;
+use super::dir_entry;
 
 /**
  * 文件系统魔数
@@ -22,15 +22,111 @@ pub const INODE_INDIRECT_DATA_SECS: usize = 1;
 pub const INODE_DATA_SECS: usize = INODE_DIRECT_DATA_SECS + INODE_INDIRECT_DATA_SECS;
 
 /**
- * 一个文件系统最大的文件数量（inode数量）
+ * 一个间接块里面，可以存放的LBA地址数量
+ *  - 不管块多大，间接块只使用第一个扇区，这样OpenedInode中缓存的数据块地址数量是固定的
  */
-pub const MAX_FILE_PER_FS: u32 = 4096;
+pub const LBA_PER_BLOCK: usize = constants::DISK_SECTOR_SIZE / size_of::<LbaAddr>();
 
+/**
+ * 直接块 + 一级间接块，可以索引的数据块数量（这部分数据块的地址会缓存在OpenedInode中）
+ */
+pub const INODE_CACHED_DATA_SECS: usize = INODE_DIRECT_DATA_SECS + INODE_INDIRECT_DATA_SECS * LBA_PER_BLOCK;
+
+/**
+ * 一个inode最多可以索引的数据块数量 = 直接块 + 一级间接 + 二级间接 + 三级间接
+ */
+pub const INODE_MAX_DATA_SECS: usize = INODE_CACHED_DATA_SECS + LBA_PER_BLOCK * LBA_PER_BLOCK + LBA_PER_BLOCK * LBA_PER_BLOCK * LBA_PER_BLOCK;
+
+/**
+ * 旧版本的inode大小（没有二级、三级间接块）。单位字节
+ * 旧版本的超级块中没有记录inode的大小，挂载的时候按照这个大小解析inode数组
+ */
+pub const LEGACY_INODE_SIZE: usize = 60;
+
+/**
+ * inode的mode字段中，文件类型所占的位
+ */
+pub const INODE_MODE_TYPE_MASK: u16 = 0o170000;
+/**
+ * 文件类型：普通文件
+ */
+pub const INODE_MODE_REGULAR: u16 = 0o100000;
+/**
+ * 文件类型：目录
+ */
+pub const INODE_MODE_DIRECTORY: u16 = 0o040000;
+/**
+ * 文件类型：符号链接
+ */
+pub const INODE_MODE_SYMLINK: u16 = 0o120000;
+/**
+ * 文件类型：字符设备
+ */
+pub const INODE_MODE_CHAR_DEVICE: u16 = 0o020000;
+/**
+ * 文件类型：块设备
+ */
+pub const INODE_MODE_BLOCK_DEVICE: u16 = 0o060000;
+/**
+ * inode的mode字段中，权限所占的位（rwxrwxrwx）
+ */
+pub const INODE_MODE_PERM_MASK: u16 = 0o777;
+/**
+ * 新建普通文件的默认权限：rw-r--r--
+ */
+pub const DEFAULT_FILE_PERM: u16 = 0o644;
+/**
+ * 新建目录的默认权限：rwxr-xr-x
+ */
+pub const DEFAULT_DIR_PERM: u16 = 0o755;
+/**
+ * 符号链接的权限：rwxrwxrwx（符号链接自身的权限没有意义，以目标文件为准）
+ */
+pub const DEFAULT_SYMLINK_PERM: u16 = 0o777;
+
+/**
+ * 解析一个路径的时候，最多跟随的符号链接次数。超过了认为符号链接有环
+ */
+pub const MAX_SYMLINK_FOLLOW: u32 = 8;
+
+/**
+ * 格式化的时候，默认的块大小。单位字节
+ */
+pub const DEFAULT_BLOCK_SIZE: u32 = 1024;
+/**
+ * 格式化的时候，可以选择的最小块大小（1KB）。单位字节
+ * 旧版本的文件系统一个块就是一个扇区，超级块中的块大小读出来是0
+ */
+pub const MIN_BLOCK_SIZE: u32 = 1024;
+/**
+ * 可以挂载的最大块大小（4KB）。单位字节
+ */
+pub const MAX_BLOCK_SIZE: u32 = 4096;
+/**
+ * 格式化的时候，可以选择的最大块大小（1KB）。单位字节
+ *  - 间接块只使用第一个扇区，目录项也是按照扇区存放的，块越大浪费的空间越多
+ *  - 间接块、目录块按照块大小组织之前，只格式化1KB的块；已经格式化成2KB、4KB块的分区，仍然可以挂载
+ */
+pub const MAX_MKFS_BLOCK_SIZE: u32 = 1024;
 
 /**
- * 文件名称最大长度。单位字节
+ * 格式化的时候，默认每多少个字节的分区空间，分配一个inode
+ */
+pub const DEFAULT_BYTES_PER_INODE: u32 = 16 * 1024;
+/**
+ * 一个文件系统最少的文件数量（inode数量）
+ */
+pub const MIN_INODE_CNT: u32 = 64;
+/**
+ * 一个文件系统最多的文件数量（inode数量）。挂载、检查文件系统的时候，inode位图和数组都要放进内存
  */
-pub const MAX_FILE_NAME: usize = 20;
+pub const MAX_INODE_CNT: u32 = 16384;
+
+
+/**
+ * 文件名称最大长度。单位字节（UTF-8编码后的字节数）
+ */
+pub const MAX_FILE_NAME: usize = 255;
 
 /**
  * 整个系统最大可以打开的文件数量
@@ -40,10 +136,94 @@ pub const MAX_OPENED_FILE_IN_SYSTEM: usize = 32;
 /**
  * 文件路径最大长度
  */
-pub const MAX_FILE_PATH_LEN: usize = 100;
+pub const MAX_FILE_PATH_LEN: usize = 512;
+
+/**
+ * 扇区缓冲区，最多缓存的扇区数量
+ */
+pub const BUFFER_CACHE_SECS: usize = 64;
+
+/**
+ * 日志的魔数
+ */
+pub const JOURNAL_MAGIC: u32 = 0x4A524E4C;
+
+/**
+ * 一个日志事务，最多记录的元数据扇区数量。必须小于缓冲区的扇区数量（事务中的扇区在提交之前，会一直占用缓冲区）
+ */
+pub const JOURNAL_MAX_BLOCKS: usize = 32;
+
+/**
+ * 日志区占用的扇区数量 = 日志头（1扇区） + 元数据扇区的副本
+ */
+pub const JOURNAL_SECS: u32 = 1 + JOURNAL_MAX_BLOCKS as u32;
+
+/**
+ * 写文件的时候，申请一个数据块最多修改的元数据扇区数量
+ *  - 三级间接块树中途经的3个间接块：每个间接块的位图、清零、父节点各1个扇区
+ *  - 数据块的位图1个扇区
+ *  - 同步inode：inode自身最多2个扇区，一级间接块1个扇区
+ */
+pub const JOURNAL_WRITE_BLOCK_CREDITS: usize = 3 * 3 + 1 + 3;
+
+/**
+ * 目录中放入一个目录项，最多修改的元数据扇区数量
+ *  - 直接块用完了，申请一级间接块：位图、清零各1个扇区
+ *  - 新的目录数据块：位图1个扇区，块中所有的扇区
+ *  - 同步目录的inode：inode自身最多2个扇区（一级间接块上面已经算过了）
+ */
+pub const JOURNAL_DIR_ENTRY_CREDITS: usize = 2 + 1 + MAX_BLOCK_SIZE as usize / constants::DISK_SECTOR_SIZE + 2;
+
+/**
+ * 创建文件：inode位图1个扇区，新的inode最多2个扇区，以及父目录中的目录项
+ */
+pub const JOURNAL_CREATE_CREDITS: usize = 1 + 2 + JOURNAL_DIR_ENTRY_CREDITS;
 
+/**
+ * 创建目录：创建文件，以及新目录中的第一个目录项（.和..在同一个扇区）。父目录的链接数量在父目录的inode中，已经算过了
+ */
+pub const JOURNAL_MKDIR_CREDITS: usize = JOURNAL_CREATE_CREDITS + JOURNAL_DIR_ENTRY_CREDITS;
+
+/**
+ * 创建硬链接：inode的链接数量最多2个扇区，以及新父目录中的目录项
+ */
+pub const JOURNAL_LINK_CREDITS: usize = 2 + JOURNAL_DIR_ENTRY_CREDITS;
+
+/**
+ * 释放inode：inode位图1个扇区。数据块位图放不下的时候，会中途提交，至少预留1个扇区
+ */
+pub const JOURNAL_REMOVE_INODE_CREDITS: usize = 1 + 1;
+
+/**
+ * 删除目录项：目录项所在的扇区，父目录的inode最多2个扇区，被删除的inode最多2个扇区。最后一个链接，还要释放inode
+ */
+pub const JOURNAL_UNLINK_CREDITS: usize = 1 + 2 + 2 + JOURNAL_REMOVE_INODE_CREDITS;
+
+/**
+ * 重命名：新父目录中的目录项；旧父目录中目录项所在的扇区，以及inode最多2个扇区；移动目录的时候，..所在的扇区
+ */
+pub const JOURNAL_RENAME_CREDITS: usize = JOURNAL_DIR_ENTRY_CREDITS + 1 + 2 + 1;
+
+/**
+ * 硬盘中的目录项，按照这个字节数对齐
+ */
+pub const DIR_ENTRY_ALIGN: usize = 4;
+
+/**
+ * 一个块里面最多有多少个目录项（名称都只有1个字节）
+ */
+pub const MAX_ENTRY_IN_BLOCK: usize = constants::DISK_SECTOR_SIZE / dir_entry::entry_rec_len(1);
+/**
+ * fsck找回的孤儿inode，放在根目录下的这个目录中
+ */
+pub const LOST_FOUND_DIR_NAME: &str = "lost+found";
+
+/**
+ * 最多同时挂载的文件系统数量（包括根文件系统）
+ */
+pub const MAX_MOUNT_CNT: usize = 8;
 
 /**
- * 一个块里面最多有多少个目录项
+ * 一个tmpfs最多使用的内核页数量（数据页以及数据页的页表）。1MB
  */
-pub const MAX_ENTRY_IN_BLOCK: usize = constants::DISK_SECTOR_SIZE / size_of::<DirEntry>();
\ No newline at end of file
+pub const TMPFS_MAX_PAGES: usize = 256;
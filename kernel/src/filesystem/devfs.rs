@@ -0,0 +1,370 @@
+use core::str;
+
+use os_in_rust_common::{constants, domain::{InodeNo, LbaAddr}, printkln, racy_cell::RacyCell};
+
+use crate::{ascii::AsciiKey, console_print, device::{self, Disk, Partition}, keyboard, memory};
+
+use super::{buffer_cache, dir_api, dir_entry::{DirEntry, FileType}, file::FileError, mount::{self, MountError}, stat::FileStat, vfs::{Vfs, Vnode}};
+
+/**
+ * devfs：把设备以文件的形式展示出来，通过普通的open、read、write操作设备
+ *  - 字符设备：null、zero、tty（控制台）、keyboard（键盘）
+ *  - 块设备：每个硬盘（sda、sdb）以及每个分区（sdb1、sdb5……），按字节读写扇区
+ * 不能在/dev下创建、删除文件
+ */
+
+/**
+ * devfs的来源名称。mount dev <path> 挂载devfs
+ */
+pub const DEV_FS_SOURCE: &str = "dev";
+
+/**
+ * 根目录的编号
+ */
+const ROOT_INO: u32 = 1;
+
+/**
+ * 字符设备。编号从CHAR_DEVICE_INO_START开始，按顺序排列
+ */
+const CHAR_DEVICES: [&str; 4] = ["null", "zero", "tty", "keyboard"];
+const CHAR_DEVICE_INO_START: u32 = 2;
+
+/**
+ * 块设备的编号
+ *  - 硬盘 = DISK_INO_START + 硬盘的下标
+ *  - 分区 = PARTITION_INO_START + 分区在分区列表中的下标
+ */
+const DISK_INO_START: u32 = 0x100;
+const PARTITION_INO_START: u32 = 0x200;
+
+/**
+ * 块设备。硬盘或者分区
+ */
+#[derive(Clone, Copy)]
+struct BlockDevice {
+    ino: u32,
+    name: &'static str,
+    disk: *mut Disk,
+    /**
+     * 设备在硬盘上的起始扇区（绝对LBA地址）
+     */
+    lba_start: u32,
+    sec_cnt: u32,
+}
+
+impl BlockDevice {
+    fn size(&self) -> usize {
+        self.sec_cnt as usize * constants::DISK_SECTOR_SIZE
+    }
+}
+
+/**
+ * devfs中的节点
+ */
+#[derive(Clone, Copy)]
+enum DevNode {
+    Root,
+    /**
+     * 字符设备。CHAR_DEVICES的下标
+     */
+    Char(usize),
+    Block(BlockDevice),
+}
+
+impl DevNode {
+    #[inline(never)]
+    fn from_ino(ino: InodeNo) -> Option<Self> {
+        let ino = ino.get_data();
+        if ino == ROOT_INO {
+            return Option::Some(Self::Root);
+        }
+        if ino >= CHAR_DEVICE_INO_START && ino < CHAR_DEVICE_INO_START + CHAR_DEVICES.len() as u32 {
+            return Option::Some(Self::Char((ino - CHAR_DEVICE_INO_START) as usize));
+        }
+        self::block_devices().find(|device| device.ino == ino).map(Self::Block)
+    }
+
+    fn to_ino(&self) -> InodeNo {
+        let ino = match self {
+            Self::Root => ROOT_INO,
+            Self::Char(idx) => CHAR_DEVICE_INO_START + *idx as u32,
+            Self::Block(device) => device.ino,
+        };
+        InodeNo::new(ino)
+    }
+
+    fn file_type(&self) -> FileType {
+        match self {
+            Self::Root => FileType::Directory,
+            Self::Char(_) => FileType::CharDevice,
+            Self::Block(_) => FileType::BlockDevice,
+        }
+    }
+}
+
+/**
+ * 所有的块设备：先是硬盘，然后是分区
+ */
+#[inline(never)]
+fn block_devices() -> impl Iterator<Item = BlockDevice> {
+    let disks = device::get_all_disk().enumerate().map(|(idx, disk)| {
+        let disk_ptr = disk as *mut Disk;
+        BlockDevice {
+            ino: DISK_INO_START + idx as u32,
+            name: unsafe { &*disk_ptr }.get_name(),
+            disk: disk_ptr,
+            lba_start: 0,
+            sec_cnt: unsafe { &*disk_ptr }.sec_cnt,
+        }
+    });
+    let partitions = device::get_all_partition().iter().enumerate().map(|(idx, part_tag)| {
+        let part = Partition::parse_by_tag(part_tag);
+        BlockDevice {
+            ino: PARTITION_INO_START + idx as u32,
+            name: part.get_name(),
+            disk: part.from_disk,
+            lba_start: part.abs_lba_start(0).get_lba(),
+            sec_cnt: part.sec_cnt,
+        }
+    });
+    disks.chain(partitions)
+}
+
+/**
+ * devfs。没有任何数据需要保存，只记录打开的节点数量
+ */
+pub struct DevFs {
+    open_cnt: u32,
+}
+
+static DEV_FS: RacyCell<DevFs> = RacyCell::new(DevFs { open_cnt: 0 });
+
+/**
+ * devfs的实例。全局只有一个，卸载之后可以再次挂载
+ */
+pub fn get_dev_fs() -> *mut dyn Vfs {
+    unsafe { DEV_FS.get_mut() as *mut DevFs }
+}
+
+/**
+ * 创建目录path，把devfs挂载上去。系统启动的时候调用
+ */
+#[inline(never)]
+pub fn mount_devfs(path: &str) {
+    let res = dir_api::create_dir_all(path).map_err(|_| MountError::MountPointNotFound)
+        .and_then(|_| mount::mount_fs(self::get_dev_fs(), DEV_FS_SOURCE, path));
+    if res.is_err() {
+        printkln!("failed to mount {} on {}, error:{:?}", DEV_FS_SOURCE, path, res.unwrap_err());
+    }
+}
+
+impl DevFs {
+    #[inline(never)]
+    fn open_node(&mut self, node: DevNode) -> Vnode {
+        self.open_cnt += 1;
+        Vnode::new(self as *mut DevFs, node.to_ino(), node.file_type(), 0)
+    }
+}
+
+impl Vfs for DevFs {
+    fn root_ino(&self) -> InodeNo {
+        InodeNo::new(ROOT_INO)
+    }
+
+    #[inline(never)]
+    fn open_dir(&mut self, _ino: InodeNo) -> Vnode {
+        self.open_node(DevNode::Root)
+    }
+
+    #[inline(never)]
+    fn lookup(&mut self, dir: &Vnode, name: &str) -> Option<Vnode> {
+        if dir.ino.get_data() != ROOT_INO {
+            return Option::None;
+        }
+        let char_idx = CHAR_DEVICES.iter().position(|device_name| *device_name == name);
+        let node = if name == "." || name == ".." {
+            DevNode::Root
+        } else if char_idx.is_some() {
+            DevNode::Char(char_idx.unwrap())
+        } else {
+            DevNode::Block(self::block_devices().find(|device| device.name == name)?)
+        };
+        Option::Some(self.open_node(node))
+    }
+
+    fn create(&mut self, _dir: &Vnode, _name: &str, _file_type: FileType) -> Result<Vnode, FileError> {
+        Result::Err(FileError::PermissionDenied)
+    }
+
+    /**
+     * - null：没有数据
+     * - zero：全部是0
+     * - tty、keyboard：从键盘读取输入的键，没有输入的时候阻塞
+     * - 块设备：从off所在的扇区开始读取，不超过设备的大小
+     */
+    #[inline(never)]
+    fn read(&mut self, node: &Vnode, off: u32, buff: &mut [u8]) -> usize {
+        let dev_node = DevNode::from_ino(node.ino);
+        if dev_node.is_none() {
+            return 0;
+        }
+        match dev_node.unwrap() {
+            DevNode::Root => 0,
+            DevNode::Char(idx) => match CHAR_DEVICES[idx] {
+                "null" => 0,
+                "zero" => {
+                    buff.fill(0);
+                    buff.len()
+                },
+                _ => {
+                    let key_buff = unsafe { core::slice::from_raw_parts_mut(buff.as_mut_ptr() as *mut AsciiKey, buff.len()) };
+                    keyboard::read_keys(key_buff)
+                },
+            },
+            DevNode::Block(device) => self::read_block_device(&device, off as usize, buff),
+        }
+    }
+
+    /**
+     * - null、zero：丢弃写入的数据
+     * - tty：打印到控制台
+     * - keyboard：不能写
+     * - 块设备：写入off所在的扇区，不超过设备的大小
+     */
+    #[inline(never)]
+    fn write(&mut self, node: &Vnode, off: u32, buff: &[u8]) -> Result<usize, FileError> {
+        let dev_node = DevNode::from_ino(node.ino).ok_or(FileError::NotFound)?;
+        match dev_node {
+            DevNode::Root => Result::Err(FileError::IsADirectory),
+            DevNode::Char(idx) => match CHAR_DEVICES[idx] {
+                "null" | "zero" => Result::Ok(buff.len()),
+                "tty" => {
+                    // 不是UTF-8的部分，不打印
+                    let valid_len = str::from_utf8(buff).map_or_else(|err| err.valid_up_to(), |string| string.len());
+                    console_print!("{}", unsafe { str::from_utf8_unchecked(&buff[..valid_len]) });
+                    Result::Ok(buff.len())
+                },
+                _ => Result::Err(FileError::PermissionDenied),
+            },
+            DevNode::Block(device) => Result::Ok(self::write_block_device(&device, off as usize, buff)),
+        }
+    }
+
+    /**
+     * 目录项的偏移量，就是目录项的下标：.、..、字符设备、块设备
+     */
+    #[inline(never)]
+    fn readdir(&mut self, dir: &Vnode, off: u32) -> Option<(DirEntry, u32)> {
+        if dir.ino.get_data() != ROOT_INO {
+            return Option::None;
+        }
+        let idx = off as usize;
+        let entry = if idx == 0 {
+            DirEntry::new(InodeNo::new(ROOT_INO), ".", FileType::Directory)
+        } else if idx == 1 {
+            DirEntry::new(InodeNo::new(ROOT_INO), "..", FileType::Directory)
+        } else if idx < 2 + CHAR_DEVICES.len() {
+            let node = DevNode::Char(idx - 2);
+            DirEntry::new(node.to_ino(), CHAR_DEVICES[idx - 2], FileType::CharDevice)
+        } else {
+            let device = self::block_devices().nth(idx - 2 - CHAR_DEVICES.len())?;
+            DirEntry::new(InodeNo::new(device.ino), device.name, FileType::BlockDevice)
+        };
+        Option::Some((entry, off + 1))
+    }
+
+    fn unlink(&mut self, _dir: &Vnode, _name: &str) -> Result<(), FileError> {
+        Result::Err(FileError::PermissionDenied)
+    }
+
+    /**
+     * 块设备的大小，是扇区数量 * 扇区大小。其他的节点大小都是0
+     */
+    #[inline(never)]
+    fn stat(&mut self, node: &Vnode) -> FileStat {
+        let dev_node = DevNode::from_ino(node.ino);
+        let (size, blocks) = match dev_node {
+            Option::Some(DevNode::Block(device)) => (device.size() as u32, device.sec_cnt),
+            _ => (0, 0),
+        };
+        FileStat {
+            i_no: node.ino.get_data(),
+            mode: node.file_type.to_mode() as u32,
+            nlink: if node.is_dir() { 2 } else { 1 },
+            uid: 0,
+            gid: 0,
+            size,
+            blocks,
+            flags: 0,
+            ctime: 0,
+            mtime: 0,
+            atime: 0,
+        }
+    }
+
+    fn reopen(&mut self, _node: &Vnode) {
+        self.open_cnt += 1;
+    }
+
+    fn close(&mut self, _node: &Vnode) {
+        self.open_cnt = self.open_cnt.saturating_sub(1);
+    }
+
+    fn is_busy(&mut self) -> bool {
+        self.open_cnt > 0
+    }
+
+    /**
+     * devfs没有需要写回的数据，也不需要释放内存
+     */
+    fn unmount(&mut self) {
+        self.open_cnt = 0;
+    }
+}
+
+/**
+ * 从块设备字节偏移量为off的地方开始，读取数据到buff中。经过缓冲区
+ */
+#[inline(never)]
+fn read_block_device(device: &BlockDevice, off: usize, buff: &mut [u8]) -> usize {
+    let disk = unsafe { &mut *device.disk };
+    let sector_buf: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(constants::DISK_SECTOR_SIZE);
+    let total_len = buff.len().min(device.size().saturating_sub(off));
+    let mut done_len = 0;
+    while done_len < total_len {
+        let cur_off = off + done_len;
+        let sector_off = cur_off % constants::DISK_SECTOR_SIZE;
+        let copy_len = (constants::DISK_SECTOR_SIZE - sector_off).min(total_len - done_len);
+        let lba = LbaAddr::new(device.lba_start + (cur_off / constants::DISK_SECTOR_SIZE) as u32);
+        buffer_cache::read_sectors(disk, lba, 1, sector_buf);
+        buff[done_len..done_len + copy_len].copy_from_slice(&sector_buf[sector_off..sector_off + copy_len]);
+        done_len += copy_len;
+    }
+    memory::sys_free(sector_buf.as_ptr() as usize);
+    total_len
+}
+
+/**
+ * 把buff写入到块设备字节偏移量为off的地方。不是整个扇区的，先读出来再修改。只写入缓冲区，sync的时候写回硬盘
+ */
+#[inline(never)]
+fn write_block_device(device: &BlockDevice, off: usize, buff: &[u8]) -> usize {
+    let disk = unsafe { &mut *device.disk };
+    let sector_buf: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(constants::DISK_SECTOR_SIZE);
+    let total_len = buff.len().min(device.size().saturating_sub(off));
+    let mut done_len = 0;
+    while done_len < total_len {
+        let cur_off = off + done_len;
+        let sector_off = cur_off % constants::DISK_SECTOR_SIZE;
+        let copy_len = (constants::DISK_SECTOR_SIZE - sector_off).min(total_len - done_len);
+        let lba = LbaAddr::new(device.lba_start + (cur_off / constants::DISK_SECTOR_SIZE) as u32);
+        if copy_len < constants::DISK_SECTOR_SIZE {
+            buffer_cache::read_sectors(disk, lba, 1, sector_buf);
+        }
+        sector_buf[sector_off..sector_off + copy_len].copy_from_slice(&buff[done_len..done_len + copy_len]);
+        buffer_cache::write_sector(disk, sector_buf, lba, 1);
+        done_len += copy_len;
+    }
+    memory::sys_free(sector_buf.as_ptr() as usize);
+    total_len
+}
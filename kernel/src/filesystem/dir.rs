@@ -4,7 +4,7 @@ use crate::filesystem::dir_entry::DirEntrySearchReq;
 use crate::{memory, thread};
 use crate::thread::TaskStruct;
 
-use super::{dir_entry::{self, FileType}, file, file_util, fs::{self, FileSystem}, inode::{self, OpenedInode}};
+use super::{constant, dir_entry::{self, FileType}, file::FileError, file_util, fs::FileSystem, inode::{self, OpenedInode}, mount, vfs::{self, Vfs}};
 
 /** 
  * 文件系统中的目录的结构以及操作
@@ -12,54 +12,81 @@ use super::{dir_entry::{self, FileType}, file, file_util, fs::{self, FileSystem}
 
 
 
+/**
+ * 根文件系统挂载之后（挂载的时候已经加载了根目录），初始化每个任务的工作目录
+ */
 #[inline(never)]
 pub fn init_root_dir() {
-    // 设置文件小系统的根目录
-    self::load_root_dir();
-
     // 每个任务的当前工作目录都设置为根目录
     self::set_root_dir_for_task();
 }
 
-/**
- * 加载根目录。
- */
-#[inline(never)]
-pub fn load_root_dir() {
-    let file_system = fs::get_filesystem();
-    let root_inode = inode::load_inode(file_system, file_system.super_block.root_inode_no);
-    file_system.set_root_inode(root_inode);
-}
-
 
 #[inline(never)]
 fn set_root_dir_for_task() {
     let old = instruction::disable_interrupt();
-    let file_system = fs::get_filesystem();
+    let root_fs = mount::root_fs();
     for tag in thread::get_all_thread().iter() {
         let task = unsafe { &mut *TaskStruct::parse_by_all_tag(&*tag) };
-        task.cwd_inode = Option::Some(file_system.super_block.root_inode_no);
+        task.cwd_inode = Option::Some(root_fs.root_ino());
+        task.cwd_fs = Option::Some(root_fs as *mut dyn Vfs);
     }
     instruction::set_interrupt(old);
 }
 /**
  * 在parent_dir目录下，创建一个名为dir_name的子目录
+ *  - 没有空间了，返回NoSpace（已经创建的目录项和inode会被删除）
  */
 #[inline(never)]
-pub fn mkdir(fs: &mut FileSystem, parent_dir_inode: &mut OpenedInode, dir_name: &str) -> InodeNo {
+pub fn mkdir(fs: &mut FileSystem, parent_dir_inode: &mut OpenedInode, dir_name: &str) -> Result<InodeNo, FileError> {
     let parent_ino = parent_dir_inode.i_no;
     // 在该目录下创建一个文件夹类型的目录项
-    let entry_i_no = dir_entry::create_dir_entry(fs, parent_dir_inode, dir_name, FileType::Directory);
+    let entry_i_no = dir_entry::create_dir_entry(fs, parent_dir_inode, dir_name, FileType::Directory)?;
     let entry_inode = inode::inode_open(fs, entry_i_no);
-    // 该目录项下应该还有两项，分别是: ..和.
-    // 创建..目录项
-    dir_entry::do_create_dir_entry_with_inode(fs, entry_inode, parent_ino, "..", FileType::Directory);
-    // 创建 .目录项
-    dir_entry::do_create_dir_entry_with_inode(fs, entry_inode, entry_inode.i_no, ".", FileType::Directory);
+    // 该目录项下应该还有两项，分别是: ..和.（新目录的第一个数据块，在创建..的时候申请）
+    let res = dir_entry::do_create_dir_entry_with_inode(fs, entry_inode, parent_ino, "..", FileType::Directory);
+    if res.is_err() {
+        // 申请不到数据块，把刚刚创建的目录项和inode删掉
+        dir_entry::remove_dir_entry(fs, parent_dir_inode, DirEntrySearchReq::build().i_no(entry_i_no));
+        inode::sync_inode(fs, parent_dir_inode);
+        // 关闭的时候，释放inode
+        entry_inode.unlinked = true;
+        inode::inode_close(fs, entry_inode);
+        return Result::Err(res.unwrap_err());
+    }
+    // 创建 .目录项（跟..在同一个扇区，一定放得下）
+    let res = dir_entry::do_create_dir_entry_with_inode(fs, entry_inode, entry_inode.i_no, ".", FileType::Directory);
+    ASSERT!(res.is_ok());
+
+    // 新目录有两个链接：父目录中的目录项，以及自己的.目录项
+    entry_inode.i_nlink = 2;
+    inode::sync_inode(fs, entry_inode);
+    // 新目录的..指向父目录，父目录的链接数量 + 1
+    parent_dir_inode.i_nlink += 1;
+    inode::sync_inode(fs, parent_dir_inode);
 
     inode::inode_close(fs, entry_inode);
 
-    entry_i_no
+    Result::Ok(entry_i_no)
+}
+
+/**
+ * 删除parent_dir_inode目录下的空目录dir_inode
+ */
+#[inline(never)]
+pub fn rmdir(fs: &mut FileSystem, parent_dir_inode: &mut OpenedInode, dir_inode: &mut OpenedInode) -> Result<(), FileError> {
+    // 如果inode的数据区，不止两个目录（.和..），无法删除
+    if dir_inode.i_size as usize > dir_entry::entry_rec_len(".".len()) + dir_entry::entry_rec_len("..".len()) {
+        return Result::Err(FileError::DirectoryNotEmpty);
+    }
+    // 指定父目录，删除当前目录项
+    let succeed = dir_entry::remove_dir_entry(fs, parent_dir_inode, DirEntrySearchReq::build().i_no(dir_inode.i_no));
+    if !succeed {
+        return Result::Err(FileError::NotFound);
+    }
+    // 父目录操作完成后，保存到硬盘
+    inode::sync_inode(fs, parent_dir_inode);
+    Result::Ok(())
 }
 
 
@@ -72,39 +99,54 @@ pub fn get_cwd<'a>(task: &TaskStruct, path: &'a mut [u8]) -> Option<&'a str> {
         return Option::None;
     }
 
-    let buf: &mut [u8; 100] = memory::malloc(100);
-    let fs = fs::get_filesystem();
-    let mut base_inode = inode::inode_open(fs, task.cwd_inode.unwrap());
+    let buf: &mut [u8; constant::MAX_FILE_PATH_LEN] = memory::malloc(constant::MAX_FILE_PATH_LEN);
+    let cwd_fs = task.cwd_fs.unwrap_or(mount::root_fs() as *mut dyn Vfs);
+    let mut base_node = unsafe { &mut *cwd_fs }.open_dir(task.cwd_inode.unwrap());
     let mut idx = 0;
     loop {
-        // 找到这个inode对应的父目录的inode
-        let parent_inode = dir_entry::parent_entry(base_inode);
-
-        // 当前目录跟父目录的inode号相同，说明是根目录直接返回
-        if base_inode.i_no == parent_inode {
-            break;
+        // 某个文件系统的根目录
+        if base_node.is_fs_root() {
+            // 挂载的文件系统的根目录，换成挂载点目录，继续往上找
+            let covered = mount::find_covered(base_node.get_fs_ptr());
+            base_node.close();
+            if covered.is_none() {
+                break;
+            }
+            let (parent_fs, covered_ino) = covered.unwrap();
+            base_node = parent_fs.open_dir(covered_ino);
+            continue;
         }
 
         // 打开父目录
-        let parent_inode = inode::inode_open(fs, parent_inode);
+        let parent_node = base_node.get_fs().lookup(&base_node, "..");
+        if parent_node.is_none() {
+            base_node.close();
+            break;
+        }
+        let parent_node = parent_node.unwrap();
         // 父目录下搜索当前目录，得到目录项名称
-        let cur_dir_entry = dir_entry::do_search_dir_entry(fs, parent_inode, DirEntrySearchReq::build().i_no(base_inode.i_no));
-        inode::inode_close(fs, base_inode);
+        let cur_dir_entry = vfs::find_entry(&parent_node, base_node.ino);
+        base_node.close();
 
         // 当前节点，对应不上目录项。也返回
-        if cur_dir_entry.is_none() || cur_dir_entry.unwrap().is_empty() {
-            inode::inode_close(fs, parent_inode);
+        if cur_dir_entry.is_none() {
+            parent_node.close();
             break;
         }
         let cur_dir_entry = cur_dir_entry.unwrap();
         // 拼接目录项名称
         let entry_name = cur_dir_entry.get_name();
+        // 路径太长了，放不下
+        if idx + "/".len() + entry_name.len() > buf.len() {
+            parent_node.close();
+            break;
+        }
         // 把这个目录名称保存下来
         buf[idx ..idx + 1].copy_from_slice("/".as_bytes());
         buf[idx + 1 ..idx + 1 + entry_name.len()].copy_from_slice(entry_name.as_bytes());
         idx += "/".len();
         idx += entry_name.len();
-        base_inode = parent_inode;
+        base_node = parent_node;
     }
     if idx == 0 {
         buf[idx ..idx + 1].copy_from_slice("/".as_bytes());
@@ -132,13 +174,18 @@ pub fn get_cwd<'a>(task: &TaskStruct, path: &'a mut [u8]) -> Option<&'a str> {
  */
 #[inline(never)]
 pub fn change_dir(task: &mut TaskStruct, path: &str) -> Option<()> {
-    let fs = fs::get_filesystem();
-    // 找到这个目录项
-    let (entry, entry_inode) = dir_entry::search_dir_entry(fs, path)?;
-    // 关闭inode
-    inode::inode_close(fs, entry_inode);
-    // 取出inode号
-    task.cwd_inode = Option::Some(entry.i_no);
+    if !file_util::is_path_legal(path) {
+        return Option::None;
+    }
+    // 找到这个目录。目录可能在挂载的文件系统中
+    let dir_node = vfs::lookup_path(path, true)?;
+    dir_node.close();
+    if !dir_node.is_dir() {
+        return Option::None;
+    }
+    // 取出编号
+    task.cwd_inode = Option::Some(dir_node.ino);
+    task.cwd_fs = Option::Some(dir_node.get_fs_ptr());
 
     return Option::Some(());
-}
\ No newline at end of file
+}
@@ -1,12 +1,8 @@
-use core::mem::size_of;
+use core::marker::PhantomData;
 
-use os_in_rust_common::{constants, cstr_write, cstring_utils, MY_PANIC};
+use os_in_rust_common::{cstr_write, cstring_utils};
 
-use crate::memory;
-
-use super::{
-    constant, dir, dir_entry::{self, DirEntry, DirEntrySearchReq}, file_util, fs, inode::{self, OpenedInode}
-};
+use super::{constant, dir_entry::{DirEntry, FileType}, file::FileError, file_util, mount, vfs::{self, Vnode}};
 
 #[derive(Debug)]
 pub enum DirError {
@@ -15,35 +11,36 @@ pub enum DirError {
     ParentDirNotExists,
     AlreadyExists,
     DirectoryNotEmpty,
+    NotADirectory,
+    /**
+     * 目录是某个文件系统的根目录（根目录，或者挂载点），不能删除
+     */
+    DirectoryBusy,
 }
 
 #[derive(Debug)]
 pub struct ReadDir<'a> {
     /**
-     * 该目录对应的inode
+     * 该目录对应的节点
      */
-    inode: &'a mut OpenedInode,
+    node: Vnode,
 
     /**
      * 该目录的全路径
      */
     path: [u8; constant::MAX_FILE_PATH_LEN],
 
+    _marker: PhantomData<&'a mut Vnode>,
 }
 
-// impl <'a> Drop for ReadDir<'a> {
-//     fn drop(&mut self) {
-//         inode::inode_close(fs::get_filesystem(), self.inode);
-//     }
-// }
-
 impl <'a> ReadDir<'a> {
     
     #[inline(never)]
-    pub fn new(inode: &'a mut OpenedInode, dir_path: &str) -> Self {
+    pub fn new(node: Vnode, dir_path: &str) -> Self {
         let mut dir = Self {
-            inode: inode,
+            node,
             path: [0; constant::MAX_FILE_PATH_LEN],
+            _marker: PhantomData,
         };
         // 保存文件路径
         cstr_write!(&mut dir.path, "{}", dir_path);
@@ -56,8 +53,7 @@ impl <'a> ReadDir<'a> {
 
     #[inline(never)]
     pub fn iter(&'a mut self) -> ReadDirIterator<'a> {
-        let buf: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(constants::DISK_SECTOR_SIZE);
-        ReadDirIterator::new(self.inode, buf)
+        ReadDirIterator::new(self.node)
     }
 
     #[inline(never)]
@@ -67,50 +63,49 @@ impl <'a> ReadDir<'a> {
         return iter;
     }
 
-    pub fn is_empty(&self) -> bool {
-        // 如果inode的数据区，只有两个目录，那么说明空了
-        self.inode.i_size <= (size_of::<DirEntry>() * 2).try_into().unwrap()
-    }
-
     /**
-     * 关掉这个inode
+     * 目录下是否只有.和..
      */
-    #[inline(never)]
-    fn close(&mut self) {
-        let fs = fs::get_filesystem();
-        inode::inode_close(fs, self.inode);
+    pub fn is_empty(&self) -> bool {
+        vfs::is_empty_dir(&self.node)
     }
 
+    #[inline(never)]
     pub fn get_file_size(&self) -> usize {
-        self.inode.i_size.try_into().unwrap()
+        self.node.get_fs().stat(&self.node).size.try_into().unwrap()
     }
 }
 
 #[derive(Debug)]
 pub struct ReadDirIterator<'a> {
-    inode: &'a mut OpenedInode,
-    block_idx: usize,
-    dir_entry_buf: &'a mut [u8; constants::DISK_SECTOR_SIZE],
-    dir_entry_idx: usize,
+    node: Vnode,
+    /**
+     * 下一个目录项的偏移量
+     */
+    dir_entry_off: u32,
+    /**
+     * 最近一次读取到的目录项。迭代器返回的是它的引用
+     */
+    cur_entry: DirEntry,
     ignore_drop: bool,
+    _marker: PhantomData<&'a DirEntry>,
 }
 
 impl <'a> ReadDirIterator<'a> {
     #[inline(never)]
-    pub fn new(inode: &'a mut OpenedInode, dir_entry_buf: &'a mut [u8; constants::DISK_SECTOR_SIZE]) -> Self {
+    pub fn new(node: Vnode) -> Self {
         Self {
-            inode,
-            block_idx: 0,
-            dir_entry_buf: dir_entry_buf,
-            dir_entry_idx: 0,
+            node,
+            dir_entry_off: 0,
+            cur_entry: DirEntry::empty(),
             ignore_drop: false,
+            _marker: PhantomData,
         }
     }
 
     #[inline(never)]
     pub fn drop(&mut self) {
-        inode::inode_close(fs::get_filesystem(), self.inode);
-        memory::sys_free(self.dir_entry_buf.as_ptr() as usize);
+        self.node.close();
     }
 }
 
@@ -132,91 +127,45 @@ impl <'a>Iterator for ReadDirIterator<'a> {
 
     #[inline(never)]
     fn next(&mut self) -> Option<Self::Item> {
-        let data_blocks = self.inode.get_data_blocks_ref();
-        // 遍历所有的数据块
-        while self.block_idx < data_blocks.len() && !data_blocks[self.block_idx].is_empty() {
-            // 如果是数据扇区内的，第一个目录项，那么加载一下
-            if self.dir_entry_idx == 0 {
-                let disk = unsafe { &mut *fs::get_filesystem().base_part.from_disk };
-                disk.read_sectors(data_blocks[self.block_idx], 1, self.dir_entry_buf);
-            }
-            let dir_entry_list = unsafe { core::slice::from_raw_parts(self.dir_entry_buf as *const _ as *const DirEntry, self.dir_entry_buf.len() / size_of::<DirEntry>()) };
-            
-            if self.dir_entry_idx >= dir_entry_list.len() {
-                MY_PANIC!("idx:{}, len:{}", self.dir_entry_idx, dir_entry_list.len());
-            }
-
-            // 我们找到了目录项
-            let target_dir_entry = &dir_entry_list[self.dir_entry_idx];
-
-            // 目录项所在的目录项列表下标，往后走
-            self.dir_entry_idx += 1;
-
-            // 如果走完了当前扇区的所有目录项
-            if self.dir_entry_idx >= dir_entry_list.len() {
-                // 往后走
-                self.block_idx += 1;
-                // 目录项下标清零
-                self.dir_entry_idx = 0;
-            }
-
-            if !target_dir_entry.is_empty() {
-                return Option::Some(target_dir_entry);
-            }
-        };
-        return Option::None;
+        let (entry, next_off) = self.node.get_fs().readdir(&self.node, self.dir_entry_off)?;
+        self.dir_entry_off = next_off;
+        self.cur_entry = entry;
+        return Option::Some(unsafe { &*(&self.cur_entry as *const DirEntry) });
     }
 }
 
 #[inline(never)]
 pub fn create_dir(path: &str) -> Result<(), DirError> {
-    if !path.starts_with("/") {
+    if !path.starts_with("/") || !file_util::is_path_legal(path) {
         return Result::Err(DirError::DirPathIllegal);
     }
     if path == "/" {
         return Result::Err(DirError::DirPathIllegal);
     }
-    
-    let fs = fs::get_filesystem();
 
-    let split_res = file_util::split_file_path(path);
-    if split_res.is_none() {
-        return Result::Err(DirError::DirPathIllegal);
-    }
-
-    let (parent_dir_path, dir_entry_name) = split_res.unwrap();
-
-    // 父目录的inode
-    let parent_dir = dir_entry::search_dir_entry(fs, parent_dir_path);
-    // 父目录不存在，报错
-    if parent_dir.is_none() {
-        return Result::Err(DirError::ParentDirNotExists);
-    }
-    let (_, parent_dir_inode) = parent_dir.unwrap();
-    // 搜索要创建的inode
-    let dir_entry = dir_entry::do_search_dir_entry(fs, parent_dir_inode, DirEntrySearchReq::build().entry_name(dir_entry_name));
-    // 如果已经存在了，报错
-    if dir_entry.is_some() {
-        // 把这个parent dir inode关闭掉
-        inode::inode_close(fs, parent_dir_inode);
-        return Result::Err(DirError::AlreadyExists);
-    }
-
-    dir::mkdir(fs, parent_dir_inode, dir_entry_name);
-    // 把这个parent dir inode关闭掉
-    inode::inode_close(fs, parent_dir_inode);
+    // 父目录
+    let (parent_dir, dir_entry_name) = vfs::lookup_parent(path).map_err(|err| match err {
+        FileError::ParentDirNotExists => DirError::ParentDirNotExists,
+        _ => DirError::DirPathIllegal,
+    })?;
+    // 在父目录下创建子目录（已经存在的话报错）
+    let created = parent_dir.get_fs().create(&parent_dir, dir_entry_name, FileType::Directory);
+    parent_dir.close();
+    let created = created.map_err(|err| match err {
+        FileError::AlreadyExists => DirError::AlreadyExists,
+        _ => DirError::NotFound,
+    })?;
+    created.close();
     return Result::Ok(());
 }
 
 #[inline(never)]
 pub fn create_dir_all(path: &str) -> Result<(), DirError> {
-    if !path.starts_with("/") {
+    if !path.starts_with("/") || !file_util::is_path_legal(path) {
         return Result::Err(DirError::DirPathIllegal);
     }
-    let fs = fs::get_filesystem();
-    
     // 根目录为基准目录
-    let mut base_inode = inode::inode_open(fs, fs.super_block.root_inode_no);
+    let mut base_node = mount::root_fs().root();
 
     // 使用/分隔每个目录项
     let mut dir_entry_split = path.split("/");
@@ -225,24 +174,32 @@ pub fn create_dir_all(path: &str) -> Result<(), DirError> {
         if entry_name.is_empty() {
             continue;
         }
-        let search_result = dir_entry::do_search_dir_entry(fs, base_inode, DirEntrySearchReq::build().entry_name(entry_name));
+        // 路径可能穿过挂载点，每一层都在当前目录所在的文件系统中操作
+        let fs = base_node.get_fs();
+        let search_result = fs.lookup(&base_node, entry_name);
         // 如果该目录项已经存在了，搜索下一层
         if search_result.is_some() {
-            // 关掉inode
-            inode::inode_close(fs, base_inode);
-            // 打开找到了inode
-            base_inode = inode::inode_open(fs, search_result.unwrap().i_no);
+            base_node.close();
+            // 挂载点会进入挂载的文件系统的根目录
+            base_node = vfs::enter_mount(search_result.unwrap());
+            if !base_node.is_dir() {
+                base_node.close();
+                return Result::Err(DirError::NotADirectory);
+            }
             continue;
         }
         // 创建子目录
-        let sub_dir_inode = dir::mkdir(fs, base_inode, entry_name);
-        inode::inode_close(fs, base_inode);
+        let sub_dir = fs.create(&base_node, entry_name, FileType::Directory);
+        base_node.close();
+        if sub_dir.is_err() {
+            return Result::Err(DirError::NotFound);
+        }
 
         // 再次遍历基于子目录
-        base_inode = inode::inode_open(fs, sub_dir_inode);
+        base_node = sub_dir.unwrap();
     }
 
-    inode::inode_close(fs, base_inode);
+    base_node.close();
     return Result::Ok(());
 }
 
@@ -251,25 +208,13 @@ pub fn create_dir_all(path: &str) -> Result<(), DirError> {
  */
 #[inline(never)]
 pub fn read_dir(path: &str) -> Result<ReadDir, DirError> {
-    // 根据名称，搜索到这个目录项对应的inode
-    let entry_inode = self::search_dir_entry(path)?;
-    let dir = ReadDir::new(entry_inode, path);
-    Result::Ok(dir)
-}
-
-#[inline(never)]
-fn search_dir_entry(path: &str) -> Result<&'static mut OpenedInode, DirError> {
-    if !path.starts_with("/") {
+    if !path.starts_with("/") || !file_util::is_path_legal(path) {
         return Result::Err(DirError::DirPathIllegal);
     }
-    let fs = fs::get_filesystem();
-
-    // 搜索到这个文件
-    let searched_file = dir_entry::search_dir_entry(fs, path);
-    if searched_file.is_none() {
-        return Result::Err(DirError::NotFound);
-    }
-    return Result::Ok(searched_file.unwrap().1);
+    // 根据名称，搜索到这个目录项对应的节点
+    let dir_node = vfs::lookup_path(path, true).ok_or(DirError::NotFound)?;
+    let dir = ReadDir::new(dir_node, path);
+    Result::Ok(dir)
 }
 
 
@@ -278,31 +223,37 @@ fn search_dir_entry(path: &str) -> Result<&'static mut OpenedInode, DirError> {
  */
 #[inline(never)]
 pub fn remove_dir(path: &str) -> Result<(),  DirError> {
-    let fs = fs::get_filesystem();
-    let mut dir_to_remove = self::read_dir(path)?;
+    if !path.starts_with("/") || !file_util::is_path_legal(path) {
+        return Result::Err(DirError::DirPathIllegal);
+    }
+    // 符号链接不是目录，不能跟随到目标目录去删除
+    let dir_node = vfs::lookup_path(path, false).ok_or(DirError::NotFound)?;
+    let is_dir = dir_node.is_dir();
+    let is_fs_root = dir_node.is_fs_root();
     // 如果存在数据，无法删除
-    if !dir_to_remove.is_empty() {
-        dir_to_remove.close();
+    let is_empty = is_dir && vfs::is_empty_dir(&dir_node);
+    dir_node.close();
+    if !is_dir {
+        return Result::Err(DirError::NotADirectory);
+    }
+    // 文件系统的根目录（包括挂载点）不能删除
+    if is_fs_root {
+        return Result::Err(DirError::DirectoryBusy);
+    }
+    if !is_empty {
         return Result::Err(DirError::DirectoryNotEmpty);
     }
 
-    // 找到父目录
-    let parent_dir_inode = dir_entry::parent_entry(dir_to_remove.inode);
-
-    let parent_dir_inode = inode::inode_open(fs, parent_dir_inode);
-
-    // 指定父目录，删除当前目录项
-    let succeed = dir_entry::remove_dir_entry(fs, parent_dir_inode, DirEntrySearchReq::build().i_no(dir_to_remove.inode.i_no));
-    // 已经删除完了，关闭那个删除过的inode
-    dir_to_remove.close();
-    if !succeed {
-        inode::inode_close(fs, parent_dir_inode);
-        return Result::Err(DirError::NotFound);
+    // 找到父目录，删除当前目录项
+    let (parent_dir, dir_name) = vfs::lookup_parent(path).map_err(|_| DirError::NotFound)?;
+    if dir_name == "." || dir_name == ".." {
+        parent_dir.close();
+        return Result::Err(DirError::DirPathIllegal);
     }
-    // 父目录操作完成后，保存到硬盘
-    inode::sync_inode(fs, parent_dir_inode);
-    // 关闭打开的父目录
-    inode::inode_close(fs, parent_dir_inode);
-
-    return Result::Ok(());
-}
\ No newline at end of file
+    let res = parent_dir.get_fs().unlink(&parent_dir, dir_name);
+    parent_dir.close();
+    res.map_err(|err| match err {
+        FileError::DirectoryNotEmpty => DirError::DirectoryNotEmpty,
+        _ => DirError::NotFound,
+    })
+}
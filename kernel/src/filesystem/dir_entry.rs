@@ -1,10 +1,10 @@
-use core::{mem::size_of, slice};
+use core::mem::size_of;
 
-use os_in_rust_common::{constants, cstr_write, cstring_utils, domain::{InodeNo, LbaAddr}, printkln, utils, ASSERT, MY_PANIC};
+use os_in_rust_common::{constants, domain::{InodeNo, LbaAddr}, printkln, utils, ASSERT, MY_PANIC};
 
-use crate::{device::Disk, memory};
+use crate::{device::Disk, memory, time};
 
-use super::{constant, fs::{self, FileSystem}, inode::{self, Inode, OpenedInode}};
+use super::{buffer_cache, constant, file::FileError, fs::FileSystem, inode::{self, Inode, OpenedInode}, journal::{self, Journal}};
 
 
 /**
@@ -26,27 +26,88 @@ pub enum FileType {
      * 未知
      */
     Unknown,
+    /**
+     * 符号链接。链接的目标路径，存放在inode的数据区
+     */
+    Symlink,
+    /**
+     * 字符设备。例如：/dev/null、/dev/tty
+     */
+    CharDevice,
+    /**
+     * 块设备。例如：/dev/sda、/dev/sdb1
+     */
+    BlockDevice,
+}
+
+impl FileType {
+    /**
+     * 该文件类型，新建的时候inode的mode（文件类型 + 默认权限）
+     */
+    pub fn to_mode(&self) -> u16 {
+        match self {
+            FileType::Regular => constant::INODE_MODE_REGULAR | constant::DEFAULT_FILE_PERM,
+            FileType::Directory => constant::INODE_MODE_DIRECTORY | constant::DEFAULT_DIR_PERM,
+            FileType::Symlink => constant::INODE_MODE_SYMLINK | constant::DEFAULT_SYMLINK_PERM,
+            FileType::CharDevice => constant::INODE_MODE_CHAR_DEVICE | constant::DEFAULT_FILE_PERM,
+            FileType::BlockDevice => constant::INODE_MODE_BLOCK_DEVICE | constant::DEFAULT_FILE_PERM,
+            FileType::Unknown => 0,
+        }
+    }
+
+    /**
+     * 根据目录项中保存的类型值，得到文件类型
+     */
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0 => FileType::Regular,
+            1 => FileType::Directory,
+            3 => FileType::Symlink,
+            4 => FileType::CharDevice,
+            5 => FileType::BlockDevice,
+            _ => FileType::Unknown,
+        }
+    }
+
+    /**
+     * 根据inode的mode，得到文件类型
+     */
+    pub fn from_mode(mode: u16) -> Self {
+        match mode & constant::INODE_MODE_TYPE_MASK {
+            constant::INODE_MODE_REGULAR => FileType::Regular,
+            constant::INODE_MODE_DIRECTORY => FileType::Directory,
+            constant::INODE_MODE_SYMLINK => FileType::Symlink,
+            constant::INODE_MODE_CHAR_DEVICE => FileType::CharDevice,
+            constant::INODE_MODE_BLOCK_DEVICE => FileType::BlockDevice,
+            _ => FileType::Unknown,
+        }
+    }
 }
 
 /**
- * 目录项的结构。物理结构，保存到硬盘中
+ * 目录项的结构。内存结构
+ * 硬盘中的目录项是变长的（见DirEntryHeader），读取出来以后转成这个结构
  */
 #[derive(Debug)]
 #[derive(Copy, Clone)]
-#[repr(C, packed)]
+#[repr(C)]
 pub struct DirEntry {
     /**
      * 该目录项对应的inode编号
      */
     pub i_no: InodeNo, 
-    /**
-     * 目录项名称
-     */
-    name:  [u8; constant::MAX_FILE_NAME],
     /**
      * 文件类型
      */
     pub file_type: FileType,
+    /**
+     * 目录项名称的长度。单位字节。0表示空目录项
+     */
+    name_len: u8,
+    /**
+     * 目录项名称。UTF-8编码，只有前name_len个字节有效
+     */
+    name:  [u8; constant::MAX_FILE_NAME],
 }
 
 
@@ -55,92 +116,198 @@ impl DirEntry {
     pub fn empty() -> Self {
         Self {
             i_no: InodeNo::new(0),
-            name: [0; constant::MAX_FILE_NAME],
             file_type: FileType::Unknown,
+            name_len: 0,
+            name: [0; constant::MAX_FILE_NAME],
         }
     }
 
+    /**
+     * 创建一个目录项。名称不能为空，也不能超过MAX_FILE_NAME个字节（调用方需要先校验）
+     */
     pub fn new(i_no: InodeNo, file_name: &str, file_type: FileType) -> Self {
+        ASSERT!(!file_name.is_empty() && file_name.len() <= constant::MAX_FILE_NAME);
         let mut dir_entry = Self {
             i_no: i_no,
-            name: [0; constant::MAX_FILE_NAME],
             file_type: file_type,
+            name_len: file_name.len() as u8,
+            name: [0; constant::MAX_FILE_NAME],
         };
         // 写入文件名称
-        cstr_write!(&mut dir_entry.name, "{}", file_name);
+        dir_entry.name[..file_name.len()].copy_from_slice(file_name.as_bytes());
         dir_entry
     }
 
     #[inline(never)]
     pub fn get_name(&self) -> &str {
-        let name = cstring_utils::read_from_bytes(&self.name);
-        ASSERT!(name.is_some());
+        let name = core::str::from_utf8(&self.name[..self.name_len as usize]);
+        ASSERT!(name.is_ok());
         name.unwrap()
     }
 
     #[inline(never)]
     pub fn is_empty(&self) -> bool {
-        let i = usize::from(self.i_no);
-        // 这是根目录，非空
-        if i == 0 && self.file_type as FileType  == FileType::Directory {
-            return false;
-        }
-        return i == 0;
+        self.name_len == 0
     }
 }
 
+/**
+ * 目录项在硬盘中的头部。物理结构
+ * 硬盘中的目录项是变长的：头部 + 名称（name_len个字节），按照DIR_ENTRY_ALIGN字节对齐后，占用rec_len个字节
+ *  - 目录项不会跨扇区，一个扇区内所有目录项的rec_len加起来，正好是一个扇区的大小
+ *  - name_len为0的目录项是空闲的。删除目录项的时候，空间合并到前一个目录项中
+ *  - 全是0的扇区（rec_len为0），认为整个扇区都是空闲的
+ */
+#[derive(Debug, Clone, Copy)]
+#[repr(C, packed)]
+pub struct DirEntryHeader {
+    /**
+     * 该目录项对应的inode编号
+     */
+    pub i_no: InodeNo,
+    /**
+     * 该目录项占用的字节数（包括后面空闲的部分）
+     */
+    pub rec_len: u16,
+    /**
+     * 名称的长度。单位字节
+     */
+    pub name_len: u8,
+    /**
+     * 文件类型。FileType的值
+     */
+    pub file_type: u8,
+}
 
-pub fn read_dir_entry<'a, 'b>(fs: &'a mut FileSystem, lba: LbaAddr, buff: &'b mut [u8; constants::DISK_SECTOR_SIZE]) -> &'b mut [DirEntry] {
-    let disk = unsafe { &mut *fs.base_part.from_disk };
-    disk.read_sectors(lba, 1, buff);
-    
-    unsafe { core::slice::from_raw_parts_mut(buff.as_mut_ptr() as *mut DirEntry, buff.len() / size_of::<DirEntry>()) }
+/**
+ * 名称为name_len个字节的目录项，在硬盘中至少需要占用多少字节
+ */
+pub const fn entry_rec_len(name_len: usize) -> usize {
+    let len = size_of::<DirEntryHeader>() + name_len;
+    (len + constant::DIR_ENTRY_ALIGN - 1) / constant::DIR_ENTRY_ALIGN * constant::DIR_ENTRY_ALIGN
 }
 
 /**
- * 指定目录项的路径，搜索这个目录项
+ * 遍历一个扇区内的所有目录项（包括空闲的）
+ * 每一项是：(目录项在扇区内的偏移量, 目录项占用的字节数rec_len, 目录项)
  */
-#[inline(never)]
-pub fn search_dir_entry(filesystem: &mut FileSystem, file_path: &str) -> Option<(DirEntry, &'static mut OpenedInode)> {
-    if file_path.is_empty() {
-        return Option::None;
-    }
+pub struct DirEntryBlockIter<'a> {
+    buf: &'a [u8; constants::DISK_SECTOR_SIZE],
+    off: usize,
+}
 
-    let root_inode_no = filesystem.get_root_inode().i_no;
-    // 当前的inode，是根目录的inode
-    let mut cur_inode = inode::inode_open(filesystem, root_inode_no);
-    // 当前的目录项。默认是根目录
-    let mut cur_dir_entry = DirEntry::new(root_inode_no, "/", FileType::Directory);
+impl <'a> DirEntryBlockIter<'a> {
+    pub fn new(buf: &'a [u8; constants::DISK_SECTOR_SIZE]) -> Self {
+        Self { buf, off: 0 }
+    }
 
-    // 如果就是根目录
-    if file_path == "/" {
-        return Option::Some((cur_dir_entry, cur_inode));
+    /**
+     * 从扇区内偏移量为off的目录项开始遍历。off必须是某个目录项的开始
+     */
+    pub fn skip_to(&mut self, off: usize) {
+        self.off = off;
     }
+}
 
-    // 把要搜索的路径，分隔
-    let mut file_entry_split = file_path.split("/");
-    while let Option::Some(file_entry_name) = file_entry_split.next() {
-        if file_entry_name.is_empty() {
-            continue;
-        }
-        // 根据名称搜索目录项
-        let dir_entry = do_search_dir_entry(filesystem, cur_inode, DirEntrySearchReq::build().entry_name(file_entry_name));
-        // 关掉inode
-        inode::inode_close(filesystem, cur_inode);
-        // 如果目录项不存在
-        if dir_entry.is_none() {
+impl <'a> Iterator for DirEntryBlockIter<'a> {
+    type Item = (usize, usize, DirEntry);
+
+    #[inline(never)]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.off >= self.buf.len() {
             return Option::None;
         }
+        let off = self.off;
+        let (rec_len, entry) = self::read_entry(self.buf, off);
+        self.off += rec_len;
+        Option::Some((off, rec_len, entry))
+    }
+}
 
-        let dir_entry = dir_entry.unwrap();
-        // 根据inode号，打开
-        cur_inode = inode::inode_open(filesystem, dir_entry.i_no);
+/**
+ * 读取扇区buf中，偏移量为off的目录项。返回(目录项占用的字节数rec_len, 目录项)
+ *  - rec_len非法（0、没有对齐、超出扇区），认为从off开始到扇区结束，都是空闲的
+ */
+#[inline(never)]
+fn read_entry(buf: &[u8; constants::DISK_SECTOR_SIZE], off: usize) -> (usize, DirEntry) {
+    let free_len = buf.len() - off;
+    if free_len < size_of::<DirEntryHeader>() {
+        return (free_len, DirEntry::empty());
+    }
+    let header = unsafe { (buf.as_ptr().add(off) as *const DirEntryHeader).read_unaligned() };
+    let rec_len = header.rec_len as usize;
+    let name_len = header.name_len as usize;
+    if rec_len == 0 || rec_len % constant::DIR_ENTRY_ALIGN != 0 || rec_len > free_len {
+        return (free_len, DirEntry::empty());
+    }
+    // 空闲的目录项，或者名称超出了目录项
+    if name_len == 0 || entry_rec_len(name_len) > rec_len {
+        return (rec_len, DirEntry::empty());
+    }
+    let mut entry = DirEntry::empty();
+    entry.i_no = header.i_no;
+    entry.file_type = FileType::from_code(header.file_type);
+    entry.name_len = header.name_len;
+    let name_start = off + size_of::<DirEntryHeader>();
+    entry.name[..name_len].copy_from_slice(&buf[name_start..name_start + name_len]);
+    // 名称不是合法的UTF-8，当作空闲的目录项
+    if core::str::from_utf8(&entry.name[..name_len]).is_err() {
+        return (rec_len, DirEntry::empty());
+    }
+    (rec_len, entry)
+}
 
-        // 搜索下一个目录项
-        cur_dir_entry = dir_entry;
+/**
+ * 把目录项entry写入到扇区buf中偏移量为off的地方，占用rec_len个字节
+ */
+#[inline(never)]
+fn write_entry(buf: &mut [u8; constants::DISK_SECTOR_SIZE], off: usize, rec_len: usize, entry: &DirEntry) {
+    let header = DirEntryHeader {
+        i_no: entry.i_no,
+        rec_len: rec_len as u16,
+        name_len: entry.name_len,
+        file_type: entry.file_type as u8,
+    };
+    unsafe { (buf.as_mut_ptr().add(off) as *mut DirEntryHeader).write_unaligned(header) };
+    let name_start = off + size_of::<DirEntryHeader>();
+    let name_len = entry.name_len as usize;
+    buf[name_start..name_start + name_len].copy_from_slice(&entry.name[..name_len]);
+}
+
+/**
+ * 在扇区buf中，找一个可以放下entry的位置，放进去。返回是否放入成功
+ *  - 空闲的目录项，够大就直接用
+ *  - 正在使用的目录项，后面剩余的空间够大，就把这个目录项截短，剩余的空间给entry使用
+ */
+#[inline(never)]
+pub fn insert_entry(buf: &mut [u8; constants::DISK_SECTOR_SIZE], entry: &DirEntry) -> bool {
+    let need_len = entry_rec_len(entry.name_len as usize);
+    let found = DirEntryBlockIter::new(buf).find(|(_, rec_len, cur_entry)| {
+        let used_len = if cur_entry.is_empty() { 0 } else { entry_rec_len(cur_entry.name_len as usize) };
+        rec_len - used_len >= need_len
+    });
+    if found.is_none() {
+        return false;
+    }
+    let (off, rec_len, cur_entry) = found.unwrap();
+    if cur_entry.is_empty() {
+        self::write_entry(buf, off, rec_len, entry);
+        return true;
     }
-    // 返回找到的最后的那个目录项
-    return Option::Some((cur_dir_entry, cur_inode));
+    // 截短当前的目录项，后面的空间放新的目录项
+    let used_len = entry_rec_len(cur_entry.name_len as usize);
+    self::write_entry(buf, off, used_len, &cur_entry);
+    self::write_entry(buf, off + used_len, rec_len - used_len, entry);
+    true
+}
+
+/**
+ * 往一个新的（全是0的）目录数据块中，放入目录项。安装文件系统的时候使用
+ */
+#[inline(never)]
+pub fn init_dir_block(buf: &mut [u8; constants::DISK_SECTOR_SIZE], entry: &DirEntry) {
+    let inserted = self::insert_entry(buf, entry);
+    ASSERT!(inserted);
 }
 
 #[derive(Clone, Copy)]
@@ -184,6 +351,8 @@ pub fn do_search_dir_entry(fs: &mut FileSystem, dir_inode: &mut OpenedInode, sea
 
     // 取出所有的数据块
     let data_blocks = dir_inode.get_data_blocks_ref();
+    // 一个块中的每个扇区，都是独立存放目录项的
+    let sec_per_block = fs.super_block.sec_per_block();
 
     let disk = unsafe { &mut *fs.base_part.from_disk };
     
@@ -197,17 +366,18 @@ pub fn do_search_dir_entry(fs: &mut FileSystem, dir_inode: &mut OpenedInode, sea
         if block_lba.is_empty() {
             continue;
         }
-        disk.read_sectors(*block_lba, 1, buff_u8);
-
-        // 读取出的数据，转成页目录项列表
-        let dir_entry_list = unsafe { slice::from_raw_parts(buff_u8.as_ptr() as *const DirEntry, constants::DISK_SECTOR_SIZE / size_of::<DirEntry>()) };
-        let find = self::find_dir_entry(dir_entry_list, search_req);
-        
-        // 找到了，直接返回
-        if find.is_some() {
-            let target_entry = dir_entry_list[find.unwrap()];
-            memory::sys_free(buff_ptr);
-            return Option::Some(target_entry);
+        for sec_idx in 0..sec_per_block {
+            buffer_cache::read_sectors(disk, block_lba.add(sec_idx as u32), 1, buff_u8);
+
+            // 遍历扇区内的目录项
+            let find = DirEntryBlockIter::new(buff_u8).find(|(_, _, entry)| self::is_entry_matched(entry, search_req));
+            
+            // 找到了，直接返回
+            if find.is_some() {
+                let (_, _, target_entry) = find.unwrap();
+                memory::sys_free(buff_ptr);
+                return Option::Some(target_entry);
+            }
         }
     }
     memory::sys_free(buff_ptr);
@@ -221,169 +391,168 @@ pub fn do_search_dir_entry(fs: &mut FileSystem, dir_inode: &mut OpenedInode, sea
  * 在实现上，分成两个步骤：
  *   - 创建文件（inode）以及对应的目录项（文件名称）
  *   - 把这个inode挂到该目录下（把目录项放写入到目录对应的数据区）
+ * inode用完了，或者父目录申请不到数据块，返回NoSpace
  */
 #[inline(never)]
-pub fn create_dir_entry(fs: &mut FileSystem, parent_inode: &mut OpenedInode, entry_name: &str, file_type: FileType) -> InodeNo {
+pub fn create_dir_entry(fs: &mut FileSystem, parent_inode: &mut OpenedInode, entry_name: &str, file_type: FileType) -> Result<InodeNo, FileError> {
 
     /****1. 创建一个目录项 */
-    let created_entry_inode = self::do_create_dir_entry(fs, parent_inode, entry_name, file_type);
+    let created_entry_inode = self::do_create_dir_entry(fs, parent_inode, entry_name, file_type)?;
 
     /***2. 填充内存结构*****/
     fs.append_inode(created_entry_inode);
 
-    created_entry_inode.i_no
+    Result::Ok(created_entry_inode.i_no)
 }
 
 /**
  * 在parent_inode目录下，创建名为entry_name，并且inode号为entry_inode的目录项。
  */
 #[inline(never)]
-pub fn do_create_dir_entry(fs: &mut FileSystem, parent_inode: &mut OpenedInode, entry_name: &str, file_type: FileType) -> &'static mut OpenedInode {
+pub fn do_create_dir_entry(fs: &mut FileSystem, parent_inode: &mut OpenedInode, entry_name: &str, file_type: FileType) -> Result<&'static mut OpenedInode, FileError> {
     /***1. 创建文件的inode。物理结构，同步到硬盘中*****/
     // 从当前分区中，申请1个inode，并且写入硬盘（inode位图）
-    let inode_no = fs.inode_pool.apply_inode(1);
-
-    // 创建一个inode
-    let inode = Inode::new(inode_no);
+    let inode_no = fs.inode_pool.apply_inode(&mut fs.journal, 1);
+    if inode_no.is_none() {
+        return Result::Err(FileError::NoSpace);
+    }
+    let inode_no = inode_no.unwrap();
+
+    // 创建一个inode。创建时间、修改时间、访问时间都是现在
+    let mut inode = Inode::new(inode_no);
+    let now = time::get_current_timestamp();
+    inode.i_mode = file_type.to_mode();
+    inode.i_nlink = 1;
+    inode.i_ctime = now;
+    inode.i_mtime = now;
+    inode.i_atime = now;
     let opened_inode: &mut OpenedInode = memory::malloc_system(size_of::<OpenedInode>());
     *opened_inode = OpenedInode::new(inode);
+    opened_inode.bind_fs(fs);
 
     // 把inode写入硬盘（inode列表）
     inode::sync_inode(fs, opened_inode);
 
     /***2. 把这个新文件作为一个目录项，挂到父目录中*****/
-    self::do_create_dir_entry_with_inode(fs, parent_inode, inode_no, entry_name, file_type);
+    let res = self::do_create_dir_entry_with_inode(fs, parent_inode, inode_no, entry_name, file_type);
+    // 父目录放不下了，把申请的inode还回去
+    if res.is_err() {
+        fs.inode_pool.release_inode(&mut fs.journal, inode_no);
+        memory::free_system(opened_inode as *const OpenedInode);
+        return Result::Err(res.unwrap_err());
+    }
 
-    opened_inode
+    Result::Ok(opened_inode)
 }
 
 #[inline(never)]
-pub fn do_create_dir_entry_with_inode(fs: &mut FileSystem, parent_inode: &mut OpenedInode, i_no: InodeNo, entry_name: &str, file_type: FileType) {
+pub fn do_create_dir_entry_with_inode(fs: &mut FileSystem, parent_inode: &mut OpenedInode, i_no: InodeNo, entry_name: &str, file_type: FileType) -> Result<(), FileError> {
     // 根据节点的inode号，创建一个目录项
     let dir_entry = DirEntry::new(i_no, entry_name, file_type);
     // 把目录项挂到目录并且写入硬盘（inode数据区）
-    self::sync_dir_entry(fs, parent_inode, &dir_entry);
-}
-
-/**
- * 已知多个数据块data_blocks（一个数据块的内容里面都是多个目录项），找出可以可用的目录项的位置
- * 入参：
- *    - data_blocks 数据扇区信息
- *    - buf 缓冲区，一个扇区大小，存放间接块内的目录项
- *    - disk 操作的硬盘
- * 返回值：
- *    - Option<(&'a mut LbaAddr, &'b mut DirEntry)> 是否找到空闲目录项(该目录项所在扇区的LBA地址，该目录项在buf参数中的地址)
- *  比如buf[512]，发现第100项可用，那么返回值是 &buf[100]
- */
-// #[inline(never)]
-fn find_available_entry(data_blocks: &[LbaAddr], u8buf: &mut[u8; constants::DISK_SECTOR_SIZE], disk: &mut Disk) -> Option<(usize, usize)> {
-    // 再转成DirEntryList
-    let dir_buf = unsafe { slice::from_raw_parts(u8buf.as_ptr() as *const DirEntry, u8buf.len() / size_of::<DirEntry>()) };
-
-    // 清空缓冲区
-    unsafe { u8buf.as_mut_ptr().write_bytes(0, u8buf.len()); }
-    // 先找空的直接块
-    let empty_dix = data_blocks
-        .iter()
-        .enumerate()
-        // 找到为空的数据块
-        .find(|(idx, &lba)| lba.is_empty())
-        .map(|(idx, &lba)| idx);
-
-    // 如果没有空的直接块
-    if empty_dix.is_none() {
-        // 看看这个最后一个块，有没有空位
-        let last_data_block_idx = data_blocks.len() - 1;
-        disk.read_sectors(data_blocks[last_data_block_idx], 1, u8buf);
-        return dir_buf.iter().enumerate()
-            .find(|(idx, &entry)| entry.is_empty())
-            // 有空目录项。那么很好，就是这里了。也不用开辟新数据块
-            .map(|(idx, _)| (last_data_block_idx, idx))
-    }
-    let empty_dix = empty_dix.unwrap();
-    // 如果第0项就是空的，那么就返回0了
-    if empty_dix == 0 {
-        return Option::Some((0, 0));
-    }
-
-    // 如果有空的数据块，那么往前找一个,有没有空位
-    let previous_idx = empty_dix - 1;
-    disk.read_sectors(data_blocks[previous_idx], 1, u8buf);
-    
-    let previous_find = dir_buf.iter().enumerate()
-        .find(|(idx, &entry)| entry.is_empty())
-        .map(|(idx, _)| idx);
-
-    // 如果前一个找到了，用前一个
-    if previous_find.is_some() {
-        Option::Some((previous_idx, previous_find.unwrap()))
-    } else {
-        // 用新的，需要清空目录项缓冲区
-        unsafe { u8buf.as_mut_ptr().write_bytes(0, u8buf.len()); }
-        return Option::Some((empty_dix, 0));
-    }
+    self::sync_dir_entry(fs, parent_inode, &dir_entry)
 }
 
 /**
  * 把目录项dir_entry放入到parent目录中。并且保存到硬盘
- *  - 目录项存放在目录inode的数据扇区中
- *  - 先遍历数据扇区，找到空闲可以存放目录项的地方，然后放进去
+ *  - 目录项存放在目录inode的数据块中，块中的每个扇区都独立存放目录项（目录项不跨扇区）
+ *  - 先遍历已有的数据块中的扇区，找到空闲可以存放目录项的地方，然后放进去
+ *  - 已有的数据块都放不下，再申请一个新的数据块，目录项放在第一个扇区
+ *  - 目录的数据块都用完了，或者申请不到新的数据块，返回NoSpace
  */
 #[inline(never)]
-pub fn sync_dir_entry(fs: &mut FileSystem, parent_inode: &mut OpenedInode, dir_entry: &DirEntry) {
+pub fn sync_dir_entry(fs: &mut FileSystem, parent_inode: &mut OpenedInode, dir_entry: &DirEntry) -> Result<(), FileError> {
 
     let disk = unsafe { &mut *fs.base_part.from_disk };
 
     // 申请内存，搞一个缓冲区
     let buf: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(constants::DISK_SECTOR_SIZE);
-    // let buf = unsafe { slice::from_raw_parts_mut(buff_addr as *mut u8, constants::DISK_SECTOR_SIZE) };
-    // 缓冲区格式转成 目录项
-    // let entry_len = utils::div_ceil(constants::DISK_SECTOR_SIZE as u32, size_of::<DirEntry>() as u32) as usize;
-    // let entry_len = entry_len - 1;
-    let entry_len = constants::DISK_SECTOR_SIZE / size_of::<DirEntry>();
-    // let entry_len = (constants::DISK_SECTOR_SIZE + size_of::<DirEntry>() - 1) / size_of::<DirEntry>();
-    let dir_list = unsafe { slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut DirEntry,  entry_len) };
-
-
-    // 在直接块中，找是否有空闲目录项
-    let direct_find = self::find_available_entry(parent_inode.get_direct_data_blocks(), buf, disk);
-    // 我们的目录项所在的数据块，位于当前数据块列表的下标
-    let block_idx = if direct_find.is_some() {
-        let (block_idx, entry_idx) = direct_find.unwrap();
-        dir_list[entry_idx] = *dir_entry;
-        block_idx
-    // 再找间接块
-    } else {
-        // 申请一个间接块
-        inode::apply_indirect_data_block(fs, parent_inode);
-
-        // 在间接块中，找是否有空闲目录项
-        let indirect_find = self::find_available_entry(parent_inode.get_indirect_data_blocks(), buf, disk);
-        ASSERT!(indirect_find.is_some());
-        let (block_idx, entry_idx) = indirect_find.unwrap();
-        dir_list[entry_idx] = *dir_entry;
-        // 直接块的长度  + 间接块的下标
-        parent_inode.get_direct_data_blocks_ref().len() + block_idx
-    };
 
+    // 如果直接块都满了，那么就需要加载间接块
+    if parent_inode.get_direct_data_blocks_ref().iter().all(|block| !block.is_empty()) {
+        inode::load_indirect_data_block(fs, parent_inode);
+    }
 
-    let target_block_lba = &mut parent_inode.get_data_blocks()[block_idx];
-    // 找到的数据块LBA是空的
-    if target_block_lba.is_empty() {
-        // 申请一个数据块
-        *target_block_lba = fs.data_block_pool.apply_block(1);
+    // 在已有的数据块中，找是否有空闲的位置。记录数据块的下标，以及块内扇区的下标
+    let sec_per_block = fs.super_block.sec_per_block();
+    let mut block_idx = Option::None;
+    let mut sec_idx = 0;
+    for (idx, block_lba) in parent_inode.get_data_blocks_ref().iter().enumerate() {
+        if block_lba.is_empty() {
+            continue;
+        }
+        let found_sec = (0..sec_per_block).find(|cur_sec_idx| {
+            buffer_cache::read_sectors(disk, block_lba.add(*cur_sec_idx as u32), 1, buf);
+            self::insert_entry(buf, dir_entry)
+        });
+        if found_sec.is_some() {
+            block_idx = Option::Some(idx);
+            sec_idx = found_sec.unwrap();
+            break;
+        }
     }
 
-    // 写入 目录项 到硬盘中
-    disk.write_sector(buf, *target_block_lba, 1);
+    // 已有的数据块都放不下，使用一个新的数据块
+    let mut new_block_lba = Option::None;
+    if block_idx.is_none() {
+        new_block_lba = self::apply_dir_block(fs, parent_inode);
+        if new_block_lba.is_none() {
+            memory::sys_free(buf.as_ptr() as usize);
+            return Result::Err(FileError::NoSpace);
+        }
+        // 新的数据块，清空缓冲区
+        unsafe { buf.as_mut_ptr().write_bytes(0, buf.len()); }
+        let inserted = self::insert_entry(buf, dir_entry);
+        ASSERT!(inserted);
+        block_idx = parent_inode.get_data_blocks_ref().iter().position(|block| block.is_empty());
+    }
+
+    let target_block_lba = &mut parent_inode.get_data_blocks()[block_idx.unwrap()];
+    // 新申请的数据块
+    if new_block_lba.is_some() {
+        *target_block_lba = new_block_lba.unwrap();
+        // 写入 目录项 到第一个扇区，块中其他的扇区清零（空闲的目录扇区）
+        journal::write_metadata(&mut fs.journal, buf, *target_block_lba, 1);
+        unsafe { buf.as_mut_ptr().write_bytes(0, buf.len()); }
+        for zero_sec_idx in 1..sec_per_block {
+            journal::write_metadata(&mut fs.journal, buf, target_block_lba.add(zero_sec_idx as u32), 1);
+        }
+    } else {
+        // 写入 目录项 所在的扇区到硬盘中
+        journal::write_metadata(&mut fs.journal, buf, target_block_lba.add(sec_idx as u32), 1);
+    }
 
     // 增加当前文件的大小
-    parent_inode.i_size += size_of::<DirEntry>() as u32;
+    parent_inode.i_size += entry_rec_len(dir_entry.name_len as usize) as u32;
+    // 目录的内容发生了变化
+    parent_inode.i_mtime = time::get_current_timestamp();
     // 如果是直接块找到空闲目录项，那么需要同步inode（直接块的地址放在inode的i_sectors字段中）
     inode::sync_inode(fs, parent_inode);
 
     // 释放缓冲区的空间
     memory::sys_free(buf.as_ptr() as usize);
+    Result::Ok(())
+}
+
+/**
+ * 给目录申请一个新的数据块（还没有放入目录的数据块列表中）
+ *  - 直接块都用完了，先申请一级间接块
+ *  - 目录的数据块都用完了，或者没有空闲的数据块了，返回None
+ */
+#[inline(never)]
+fn apply_dir_block(fs: &mut FileSystem, parent_inode: &mut OpenedInode) -> Option<LbaAddr> {
+    if parent_inode.get_direct_data_blocks_ref().iter().all(|block| !block.is_empty()) {
+        inode::apply_indirect_data_block(fs, parent_inode)?;
+    }
+    if parent_inode.get_data_blocks_ref().iter().all(|block| !block.is_empty()) {
+        return Option::None;
+    }
+    let block_lba = fs.data_block_pool.try_apply_block(&mut fs.journal);
+    // 间接块是新申请的，已经记录在inode中了，同步inode，不然这个间接块就丢了
+    if block_lba.is_none() {
+        inode::sync_inode(fs, parent_inode);
+    }
+    block_lba
 }
 
 /**
@@ -391,7 +560,7 @@ pub fn sync_dir_entry(fs: &mut FileSystem, parent_inode: &mut OpenedInode, dir_e
  */
 #[inline(never)]
 pub fn parent_entry(opened_inode: &mut OpenedInode) -> InodeNo {
-    let fs = fs::get_filesystem();
+    let fs = opened_inode.get_fs();
     // 找到..目录项，这个就是上一级目录
     let parent_entry = self::do_search_dir_entry(fs, opened_inode, DirEntrySearchReq::build().entry_name(".."));
     ASSERT!(parent_entry.is_some());
@@ -404,7 +573,7 @@ pub fn parent_entry(opened_inode: &mut OpenedInode) -> InodeNo {
  */
 #[inline(never)]
 pub fn current_inode_entry(opened_inode: &mut OpenedInode) -> DirEntry {
-    let fs = fs::get_filesystem();
+    let fs = opened_inode.get_fs();
 
     // 根目录，就是当前目录
     if opened_inode.i_no == fs.super_block.root_inode_no {
@@ -424,93 +593,146 @@ pub fn current_inode_entry(opened_inode: &mut OpenedInode) -> DirEntry {
 
 
 /**
- * 在dir_entry_list列表中，根据搜索条件entry_req找到符合条件的数据，返回dir_entry_list的下标
+ * 目录项entry，是否符合搜索条件entry_req。空闲的目录项都不符合
  */
 #[inline(never)]
-fn find_dir_entry(dir_entry_list: &[DirEntry], entry_req: DirEntrySearchReq) -> Option<usize> {
-    for (idx, entry) in dir_entry_list.iter().enumerate() {
-        // 根据名称过滤
-        if entry_req.entry_name.is_some() {
-            if entry.get_name() != entry_req.entry_name.unwrap() {
-                continue;
-            }
+fn is_entry_matched(entry: &DirEntry, entry_req: DirEntrySearchReq) -> bool {
+    if entry.is_empty() {
+        return false;
+    }
+    // 根据名称过滤
+    if entry_req.entry_name.is_some() {
+        if entry.get_name() != entry_req.entry_name.unwrap() {
+            return false;
         }
-        // 根据inode编号过滤
-        if entry_req.i_no.is_some() {
-            if entry.i_no != entry_req.i_no.unwrap() {
-                continue;
-            }
+    }
+    // 根据inode编号过滤
+    if entry_req.i_no.is_some() {
+        if entry.i_no != entry_req.i_no.unwrap() {
+            return false;
         }
-        return Option::Some(idx);
     }
-    return Option::None;
+    return true;
 }
 
 /**
- * 删除某一个目录项
- * 读取block_lba该扇区的数据，并且把数据加载到buf中，然后根据entry_req作为搜索条件，找到这个目录项，进行删除
+ * 替换或者删除某一个目录项
+ * 读取sec_lba该扇区（目录数据块中的某个扇区）的数据，并且把数据加载到buf中，然后根据entry_req作为搜索条件，找到这个目录项，替换（删除）之后写回
+ * 返回被替换（删除）的目录项
  */
-fn do_remove_dir_entry(disk: &mut Disk, block_lba: LbaAddr, buf: &mut [u8; constants::DISK_SECTOR_SIZE], entry_req: DirEntrySearchReq) -> bool {
-    if block_lba.is_empty() {
-        return false;
+fn do_replace_dir_entry(disk: &mut Disk, journal: &mut Journal, sec_lba: LbaAddr, buf: &mut [u8; constants::DISK_SECTOR_SIZE], entry_req: DirEntrySearchReq, new_entry: Option<&DirEntry>) -> Option<DirEntry> {
+    if sec_lba.is_empty() {
+        return Option::None;
     }
-    // 清零缓冲区
-    unsafe { buf.as_mut_ptr().write_bytes(0, buf.len()) };
-
-    let entry_len = constants::DISK_SECTOR_SIZE / size_of::<DirEntry>();
-    let dir_entry_list: &mut [DirEntry] = unsafe { slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut DirEntry,  entry_len) };
     // 读取该扇区
-    disk.read_sectors(block_lba, 1, buf);
-    let find = self::find_dir_entry(dir_entry_list, entry_req);
+    buffer_cache::read_sectors(disk, sec_lba, 1, buf);
+    let replaced = self::replace_entry(buf, entry_req, new_entry);
+    if replaced.is_some() {
+        journal::write_metadata(journal, buf, sec_lba, 1);
+    }
+    replaced
+}
+
+/**
+ * 在扇区buf中，根据entry_req找到一个目录项，替换或者删除
+ *  - new_entry是Some：替换为new_entry。new_entry必须放得下（占用的字节数不超过原来的目录项）
+ *  - new_entry是None：删除这个目录项。空间合并到前一个目录项；如果是扇区的第一个目录项，标记为空闲
+ * 返回被替换（删除）的目录项
+ */
+#[inline(never)]
+pub fn replace_entry(buf: &mut [u8; constants::DISK_SECTOR_SIZE], entry_req: DirEntrySearchReq, new_entry: Option<&DirEntry>) -> Option<DirEntry> {
+    // 找到这个目录项，以及它前一个目录项
+    let mut prev = Option::None;
+    let mut find = Option::None;
+    for (off, rec_len, entry) in DirEntryBlockIter::new(buf) {
+        if self::is_entry_matched(&entry, entry_req) {
+            find = Option::Some((off, rec_len, entry));
+            break;
+        }
+        prev = Option::Some((off, rec_len, entry));
+    }
     // 如果在找不到目录项，返回
     if find.is_none() {
-        return false;
+        return Option::None;
     }
+    let (off, rec_len, old_entry) = find.unwrap();
 
-    // 如果找到了，清空这个目录项
-    dir_entry_list[find.unwrap()] = DirEntry::empty();
-    disk.write_sector(buf, block_lba, 1);
-    return true;
+    if new_entry.is_some() {
+        let new_entry = new_entry.unwrap();
+        // 放不下，无法替换
+        if entry_rec_len(new_entry.name_len as usize) > rec_len {
+            return Option::None;
+        }
+        self::write_entry(buf, off, rec_len, new_entry);
+    } else if prev.is_some() {
+        // 前一个目录项吞掉这个目录项的空间
+        let (prev_off, prev_rec_len, prev_entry) = prev.unwrap();
+        self::write_entry(buf, prev_off, prev_rec_len + rec_len, &prev_entry);
+    } else {
+        // 扇区内的第一个目录项，标记为空闲
+        self::write_entry(buf, off, rec_len, &DirEntry::empty());
+    }
+    return Option::Some(old_entry);
 }
 
 /**
- * 删除某个目录项
- *  - 先遍历直接块，然后遍历间接块，找到那个目录项。
- *  - 然后把目录项清空，然后写回到硬盘中
+ * 遍历目录所有的数据块（直接块 + 间接块），找到符合entry_req的目录项，替换为new_entry（None则删除），写回到硬盘中
  */
 #[inline(never)]
-pub fn remove_dir_entry(fs: &mut FileSystem, parent_dir_inode: &mut OpenedInode, entry_req: DirEntrySearchReq) -> bool {
+fn do_replace_in_dir(fs: &mut FileSystem, parent_dir_inode: &mut OpenedInode, entry_req: DirEntrySearchReq, new_entry: Option<&DirEntry>) -> Option<DirEntry> {
     let disk: &mut Disk = unsafe { &mut *fs.base_part.from_disk };
     // 搞一个缓冲区
     let buf: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(constants::DISK_SECTOR_SIZE);
 
-    // 遍历直接块
-    for block_lba in parent_dir_inode.get_direct_data_blocks_ref().iter() {
-        unsafe { buf.as_mut_ptr().write_bytes(0, buf.len()) };
-        if block_lba.is_empty() {
-            continue;
-        }
-        // 删除目录项
-        if self::do_remove_dir_entry(disk, *block_lba, buf, entry_req) {
-            parent_dir_inode.i_size -= size_of::<DirEntry>() as u32;
-            return true;
-        }
-    }
-
     // 加载间接块
     inode::load_indirect_data_block(fs, parent_dir_inode);
 
-    // 遍历间接块
-    for block_lba in parent_dir_inode.get_indirect_data_blocks_ref().iter() {
-        unsafe { buf.as_mut_ptr().write_bytes(0, buf.len()) };
+    // 遍历所有的数据块（直接块 + 间接块）中的每一个扇区
+    let sec_per_block = fs.super_block.sec_per_block();
+    let journal = &mut fs.journal;
+    let mut replaced = Option::None;
+    for block_lba in parent_dir_inode.get_data_blocks_ref().iter() {
         if block_lba.is_empty() {
             continue;
         }
-        // 删除目录项
-        if self::do_remove_dir_entry(disk, *block_lba, buf, entry_req) {
-            parent_dir_inode.i_size -= size_of::<DirEntry>() as u32;
-            return true;
+        replaced = (0..sec_per_block).find_map(|sec_idx| self::do_replace_dir_entry(disk, journal, block_lba.add(sec_idx as u32), buf, entry_req, new_entry));
+        if replaced.is_some() {
+            break;
         }
     }
-    return false;
-}
\ No newline at end of file
+    memory::sys_free(buf.as_ptr() as usize);
+    replaced
+}
+
+/**
+ * 替换某个目录项
+ *  - 先遍历直接块，然后遍历间接块，找到那个目录项。
+ *  - 然后把目录项替换为new_entry，写回到硬盘中。new_entry的名称不能比原来的目录项长
+ */
+#[inline(never)]
+pub fn replace_dir_entry(fs: &mut FileSystem, parent_dir_inode: &mut OpenedInode, entry_req: DirEntrySearchReq, new_entry: &DirEntry) -> bool {
+    let replaced = self::do_replace_in_dir(fs, parent_dir_inode, entry_req, Option::Some(new_entry));
+    if replaced.is_none() {
+        return false;
+    }
+    // 目录项的名称长度可能变了
+    parent_dir_inode.i_size -= entry_rec_len(replaced.unwrap().name_len as usize) as u32;
+    parent_dir_inode.i_size += entry_rec_len(new_entry.name_len as usize) as u32;
+    true
+}
+
+/**
+ * 删除某个目录项
+ *  - 找到那个目录项，然后把目录项的空间释放，然后写回到硬盘中
+ */
+#[inline(never)]
+pub fn remove_dir_entry(fs: &mut FileSystem, parent_dir_inode: &mut OpenedInode, entry_req: DirEntrySearchReq) -> bool {
+    let removed = self::do_replace_in_dir(fs, parent_dir_inode, entry_req, Option::None);
+    if removed.is_none() {
+        return false;
+    }
+    parent_dir_inode.i_size -= entry_rec_len(removed.unwrap().name_len as usize) as u32;
+    // 目录的内容发生了变化
+    parent_dir_inode.i_mtime = time::get_current_timestamp();
+    return true;
+}
@@ -0,0 +1,678 @@
+use core::mem::size_of;
+
+use os_in_rust_common::{constants, domain::InodeNo, ASSERT};
+
+use crate::{device::{Partition, PartitionType}, memory, time};
+
+use super::{buffer_cache, constant, dir_entry::{DirEntry, FileType}, file::FileError, stat::{FileStat, FsStat}, vfs::{Vfs, Vnode}};
+
+/**
+ * ext2文件系统的只读驱动
+ *  - 分区表中类型是Linux（0x83），并且有ext2超级块的分区，挂载的时候使用这个驱动
+ *  - 支持直接块、一级/二级/三级间接块，以及线性的目录（有目录索引的目录，也按照线性目录读取）
+ *  - 节点的编号，就是ext2的inode号
+ * <https://www.nongnu.org/ext2-doc/ext2.html>
+ */
+
+/**
+ * 根目录的inode号
+ */
+const ROOT_INO: u32 = 2;
+
+/**
+ * 超级块在分区中的字节偏移量，以及超级块中的魔数
+ */
+const SUPER_BLOCK_OFFSET: usize = 1024;
+const EXT2_MAGIC: u16 = 0xef53;
+
+/**
+ * 块大小 = 1024 << s_log_block_size。只支持1KiB、2KiB、4KiB
+ */
+const MIN_BLOCK_SIZE: u32 = 1024;
+const MAX_LOG_BLOCK_SIZE: u32 = 2;
+
+/**
+ * 版本0的文件系统，inode固定是128字节
+ */
+const GOOD_OLD_INODE_SIZE: u32 = 128;
+
+/**
+ * 块组描述符的大小
+ */
+const GROUP_DESC_SIZE: u32 = 32;
+
+/**
+ * 支持的不兼容特性：目录项中有文件类型、灵活块组（只影响元数据的位置）
+ * 其他的不兼容特性（压缩、需要恢复的日志、extent、64位等），不能挂载
+ */
+const INCOMPAT_FILETYPE: u32 = 0x0002;
+const INCOMPAT_FLEX_BG: u32 = 0x0200;
+const INCOMPAT_SUPPORTED: u32 = INCOMPAT_FILETYPE | INCOMPAT_FLEX_BG;
+
+/**
+ * inode中的块指针：12个直接块，然后是一级、二级、三级间接块
+ */
+const DIRECT_BLOCKS: usize = 12;
+const IND_BLOCK: usize = 12;
+const DIND_BLOCK: usize = 13;
+const TIND_BLOCK: usize = 14;
+const BLOCK_PTRS: usize = 15;
+
+/**
+ * 目录项头部的大小：inode号(4) + 目录项长度(2) + 名称长度(1) + 文件类型(1)
+ */
+const DIR_ENTRY_HEADER_SIZE: usize = 8;
+
+/**
+ * 快速符号链接：目标路径不超过60字节，直接保存在块指针的位置
+ */
+const FAST_SYMLINK_MAX: u32 = (BLOCK_PTRS * size_of::<u32>()) as u32;
+
+/**
+ * 打开的节点。每次打开（lookup、open_dir）都创建一个，最后一次关闭的时候释放
+ */
+pub struct Ext2Node {
+    ino: InodeNo,
+    file_type: FileType,
+    mode: u16,
+    uid: u16,
+    gid: u16,
+    nlink: u16,
+    size: u32,
+    /**
+     * 占用的扇区数量
+     */
+    blocks: u32,
+    ctime: u32,
+    mtime: u32,
+    atime: u32,
+    /**
+     * 块指针。快速符号链接的话，是目标路径
+     */
+    block: [u32; BLOCK_PTRS],
+    /**
+     * 是否是快速符号链接
+     */
+    fast_symlink: bool,
+    open_cnt: u32,
+}
+
+/**
+ * ext2文件系统所在的设备。扇区号都是相对分区的
+ */
+pub trait Ext2Device {
+    fn read_sector(&self, rel_lba: u32, buf: &mut [u8; constants::DISK_SECTOR_SIZE]);
+}
+
+/**
+ * 内核中，通过缓冲区读取分区所在的硬盘
+ */
+impl Ext2Device for Partition {
+    fn read_sector(&self, rel_lba: u32, buf: &mut [u8; constants::DISK_SECTOR_SIZE]) {
+        buffer_cache::read_sectors(unsafe { &mut *self.from_disk }, self.abs_lba_start(rel_lba), 1, buf);
+    }
+}
+
+/**
+ * 挂载的ext2文件系统
+ */
+pub struct Ext2Fs {
+    /**
+     * 文件系统所在的设备（分区）
+     */
+    dev: &'static dyn Ext2Device,
+    block_size: u32,
+    /**
+     * 每个块的扇区数量
+     */
+    sec_per_block: u32,
+    inodes_count: u32,
+    inodes_per_group: u32,
+    inode_size: u32,
+    /**
+     * 块组的数量
+     */
+    group_cnt: u32,
+    /**
+     * 块的总数量、保留的块数量、空闲的块数量、空闲的inode数量。只读挂载，挂载之后不会变化
+     */
+    blocks_count: u32,
+    r_blocks_count: u32,
+    free_blocks_count: u32,
+    free_inodes_count: u32,
+    /**
+     * 块组描述符表所在的块（超级块所在块的下一个块）
+     */
+    group_desc_block: u32,
+    /**
+     * 目录项中是否有文件类型
+     */
+    has_filetype: bool,
+    /**
+     * 打开的节点数量
+     */
+    open_cnt: u32,
+}
+
+/**
+ * 分区是不是ext2分区：分区表中的类型是Linux，并且超级块是合法的ext2超级块
+ */
+#[inline(never)]
+pub fn is_ext2_volume(part: &Partition) -> bool {
+    self::parse(part).is_some()
+}
+
+/**
+ * 加载分区中的ext2文件系统。调用方需要保证is_ext2_volume(part)
+ * 文件系统的结构放在堆中，卸载之前地址不会变化
+ */
+#[inline(never)]
+pub fn load(part: &'static Partition) -> &'static mut Ext2Fs {
+    let parsed = self::parse(part);
+    ASSERT!(parsed.is_some());
+    let fs: &mut Ext2Fs = memory::malloc_system(size_of::<Ext2Fs>());
+    *fs = parsed.unwrap();
+    fs
+}
+
+/**
+ * 读取分区的超级块，解析出ext2文件系统的布局。用到的字段都在超级块的前512字节
+ */
+#[inline(never)]
+fn parse(part: &Partition) -> Option<Ext2Fs> {
+    if part.part_type != PartitionType::Linux as u8 {
+        return Option::None;
+    }
+    let buf: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(constants::DISK_SECTOR_SIZE);
+    part.read_sector((SUPER_BLOCK_OFFSET / constants::DISK_SECTOR_SIZE) as u32, buf);
+    // 分区在分区表中，地址不会变化
+    let dev: &'static Partition = unsafe { &*(part as *const Partition) };
+    let fs = self::parse_super_block(dev, part.sec_cnt, buf);
+    memory::sys_free(buf.as_ptr() as usize);
+    fs
+}
+
+/**
+ * 解析超级块
+ *  - dev: 文件系统所在的设备；part_secs: 分区的扇区数量；buf: 超级块的前512字节（分区的第2个扇区）
+ */
+#[inline(never)]
+pub fn parse_super_block(dev: &'static dyn Ext2Device, part_secs: u32, buf: &[u8]) -> Option<Ext2Fs> {
+    let inodes_count = self::read_u32(buf, 0);
+    let blocks_count = self::read_u32(buf, 4);
+    let r_blocks_count = self::read_u32(buf, 8);
+    let free_blocks_count = self::read_u32(buf, 12);
+    let free_inodes_count = self::read_u32(buf, 16);
+    let first_data_block = self::read_u32(buf, 20);
+    let log_block_size = self::read_u32(buf, 24);
+    let blocks_per_group = self::read_u32(buf, 32);
+    let inodes_per_group = self::read_u32(buf, 40);
+    let magic = self::read_u16(buf, 56);
+    let rev_level = self::read_u32(buf, 76);
+    if magic != EXT2_MAGIC || log_block_size > MAX_LOG_BLOCK_SIZE || blocks_per_group == 0 || inodes_per_group == 0 || inodes_count < ROOT_INO {
+        return Option::None;
+    }
+    let block_size = MIN_BLOCK_SIZE << log_block_size;
+    let sec_per_block = block_size / constants::DISK_SECTOR_SIZE as u32;
+    if blocks_count as u64 * sec_per_block as u64 > part_secs as u64 || first_data_block >= blocks_count {
+        return Option::None;
+    }
+    // 每个块组都有一个inode表，inode的数量不能超过所有块组能放下的
+    let group_cnt = (blocks_count - first_data_block).div_ceil(blocks_per_group);
+    if inodes_count as u64 > inodes_per_group as u64 * group_cnt as u64 {
+        return Option::None;
+    }
+
+    // 版本0没有下面这些字段
+    let (inode_size, feature_incompat) = if rev_level == 0 {
+        (GOOD_OLD_INODE_SIZE, 0)
+    } else {
+        (self::read_u16(buf, 88) as u32, self::read_u32(buf, 96))
+    };
+    if inode_size < GOOD_OLD_INODE_SIZE || !inode_size.is_power_of_two() || inode_size > block_size {
+        return Option::None;
+    }
+    if feature_incompat & !INCOMPAT_SUPPORTED != 0 {
+        return Option::None;
+    }
+
+    Option::Some(Ext2Fs {
+        dev,
+        block_size,
+        sec_per_block,
+        inodes_count,
+        inodes_per_group,
+        inode_size,
+        group_cnt,
+        blocks_count,
+        r_blocks_count,
+        free_blocks_count: free_blocks_count.min(blocks_count),
+        free_inodes_count: free_inodes_count.min(inodes_count),
+        group_desc_block: first_data_block + 1,
+        has_filetype: feature_incompat & INCOMPAT_FILETYPE != 0,
+        open_cnt: 0,
+    })
+}
+
+/**
+ * 节点对应的打开的ext2节点
+ */
+fn ext2_node(node: &Vnode) -> &'static mut Ext2Node {
+    unsafe { &mut *(node.get_data() as *mut Ext2Node) }
+}
+
+/**
+ * 目录项中的文件类型
+ */
+fn file_type_from_code(code: u8) -> FileType {
+    match code {
+        1 => FileType::Regular,
+        2 => FileType::Directory,
+        3 => FileType::CharDevice,
+        4 => FileType::BlockDevice,
+        7 => FileType::Symlink,
+        _ => FileType::Unknown,
+    }
+}
+
+fn read_u16(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes([buf[off], buf[off + 1]])
+}
+
+fn read_u32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]])
+}
+
+impl Ext2Node {
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+impl Ext2Fs {
+    /**
+     * 读取分区中的第rel_lba个扇区
+     */
+    fn read_sector(&self, rel_lba: u32, buf: &mut [u8; constants::DISK_SECTOR_SIZE]) {
+        self.dev.read_sector(rel_lba, buf);
+    }
+
+    pub fn block_size(&self) -> u32 {
+        self.block_size
+    }
+
+    pub fn group_cnt(&self) -> u32 {
+        self.group_cnt
+    }
+
+    /**
+     * 块号是否在文件系统的范围内。0不是合法的块号（表示空洞）
+     */
+    fn is_valid_block(&self, block: u32) -> bool {
+        block != 0 && block < self.blocks_count
+    }
+
+    /**
+     * 读取块block中，字节偏移量为off的u32
+     */
+    #[inline(never)]
+    fn read_block_u32(&self, block: u32, off: u32, buf: &mut [u8; constants::DISK_SECTOR_SIZE]) -> u32 {
+        let sector_size = constants::DISK_SECTOR_SIZE as u32;
+        self.read_sector(block * self.sec_per_block + off / sector_size, buf);
+        self::read_u32(buf, (off % sector_size) as usize)
+    }
+
+    /**
+     * 块组描述符中，块组group的inode表的起始块。块组不存在，或者inode表超出了文件系统的范围，返回None
+     */
+    #[inline(never)]
+    pub fn inode_table(&self, group: u32, buf: &mut [u8; constants::DISK_SECTOR_SIZE]) -> Option<u32> {
+        if group >= self.group_cnt {
+            return Option::None;
+        }
+        let desc_off = group * GROUP_DESC_SIZE;
+        let desc_block = self.group_desc_block + desc_off / self.block_size;
+        if !self.is_valid_block(desc_block) {
+            return Option::None;
+        }
+        let inode_table = self.read_block_u32(desc_block, desc_off % self.block_size + 8, buf);
+        let table_blocks = (self.inodes_per_group as u64 * self.inode_size as u64).div_ceil(self.block_size as u64);
+        if !self.is_valid_block(inode_table) || inode_table as u64 + table_blocks > self.blocks_count as u64 {
+            return Option::None;
+        }
+        Option::Some(inode_table)
+    }
+
+    /**
+     * 读取inode。inode号不合法，返回None
+     */
+    #[inline(never)]
+    fn load_inode(&self, ino: u32) -> Option<Ext2Node> {
+        let buf: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(constants::DISK_SECTOR_SIZE);
+        let node = self.read_inode(ino, buf);
+        memory::sys_free(buf.as_ptr() as usize);
+        node
+    }
+
+    /**
+     * 使用buf读取inode。inode号不合法，或者所在块组的inode表不合法，返回None
+     */
+    #[inline(never)]
+    pub fn read_inode(&self, ino: u32, buf: &mut [u8; constants::DISK_SECTOR_SIZE]) -> Option<Ext2Node> {
+        if ino == 0 || ino > self.inodes_count {
+            return Option::None;
+        }
+        let sector_size = constants::DISK_SECTOR_SIZE as u32;
+        let inode_table = self.inode_table((ino - 1) / self.inodes_per_group, buf)?;
+
+        // inode的大小是2的幂，不小于128，前128字节一定在同一个扇区中
+        let inode_off = ((ino - 1) % self.inodes_per_group) * self.inode_size;
+        self.read_sector(inode_table * self.sec_per_block + inode_off / sector_size, buf);
+        let raw = &buf[(inode_off % sector_size) as usize..];
+
+        let mode = self::read_u16(raw, 0);
+        let size = self::read_u32(raw, 4);
+        let blocks = self::read_u32(raw, 28);
+        let file_acl = self::read_u32(raw, 104);
+        let mut block = [0u32; BLOCK_PTRS];
+        for (idx, ptr) in block.iter_mut().enumerate() {
+            *ptr = self::read_u32(raw, 40 + idx * size_of::<u32>());
+        }
+        let file_type = FileType::from_mode(mode);
+        // 扩展属性块也算在占用的扇区中
+        let acl_blocks = if file_acl != 0 { self.sec_per_block } else { 0 };
+        Option::Some(Ext2Node {
+            ino: InodeNo::new(ino),
+            file_type,
+            mode,
+            uid: self::read_u16(raw, 2),
+            gid: self::read_u16(raw, 24),
+            nlink: self::read_u16(raw, 26),
+            size,
+            blocks,
+            atime: time::from_unix_timestamp(self::read_u32(raw, 8)),
+            ctime: time::from_unix_timestamp(self::read_u32(raw, 12)),
+            mtime: time::from_unix_timestamp(self::read_u32(raw, 16)),
+            block,
+            fast_symlink: file_type == FileType::Symlink && size <= FAST_SYMLINK_MAX && blocks == acl_blocks,
+            open_cnt: 0,
+        })
+    }
+
+    /**
+     * 文件的第idx个块，在硬盘中的块号。0表示空洞
+     * 块号超出了文件系统的范围（包括间接块中的块号），也当作空洞，不会读到分区外面去
+     */
+    #[inline(never)]
+    pub fn map_block(&self, node: &Ext2Node, idx: u32, buf: &mut [u8; constants::DISK_SECTOR_SIZE]) -> u32 {
+        let ptrs = self.block_size / size_of::<u32>() as u32;
+        let ptr_size = size_of::<u32>() as u32;
+        let mut idx = idx;
+        if (idx as usize) < DIRECT_BLOCKS {
+            let block = node.block[idx as usize];
+            return if self.is_valid_block(block) { block } else { 0 };
+        }
+        idx -= DIRECT_BLOCKS as u32;
+
+        // 从间接块开始，逐级找下去。每一级的块中，是下一级的块号
+        let (mut block, mut span) = if idx < ptrs {
+            (node.block[IND_BLOCK], 1)
+        } else if idx - ptrs < ptrs * ptrs {
+            idx -= ptrs;
+            (node.block[DIND_BLOCK], ptrs)
+        } else {
+            idx -= ptrs + ptrs * ptrs;
+            (node.block[TIND_BLOCK], ptrs * ptrs)
+        };
+        loop {
+            if !self.is_valid_block(block) {
+                return 0;
+            }
+            block = self.read_block_u32(block, (idx / span) * ptr_size, buf);
+            if span == 1 {
+                return if self.is_valid_block(block) { block } else { 0 };
+            }
+            idx %= span;
+            span /= ptrs;
+        }
+    }
+
+    /**
+     * 从节点数据的off处开始，读取数据到buff中。空洞读取出来是0
+     */
+    #[inline(never)]
+    fn read_data(&self, node: &Ext2Node, off: u32, buff: &mut [u8]) -> usize {
+        let total_len = buff.len().min((node.size as usize).saturating_sub(off as usize));
+        if total_len == 0 {
+            return 0;
+        }
+        if node.fast_symlink {
+            let target = unsafe { core::slice::from_raw_parts(node.block.as_ptr() as *const u8, node.size as usize) };
+            buff[..total_len].copy_from_slice(&target[off as usize..off as usize + total_len]);
+            return total_len;
+        }
+        let sector_size = constants::DISK_SECTOR_SIZE;
+        let block_size = self.block_size as usize;
+        let sector_buf: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(sector_size);
+        let mut done_len = 0;
+        while done_len < total_len {
+            let cur_off = off as usize + done_len;
+            let sector_off = cur_off % sector_size;
+            let copy_len = (sector_size - sector_off).min(total_len - done_len);
+            let block = self.map_block(node, (cur_off / block_size) as u32, sector_buf);
+            if block == 0 {
+                buff[done_len..done_len + copy_len].fill(0);
+            } else {
+                self.read_sector(block * self.sec_per_block + ((cur_off % block_size) / sector_size) as u32, sector_buf);
+                buff[done_len..done_len + copy_len].copy_from_slice(&sector_buf[sector_off..sector_off + copy_len]);
+            }
+            done_len += copy_len;
+        }
+        memory::sys_free(sector_buf.as_ptr() as usize);
+        total_len
+    }
+
+    /**
+     * 从目录dir字节偏移量为off的地方开始，找到下一个目录项（跳过inode号是0的空目录项）
+     * 返回(inode号, 文件类型, 名称长度, 下一个目录项的偏移量)，名称放在name中
+     */
+    #[inline(never)]
+    fn next_entry(&self, dir: &Ext2Node, off: u32, name: &mut [u8; constant::MAX_FILE_NAME]) -> Option<(u32, FileType, usize, u32)> {
+        let mut off = off;
+        let mut header = [0u8; DIR_ENTRY_HEADER_SIZE];
+        loop {
+            if off as usize + DIR_ENTRY_HEADER_SIZE > dir.size as usize {
+                return Option::None;
+            }
+            self.read_data(dir, off, &mut header);
+            let ino = self::read_u32(&header, 0);
+            let rec_len = self::read_u16(&header, 4) as u32;
+            let name_len = header[6] as usize;
+            // 目录项损坏了，不再往下读
+            if (rec_len as usize) < DIR_ENTRY_HEADER_SIZE || rec_len % 4 != 0 || off + rec_len > dir.size {
+                return Option::None;
+            }
+            let cur_off = off;
+            off += rec_len;
+            if ino == 0 || name_len == 0 || DIR_ENTRY_HEADER_SIZE + name_len > rec_len as usize {
+                continue;
+            }
+            self.read_data(dir, cur_off + DIR_ENTRY_HEADER_SIZE as u32, &mut name[..name_len]);
+            // 不是UTF-8的名称，无法表示，跳过
+            if core::str::from_utf8(&name[..name_len]).is_err() {
+                continue;
+            }
+            // 没有文件类型特性的话，需要读取inode得到类型
+            let file_type = if self.has_filetype {
+                self::file_type_from_code(header[7])
+            } else {
+                self.load_inode(ino).map_or(FileType::Unknown, |node| node.file_type)
+            };
+            return Option::Some((ino, file_type, name_len, off));
+        }
+    }
+
+    #[inline(never)]
+    fn open_node(&mut self, node: Ext2Node) -> Vnode {
+        let opened: &mut Ext2Node = memory::malloc_system(size_of::<Ext2Node>());
+        *opened = node;
+        opened.open_cnt = 1;
+        self.open_cnt += 1;
+        Vnode::new(self as *mut Ext2Fs, opened.ino, opened.file_type, opened as *mut Ext2Node as usize)
+    }
+}
+
+impl Vfs for Ext2Fs {
+    fn root_ino(&self) -> InodeNo {
+        InodeNo::new(ROOT_INO)
+    }
+
+    /**
+     * 不是目录的话，打开根目录
+     */
+    #[inline(never)]
+    fn open_dir(&mut self, ino: InodeNo) -> Vnode {
+        let node = self.load_inode(ino.get_data()).filter(|node| node.file_type == FileType::Directory);
+        if node.is_some() {
+            return self.open_node(node.unwrap());
+        }
+        let root = self.load_inode(ROOT_INO);
+        ASSERT!(root.is_some());
+        self.open_node(root.unwrap())
+    }
+
+    /**
+     * 每个目录（包括根目录）中都有.和..目录项
+     */
+    #[inline(never)]
+    fn lookup(&mut self, dir: &Vnode, name: &str) -> Option<Vnode> {
+        let dir_node = ext2_node(dir);
+        let name_buf: &mut [u8; constant::MAX_FILE_NAME] = memory::malloc(constant::MAX_FILE_NAME);
+        let mut off = 0;
+        let mut found = Option::None;
+        loop {
+            let entry = self.next_entry(dir_node, off, name_buf);
+            if entry.is_none() {
+                break;
+            }
+            let (ino, _, name_len, next_off) = entry.unwrap();
+            if &name_buf[..name_len] == name.as_bytes() {
+                found = Option::Some(ino);
+                break;
+            }
+            off = next_off;
+        }
+        memory::sys_free(name_buf.as_ptr() as usize);
+        let node = self.load_inode(found?)?;
+        Option::Some(self.open_node(node))
+    }
+
+    fn create(&mut self, _dir: &Vnode, _name: &str, _file_type: FileType) -> Result<Vnode, FileError> {
+        Result::Err(FileError::ReadOnly)
+    }
+
+    /**
+     * 设备文件中保存的是设备号，没有数据
+     */
+    #[inline(never)]
+    fn read(&mut self, node: &Vnode, off: u32, buff: &mut [u8]) -> usize {
+        let file_node = ext2_node(node);
+        if file_node.file_type != FileType::Regular && file_node.file_type != FileType::Symlink {
+            return 0;
+        }
+        self.read_data(file_node, off, buff)
+    }
+
+    fn write(&mut self, _node: &Vnode, _off: u32, _buff: &[u8]) -> Result<usize, FileError> {
+        Result::Err(FileError::ReadOnly)
+    }
+
+    /**
+     * 目录项的偏移量，就是目录项在目录数据中的字节偏移量
+     */
+    #[inline(never)]
+    fn readdir(&mut self, dir: &Vnode, off: u32) -> Option<(DirEntry, u32)> {
+        let name_buf: &mut [u8; constant::MAX_FILE_NAME] = memory::malloc(constant::MAX_FILE_NAME);
+        let res = self.next_entry(ext2_node(dir), off, name_buf).map(|(ino, file_type, name_len, next_off)| {
+            let name = unsafe { core::str::from_utf8_unchecked(&name_buf[..name_len]) };
+            (DirEntry::new(InodeNo::new(ino), name, file_type), next_off)
+        });
+        memory::sys_free(name_buf.as_ptr() as usize);
+        res
+    }
+
+    fn unlink(&mut self, _dir: &Vnode, _name: &str) -> Result<(), FileError> {
+        Result::Err(FileError::ReadOnly)
+    }
+
+    #[inline(never)]
+    fn stat(&mut self, node: &Vnode) -> FileStat {
+        let file_node = ext2_node(node);
+        FileStat {
+            i_no: file_node.ino.get_data(),
+            mode: file_node.mode as u32,
+            nlink: file_node.nlink as u32,
+            uid: file_node.uid as u32,
+            gid: file_node.gid as u32,
+            size: file_node.size,
+            blocks: file_node.blocks,
+            flags: FileStat::FLAG_HAS_TIME,
+            ctime: file_node.ctime,
+            mtime: file_node.mtime,
+            atime: file_node.atime,
+        }
+    }
+
+    fn reopen(&mut self, node: &Vnode) {
+        ext2_node(node).open_cnt += 1;
+        self.open_cnt += 1;
+    }
+
+    #[inline(never)]
+    fn close(&mut self, node: &Vnode) {
+        let file_node = ext2_node(node);
+        file_node.open_cnt -= 1;
+        self.open_cnt -= 1;
+        if file_node.open_cnt == 0 {
+            memory::free_system(file_node as *const Ext2Node);
+        }
+    }
+
+    fn rename(&mut self, _old_dir: &Vnode, _old_name: &str, _new_dir: &Vnode, _new_name: &str) -> Result<(), FileError> {
+        Result::Err(FileError::ReadOnly)
+    }
+
+    fn link(&mut self, _node: &Vnode, _dir: &Vnode, _name: &str) -> Result<(), FileError> {
+        Result::Err(FileError::ReadOnly)
+    }
+
+    /**
+     * 超级块中记录的数量
+     */
+    fn statfs(&mut self) -> FsStat {
+        FsStat {
+            block_size: self.block_size,
+            total_blocks: self.blocks_count,
+            free_blocks: self.free_blocks_count,
+            avail_blocks: self.free_blocks_count.saturating_sub(self.r_blocks_count),
+            total_inodes: self.inodes_count,
+            free_inodes: self.free_inodes_count,
+        }
+    }
+
+    fn is_busy(&mut self) -> bool {
+        self.open_cnt > 0
+    }
+
+    /**
+     * 只读的文件系统，没有需要写回的数据。释放文件系统自己
+     */
+    #[inline(never)]
+    fn unmount(&mut self) {
+        memory::free_system(self as *const Ext2Fs);
+    }
+}
@@ -0,0 +1,978 @@
+use core::mem::size_of;
+
+use os_in_rust_common::{constants, domain::InodeNo, ASSERT};
+
+use crate::{device::{Partition, PartitionType}, memory, time::Time};
+
+use super::{buffer_cache, constant, dir_entry::{DirEntry, FileType}, file::FileError, stat::{FileStat, FsStat}, vfs::{Vfs, Vnode}};
+
+/**
+ * FAT文件系统（FAT16、FAT32）的只读驱动
+ *  - 分区表中类型是FAT，并且引导扇区是合法的FAT引导扇区的分区，挂载的时候使用这个驱动
+ *  - 目录项支持8.3短文件名，以及长文件名（LFN）。查找文件名的时候，不区分大小写
+ *  - 节点的编号：根目录是ROOT_INO；其他目录是第一个簇的簇号；文件是短文件名目录项所在的位置，加上FILE_INO_FLAG
+ * <https://wiki.osdev.org/FAT>
+ */
+
+/**
+ * 根目录的编号。簇号从2开始，不会和目录的编号冲突
+ */
+const ROOT_INO: u32 = 1;
+
+/**
+ * 文件编号的标志位，和目录的编号（簇号）区分开
+ */
+const FILE_INO_FLAG: u32 = 0x8000_0000;
+
+/**
+ * 目录项的大小，以及一个扇区中目录项的数量
+ */
+const DIR_ENTRY_SIZE: usize = 32;
+const ENTRIES_PER_SECTOR: u32 = (constants::DISK_SECTOR_SIZE / DIR_ENTRY_SIZE) as u32;
+
+/**
+ * 目录项的属性：卷标、目录、长文件名。长文件名只看低6位
+ */
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_LONG_NAME: u8 = 0x0f;
+const ATTR_LONG_NAME_MASK: u8 = 0x3f;
+
+/**
+ * 目录项的第一个字节：后面没有目录项了、目录项已经删除了
+ */
+const ENTRY_END: u8 = 0x00;
+const ENTRY_FREE: u8 = 0xe5;
+
+/**
+ * 短文件名的主文件名、扩展名是小写的（Windows NT使用的保留字节）
+ */
+const NT_LOWER_BASE: u8 = 0x08;
+const NT_LOWER_EXT: u8 = 0x10;
+
+/**
+ * 长文件名目录项：最后一项的标志、序号的掩码、每一项的字符数量、最多的项数
+ */
+const LFN_LAST: u8 = 0x40;
+const LFN_ORD_MASK: u8 = 0x1f;
+const LFN_CHARS: usize = 13;
+const LFN_MAX_ENTRIES: usize = 20;
+
+/**
+ * 长文件名目录项中，13个UCS-2字符所在的偏移量
+ */
+const LFN_CHAR_OFFSETS: [usize; LFN_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+/**
+ * 簇的数量少于FAT16_MIN_CLUSTERS的是FAT12（不支持），不少于FAT32_MIN_CLUSTERS的是FAT32
+ */
+const FAT16_MIN_CLUSTERS: u32 = 4085;
+const FAT32_MIN_CLUSTERS: u32 = 65525;
+
+/**
+ * FAT32的FAT表项只有低28位有效
+ */
+const FAT32_ENTRY_MASK: u32 = 0x0fff_ffff;
+
+/**
+ * 引导扇区最后两个字节的魔数
+ */
+const BOOT_SECTOR_SIGN: u16 = 0xaa55;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatType {
+    Fat16,
+    Fat32,
+}
+
+/**
+ * 短文件名目录项（32字节）中用到的字段
+ */
+struct FatDirEntry {
+    /**
+     * 8个字节的主文件名 + 3个字节的扩展名，不足的用空格补齐
+     */
+    name: [u8; 11],
+    attr: u8,
+    nt_res: u8,
+    crt_time: u16,
+    crt_date: u16,
+    acc_date: u16,
+    wrt_time: u16,
+    wrt_date: u16,
+    /**
+     * 第一个簇。高16位只有FAT32才有
+     */
+    first_cluster: u32,
+    size: u32,
+}
+
+impl FatDirEntry {
+    fn parse(raw: &[u8]) -> Self {
+        let mut name = [0; 11];
+        name.copy_from_slice(&raw[..11]);
+        Self {
+            name,
+            attr: raw[11],
+            nt_res: raw[12],
+            crt_time: self::read_u16(raw, 14),
+            crt_date: self::read_u16(raw, 16),
+            acc_date: self::read_u16(raw, 18),
+            wrt_time: self::read_u16(raw, 22),
+            wrt_date: self::read_u16(raw, 24),
+            first_cluster: (self::read_u16(raw, 20) as u32) << 16 | self::read_u16(raw, 26) as u32,
+            size: self::read_u32(raw, 28),
+        }
+    }
+
+    fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+
+    fn file_type(&self) -> FileType {
+        if self.is_dir() { FileType::Directory } else { FileType::Regular }
+    }
+
+    /**
+     * 短文件名的校验和。长文件名目录项中保存了这个校验和，用来确认长文件名属于这个目录项
+     */
+    fn checksum(&self) -> u8 {
+        self.name.iter().fold(0u8, |sum, byte| ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(*byte))
+    }
+}
+
+/**
+ * 打开的节点。每次打开（lookup、open_dir）都创建一个，最后一次关闭的时候释放
+ */
+struct FatNode {
+    ino: InodeNo,
+    file_type: FileType,
+    /**
+     * 第一个簇。空文件是0；FAT16的根目录也是0（根目录在单独的根目录区中）
+     */
+    first_cluster: u32,
+    /**
+     * 文件的大小。目录的目录项中没有大小，是0
+     */
+    size: u32,
+    has_time: bool,
+    ctime: u32,
+    mtime: u32,
+    atime: u32,
+    open_cnt: u32,
+    /**
+     * 上一次访问的簇是第几个簇，以及它的簇号。顺序读取的时候，不用每次都从第一个簇开始查FAT表
+     */
+    hint_idx: u32,
+    hint_cluster: u32,
+}
+
+impl FatNode {
+    fn new(ino: InodeNo, file_type: FileType, first_cluster: u32, size: u32) -> Self {
+        Self {
+            ino,
+            file_type,
+            first_cluster,
+            size,
+            has_time: false,
+            ctime: 0,
+            mtime: 0,
+            atime: 0,
+            open_cnt: 0,
+            hint_idx: 0,
+            hint_cluster: 0,
+        }
+    }
+
+    /**
+     * 使用目录项中的创建时间、修改时间、访问日期
+     */
+    fn set_times(&mut self, entry: &FatDirEntry) {
+        self.has_time = true;
+        self.ctime = self::fat_timestamp(entry.crt_date, entry.crt_time);
+        self.mtime = self::fat_timestamp(entry.wrt_date, entry.wrt_time);
+        self.atime = self::fat_timestamp(entry.acc_date, 0);
+    }
+
+    fn is_dir(&self) -> bool {
+        self.file_type == FileType::Directory
+    }
+}
+
+/**
+ * 扫描目录使用的缓冲区。比较大，放在堆中
+ */
+struct DirScan {
+    /**
+     * 目录项所在的扇区，以及它的扇区号（相对分区）。扇区号是0表示还没有读取（第0个扇区是引导扇区，不会是目录）
+     */
+    sector: [u8; constants::DISK_SECTOR_SIZE],
+    sector_lba: u32,
+    /**
+     * 查询FAT表使用的扇区
+     */
+    fat_sector: [u8; constants::DISK_SECTOR_SIZE],
+    /**
+     * 正在拼接的长文件名（UCS-2）
+     */
+    lfn: [u16; LFN_MAX_ENTRIES * LFN_CHARS],
+    /**
+     * 下一个长文件名目录项应该是的序号，0表示没有正在拼接的长文件名
+     */
+    lfn_next: u8,
+    /**
+     * 长文件名是否已经拼接完整
+     */
+    lfn_done: bool,
+    /**
+     * 长文件名所属的短文件名目录项的校验和
+     */
+    lfn_sum: u8,
+    /**
+     * 目录项的名称（UTF-8）
+     */
+    name: [u8; constant::MAX_FILE_NAME],
+    name_len: usize,
+}
+
+impl DirScan {
+    fn new() -> &'static mut Self {
+        let scan: &mut Self = memory::malloc(size_of::<Self>());
+        scan.sector_lba = 0;
+        scan.lfn_next = 0;
+        scan.lfn_done = false;
+        scan.name_len = 0;
+        scan
+    }
+
+    fn free(&self) {
+        memory::sys_free(self as *const Self as usize);
+    }
+
+    fn get_name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("")
+    }
+
+    fn reset_lfn(&mut self) {
+        self.lfn_next = 0;
+        self.lfn_done = false;
+    }
+
+    /**
+     * 拼接长文件名。长文件名的目录项倒序存放：第一项是最后一部分（带LFN_LAST标志），序号依次减1，直到1
+     */
+    #[inline(never)]
+    fn push_lfn(&mut self, raw: &[u8]) {
+        let ord = raw[0] & LFN_ORD_MASK;
+        if raw[0] & LFN_LAST != 0 {
+            self.lfn_next = ord;
+            self.lfn_sum = raw[13];
+            self.lfn.fill(0);
+        }
+        self.lfn_done = false;
+        // 序号不连续，或者不属于同一个短文件名，丢弃
+        if ord == 0 || ord as usize > LFN_MAX_ENTRIES || ord != self.lfn_next || raw[13] != self.lfn_sum {
+            self.lfn_next = 0;
+            return;
+        }
+        let start = (ord as usize - 1) * LFN_CHARS;
+        for (idx, off) in LFN_CHAR_OFFSETS.iter().enumerate() {
+            self.lfn[start + idx] = self::read_u16(raw, *off);
+        }
+        self.lfn_next = ord - 1;
+        self.lfn_done = ord == 1;
+    }
+
+    /**
+     * 目录项的名称：有完整的长文件名（并且校验和对得上）的话，使用长文件名；否则使用短文件名
+     */
+    #[inline(never)]
+    fn set_name(&mut self, entry: &FatDirEntry) {
+        let use_lfn = self.lfn_done && self.lfn_sum == entry.checksum() && self.decode_lfn();
+        self.reset_lfn();
+        if !use_lfn {
+            self.decode_short_name(entry);
+        }
+    }
+
+    /**
+     * 把长文件名转成UTF-8。长文件名以0结束，后面用0xffff填充
+     */
+    #[inline(never)]
+    fn decode_lfn(&mut self) -> bool {
+        let lfn_len = self.lfn.iter().position(|c| *c == 0 || *c == 0xffff).unwrap_or(self.lfn.len());
+        let mut name_len = 0;
+        for c in char::decode_utf16(self.lfn[..lfn_len].iter().copied()) {
+            if c.is_err() {
+                return false;
+            }
+            let c = c.unwrap();
+            if c == '/' || name_len + c.len_utf8() > constant::MAX_FILE_NAME {
+                return false;
+            }
+            c.encode_utf8(&mut self.name[name_len..]);
+            name_len += c.len_utf8();
+        }
+        self.name_len = name_len;
+        name_len > 0
+    }
+
+    /**
+     * 8.3短文件名：去掉主文件名、扩展名后面的空格，按照NT保留字节的标志转成小写
+     */
+    #[inline(never)]
+    fn decode_short_name(&mut self, entry: &FatDirEntry) {
+        let base_len = entry.name[..8].iter().rposition(|byte| *byte != b' ').map_or(0, |pos| pos + 1);
+        let ext_len = entry.name[8..].iter().rposition(|byte| *byte != b' ').map_or(0, |pos| pos + 1);
+        self.name_len = 0;
+        self.push_short(&entry.name[..base_len], entry.nt_res & NT_LOWER_BASE != 0);
+        if ext_len > 0 {
+            self.name[self.name_len] = b'.';
+            self.name_len += 1;
+            self.push_short(&entry.name[8..8 + ext_len], entry.nt_res & NT_LOWER_EXT != 0);
+        }
+    }
+
+    fn push_short(&mut self, part: &[u8], lower: bool) {
+        for byte in part {
+            // 其他代码页的字符，没法转成UTF-8，使用_代替
+            let byte = if !byte.is_ascii() { b'_' } else if lower { byte.to_ascii_lowercase() } else { *byte };
+            self.name[self.name_len] = byte;
+            self.name_len += 1;
+        }
+    }
+}
+
+/**
+ * FAT文件系统所在的设备。扇区号都是相对分区的
+ */
+pub trait FatDevice {
+    fn read_sector(&self, rel_lba: u32, buf: &mut [u8; constants::DISK_SECTOR_SIZE]);
+}
+
+/**
+ * 内核中，通过缓冲区读取分区所在的硬盘
+ */
+impl FatDevice for Partition {
+    fn read_sector(&self, rel_lba: u32, buf: &mut [u8; constants::DISK_SECTOR_SIZE]) {
+        buffer_cache::read_sectors(unsafe { &mut *self.from_disk }, self.abs_lba_start(rel_lba), 1, buf);
+    }
+}
+
+/**
+ * 挂载的FAT文件系统。扇区号都是相对分区的
+ */
+pub struct FatFs {
+    /**
+     * 文件系统所在的设备（分区）
+     */
+    dev: &'static dyn FatDevice,
+    fat_type: FatType,
+    /**
+     * 每个簇的扇区数量
+     */
+    sec_per_clus: u32,
+    /**
+     * 第一个FAT表的起始扇区
+     */
+    fat_lba: u32,
+    /**
+     * FAT16根目录区的起始扇区、目录项的数量。FAT32的根目录在数据区中，这两个都是0
+     */
+    root_dir_lba: u32,
+    root_ent_cnt: u32,
+    /**
+     * 数据区（第2个簇）的起始扇区
+     */
+    data_lba: u32,
+    /**
+     * 数据区中簇的数量。有效的簇号是[2, cluster_cnt + 2)
+     */
+    cluster_cnt: u32,
+    /**
+     * FAT32根目录的第一个簇。FAT16是0
+     */
+    root_cluster: u32,
+    /**
+     * 打开的节点数量
+     */
+    open_cnt: u32,
+}
+
+/**
+ * 分区是不是FAT16、FAT32分区：分区表中的类型是FAT，并且引导扇区是合法的
+ */
+#[inline(never)]
+pub fn is_fat_volume(part: &Partition) -> bool {
+    self::parse(part).is_some()
+}
+
+/**
+ * 加载分区中的FAT文件系统。调用方需要保证is_fat_volume(part)
+ * 文件系统的结构放在堆中，卸载之前地址不会变化
+ */
+#[inline(never)]
+pub fn load(part: &'static Partition) -> &'static mut FatFs {
+    let parsed = self::parse(part);
+    ASSERT!(parsed.is_some());
+    let fs: &mut FatFs = memory::malloc_system(size_of::<FatFs>());
+    *fs = parsed.unwrap();
+    fs
+}
+
+/**
+ * 读取分区的引导扇区，解析出FAT文件系统的布局
+ */
+#[inline(never)]
+fn parse(part: &Partition) -> Option<FatFs> {
+    if !PartitionType::is_fat(part.part_type) {
+        return Option::None;
+    }
+    let buf: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(constants::DISK_SECTOR_SIZE);
+    part.read_sector(0, buf);
+    // 分区在分区表中，地址不会变化
+    let dev: &'static Partition = unsafe { &*(part as *const Partition) };
+    let fs = self::parse_boot_sector(dev, part.sec_cnt, buf);
+    memory::sys_free(buf.as_ptr() as usize);
+    fs
+}
+
+/**
+ * 解析引导扇区中的BPB。只支持512字节的扇区，FAT12不支持
+ *  - dev: 文件系统所在的设备；part_secs: 分区的扇区数量；buf: 引导扇区（分区的第一个扇区）
+ */
+#[inline(never)]
+pub fn parse_boot_sector(dev: &'static dyn FatDevice, part_secs: u32, buf: &[u8]) -> Option<FatFs> {
+    let bytes_per_sec = self::read_u16(buf, 11) as usize;
+    let sec_per_clus = buf[13] as u32;
+    let rsvd_sec_cnt = self::read_u16(buf, 14) as u32;
+    let num_fats = buf[16] as u32;
+    let root_ent_cnt = self::read_u16(buf, 17) as u32;
+    let tot_sec16 = self::read_u16(buf, 19) as u32;
+    let fat_sz16 = self::read_u16(buf, 22) as u32;
+    let tot_sec32 = self::read_u32(buf, 32);
+    let fat_sz32 = self::read_u32(buf, 36);
+    let root_clus = self::read_u32(buf, 44);
+    if self::read_u16(buf, 510) != BOOT_SECTOR_SIGN || bytes_per_sec != constants::DISK_SECTOR_SIZE
+        || !sec_per_clus.is_power_of_two() || rsvd_sec_cnt == 0 || num_fats == 0 {
+        return Option::None;
+    }
+
+    let fat_sz = if fat_sz16 != 0 { fat_sz16 } else { fat_sz32 };
+    let tot_sec = if tot_sec16 != 0 { tot_sec16 } else { tot_sec32 };
+    let root_dir_secs = (root_ent_cnt * DIR_ENTRY_SIZE as u32).div_ceil(bytes_per_sec as u32);
+    let root_dir_lba = rsvd_sec_cnt + num_fats * fat_sz;
+    let data_lba = root_dir_lba + root_dir_secs;
+    if fat_sz == 0 || tot_sec <= data_lba || tot_sec > part_secs {
+        return Option::None;
+    }
+
+    // FAT的类型，只由簇的数量决定
+    let cluster_cnt = (tot_sec - data_lba) / sec_per_clus;
+    if cluster_cnt < FAT16_MIN_CLUSTERS {
+        return Option::None;
+    }
+    let fat_type = if cluster_cnt < FAT32_MIN_CLUSTERS { FatType::Fat16 } else { FatType::Fat32 };
+    if fat_type == FatType::Fat32 && (root_ent_cnt != 0 || root_clus < 2 || root_clus >= cluster_cnt + 2) {
+        return Option::None;
+    }
+
+    let is_fat16 = fat_type == FatType::Fat16;
+    Option::Some(FatFs {
+        dev,
+        fat_type,
+        sec_per_clus,
+        fat_lba: rsvd_sec_cnt,
+        root_dir_lba: if is_fat16 { root_dir_lba } else { 0 },
+        root_ent_cnt: if is_fat16 { root_ent_cnt } else { 0 },
+        data_lba,
+        cluster_cnt,
+        root_cluster: if is_fat16 { 0 } else { root_clus },
+        open_cnt: 0,
+    })
+}
+
+/**
+ * 节点对应的打开的FAT节点
+ */
+fn fat_node(node: &Vnode) -> &'static mut FatNode {
+    unsafe { &mut *(node.get_data() as *mut FatNode) }
+}
+
+/**
+ * FAT的日期、时间，转成时间戳
+ *  - 日期：[9, 16)位是从1980年开始的年数，[5, 9)位是月，[0, 5)位是日
+ *  - 时间：[11, 16)位是时，[5, 11)位是分，[0, 5)位是秒数的一半
+ */
+fn fat_timestamp(date: u16, time: u16) -> u32 {
+    Time::new(1980 + (date >> 9), ((date >> 5) & 0xf) as u8, (date & 0x1f) as u8, (time >> 11) as u8, ((time >> 5) & 0x3f) as u8, ((time & 0x1f) * 2) as u8).to_timestamp()
+}
+
+fn read_u16(buf: &[u8], off: usize) -> u16 {
+    u16::from_le_bytes([buf[off], buf[off + 1]])
+}
+
+fn read_u32(buf: &[u8], off: usize) -> u32 {
+    u32::from_le_bytes([buf[off], buf[off + 1], buf[off + 2], buf[off + 3]])
+}
+
+impl FatFs {
+    /**
+     * 读取分区中的第rel_lba个扇区
+     */
+    fn read_sector(&self, rel_lba: u32, buf: &mut [u8; constants::DISK_SECTOR_SIZE]) {
+        self.dev.read_sector(rel_lba, buf);
+    }
+
+    pub fn fat_type(&self) -> FatType {
+        self.fat_type
+    }
+
+    /**
+     * 数据区中簇的数量
+     */
+    pub fn cluster_cnt(&self) -> u32 {
+        self.cluster_cnt
+    }
+
+    fn cluster_size(&self) -> u32 {
+        self.sec_per_clus * constants::DISK_SECTOR_SIZE as u32
+    }
+
+    fn cluster_lba(&self, cluster: u32) -> u32 {
+        self.data_lba + (cluster - 2) * self.sec_per_clus
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.cluster_cnt + 2
+    }
+
+    /**
+     * 根目录，或者指向根目录的簇号（..目录项中，根目录的簇号是0）
+     */
+    fn is_root_cluster(&self, cluster: u32) -> bool {
+        cluster == 0 || cluster == self.root_cluster
+    }
+
+    /**
+     * 目录项中的第一个簇。FAT16的目录项中，高16位是保留的
+     */
+    fn entry_cluster(&self, entry: &FatDirEntry) -> u32 {
+        match self.fat_type {
+            FatType::Fat16 => entry.first_cluster & 0xffff,
+            FatType::Fat32 => entry.first_cluster & FAT32_ENTRY_MASK,
+        }
+    }
+
+    /**
+     * 目录项指向的节点的编号
+     */
+    fn entry_ino(&self, entry: &FatDirEntry, entry_pos: u32) -> InodeNo {
+        if !entry.is_dir() {
+            return InodeNo::new(FILE_INO_FLAG | entry_pos);
+        }
+        let cluster = self.entry_cluster(entry);
+        if self.is_root_cluster(cluster) {
+            return InodeNo::new(ROOT_INO);
+        }
+        InodeNo::new(cluster)
+    }
+
+    /**
+     * 查询FAT表，簇链中cluster的下一个簇。结束标记、坏簇、空闲簇，都当作簇链结束
+     */
+    #[inline(never)]
+    pub fn next_cluster(&self, cluster: u32, fat_sector: &mut [u8; constants::DISK_SECTOR_SIZE]) -> Option<u32> {
+        let entry_size = match self.fat_type {
+            FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
+        };
+        let fat_off = cluster * entry_size;
+        self.read_sector(self.fat_lba + fat_off / constants::DISK_SECTOR_SIZE as u32, fat_sector);
+        let sector_off = fat_off as usize % constants::DISK_SECTOR_SIZE;
+        let next = match self.fat_type {
+            FatType::Fat16 => self::read_u16(fat_sector, sector_off) as u32,
+            FatType::Fat32 => self::read_u32(fat_sector, sector_off) & FAT32_ENTRY_MASK,
+        };
+        if !self.is_valid_cluster(next) {
+            return Option::None;
+        }
+        Option::Some(next)
+    }
+
+    /**
+     * 遍历FAT表，统计空闲簇（表项是0）的数量
+     */
+    #[inline(never)]
+    fn count_free_clusters(&self) -> u32 {
+        let entry_size = match self.fat_type {
+            FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
+        };
+        let entries_per_sector = constants::DISK_SECTOR_SIZE as u32 / entry_size;
+        let fat_sector: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(constants::DISK_SECTOR_SIZE);
+        let mut free_cnt = 0;
+        let mut loaded_lba = 0;
+        // 簇号从2开始
+        for cluster in 2..self.cluster_cnt + 2 {
+            let lba = self.fat_lba + cluster / entries_per_sector;
+            if loaded_lba != lba {
+                self.read_sector(lba, fat_sector);
+                loaded_lba = lba;
+            }
+            let sector_off = ((cluster % entries_per_sector) * entry_size) as usize;
+            let entry = match self.fat_type {
+                FatType::Fat16 => self::read_u16(fat_sector, sector_off) as u32,
+                FatType::Fat32 => self::read_u32(fat_sector, sector_off) & FAT32_ENTRY_MASK,
+            };
+            if entry == 0 {
+                free_cnt += 1;
+            }
+        }
+        memory::sys_free(fat_sector.as_ptr() as usize);
+        free_cnt
+    }
+
+    /**
+     * 节点的第n个簇（从0开始）的簇号。超过簇链的长度，返回None
+     */
+    #[inline(never)]
+    fn nth_cluster(&self, node: &mut FatNode, n: u32, fat_sector: &mut [u8; constants::DISK_SECTOR_SIZE]) -> Option<u32> {
+        if !self.is_valid_cluster(node.first_cluster) {
+            return Option::None;
+        }
+        // 从上一次访问的簇开始找
+        let (mut idx, mut cluster) = if node.hint_cluster != 0 && node.hint_idx <= n {
+            (node.hint_idx, node.hint_cluster)
+        } else {
+            (0, node.first_cluster)
+        };
+        while idx < n {
+            cluster = self.next_cluster(cluster, fat_sector)?;
+            idx += 1;
+        }
+        node.hint_idx = idx;
+        node.hint_cluster = cluster;
+        Option::Some(cluster)
+    }
+
+    /**
+     * 从first_cluster开始的簇链的长度（簇的数量）。第一个簇不是有效的簇号，长度是0
+     */
+    #[inline(never)]
+    pub fn chain_len(&self, first_cluster: u32, fat_sector: &mut [u8; constants::DISK_SECTOR_SIZE]) -> u32 {
+        if !self.is_valid_cluster(first_cluster) {
+            return 0;
+        }
+        let mut len = 1;
+        let mut cluster = first_cluster;
+        // 簇链有环的话，最多也只有cluster_cnt个簇
+        while len < self.cluster_cnt {
+            let next = self.next_cluster(cluster, fat_sector);
+            if next.is_none() {
+                break;
+            }
+            cluster = next.unwrap();
+            len += 1;
+        }
+        len
+    }
+
+    /**
+     * 目录dir中，第idx个目录项所在的扇区
+     */
+    #[inline(never)]
+    fn entry_lba(&self, dir: &mut FatNode, idx: u32, fat_sector: &mut [u8; constants::DISK_SECTOR_SIZE]) -> Option<u32> {
+        // FAT16的根目录，在单独的根目录区中
+        if dir.first_cluster == 0 {
+            if idx >= self.root_ent_cnt {
+                return Option::None;
+            }
+            return Option::Some(self.root_dir_lba + idx / ENTRIES_PER_SECTOR);
+        }
+        let entries_per_cluster = ENTRIES_PER_SECTOR * self.sec_per_clus;
+        let cluster = self.nth_cluster(dir, idx / entries_per_cluster, fat_sector)?;
+        Option::Some(self.cluster_lba(cluster) + (idx % entries_per_cluster) / ENTRIES_PER_SECTOR)
+    }
+
+    /**
+     * 从目录dir的第idx个目录项开始，找到下一个文件或者目录（跳过已删除的目录项、卷标，拼接长文件名）
+     * 返回(短文件名目录项, 目录项的位置, 下一个目录项的下标)，名称放在scan中
+     */
+    #[inline(never)]
+    fn next_entry(&self, dir: &mut FatNode, idx: u32, scan: &mut DirScan) -> Option<(FatDirEntry, u32, u32)> {
+        scan.reset_lfn();
+        let mut idx = idx;
+        let mut raw = [0u8; DIR_ENTRY_SIZE];
+        loop {
+            let lba = self.entry_lba(dir, idx, &mut scan.fat_sector)?;
+            if scan.sector_lba != lba {
+                self.read_sector(lba, &mut scan.sector);
+                scan.sector_lba = lba;
+            }
+            let entry_off = (idx % ENTRIES_PER_SECTOR) as usize * DIR_ENTRY_SIZE;
+            raw.copy_from_slice(&scan.sector[entry_off..entry_off + DIR_ENTRY_SIZE]);
+            // 目录项的位置：所在扇区号 * 每个扇区的目录项数量 + 在扇区中的下标
+            let entry_pos = lba * ENTRIES_PER_SECTOR + idx % ENTRIES_PER_SECTOR;
+            idx += 1;
+
+            if raw[0] == ENTRY_END {
+                return Option::None;
+            }
+            if raw[0] == ENTRY_FREE {
+                scan.reset_lfn();
+                continue;
+            }
+            if raw[11] & ATTR_LONG_NAME_MASK == ATTR_LONG_NAME {
+                scan.push_lfn(&raw);
+                continue;
+            }
+            let entry = FatDirEntry::parse(&raw);
+            if entry.attr & ATTR_VOLUME_ID != 0 {
+                scan.reset_lfn();
+                continue;
+            }
+            scan.set_name(&entry);
+            if scan.name_len == 0 {
+                continue;
+            }
+            return Option::Some((entry, entry_pos, idx));
+        }
+    }
+
+    #[inline(never)]
+    fn open_node(&mut self, node: FatNode) -> Vnode {
+        let opened: &mut FatNode = memory::malloc_system(size_of::<FatNode>());
+        *opened = node;
+        opened.open_cnt = 1;
+        self.open_cnt += 1;
+        Vnode::new(self as *mut FatFs, opened.ino, opened.file_type, opened as *mut FatNode as usize)
+    }
+
+    fn open_root(&mut self) -> Vnode {
+        let root = FatNode::new(InodeNo::new(ROOT_INO), FileType::Directory, self.root_cluster, 0);
+        self.open_node(root)
+    }
+
+    /**
+     * 打开目录项指向的节点
+     */
+    #[inline(never)]
+    fn open_entry(&mut self, entry: &FatDirEntry, entry_pos: u32) -> Vnode {
+        let ino = self.entry_ino(entry, entry_pos);
+        if ino.get_data() == ROOT_INO {
+            return self.open_root();
+        }
+        let size = if entry.is_dir() { 0 } else { entry.size };
+        let mut node = FatNode::new(ino, entry.file_type(), self.entry_cluster(entry), size);
+        node.set_times(entry);
+        self.open_node(node)
+    }
+}
+
+impl Vfs for FatFs {
+    fn root_ino(&self) -> InodeNo {
+        InodeNo::new(ROOT_INO)
+    }
+
+    /**
+     * 目录的编号就是第一个簇。目录的时间，使用目录中.目录项的时间
+     */
+    #[inline(never)]
+    fn open_dir(&mut self, ino: InodeNo) -> Vnode {
+        let cluster = ino.get_data();
+        if cluster == ROOT_INO || !self.is_valid_cluster(cluster) {
+            return self.open_root();
+        }
+        let mut node = FatNode::new(ino, FileType::Directory, cluster, 0);
+        let scan = DirScan::new();
+        let dot = self.next_entry(&mut node, 0, scan);
+        if dot.is_some() && scan.get_name() == "." {
+            node.set_times(&dot.unwrap().0);
+        }
+        scan.free();
+        self.open_node(node)
+    }
+
+    /**
+     * FAT的文件名不区分大小写
+     */
+    #[inline(never)]
+    fn lookup(&mut self, dir: &Vnode, name: &str) -> Option<Vnode> {
+        // 根目录中没有.和..目录项
+        if name == "." || (name == ".." && dir.ino.get_data() == ROOT_INO) {
+            self.reopen(dir);
+            return Option::Some(*dir);
+        }
+        let dir_node = fat_node(dir);
+        let scan = DirScan::new();
+        let mut idx = 0;
+        let mut found = Option::None;
+        loop {
+            let entry = self.next_entry(dir_node, idx, scan);
+            if entry.is_none() {
+                break;
+            }
+            let (entry, entry_pos, next_idx) = entry.unwrap();
+            if scan.get_name().eq_ignore_ascii_case(name) {
+                found = Option::Some((entry, entry_pos));
+                break;
+            }
+            idx = next_idx;
+        }
+        scan.free();
+        let (entry, entry_pos) = found?;
+        Option::Some(self.open_entry(&entry, entry_pos))
+    }
+
+    fn create(&mut self, _dir: &Vnode, _name: &str, _file_type: FileType) -> Result<Vnode, FileError> {
+        Result::Err(FileError::ReadOnly)
+    }
+
+    /**
+     * 按照簇链，一个扇区一个扇区地读取
+     */
+    #[inline(never)]
+    fn read(&mut self, node: &Vnode, off: u32, buff: &mut [u8]) -> usize {
+        let file_node = fat_node(node);
+        if file_node.is_dir() {
+            return 0;
+        }
+        let sector_size = constants::DISK_SECTOR_SIZE;
+        let cluster_size = self.cluster_size() as usize;
+        let total_len = buff.len().min((file_node.size as usize).saturating_sub(off as usize));
+        let sector_buf: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(sector_size);
+        let fat_sector: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(sector_size);
+        let mut done_len = 0;
+        while done_len < total_len {
+            let cur_off = off as usize + done_len;
+            let cluster = self.nth_cluster(file_node, (cur_off / cluster_size) as u32, fat_sector);
+            // 簇链比文件的大小短，文件系统损坏了
+            if cluster.is_none() {
+                break;
+            }
+            let lba = self.cluster_lba(cluster.unwrap()) + ((cur_off % cluster_size) / sector_size) as u32;
+            let sector_off = cur_off % sector_size;
+            let copy_len = (sector_size - sector_off).min(total_len - done_len);
+            self.read_sector(lba, sector_buf);
+            buff[done_len..done_len + copy_len].copy_from_slice(&sector_buf[sector_off..sector_off + copy_len]);
+            done_len += copy_len;
+        }
+        memory::sys_free(sector_buf.as_ptr() as usize);
+        memory::sys_free(fat_sector.as_ptr() as usize);
+        done_len
+    }
+
+    fn write(&mut self, _node: &Vnode, _off: u32, _buff: &[u8]) -> Result<usize, FileError> {
+        Result::Err(FileError::ReadOnly)
+    }
+
+    /**
+     * 目录项的偏移量，就是目录项的下标
+     *  - 根目录中没有.和..目录项，偏移量0、1是.和..，之后是根目录中的目录项
+     */
+    #[inline(never)]
+    fn readdir(&mut self, dir: &Vnode, off: u32) -> Option<(DirEntry, u32)> {
+        let is_root = dir.ino.get_data() == ROOT_INO;
+        if is_root && off < 2 {
+            let name = if off == 0 { "." } else { ".." };
+            return Option::Some((DirEntry::new(InodeNo::new(ROOT_INO), name, FileType::Directory), off + 1));
+        }
+        let base = if is_root { 2 } else { 0 };
+        let scan = DirScan::new();
+        let res = self.next_entry(fat_node(dir), off - base, scan)
+            .map(|(entry, entry_pos, next_idx)| (DirEntry::new(self.entry_ino(&entry, entry_pos), scan.get_name(), entry.file_type()), next_idx + base));
+        scan.free();
+        res
+    }
+
+    fn unlink(&mut self, _dir: &Vnode, _name: &str) -> Result<(), FileError> {
+        Result::Err(FileError::ReadOnly)
+    }
+
+    /**
+     * 目录的目录项中没有大小，按照簇链的长度计算（FAT16的根目录，是根目录区的大小）
+     */
+    #[inline(never)]
+    fn stat(&mut self, node: &Vnode) -> FileStat {
+        let file_node = fat_node(node);
+        let size = if !file_node.is_dir() {
+            file_node.size
+        } else if file_node.first_cluster == 0 {
+            self.root_ent_cnt * DIR_ENTRY_SIZE as u32
+        } else {
+            let fat_sector: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(constants::DISK_SECTOR_SIZE);
+            let len = self.chain_len(file_node.first_cluster, fat_sector);
+            memory::sys_free(fat_sector.as_ptr() as usize);
+            len * self.cluster_size()
+        };
+        // 占用的扇区数量，按照整个簇计算
+        let cluster_size = self.cluster_size();
+        let blocks = size.div_ceil(cluster_size) * self.sec_per_clus;
+        let perm = if file_node.is_dir() { 0o555 } else { 0o444 };
+        FileStat {
+            i_no: file_node.ino.get_data(),
+            mode: ((file_node.file_type.to_mode() & constant::INODE_MODE_TYPE_MASK) | perm) as u32,
+            nlink: if file_node.is_dir() { 2 } else { 1 },
+            uid: 0,
+            gid: 0,
+            size,
+            blocks,
+            flags: if file_node.has_time { FileStat::FLAG_HAS_TIME } else { 0 },
+            ctime: file_node.ctime,
+            mtime: file_node.mtime,
+            atime: file_node.atime,
+        }
+    }
+
+    fn reopen(&mut self, node: &Vnode) {
+        fat_node(node).open_cnt += 1;
+        self.open_cnt += 1;
+    }
+
+    #[inline(never)]
+    fn close(&mut self, node: &Vnode) {
+        let file_node = fat_node(node);
+        file_node.open_cnt -= 1;
+        self.open_cnt -= 1;
+        if file_node.open_cnt == 0 {
+            memory::free_system(file_node as *const FatNode);
+        }
+    }
+
+    fn rename(&mut self, _old_dir: &Vnode, _old_name: &str, _new_dir: &Vnode, _new_name: &str) -> Result<(), FileError> {
+        Result::Err(FileError::ReadOnly)
+    }
+
+    fn link(&mut self, _node: &Vnode, _dir: &Vnode, _name: &str) -> Result<(), FileError> {
+        Result::Err(FileError::ReadOnly)
+    }
+
+    /**
+     * 一个块就是一个簇。FAT没有inode，inode的数量都是0
+     */
+    #[inline(never)]
+    fn statfs(&mut self) -> FsStat {
+        let free_clusters = self.count_free_clusters();
+        FsStat {
+            block_size: self.cluster_size(),
+            total_blocks: self.cluster_cnt,
+            free_blocks: free_clusters,
+            avail_blocks: free_clusters,
+            total_inodes: 0,
+            free_inodes: 0,
+        }
+    }
+
+    fn is_busy(&mut self) -> bool {
+        self.open_cnt > 0
+    }
+
+    /**
+     * 只读的文件系统，没有需要写回的数据。释放文件系统自己
+     */
+    #[inline(never)]
+    fn unmount(&mut self) {
+        memory::free_system(self as *const FatFs);
+    }
+}
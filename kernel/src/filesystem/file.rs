@@ -1,12 +1,12 @@
 
-use core::{fmt::Display, mem::size_of};
+use core::{fmt::Display, mem::size_of, slice};
 
 use os_in_rust_common::{constants, printkln, utils, ASSERT};
 
 
-use crate::{console_println, memory, thread};
+use crate::{console_println, memory, thread, time};
 use super::{
-    constant, dir_entry::{self, DirEntrySearchReq}, file_descriptor::FileDescriptor, file_util, fs::{self, FileSystem}, global_file_table, inode::{self, OpenedInode}, DirEntry, FileType
+    buffer_cache, constant, dir_entry::{self, DirEntrySearchReq}, file_descriptor::FileDescriptor, file_util, fs::FileSystem, global_file_table, inode::{self, OpenedInode}, journal, vfs::{self, Vnode}, DirEntry, FileType
 };
 
 /**
@@ -14,9 +14,9 @@ use super::{
  */
 pub struct OpenedFile {
     /**
-     * 这个打开的文件，底层指向的inode节点
+     * 这个打开的文件，底层指向的节点
      */
-    inode: &'static mut OpenedInode,
+    node: Vnode,
     /**
      * 操作的文件的偏移量（单位字节）
      */
@@ -24,11 +24,12 @@ pub struct OpenedFile {
 }
 
 impl OpenedFile {
-    pub fn new(inode: &'static mut OpenedInode, append: bool) -> Self {
-        let file_size = inode.i_size;
+    #[inline(never)]
+    pub fn new(node: Vnode, append: bool) -> Self {
+        let file_size = if append { node.get_fs().stat(&node).size } else { 0 };
         Self {
-            inode,
-            file_off: if append {file_size} else {0},
+            node,
+            file_off: file_size,
         }
     }
 
@@ -41,24 +42,43 @@ impl OpenedFile {
      * 该文件再次打开
      */
     pub fn reopen(&mut self) {
-        self.inode.reopen();
+        self.node.get_fs().reopen(&self.node);
     }
     
     /**
      * 关闭某个文件
      */
-    pub fn close_file(&mut self, fs: &mut FileSystem) {
-        inode::inode_close(fs, self.inode);
+    pub fn close_file(&mut self) {
+        self.node.close();
     }
 
-    pub fn get_inode(&self) -> &OpenedInode {
-        self.inode
+    pub fn get_node(&self) -> &Vnode {
+        &self.node
     }
 
+    /**
+     * 从文件的偏移量处读取数据，偏移量往后走
+     */
+    #[inline(never)]
+    pub fn read(&mut self, buff: &mut [u8]) -> usize {
+        let bytes_read = self.node.get_fs().read(&self.node, self.file_off, buff);
+        self.file_off += bytes_read as u32;
+        bytes_read
+    }
+
+    /**
+     * 把数据写入到文件的偏移量处，偏移量往后走
+     */
+    #[inline(never)]
+    pub fn write(&mut self, buff: &[u8]) -> Result<usize, FileError> {
+        let bytes_written = self.node.get_fs().write(&self.node, self.file_off, buff)?;
+        self.file_off += bytes_written as u32;
+        Result::Ok(bytes_written)
+    }
 }
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum FileError {
     // 文件路径非法
     FilePathIllegal,
@@ -86,7 +106,81 @@ pub enum FileError {
     BadDescriptor,
 
     // 无法删除一个打开中的文件
-    CouldNotRemoveAnOpenedFile
+    CouldNotRemoveAnOpenedFile,
+
+    // 无法把目录移动到它自己的子目录中
+    MoveIntoSubDirectory,
+
+    // 不是一个符号链接
+    NotASymlink,
+
+    // 不能跨文件系统（挂载点）重命名或者创建硬链接
+    CrossDevice,
+
+    // 目录上挂载了文件系统，无法操作
+    MountPointBusy,
+
+    // 目录不是空的，无法删除
+    DirectoryNotEmpty,
+
+    // 文件系统不支持这个操作
+    Unsupported,
+
+    // 文件系统没有剩余空间了
+    NoSpace,
+
+    // 文件系统是只读的，无法修改
+    ReadOnly,
+
+    // 一个操作修改的元数据，日志事务放不下
+    TransactionTooLarge,
+}
+
+impl FileError {
+    /**
+     * 所有的错误，按照声明的顺序
+     */
+    const ALL: [FileError; 22] = [
+        FileError::FilePathIllegal,
+        FileError::AlreadyExists,
+        FileError::ParentDirNotExists,
+        FileError::NotFound,
+        FileError::Uncategorized,
+        FileError::IsADirectory,
+        FileError::FileExceedTask,
+        FileError::FileExceedSystem,
+        FileError::PermissionDenied,
+        FileError::FileDescriptorNotFound,
+        FileError::GlobalFileStructureNotFound,
+        FileError::BadDescriptor,
+        FileError::CouldNotRemoveAnOpenedFile,
+        FileError::MoveIntoSubDirectory,
+        FileError::NotASymlink,
+        FileError::CrossDevice,
+        FileError::MountPointBusy,
+        FileError::DirectoryNotEmpty,
+        FileError::Unsupported,
+        FileError::NoSpace,
+        FileError::ReadOnly,
+        FileError::TransactionTooLarge,
+    ];
+
+    /**
+     * 系统调用通过返回值传递错误时的错误码。0表示成功，错误码 = 错误的序号 + 1
+     */
+    pub fn to_code(&self) -> u32 {
+        *self as u32 + 1
+    }
+
+    /**
+     * 根据系统调用返回的错误码，得到错误。0（成功）或者不认识的错误码，返回None
+     */
+    pub fn from_code(code: u32) -> Option<Self> {
+        if code == 0 {
+            return Option::None;
+        }
+        Self::ALL.get(code as usize - 1).copied()
+    }
 }
 
 // pub fn close_file()
@@ -94,26 +188,17 @@ pub enum FileError {
 
 #[inline(never)]
 pub fn open_file(file_path: &str, append: bool) -> Result<FileDescriptor, FileError>{
-    if !file_path.starts_with("/") {
+    if !file_path.starts_with("/") || !file_util::is_path_legal(file_path) {
         return Result::Err(FileError::FilePathIllegal);
     }
-    let fs = fs::get_filesystem();
-
-
     // 搜索到这个文件
-    let searched_file = dir_entry::search_dir_entry(fs, file_path);
-    if searched_file.is_none() {
+    let file_node = vfs::lookup_path(file_path, true);
+    if file_node.is_none() {
         return Result::Err(FileError::NotFound);
     }
-    let (_, file_inode) = searched_file.unwrap();
 
-    // // 如果打开的是一个文件夹，不允许打开
-    // if entry.file_type as FileType == FileType::Directory {
-    //     return Result::Err(FileError::IsADirectory);
-    // }
-    
     // 得到一个打开文件
-    let opened_file = OpenedFile::new(file_inode, append);
+    let opened_file = OpenedFile::new(file_node.unwrap(), append);
 
     // 把这个文件注册到 「系统文件结构数组中」
     let global_file_idx = global_file_table::register_file(opened_file);
@@ -138,38 +223,20 @@ pub fn open_file(file_path: &str, append: bool) -> Result<FileDescriptor, FileEr
  */
 #[inline(never)]
 pub fn create_file(file_path: &str) -> Result<FileDescriptor, FileError> {
-    let fs = fs::get_filesystem();
     // 斜杠结尾的，是目录，不是文件
     if file_path.ends_with("/") {
         return Result::Err(FileError::IsADirectory);
     }
 
-    // 把文件路径，分为父目录路径和文件名称
-    let split_result = file_util::split_file_path(file_path);
-    if split_result.is_none() {
-        return Result::Err(FileError::FilePathIllegal);
-    }
-    let (dir_path, file_name) = split_result.unwrap();
-
-    // 先搜索一下，目录是否存在
-    let search_dir = dir_entry::search_dir_entry(fs, dir_path);
-    if search_dir.is_none() {
-        return Result::Err(FileError::ParentDirNotExists);
-    }
-    // 在搜索一下这个文件是否存在
-    let dir_inode = search_dir.unwrap().1;
-    let search_entry = dir_entry::do_search_dir_entry(fs, dir_inode, DirEntrySearchReq::build().entry_name(file_name));
-    if search_entry.is_some() {
-        inode::inode_close(fs, dir_inode);
-        return Result::Err(FileError::AlreadyExists);
-    }
+    // 把文件路径，分为父目录路径和文件名称，打开父目录
+    let (dir_node, file_name) = vfs::lookup_parent(file_path)?;
 
-    let opened_inode = dir_entry::create_dir_entry(fs, dir_inode, file_name, FileType::Regular);
-    inode::inode_close(fs, dir_inode);
+    // 在父目录下创建这个文件（已经存在的话报错）
+    let created = dir_node.get_fs().create(&dir_node, file_name, FileType::Regular);
+    dir_node.close();
     
     // 得到一个打开文件
-    let opened_inode = inode::inode_open(fs, opened_inode);
-    let opened_file = OpenedFile::new(opened_inode, false);
+    let opened_file = OpenedFile::new(created?, false);
 
     // 把这个文件注册到 「系统文件结构数组中」
     let global_file_idx = global_file_table::register_file(opened_file);
@@ -204,104 +271,118 @@ pub fn create_file(file_path: &str) -> Result<FileDescriptor, FileError> {
            |      |       |
    扇区开始的字节   |     对于buf数组，写入后面扇区的开始数据
                   |
-           file.file_off
+           file_off
            要写入硬盘的起始数据
 */
 #[inline(never)]
-pub fn write_file(fs: &mut FileSystem, file: &mut OpenedFile, buff: &[u8]) -> usize {
+pub fn write_file(fs: &mut FileSystem, inode: &mut OpenedInode, file_off: u32, buff: &[u8]) -> Result<usize, FileError> {
+    // 申请数据块、修改inode，放在一个日志事务中
+    let _trans = journal::begin(fs, constant::JOURNAL_WRITE_BLOCK_CREDITS)?;
 
     let disk = unsafe { &mut *fs.base_part.from_disk };
+    // 按照块读写，一个块包含sec_per_block个扇区
+    let block_size = fs.super_block.get_block_size();
+    let sec_per_block = fs.super_block.sec_per_block();
 
-    let start_data_block_idx = file.file_off as usize / constants::DISK_SECTOR_SIZE;
+    let start_data_block_idx = file_off as usize / block_size;
     // 要写入到文件的最后一个字节，所在该inode数据扇区的下标
-    let end_data_block_idx = (file.file_off as usize - 1 + buff.len()) / constants::DISK_SECTOR_SIZE;
-    // 如果涉及到间接块，需要申请一个间接块
-    if end_data_block_idx >= file.inode.get_direct_data_blocks_ref().len() {
-        inode::apply_indirect_data_block(fs, file.inode);
+    let end_data_block_idx = (file_off as usize - 1 + buff.len()) / block_size;
+    // 如果涉及到间接块，需要先加载间接块的数据（间接块不存在的话，写入的时候再申请）
+    if end_data_block_idx >= inode.get_direct_data_blocks_ref().len() {
+        inode::load_indirect_data_block(fs, inode);
     }
 
     // 要操作的文件偏移量，超过1个扇区的字节数
-    let start_bytes_over_sector = file.file_off as usize % constants::DISK_SECTOR_SIZE;
+    let start_bytes_over_sector = file_off as usize % block_size;
     // 要操作的文件偏移量，在一个扇区中剩余的字节数
-    let start_bytes_left_sector = constants::DISK_SECTOR_SIZE - start_bytes_over_sector;
+    let start_bytes_left_sector = block_size - start_bytes_over_sector;
 
     // 要写入的最后一个字节，超过整扇区的部分（字节数）
-    let end_bytes_over_sector = (file.file_off as usize + buff.len()) % constants::DISK_SECTOR_SIZE;
+    let end_bytes_over_sector = (file_off as usize + buff.len()) % block_size;
 
-    // 申请单个扇区大小的缓冲区，用于循环读取扇区的数据
-    let single_sector_buffer: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(constants::DISK_SECTOR_SIZE);
+    // 申请一个块大小的缓冲区，用于循环读取块的数据
+    let block_buffer = unsafe { slice::from_raw_parts_mut(memory::sys_malloc(block_size) as *mut u8, block_size) };
     let mut succeed_bytes = 0usize;
 
     // 遍历所有的数据块扇区
     for block_idx in start_data_block_idx..=end_data_block_idx {
-        // 相对的块下标。从file.file_off所在的块开始，下标为0
+        // 相对的块下标。从file_off所在的块开始，下标为0
         let relative_block_idx = block_idx - start_data_block_idx;
         // 把缓冲区清空
-        unsafe { single_sector_buffer.as_mut_ptr().write_bytes(0, single_sector_buffer.len()) };
+        unsafe { block_buffer.as_mut_ptr().write_bytes(0, block_buffer.len()) };
 
         // 本次循环写入的字节数量
-        let mut bytes_written = constants::DISK_SECTOR_SIZE;
-        // 要写入的数据扇区的LBA地址
-        let data_block_lba = &mut file.inode.get_data_blocks()[block_idx];
-        let mut new_data_block = false;
-        // 如果这个数据扇区没有填充过，那么需要申请一个数据块
-        if data_block_lba.is_empty() {
-            *data_block_lba = fs.data_block_pool.apply_block(1);
-            new_data_block = true;
+        let mut bytes_written = block_size;
+        // 日志放不下申请一个数据块的元数据了。先把已经写入的部分（文件大小）同步并且提交，再继续写
+        if !journal::has_room(fs, constant::JOURNAL_WRITE_BLOCK_CREDITS) {
+            inode.i_size = inode.i_size.max(file_off + succeed_bytes as u32);
+            inode::sync_inode(fs, inode);
+            journal::flush(fs);
+        }
+        // 要写入的数据扇区的LBA地址。如果这个数据扇区没有填充过，那么需要申请一个数据块
+        let data_block = inode::apply_data_block(fs, inode, block_idx);
+        // 超过了单个文件的最大大小，或者硬盘满了，写不下了
+        if data_block.is_none() {
+            break;
         }
+        let (data_block_lba, new_data_block) = data_block.unwrap();
+        let data_block_lba = &data_block_lba;
 
         // 如果是第一个扇区，并且开始写入的字节开始偏移量不是整扇区
         if relative_block_idx == 0 && start_bytes_over_sector > 0 {
             if !new_data_block {
-                // 读取出这个扇区
-                disk.read_sectors(*data_block_lba, 1, single_sector_buffer);
+                // 读取出这个块
+                buffer_cache::read_sectors(disk, *data_block_lba, sec_per_block, block_buffer);
             }
             // 写入的字节数量 = 当前扇区剩余的数量和缓冲区长度的最小值
             bytes_written = start_bytes_left_sector.min(buff.len());
-            // 把缓冲区single_sector_buffer的后半部分，使用要写入的数据buff覆盖
-            single_sector_buffer[start_bytes_over_sector..start_bytes_over_sector + bytes_written].copy_from_slice(&buff[..bytes_written]);
+            // 把缓冲区block_buffer的后半部分，使用要写入的数据buff覆盖
+            block_buffer[start_bytes_over_sector..start_bytes_over_sector + bytes_written].copy_from_slice(&buff[..bytes_written]);
 
         // 如果是操作最后一个扇区，并且写入的字节结束偏移量不是整扇区
         } else if block_idx == end_data_block_idx && end_bytes_over_sector > 0 {
             if !new_data_block {
-                // 读取出这个扇区
-                disk.read_sectors(*data_block_lba, 1, single_sector_buffer);
+                // 读取出这个块
+                buffer_cache::read_sectors(disk, *data_block_lba, sec_per_block, block_buffer);
             }
             bytes_written = end_bytes_over_sector;
             // 如果这是最后一个扇区，同时也是第一个扇区
             let sector_start_idx = if block_idx == start_data_block_idx { start_bytes_over_sector } else {0};
             // 缓冲区要操作开始的字节下标
-            let buf_start_idx = (constants::DISK_SECTOR_SIZE * relative_block_idx).overflowing_sub(start_bytes_over_sector);
+            let buf_start_idx = (block_size * relative_block_idx).overflowing_sub(start_bytes_over_sector);
             // 如果溢出了，那开始字节偏移就是0
             let buf_start_idx = if buf_start_idx.1 { 0 } else {buf_start_idx.0};
             
-            single_sector_buffer[sector_start_idx..end_bytes_over_sector].copy_from_slice(&buff[buf_start_idx..buf_start_idx + end_bytes_over_sector]);
+            block_buffer[sector_start_idx..end_bytes_over_sector].copy_from_slice(&buff[buf_start_idx..buf_start_idx + end_bytes_over_sector]);
 
         // 不是第一个扇区，也不是最后一个扇区，那就是中间连续的扇区，直接复制就好
         } else {
             // 缓冲区开始的字节偏移。
-            let buf_start_byte_idx = start_bytes_left_sector as isize + constants::DISK_SECTOR_SIZE as isize * (relative_block_idx as isize - 1);
+            let buf_start_byte_idx = start_bytes_left_sector as isize + block_size as isize * (relative_block_idx as isize - 1);
             ASSERT!(buf_start_byte_idx >= 0);
             let buf_start_byte_idx = buf_start_byte_idx as usize;
             // 缓冲区结束的字节偏移
-            let buf_end_byte_idx = start_bytes_left_sector + constants::DISK_SECTOR_SIZE * relative_block_idx;
-            single_sector_buffer.copy_from_slice(&buff[buf_start_byte_idx .. buf_end_byte_idx]);
-            bytes_written = single_sector_buffer.len();
+            let buf_end_byte_idx = start_bytes_left_sector + block_size * relative_block_idx;
+            block_buffer.copy_from_slice(&buff[buf_start_byte_idx .. buf_end_byte_idx]);
+            bytes_written = block_buffer.len();
         }
-        disk.write_sector(single_sector_buffer, *data_block_lba, 1);
+        buffer_cache::write_sector(disk, block_buffer, *data_block_lba, sec_per_block);
         succeed_bytes += bytes_written;
     }
     // 释放缓冲区
-    memory::sys_free(single_sector_buffer.as_ptr() as usize);
-    // 该文件操作的偏移量增加
-    file.file_off += succeed_bytes as u32;
-
+    memory::sys_free(block_buffer.as_ptr() as usize);
     // 当前文件的数据大小发生变化
-    file.inode.i_size = file.inode.i_size.max(file.file_off);
+    inode.i_size = inode.i_size.max(file_off + succeed_bytes as u32);
+    // 文件的修改时间
+    inode.i_mtime = time::get_current_timestamp();
     // 把inode元数据同步到硬盘（inode数组）
-    inode::sync_inode(fs, file.inode);
+    inode::sync_inode(fs, inode);
 
-    return succeed_bytes;
+    // 一个字节都没有写入，说明没有空间了
+    if succeed_bytes == 0 && !buff.is_empty() {
+        return Result::Err(FileError::NoSpace);
+    }
+    return Result::Ok(succeed_bytes);
 }
 
 /**
@@ -320,39 +401,43 @@ pub fn write_file(fs: &mut FileSystem, file: &mut OpenedFile, buff: &[u8]) -> us
            |      |       |
    扇区开始的字节   |     对于buf数组，写入后面扇区的开始数据
                   |
-        file.file_off % constants::DISK_SECTOR_SIZE
+        file_off % block_size
            要写入硬盘的起始数据
 */
 #[inline(never)]
-pub fn read_file(fs: &mut FileSystem, file: &mut OpenedFile, buff: &mut [u8]) -> usize {
+pub fn read_file(fs: &mut FileSystem, inode: &mut OpenedInode, file_off: u32, buff: &mut [u8]) -> usize {
 
     // // 最多读取到文件的末尾
-    // let end_byte_off_file = (file.file_off as usize + buff.len()).min(file.inode.i_size as usize);
+    // let end_byte_off_file = (file_off as usize + buff.len()).min(inode.i_size as usize);
 
     let disk = unsafe { &mut *fs.base_part.from_disk };
+    // 按照块读写，一个块包含sec_per_block个扇区
+    let block_size = fs.super_block.get_block_size();
+    let sec_per_block = fs.super_block.sec_per_block();
 
-    let start_data_block_idx = file.file_off as usize / constants::DISK_SECTOR_SIZE;
+    let start_data_block_idx = file_off as usize / block_size;
     // 要写入到文件的最后一个字节，所在该inode数据扇区的下标
-    let end_data_block_idx = (file.file_off as usize - 1 + buff.len()) / constants::DISK_SECTOR_SIZE;
+    let end_data_block_idx = (file_off as usize - 1 + buff.len()) / block_size;
     // 如果涉及到间接块，那么需要加载间接块的数据
-    if end_data_block_idx >= file.inode.get_direct_data_blocks_ref().len() {
-        inode::load_indirect_data_block(fs, file.inode);
+    if end_data_block_idx >= inode.get_direct_data_blocks_ref().len() {
+        inode::load_indirect_data_block(fs, inode);
     }
 
     // 要操作的文件开始的字节，距离所在扇区开头的偏移量
-    let start_bytes_over_sector = file.file_off as usize % constants::DISK_SECTOR_SIZE;
+    let start_bytes_over_sector = file_off as usize % block_size;
     // 要操作的文件开始的字节，距离所在扇区结束的偏移量
-    let start_bytes_away_sector = constants::DISK_SECTOR_SIZE - start_bytes_over_sector;
+    let start_bytes_away_sector = block_size - start_bytes_over_sector;
 
     // 要写入的最后一个字节，超过整扇区的部分（字节数）
-    let end_bytes_over_sector = (file.file_off as usize + buff.len()).min(file.inode.i_size as usize) % constants::DISK_SECTOR_SIZE;
+    let end_bytes_over_sector = (file_off as usize + buff.len()).min(inode.i_size as usize) % block_size;
 
-    // 申请单个扇区大小的缓冲区，用于循环读取扇区的数据
-    let single_sector_buffer: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(constants::DISK_SECTOR_SIZE);
+    // 申请一个块大小的缓冲区，用于循环读取块的数据
+    let block_buffer = unsafe { slice::from_raw_parts_mut(memory::sys_malloc(block_size) as *mut u8, block_size) };
     let mut succeed_bytes = 0usize;
     // 剩余要读取的字节数量
-    let mut left_bytes = file.inode.i_size as i32 - file.file_off as i32;
+    let mut left_bytes = inode.i_size as i32 - file_off as i32;
     if left_bytes <= 0 {
+        memory::sys_free(block_buffer.as_ptr() as usize);
         return 0;
     }
 
@@ -361,12 +446,12 @@ pub fn read_file(fs: &mut FileSystem, file: &mut OpenedFile, buff: &mut [u8]) ->
         // file.bytes_off所在的扇区，为相对扇区。从0开始
         let relative_block_idx = block_idx - start_data_block_idx;
         // 把缓冲区清空
-        unsafe { single_sector_buffer.as_mut_ptr().write_bytes(0, single_sector_buffer.len()) };
+        unsafe { block_buffer.as_mut_ptr().write_bytes(0, block_buffer.len()) };
         
         // 本次循环读取到的字节
         let mut bytes_read = 0; 
-        // 要写入的数据扇区的LBA地址
-        let data_block_lba = &mut file.inode.get_data_blocks()[block_idx];
+        // 要读取的数据扇区的LBA地址
+        let data_block_lba = &inode::get_data_block(fs, inode, block_idx);
 
         // 没有字节可以读取了
         if left_bytes <= 0 {
@@ -376,64 +461,73 @@ pub fn read_file(fs: &mut FileSystem, file: &mut OpenedFile, buff: &mut [u8]) ->
         if data_block_lba.is_empty() {
             continue;
         }
-        // 读取出这个扇区
-        disk.read_sectors(*data_block_lba, 1, single_sector_buffer);
+        // 读取出这个块
+        buffer_cache::read_sectors(disk, *data_block_lba, sec_per_block, block_buffer);
 
         // 如果是第一个扇区，并且开始写入的字节开始偏移量不是整扇区
         if relative_block_idx == 0 && start_bytes_over_sector > 0 {
             bytes_read = start_bytes_away_sector.min(buff.len());
             // buff 的前半部分，使用读取到的扇区的后半部分代替
-            buff[..bytes_read].copy_from_slice(&single_sector_buffer[start_bytes_over_sector..start_bytes_over_sector + bytes_read]);
+            buff[..bytes_read].copy_from_slice(&block_buffer[start_bytes_over_sector..start_bytes_over_sector + bytes_read]);
         
         // 如果是操作最后一个扇区，并且写入的字节结束偏移量不是整扇区，并且不是第一个扇区
         } else if block_idx == end_data_block_idx && end_bytes_over_sector > 0 {
             // 如果这是最后一个扇区，同时也是第一个扇区
             let sector_start_idx = if block_idx == start_data_block_idx { start_bytes_over_sector } else {0};
             // 缓冲区要操作开始的字节下标
-            let buf_start_idx = (constants::DISK_SECTOR_SIZE * relative_block_idx).overflowing_sub(start_bytes_over_sector);
+            let buf_start_idx = (block_size * relative_block_idx).overflowing_sub(start_bytes_over_sector);
             // 如果溢出了，那开始字节偏移就是0
             let buf_start_idx = if buf_start_idx.1 { 0 } else {buf_start_idx.0};
             // buff后半部分，使用读取到的扇区的前半部分代替
-            buff[buf_start_idx..buf_start_idx+end_bytes_over_sector].copy_from_slice(&single_sector_buffer[sector_start_idx..end_bytes_over_sector]);
+            buff[buf_start_idx..buf_start_idx+end_bytes_over_sector].copy_from_slice(&block_buffer[sector_start_idx..end_bytes_over_sector]);
             bytes_read = end_bytes_over_sector;
         
         // 不是第一个扇区，也不是最后一个扇区，那就是中间连续的扇区，直接复制就好
         } else {
             // 要操作的buff开始的字节偏移
-            let buf_start_byte_idx = start_bytes_away_sector as isize + (relative_block_idx as isize - 1)  * constants::DISK_SECTOR_SIZE as isize;
+            let buf_start_byte_idx = start_bytes_away_sector as isize + (relative_block_idx as isize - 1)  * block_size as isize;
             // 要操作的buf结束的字节偏移
-            let buf_end_byte_idx = start_bytes_away_sector + relative_block_idx * constants::DISK_SECTOR_SIZE;
-            buff[buf_start_byte_idx as usize .. buf_end_byte_idx].copy_from_slice(single_sector_buffer);
-            bytes_read = single_sector_buffer.len();
+            let buf_end_byte_idx = start_bytes_away_sector + relative_block_idx * block_size;
+            buff[buf_start_byte_idx as usize .. buf_end_byte_idx].copy_from_slice(block_buffer);
+            bytes_read = block_buffer.len();
         }
         succeed_bytes += bytes_read;
         left_bytes -= bytes_read as i32;
-        file.file_off += bytes_read as u32;
+    }
+    memory::sys_free(block_buffer.as_ptr() as usize);
+
+    // 更新文件的访问时间。时间戳精度是秒，同一秒内多次读取，只需要同步一次
+    let now = time::get_current_timestamp();
+    if succeed_bytes > 0 && inode.i_atime != now {
+        inode.i_atime = now;
+        inode::sync_inode(fs, inode);
     }
     succeed_bytes
 }
 
 
 /**
- * 删除一个文件
- *   1. 删除这个文件的数据内容（inode）
- *   2. 删除这个文件所在父目录的目录项
+ * 删除一个文件（的一个链接）
+ *   1. 删除这个文件所在父目录的目录项
+ *   2. 链接数量 - 1。如果没有链接了，标记为已删除，等到最后一个打开者关闭的时候，再释放inode和数据块
  */
 #[inline(never)]
-pub fn remove_file(fs: &mut FileSystem, parent_inode: &mut OpenedInode, inode_to_remove: &OpenedInode) -> Result<(), FileError> {
-    if inode_to_remove.open_cnts > 1 {
-        return Result::Err(FileError::CouldNotRemoveAnOpenedFile);
-    }
-
-    // 1. 删除Inode
-    inode::inode_remove(fs, inode_to_remove);
-
-    // 2. 删除这个文件所在父目录的目录项
-    let delete = dir_entry::remove_dir_entry(fs, parent_inode, DirEntrySearchReq::build().i_no(inode_to_remove.i_no));
+pub fn remove_file(fs: &mut FileSystem, parent_inode: &mut OpenedInode, file_name: &str, inode_to_remove: &mut OpenedInode) -> Result<(), FileError> {
+    // 元数据的修改，放在一个日志事务中
+    let _trans = journal::begin(fs, constant::JOURNAL_UNLINK_CREDITS)?;
+    // 1. 删除这个文件所在父目录的目录项。同一个目录下可能有多个链接指向这个inode，因此要按照名称删除
+    let delete = dir_entry::remove_dir_entry(fs, parent_inode, DirEntrySearchReq::build().entry_name(file_name).i_no(inode_to_remove.i_no));
     if !delete {
         return Result::Err(FileError::NotFound);
     }
     // 父目录操作完成后，保存到硬盘
     inode::sync_inode(fs, parent_inode);
+
+    // 2. 链接数量 - 1。旧格式的inode没有链接数量（是0），当作只有1个链接
+    inode_to_remove.i_nlink = inode_to_remove.i_nlink.max(1) - 1;
+    if inode_to_remove.i_nlink == 0 {
+        inode_to_remove.unlinked = true;
+    }
+    inode::sync_inode(fs, inode_to_remove);
     return Result::Ok(());
 }
\ No newline at end of file
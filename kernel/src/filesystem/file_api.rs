@@ -2,9 +2,9 @@ use core::fmt::{Debug};
 
 use os_in_rust_common::{cstr_write, cstring_utils, printkln, ASSERT};
 
-use crate::{filesystem::{constant, file, fs}, thread};
+use crate::{filesystem::{constant, file}, thread};
 
-use super::{dir_entry::{self, DirEntrySearchReq}, file::{FileError, OpenedFile}, file_descriptor::FileDescriptor, file_util, global_file_table, inode};
+use super::{file::{FileError, OpenedFile}, file_descriptor::FileDescriptor, global_file_table, vfs};
 
 pub struct OpenOptions {
     write: bool, 
@@ -157,7 +157,7 @@ impl File {
         }
         // 3. 关闭这个文件inode
         let opend_file = opend_file.unwrap();
-        opend_file.close_file(fs::get_filesystem());
+        opend_file.close_file();
 
         // 2. 释放全局的文件结构
         global_file_table::release_file(global_idx);
@@ -206,8 +206,7 @@ impl File {
             return Result::Err(FileError::PermissionDenied);
         }
         let opened_file = global_file_table::get_file_by_fd(self.fd)?;
-        let fs = fs::get_filesystem();
-        Result::Ok(file::read_file(fs, opened_file, buff))
+        Result::Ok(opened_file.read(buff))
     }
 
     /**
@@ -221,11 +220,9 @@ impl File {
         }
         // 根据文件描述符，找到那个文件
         let opened_file = global_file_table::get_file_by_fd(self.fd)?;
-        
-        let fs = fs::get_filesystem();
 
         // 写入文件
-        Result::Ok(file::write_file(fs, opened_file, buff))
+        opened_file.write(buff)
     }
 
     pub fn get_path(&self) -> &str {
@@ -237,8 +234,9 @@ impl File {
     #[inline(never)]
     pub fn get_size(&self) -> Result<usize, FileError> {
         let opened_file =  global_file_table::get_file_by_fd(self.fd)?;
-        let opened_inode = opened_file.get_inode();
-        Result::Ok(opened_inode.i_size.try_into().unwrap())
+        let file_node = opened_file.get_node();
+        let file_stat = file_node.get_fs().stat(file_node);
+        Result::Ok(file_stat.size.try_into().unwrap())
     }
 
     pub fn get_file_descriptor(&self) -> FileDescriptor {
@@ -267,38 +265,25 @@ pub fn remove_file(path: &str) -> Result<(), FileError> {
     if path == "/" {
         return Result::Err(FileError::FilePathIllegal);
     }
-    let split_res = file_util::split_file_path(path);
-    if split_res.is_none() {
-        return Result::Err(FileError::FilePathIllegal);
-    }
-    let fs: &mut fs::FileSystem = fs::get_filesystem();
-    let (dir_path, file_name) = split_res.unwrap();
-
     // 该文件所在的父目录
-    let parent_dir = dir_entry::search_dir_entry(fs, dir_path);
-    if parent_dir.is_none() {
-        return Result::Err(FileError::ParentDirNotExists);
-    }
-    let (_, parent_dir_inode) = parent_dir.unwrap();
-    
+    let (parent_dir, file_name) = vfs::lookup_parent(path)?;
+
     // 在该父目录下，搜索该文件
-    let cur_file_entry = dir_entry::do_search_dir_entry(fs, parent_dir_inode, DirEntrySearchReq::build().entry_name(file_name));
-    if cur_file_entry.is_none() {
-        inode::inode_close(fs, parent_dir_inode);
+    let file_node = parent_dir.get_fs().lookup(&parent_dir, file_name);
+    if file_node.is_none() {
+        parent_dir.close();
         return Result::Err(FileError::NotFound);
     }
-    // 该文件的inode
-    let cur_file_entry = cur_file_entry.unwrap();
-    let cur_file_inode = inode::inode_open(fs, cur_file_entry.i_no);
-    
-    // 把这个文件的数据扇区LBA地址都加载出来（间接扇区）
-    inode::load_indirect_data_block(fs, cur_file_inode);
-    // 指定父目录，删除这个文件inode
-    file::remove_file(fs, parent_dir_inode, cur_file_inode)?;
-    
-    // 关闭inode
-    inode::inode_close(fs, parent_dir_inode);
-    inode::inode_close(fs, cur_file_inode);
-    // remove_res
-    return Result::Ok(());
-}
\ No newline at end of file
+    // 目录需要使用删除目录的方式
+    let file_node = file_node.unwrap();
+    file_node.close();
+    if file_node.is_dir() {
+        parent_dir.close();
+        return Result::Err(FileError::IsADirectory);
+    }
+
+    // 指定父目录，删除这个文件的链接。如果这是最后一个链接，并且没有其他打开者，会释放文件的数据
+    let remove_res = parent_dir.get_fs().unlink(&parent_dir, file_name);
+    parent_dir.close();
+    return remove_res;
+}
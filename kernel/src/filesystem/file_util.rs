@@ -1,9 +1,21 @@
+use super::constant;
+
+/**
+ * 路径是否合法：
+ *  - 路径的长度，不超过MAX_FILE_PATH_LEN个字节
+ *  - 每一级目录项的名称，不超过MAX_FILE_NAME个字节。超长的名称直接报错，不会截断
+ */
+pub fn is_path_legal(path: &str) -> bool {
+    path.len() <= constant::MAX_FILE_PATH_LEN && path.split("/").all(|entry_name| entry_name.len() <= constant::MAX_FILE_NAME)
+}
+
 /**
  * 把一个文件全路径，分为父目录路径和当前文件的目录项名称
+ *  - 路径不合法（参考is_path_legal），返回None
  */
 pub fn split_file_path(path: &str) -> Option<(&str, &str)> {
     let mut path = path;
-    if !path.starts_with("/") {
+    if !path.starts_with("/") || !self::is_path_legal(path) {
         return Option::None;
     }
     if path == "/" {
@@ -2,34 +2,14 @@ use core::slice;
 
 use os_in_rust_common::{bitmap::BitMap, constants, domain::{InodeNo, LbaAddr}, linked_list::{LinkedList, LinkedNodeIterator}, printkln, racy_cell::RacyCell, utils, ASSERT, MY_PANIC};
 
-use crate::device::{Disk, Partition};
+use crate::device::Partition;
 
-use super::{inode::{Inode, OpenedInode}, superblock::SuperBlock};
+use super::{inode::{Inode, OpenedInode}, journal::{self, Journal}, superblock::SuperBlock};
 
 /**
  * 文件系统。中任何操作都是基于分区的
  */
 
-/**
- * 当前的挂载的分区
- */
-static CUR_FILE_SYSTEM: RacyCell<Option<FileSystem>> = RacyCell::new(Option::None);
-
-#[inline(never)]
-pub fn set_filesystem(cur_part: FileSystem) {
-    *unsafe { CUR_FILE_SYSTEM.get_mut() } = Option::Some(cur_part);
-}
-
-#[inline(never)]
-pub fn get_filesystem() -> &'static mut FileSystem {
-    let fs = unsafe { CUR_FILE_SYSTEM.get_mut() }.as_mut();
-    if fs.is_none() {
-        MY_PANIC!("fs system not exist");
-    }
-    ASSERT!(fs.is_some());
-    fs.unwrap()
-}
-
 /**
  * 目录的结构。位于内存的逻辑结构
  */
@@ -78,6 +58,11 @@ pub struct FileSystem {
      */
     pub data_block_pool: DataBlockPool, 
 
+    /**
+     * 该分区的元数据日志（当前的事务）
+     */
+    pub journal: Journal,
+
     /**
      * 当前挂载的分区，打开的inode节点队列
      */
@@ -97,8 +82,9 @@ impl FileSystem {
             base_part: part,
             super_block: super_block,
             root_dir: Option::None,
-            inode_pool: InodePool::new(part.from_disk, super_block.inode_bitmap_lba, InodeNo::new(0), inode_bits),
-            data_block_pool: DataBlockPool::new(part.from_disk, super_block.block_bitmap_lba, super_block.data_lba_start, block_bits),
+            inode_pool: InodePool::new(super_block.inode_bitmap_lba, InodeNo::new(0), inode_bits, super_block.inode_cnt),
+            data_block_pool: DataBlockPool::new(super_block.block_bitmap_lba, super_block.data_lba_start, super_block.sec_per_block(), block_bits, super_block.data_blocks(), super_block.reserved_blocks),
+            journal: Journal::new(part.from_disk, super_block.journal_lba),
             open_inodes: LinkedList::new(),
         }
     }
@@ -106,7 +92,9 @@ impl FileSystem {
     pub fn set_root_inode(&mut self, inode: Inode) {
         // 填充根目录
         self.root_dir = Option::Some(RacyCell::new(Dir::new(OpenedInode::new(inode))));
+        let fs = self as *mut FileSystem;
         let opened_inode = unsafe { self.root_dir.as_mut().unwrap().get_mut()}.get_inode_ref();
+        opened_inode.bind_fs(fs);
         // 根目录的打开次数为1
         opened_inode.open_cnts = 1;
         self.open_inodes.append(&mut opened_inode.tag);
@@ -150,7 +138,7 @@ impl FileSystem {
 
 
     #[inline(never)]
-    pub fn iter_open_nodes<F>(&self, f: F) where F: Fn(&mut OpenedInode) {
+    pub fn iter_open_nodes<F>(&self, mut f: F) where F: FnMut(&mut OpenedInode) {
         for node_tag in self.open_inodes.iter() {
             let inode = OpenedInode::parse_by_tag(node_tag);
             f(inode);
@@ -162,7 +150,6 @@ impl FileSystem {
  * inode池。逻辑结构
  */
 pub struct InodePool {
-    disk: * mut Disk,
     /**
      * 池子位图所在硬盘自身的LBA地址
      */
@@ -175,56 +162,94 @@ pub struct InodePool {
      * inode池的位图
      */
     inode_bitmap: BitMap,
+    /**
+     * inode的总数量
+     */
+    total_inodes: u32,
+    /**
+     * 空闲的inode数量。挂载的时候扫描位图得到，申请、释放的时候更新
+     */
+    free_inodes: u32,
 }
 
 impl InodePool {
-    pub const fn new(disk: *mut Disk, self_lba: LbaAddr, start_ino: InodeNo, inode_bits: &mut [u8]) -> Self {
+    #[inline(never)]
+    pub fn new(self_lba: LbaAddr, start_ino: InodeNo, inode_bits: &mut [u8], total_inodes: u32) -> Self {
+        let inode_bitmap = BitMap::new(inode_bits);
+        let free_inodes = self::count_free_bits(&inode_bitmap, total_inodes);
         Self {
-            disk,
             self_bitmap_lba: self_lba,
             start_ino,
-            inode_bitmap: BitMap::new(inode_bits),
+            inode_bitmap,
+            total_inodes,
+            free_inodes,
         }
     }
 
+    pub fn total_inodes(&self) -> u32 {
+        self.total_inodes
+    }
+
+    pub fn free_inodes(&self) -> u32 {
+        self.free_inodes
+    }
+
+    /**
+     * inode位图所在的内存地址
+     */
+    pub fn bitmap_addr(&self) -> usize {
+        self.inode_bitmap.map_ptr as usize
+    }
+
     /**
-     * 从inode池中申请一个inode
+     * 从inode池中申请一个inode。inode都用完了，返回None
      */
     #[inline(never)]
-    pub fn apply_inode(&mut self, inodes: usize) -> InodeNo {
+    pub fn apply_inode(&mut self, journal: &mut Journal, inodes: usize) -> Option<InodeNo> {
+        if self.free_inodes == 0 {
+            return Option::None;
+        }
         let bit_res = self.inode_bitmap.apply_bits(inodes);
-        ASSERT!(bit_res.is_ok());
+        if bit_res.is_err() {
+            return Option::None;
+        }
         let bit_off = bit_res.unwrap();
         // 设置这位为占用
         self.inode_bitmap.set_bit(bit_off, true);
+        if bit_off < self.total_inodes as usize {
+            self.free_inodes -= 1;
+        }
         // 申请到的inode地址 = inode起始号 + 申请的第x个inode
         let i_no = self.start_ino.add(bit_off);
         // 申请了inode，同步到硬盘
-        self.sync_inode_pool(i_no);
-        i_no
+        self.sync_inode_pool(journal, i_no);
+        Option::Some(i_no)
     }
 
     /**
      * 释放某个inode
      */
-    pub fn release_inode(&mut self, i_no: InodeNo) {
+    pub fn release_inode(&mut self, journal: &mut Journal, i_no: InodeNo) {
         let bit_off = (i_no - self.start_ino).get_data() as usize;
+        // 已经是空闲的，不重复计数
+        if bit_off < self.total_inodes as usize && self.inode_bitmap.is_set(bit_off) {
+            self.free_inodes += 1;
+        }
         // 设置这位为不被占用
         self.inode_bitmap.set_bit(bit_off, false);
         // 把位图同步保存
-        self.sync_inode_pool(i_no);
+        self.sync_inode_pool(journal, i_no);
     }
 
     /**
      * ino号inode所在的inode位图同步到硬盘
      */
     #[inline(never)]
-    pub fn sync_inode_pool(&mut self, ino: InodeNo) {
-        let disk = unsafe { &mut *self.disk };
+    pub fn sync_inode_pool(&mut self, journal: &mut Journal, ino: InodeNo) {
         // 定位这个inode，所在扇区的LBA地址 和 扇区数据
         let (lba, bit_buf) = self.locate(ino);
-        // 把inode bitmap写入到硬盘中
-        disk.write_sector(bit_buf, lba, 1);
+        // 把inode bitmap写入到硬盘中（元数据，记录到日志中）
+        journal::write_metadata(journal, bit_buf, lba, 1);
     }
 
     /**
@@ -243,7 +268,7 @@ impl InodePool {
         // 该扇区的绝对LBA地址
         let abs_sec_idx = self.self_bitmap_lba + LbaAddr::new(sec_off.try_into().unwrap());
 
-        let bitmap_offset = unsafe { self.inode_bitmap.map_ptr.add(sec_off / constants::DISK_SECTOR_SIZE) };
+        let bitmap_offset = unsafe { self.inode_bitmap.map_ptr.add(sec_off * constants::DISK_SECTOR_SIZE) };
         // 把 inode bitmap 数据区转成数组
         let sec_data = unsafe { slice::from_raw_parts(bitmap_offset, constants::DISK_SECTOR_SIZE) };
 
@@ -256,7 +281,6 @@ impl InodePool {
  * 数据块池。逻辑结构
  */
 pub struct DataBlockPool {
-    disk: *mut Disk,
     /**
      * 池子中数据块位图  自身 所在硬盘的LBA地址
      */
@@ -265,27 +289,72 @@ pub struct DataBlockPool {
      * 池子起始块的LBA地址
      */
     block_start_lba: LbaAddr,
+    /**
+     * 每个数据块包含的扇区数量
+     */
+    sec_per_block: usize,
     /**
      * 池子中的块位图 结构
      */
     block_bitmap: BitMap, 
+    /**
+     * 数据块的总数量
+     */
+    total_blocks: u32,
+    /**
+     * 空闲的数据块数量。挂载的时候扫描位图得到，申请、释放的时候更新
+     */
+    free_blocks: u32,
+    /**
+     * 保留的数据块数量。文件的数据块、间接块、目录块，都不能使用保留的数据块
+     */
+    reserved_blocks: u32,
 }
 
 impl DataBlockPool {
-    pub fn new(disk: *mut Disk, self_lba:  LbaAddr, block_start_lba: LbaAddr, block_bits: &mut [u8]) -> Self {
+    #[inline(never)]
+    pub fn new(self_lba:  LbaAddr, block_start_lba: LbaAddr, sec_per_block: usize, block_bits: &mut [u8], total_blocks: u32, reserved_blocks: u32) -> Self {
+        let block_bitmap = BitMap::new(block_bits);
+        let free_blocks = self::count_free_bits(&block_bitmap, total_blocks);
         Self {
-            disk,
             self_bitmap_lba: self_lba,
             block_start_lba: block_start_lba,
-            block_bitmap: BitMap::new(block_bits),
+            sec_per_block,
+            block_bitmap,
+            total_blocks,
+            free_blocks,
+            reserved_blocks,
         }
     }
 
+    pub fn total_blocks(&self) -> u32 {
+        self.total_blocks
+    }
+
+    pub fn free_blocks(&self) -> u32 {
+        self.free_blocks
+    }
+
+    /**
+     * 可以申请的空闲数据块数量 = 空闲数据块 - 保留的数据块
+     */
+    pub fn avail_blocks(&self) -> u32 {
+        self.free_blocks.saturating_sub(self.reserved_blocks)
+    }
+
+    /**
+     * 块位图所在的内存地址
+     */
+    pub fn bitmap_addr(&self) -> usize {
+        self.block_bitmap.map_ptr as usize
+    }
+
     /**
      * 在数据块的池子中，申请一个数据块。（会同步到硬盘）
+     *  - 不检查保留的数据块，外部统一通过try_apply_block申请
      */
     #[inline(never)]
-    pub fn apply_block(&mut self, blocks: usize) -> LbaAddr {
+    fn apply_block(&mut self, journal: &mut Journal, blocks: usize) -> LbaAddr {
         // 从块位图申请1位
         let res = self.block_bitmap.apply_bits(blocks);
         if res.is_err() {
@@ -294,34 +363,52 @@ impl DataBlockPool {
         let bit_off = res.unwrap();
         // 把块位图这一位设置为占用
         self.block_bitmap.set_bit(bit_off, true);
-        // 申请到的块LBA地址 = 起始块LBA + 申请到的第bit_off位
-        let block_lba = self.block_start_lba.add(bit_off.try_into().unwrap());
+        if bit_off < self.total_blocks as usize {
+            self.free_blocks -= 1;
+        }
+        // 申请到的块LBA地址 = 起始块LBA + 申请到的第bit_off位 * 每块的扇区数量
+        let block_lba = self.block_start_lba.add((bit_off * self.sec_per_block).try_into().unwrap());
         // 把申请到的块，同步到硬盘
-        self.sync_block_pool(block_lba);
+        self.sync_block_pool(journal, block_lba);
         block_lba
     }
 
+    /**
+     * 申请一个数据块（普通文件的数据块、目录的数据块、间接块都通过这里申请）
+     *  - 只剩下保留的数据块的时候，申请失败（不会panic）
+     */
+    #[inline(never)]
+    pub fn try_apply_block(&mut self, journal: &mut Journal) -> Option<LbaAddr> {
+        if self.avail_blocks() == 0 {
+            return Option::None;
+        }
+        Option::Some(self.apply_block(journal, 1))
+    }
+
     /** 
      * 释放block_lba地址对应的块
      */
-    pub fn release_block(&mut self, block_lba: LbaAddr) {
-        let bit_off: usize = (block_lba - self.block_start_lba).try_into().unwrap();
+    pub fn release_block(&mut self, journal: &mut Journal, block_lba: LbaAddr) {
+        let bit_off = self.block_bit_off(block_lba);
+        // 已经是空闲的，不重复计数
+        if bit_off < self.total_blocks as usize && self.block_bitmap.is_set(bit_off) {
+            self.free_blocks += 1;
+        }
         // 把块位图这一位设置为不占用
         self.block_bitmap.set_bit(bit_off, false);
         // 把申请到的块，同步到硬盘
-        self.sync_block_pool(block_lba);
+        self.sync_block_pool(journal, block_lba);
     }
 
     /**
      * 空闲块为block_lba所在的块位图，同步到硬盘
      */
     #[inline(never)]
-    pub fn sync_block_pool(&mut self, block_lba: LbaAddr) {
-        let disk = unsafe { &mut *self.disk };
+    pub fn sync_block_pool(&mut self, journal: &mut Journal, block_lba: LbaAddr) {
         // 定位到这个数据块，所在的位图，
         let (lba, bitmap_buf) = self.locate_bitmap(block_lba);
-        // 把inode bitmap写入到硬盘中
-        disk.write_sector(bitmap_buf, lba, 1);
+        // 把块位图写入到硬盘中（元数据，记录到日志中）
+        journal::write_metadata(journal, bitmap_buf, lba, 1);
     }
 
     /**
@@ -332,10 +419,10 @@ impl DataBlockPool {
      */
     fn locate_bitmap(&self, block_lba: LbaAddr) -> (LbaAddr, &[u8]) {
         // 位图中「这个位所在位图的位偏移量」
-        let bit_off = block_lba - self.block_start_lba;
+        let bit_off = self.block_bit_off(block_lba);
         
         // 位图中「这个位所在的扇区，相对于位图起始扇区的偏移量」
-        let sec_off = usize::from(bit_off) / 8 / constants::DISK_SECTOR_SIZE;
+        let sec_off = bit_off / 8 / constants::DISK_SECTOR_SIZE;
 
         // 位图所在扇区开头，对应位图的地址
         let bitmap_bit_offset = unsafe { self.block_bitmap.map_ptr.add(sec_off * constants::DISK_SECTOR_SIZE) };
@@ -344,5 +431,24 @@ impl DataBlockPool {
 
         (self.self_bitmap_lba.add(sec_off.try_into().unwrap()), sec_data)
     }
+
+    /**
+     * 数据块block_lba，对应块位图中的第几位
+     */
+    fn block_bit_off(&self, block_lba: LbaAddr) -> usize {
+        usize::from(block_lba - self.block_start_lba) / self.sec_per_block
+    }
     
 }
+
+/**
+ * 位图的前total位中，空闲（是0）的位的数量
+ */
+#[inline(never)]
+fn count_free_bits(bitmap: &BitMap, total: u32) -> u32 {
+    let total = (total as usize).min(bitmap.bits_len());
+    let full_bytes = total / 8;
+    let used_in_bytes: u32 = bitmap.get_bitmap()[..full_bytes].iter().map(|byte| byte.count_ones()).sum();
+    let used_in_tail = (full_bytes * 8..total).filter(|bit_idx| bitmap.is_set(*bit_idx)).count() as u32;
+    total as u32 - used_in_bytes - used_in_tail
+}
@@ -0,0 +1,895 @@
+use core::{fmt, mem::size_of, slice};
+
+use os_in_rust_common::{constants, cstr_write, cstring_utils, domain::{InodeNo, LbaAddr}, printkln};
+
+use crate::{device::{Disk, Partition}, memory, time};
+
+use super::{buffer_cache, constant, dir_entry::{self, DirEntry, DirEntryBlockIter, DirEntryHeader, FileType}, inode::Inode, superblock::SuperBlock};
+
+/**
+ * 文件系统一致性检查（fsck）
+ *  - 从根目录开始遍历所有的目录，得到所有可以访问到的inode，和inode位图对比
+ *  - 遍历每个inode引用的数据块（包括间接块），和块位图对比。同时找出越界的块地址、被多个inode引用的块
+ *  - 检查目录项（指向非法的inode、..不是父目录）、i_size、硬链接数量
+ *  - 位图中占用、但是没有任何目录引用的inode，是孤儿inode
+ * 修复的时候：删除非法的目录项和块地址，修正i_size和硬链接数量，孤儿inode放到/lost+found下，最后根据遍历结果重建两个位图
+ *
+ * 检查只依赖FsckDevice读写扇区，内核（挂载的时候）和宿主机上的leonfs工具共用这一份代码
+ */
+
+/**
+ * 一个扇区的数据
+ */
+type Sector = [u8; constants::DISK_SECTOR_SIZE];
+
+/**
+ * 需要的扇区缓冲区数量：目录块、inode、孤儿目录项，以及三级间接块树每一层一个
+ */
+const FSCK_SECTOR_BUFS: usize = 6;
+const BUF_DIR: usize = 0;
+const BUF_INODE: usize = 1;
+const BUF_LOST_FOUND: usize = 2;
+const BUF_TREE: usize = 3;
+
+/**
+ * fsck读写的设备，以及问题的输出
+ */
+pub trait FsckDevice {
+    fn read_sector(&mut self, lba: LbaAddr, buf: &mut [u8]);
+    fn write_sector(&mut self, lba: LbaAddr, buf: &[u8]);
+    /**
+     * 输出发现的一个问题
+     */
+    fn report(&mut self, msg: fmt::Arguments);
+}
+
+/**
+ * 检查的结果。每一类问题的数量
+ */
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FsckReport {
+    /**
+     * 指向非法inode的目录项，或者目录被多个目录项引用
+     */
+    pub bad_entries: u32,
+    /**
+     * ..不是父目录的目录
+     */
+    pub bad_parents: u32,
+    /**
+     * 超出数据区范围的块地址
+     */
+    pub bad_blocks: u32,
+    /**
+     * 被多次引用的块
+     */
+    pub dup_blocks: u32,
+    /**
+     * i_size不正确的inode
+     */
+    pub bad_sizes: u32,
+    /**
+     * 硬链接数量不正确的inode
+     */
+    pub bad_links: u32,
+    /**
+     * 没有任何目录项引用的inode
+     */
+    pub orphans: u32,
+    /**
+     * inode位图中不正确的位
+     */
+    pub inode_bitmap_errs: u32,
+    /**
+     * 块位图中不正确的位
+     */
+    pub block_bitmap_errs: u32,
+    /**
+     * 是否修复了
+     */
+    pub repaired: bool,
+}
+
+impl FsckReport {
+    /**
+     * 问题的总数
+     */
+    pub fn problems(&self) -> u32 {
+        self.bad_entries + self.bad_parents + self.bad_blocks + self.dup_blocks + self.bad_sizes
+            + self.bad_links + self.orphans + self.inode_bitmap_errs + self.block_bitmap_errs
+    }
+}
+
+/**
+ * fsck需要的工作内存大小（单位：u32的个数）。由调用方申请
+ */
+pub fn workspace_words(super_block: &SuperBlock) -> usize {
+    let inode_cnt = super_block.inode_cnt as usize;
+    let word_bytes = size_of::<u32>();
+    // 遍历队列、父目录、引用计数
+    inode_cnt * 3
+        // 可以访问到的inode（位图）
+        + inode_cnt.div_ceil(32)
+        // 硬盘中的inode位图、块位图，以及被引用的块（位图）
+        + (super_block.inode_bitmap_secs as usize + super_block.block_bitmap_secs as usize * 2) * constants::DISK_SECTOR_SIZE / word_bytes
+        // 扇区缓冲区
+        + FSCK_SECTOR_BUFS * constants::DISK_SECTOR_SIZE / word_bytes
+}
+
+/**
+ * 检查文件系统。调用方需要保证这是当前格式的文件系统（super_block.is_formatted()），并且日志已经重放过了
+ *  - workspace: 工作内存，至少workspace_words个
+ *  - repair: 是否修复发现的问题
+ *  - now: 修复的时候，新建/lost+found使用的时间戳
+ */
+pub fn check<D: FsckDevice>(dev: &mut D, super_block: &SuperBlock, workspace: &mut [u32], repair: bool, now: u32) -> FsckReport {
+    let mut checker = Checker::new(dev, super_block, workspace, repair, now);
+    checker.run();
+    checker.report
+}
+
+struct Checker<'a, D: FsckDevice> {
+    dev: &'a mut D,
+    super_block: &'a SuperBlock,
+    repair: bool,
+    now: u32,
+    report: FsckReport,
+    /**
+     * 待遍历的目录。每个inode最多入队一次
+     */
+    queue: &'a mut [u32],
+    queue_head: usize,
+    queue_tail: usize,
+    /**
+     * 每个目录的父目录
+     */
+    parents: &'a mut [u32],
+    /**
+     * 每个inode被多少个目录项引用（包括.和..）
+     */
+    links: &'a mut [u32],
+    /**
+     * 可以访问到的inode
+     */
+    reached: &'a mut [u8],
+    /**
+     * 硬盘中的inode位图
+     */
+    inode_bitmap: &'a mut [u8],
+    /**
+     * 硬盘中的块位图
+     */
+    block_bitmap: &'a mut [u8],
+    /**
+     * 被inode引用的块
+     */
+    block_used: &'a mut [u8],
+    bufs: &'a mut [Sector],
+    /**
+     * /lost+found目录的inode
+     */
+    lost_found: Option<InodeNo>,
+    /**
+     * 修复的时候是否申请了inode、数据块（位图需要写回）
+     */
+    allocated: bool,
+}
+
+impl <'a, D: FsckDevice> Checker<'a, D> {
+    fn new(dev: &'a mut D, super_block: &'a SuperBlock, workspace: &'a mut [u32], repair: bool, now: u32) -> Self {
+        workspace.fill(0);
+        let inode_cnt = super_block.inode_cnt as usize;
+        let sec_words = constants::DISK_SECTOR_SIZE / size_of::<u32>();
+        let (queue, rest) = workspace.split_at_mut(inode_cnt);
+        let (parents, rest) = rest.split_at_mut(inode_cnt);
+        let (links, rest) = rest.split_at_mut(inode_cnt);
+        let (reached, rest) = rest.split_at_mut(inode_cnt.div_ceil(32));
+        let (inode_bitmap, rest) = rest.split_at_mut(super_block.inode_bitmap_secs as usize * sec_words);
+        let (block_bitmap, rest) = rest.split_at_mut(super_block.block_bitmap_secs as usize * sec_words);
+        let (block_used, rest) = rest.split_at_mut(super_block.block_bitmap_secs as usize * sec_words);
+        let (bufs, _) = rest.split_at_mut(FSCK_SECTOR_BUFS * sec_words);
+        Self {
+            dev,
+            super_block,
+            repair,
+            now,
+            report: FsckReport::default(),
+            queue,
+            queue_head: 0,
+            queue_tail: 0,
+            parents,
+            links,
+            reached: as_bytes(reached),
+            inode_bitmap: as_bytes(inode_bitmap),
+            block_bitmap: as_bytes(block_bitmap),
+            block_used: as_bytes(block_used),
+            bufs: unsafe { slice::from_raw_parts_mut(bufs.as_mut_ptr() as *mut Sector, FSCK_SECTOR_BUFS) },
+            lost_found: Option::None,
+            allocated: false,
+        }
+    }
+
+    fn run(&mut self) {
+        let sb = self.super_block;
+        self.dev.read_sector(sb.inode_bitmap_lba, &mut self.inode_bitmap[..]);
+        self.dev.read_sector(sb.block_bitmap_lba, &mut self.block_bitmap[..]);
+
+        let root = sb.root_inode_no;
+        let root_inode = self.read_inode(root);
+        if FileType::from_mode(root_inode.i_mode) != FileType::Directory {
+            self.dev.report(format_args!("root inode {} is not a directory, giving up", root.get_data()));
+            self.report.bad_entries += 1;
+            return;
+        }
+        // 1. 从根目录开始，遍历所有的目录
+        self.reach(root, root);
+        self.drain_queue();
+
+        // 2. 位图中占用、但是访问不到的inode。先处理目录，目录下的inode跟着目录一起找回来
+        self.collect_orphans(true);
+        self.collect_orphans(false);
+
+        // 3. 硬链接数量
+        self.check_links();
+
+        // 4. 位图
+        self.check_inode_bitmap();
+        self.check_block_bitmap();
+        self.report.repaired = self.repair && self.report.problems() > 0;
+    }
+
+    /**
+     * 第一次访问到某个inode：检查它引用的块，目录放入队列
+     */
+    fn reach(&mut self, i_no: InodeNo, parent: InodeNo) {
+        let idx = i_no.get_data() as usize;
+        set_bit(self.reached, idx);
+        self.parents[idx] = parent.get_data();
+        let mut inode = self.read_inode(i_no);
+        self.check_blocks(&mut inode);
+        if FileType::from_mode(inode.i_mode) == FileType::Directory {
+            self.queue[self.queue_tail] = i_no.get_data();
+            self.queue_tail += 1;
+        }
+    }
+
+    fn drain_queue(&mut self) {
+        while self.queue_head < self.queue_tail {
+            let i_no = InodeNo::new(self.queue[self.queue_head]);
+            self.queue_head += 1;
+            self.check_dir(i_no);
+        }
+    }
+
+    /**
+     * 检查目录中的每一个目录项，统计引用计数，子目录放入队列
+     */
+    fn check_dir(&mut self, dir_no: InodeNo) {
+        let mut dir_inode = self.read_inode(dir_no);
+        let parent = InodeNo::new(self.parents[dir_no.get_data() as usize]);
+        let mut dir_size = 0usize;
+        for block_idx in 0..constant::INODE_CACHED_DATA_SECS {
+            let block_lba = self.get_block(&dir_inode, block_idx);
+            if block_lba.is_empty() {
+                continue;
+            }
+            // 块中的每个扇区，都独立存放目录项
+            for sec_idx in 0..self.super_block.sec_per_block() {
+                let sec_lba = block_lba.add(sec_idx as u32);
+                self.dev.read_sector(sec_lba, &mut self.bufs[BUF_DIR]);
+                let mut dirty = false;
+                let mut off = 0;
+                while off < constants::DISK_SECTOR_SIZE {
+                    let mut iter = DirEntryBlockIter::new(&self.bufs[BUF_DIR]);
+                    iter.skip_to(off);
+                    let (entry_off, rec_len, entry) = iter.next().unwrap();
+                    off += rec_len;
+                    if entry.is_empty() {
+                        continue;
+                    }
+                    if !self.check_entry(dir_no, parent, entry_off, &entry, &mut dirty) {
+                        continue;
+                    }
+                    dir_size += dir_entry::entry_rec_len(entry.get_name().len());
+                }
+                if dirty && self.repair {
+                    self.dev.write_sector(sec_lba, &self.bufs[BUF_DIR]);
+                }
+            }
+        }
+        let i_size = dir_inode.i_size;
+        if i_size as usize != dir_size {
+            self.dev.report(format_args!("directory inode {} has size {}, should be {}", dir_no.get_data(), i_size, dir_size));
+            self.report.bad_sizes += 1;
+            dir_inode.i_size = dir_size as u32;
+            self.write_inode(&dir_inode);
+        }
+    }
+
+    /**
+     * 检查目录dir_no中，偏移量为entry_off的目录项。返回这个目录项是否保留
+     */
+    fn check_entry(&mut self, dir_no: InodeNo, parent: InodeNo, entry_off: usize, entry: &DirEntry, dirty: &mut bool) -> bool {
+        let name = entry.get_name();
+        let target = entry.i_no.get_data() as usize;
+        if name == "." || name == ".." {
+            let expect = if name == "." { dir_no } else { parent };
+            if target != expect.get_data() as usize {
+                self.dev.report(format_args!("directory inode {}: '{}' points to inode {}, should be {}", dir_no.get_data(), name, target, expect.get_data()));
+                self.report.bad_parents += 1;
+                self.update_entry(entry_off, Option::Some(expect));
+                *dirty = true;
+            }
+            self.links[expect.get_data() as usize] += 1;
+            return true;
+        }
+
+        let valid = target < self.super_block.inode_cnt as usize
+            && FileType::from_mode(self.read_inode(entry.i_no).i_mode) != FileType::Unknown;
+        // 目录只能有一个父目录
+        let dup_dir = valid && get_bit(self.reached, target) && FileType::from_mode(self.read_inode(entry.i_no).i_mode) == FileType::Directory;
+        if !valid || dup_dir {
+            if !valid {
+                self.dev.report(format_args!("directory inode {}: entry '{}' points to invalid inode {}", dir_no.get_data(), name, target));
+            } else {
+                self.dev.report(format_args!("directory inode {}: entry '{}' is another link to directory inode {}", dir_no.get_data(), name, target));
+            }
+            self.report.bad_entries += 1;
+            self.update_entry(entry_off, Option::None);
+            *dirty = true;
+            return false;
+        }
+
+        self.links[target] += 1;
+        if dir_no.get_data() == self.super_block.root_inode_no.get_data() && name == constant::LOST_FOUND_DIR_NAME {
+            self.lost_found = Option::Some(entry.i_no);
+        }
+        if !get_bit(self.reached, target) {
+            self.reach(entry.i_no, dir_no);
+        }
+        true
+    }
+
+    /**
+     * 修改目录块（BUF_DIR）中偏移量为entry_off的目录项：指向新的inode，或者删除（None）
+     */
+    fn update_entry(&mut self, entry_off: usize, i_no: Option<InodeNo>) {
+        let buf = &mut self.bufs[BUF_DIR];
+        let header_ptr = unsafe { buf.as_mut_ptr().add(entry_off) as *mut DirEntryHeader };
+        let mut header = unsafe { header_ptr.read_unaligned() };
+        match i_no {
+            Option::Some(i_no) => header.i_no = i_no,
+            // 名称长度为0，就是空闲的目录项
+            Option::None => header.name_len = 0,
+        }
+        unsafe { header_ptr.write_unaligned(header) };
+    }
+
+    /**
+     * 检查inode引用的所有块（直接块、间接块树），以及i_size
+     */
+    fn check_blocks(&mut self, inode: &mut Inode) {
+        let i_no = inode.i_no;
+        let mut changed = false;
+        let mut last_block = Option::None;
+
+        let mut direct_sectors = inode.direct_sectors;
+        for (block_idx, block_lba) in direct_sectors.iter_mut().enumerate() {
+            if block_lba.is_empty() {
+                continue;
+            }
+            if self.claim_block(i_no, *block_lba) {
+                last_block = Option::Some(block_idx);
+            } else {
+                *block_lba = LbaAddr::empty();
+                changed = true;
+            }
+        }
+        inode.direct_sectors = direct_sectors;
+
+        let double_base = constant::INODE_CACHED_DATA_SECS;
+        let triple_base = double_base + constant::LBA_PER_BLOCK * constant::LBA_PER_BLOCK;
+        let mut roots = [
+            (inode.indirect_sector, 1, constant::INODE_DIRECT_DATA_SECS),
+            (inode.double_indirect_sector, 2, double_base),
+            (inode.triple_indirect_sector, 3, triple_base),
+        ];
+        for (root_lba, level, base) in roots.iter_mut() {
+            if root_lba.is_empty() {
+                continue;
+            }
+            if !self.claim_block(i_no, *root_lba) {
+                *root_lba = LbaAddr::empty();
+                changed = true;
+                continue;
+            }
+            self.check_tree(i_no, *root_lba, *level, 0, *base, &mut last_block);
+        }
+        inode.indirect_sector = roots[0].0;
+        inode.double_indirect_sector = roots[1].0;
+        inode.triple_indirect_sector = roots[2].0;
+
+        // 目录的大小，遍历目录项的时候检查
+        let file_type = FileType::from_mode(inode.i_mode);
+        if file_type == FileType::Regular || file_type == FileType::Symlink {
+            changed |= self.check_size(inode, last_block);
+        }
+        if changed {
+            self.write_inode(inode);
+        }
+    }
+
+    /**
+     * 检查一棵level层的间接块树。depth是当前这一层使用的缓冲区
+     *  - base: 这棵树第一个数据块，在文件中的下标
+     *  - last_block: 文件最后一个数据块的下标
+     */
+    fn check_tree(&mut self, i_no: InodeNo, block_lba: LbaAddr, level: u32, depth: usize, base: usize, last_block: &mut Option<usize>) {
+        self.dev.read_sector(block_lba, &mut self.bufs[BUF_TREE + depth]);
+        let blocks_per_entry = constant::LBA_PER_BLOCK.pow(level - 1);
+        let mut dirty = false;
+        for entry_idx in 0..constant::LBA_PER_BLOCK {
+            let child_lba = read_lba(&self.bufs[BUF_TREE + depth], entry_idx);
+            if child_lba.is_empty() {
+                continue;
+            }
+            if !self.claim_block(i_no, child_lba) {
+                write_lba(&mut self.bufs[BUF_TREE + depth], entry_idx, LbaAddr::empty());
+                dirty = true;
+                continue;
+            }
+            if level > 1 {
+                self.check_tree(i_no, child_lba, level - 1, depth + 1, base + entry_idx * blocks_per_entry, last_block);
+            } else {
+                *last_block = Option::Some(base + entry_idx);
+            }
+        }
+        if dirty && self.repair {
+            self.dev.write_sector(block_lba, &self.bufs[BUF_TREE + depth]);
+        }
+    }
+
+    /**
+     * inode引用了块block_lba。返回这个引用是否合法：在数据区的范围内（并且是某个块的开始），并且没有被其他inode引用
+     */
+    fn claim_block(&mut self, i_no: InodeNo, block_lba: LbaAddr) -> bool {
+        let lba = block_lba.get_lba();
+        let bit_idx = self.block_bit(block_lba);
+        if bit_idx.is_none() {
+            self.dev.report(format_args!("inode {} references block {} outside the data area", i_no.get_data(), lba));
+            self.report.bad_blocks += 1;
+            return false;
+        }
+        let bit_idx = bit_idx.unwrap();
+        if get_bit(self.block_used, bit_idx) {
+            self.dev.report(format_args!("inode {} references block {} which is already in use", i_no.get_data(), lba));
+            self.report.dup_blocks += 1;
+            return false;
+        }
+        set_bit(self.block_used, bit_idx);
+        true
+    }
+
+    /**
+     * 检查普通文件、符号链接的i_size：不能超过最大的文件大小，也要覆盖最后一个数据块。返回是否修改了
+     */
+    fn check_size(&mut self, inode: &mut Inode, last_block: Option<usize>) -> bool {
+        let i_size = inode.i_size as usize;
+        let block_size = self.super_block.get_block_size();
+        let max_size = self.super_block.max_data_blocks() * block_size;
+        let too_small = last_block.is_some() && i_size <= last_block.unwrap() * block_size;
+        if i_size <= max_size && !too_small {
+            return false;
+        }
+        let new_size = last_block.map(|block_idx| (block_idx + 1) * block_size).unwrap_or(0);
+        self.dev.report(format_args!("inode {} has size {}, should be {}", inode.i_no.get_data(), i_size, new_size));
+        self.report.bad_sizes += 1;
+        inode.i_size = new_size as u32;
+        true
+    }
+
+    /**
+     * 位图中占用、但是访问不到的inode
+     *  - dirs: 这一轮处理目录还是其他文件
+     * 修复的时候，放到/lost+found下，名称是#inode编号
+     */
+    fn collect_orphans(&mut self, dirs: bool) {
+        for idx in 0..self.super_block.inode_cnt as usize {
+            if !get_bit(self.inode_bitmap, idx) || get_bit(self.reached, idx) {
+                continue;
+            }
+            let i_no = InodeNo::new(idx as u32);
+            let file_type = FileType::from_mode(self.read_inode(i_no).i_mode);
+            // 不是合法的inode，重建位图的时候释放掉
+            if file_type == FileType::Unknown || (file_type == FileType::Directory) != dirs {
+                continue;
+            }
+            self.dev.report(format_args!("inode {} is not referenced by any directory", idx));
+            self.report.orphans += 1;
+            if !self.repair {
+                // 不修复，也要统计它引用的块，避免重复报告块位图的问题
+                self.reach(i_no, i_no);
+                self.queue_head = self.queue_tail;
+                continue;
+            }
+            let lost_found = self.get_lost_found();
+            if lost_found.is_none() {
+                continue;
+            }
+            let lost_found = lost_found.unwrap();
+            let name_buf = &mut [0u8; 16];
+            cstr_write!(name_buf, "#{}", idx);
+            let name = cstring_utils::read_from_bytes(name_buf).unwrap();
+            if !self.add_entry(lost_found, &DirEntry::new(i_no, name, file_type)) {
+                self.dev.report(format_args!("no room in /{} for inode {}", constant::LOST_FOUND_DIR_NAME, idx));
+                continue;
+            }
+            self.links[idx] += 1;
+            if file_type == FileType::Directory {
+                // 子目录的..会指向/lost+found
+                let mut lost_found_inode = self.read_inode(lost_found);
+                lost_found_inode.i_nlink += 1;
+                self.write_inode(&lost_found_inode);
+            }
+            self.reach(i_no, lost_found);
+            self.drain_queue();
+        }
+    }
+
+    /**
+     * 找到/lost+found，没有就创建一个
+     */
+    fn get_lost_found(&mut self) -> Option<InodeNo> {
+        if self.lost_found.is_some() {
+            return self.lost_found;
+        }
+        let sb = self.super_block;
+        let root = sb.root_inode_no;
+        let i_no = self.apply_inode();
+        let block_lba = self.apply_block();
+        if i_no.is_none() || block_lba.is_none() {
+            self.dev.report(format_args!("no space to create /{}", constant::LOST_FOUND_DIR_NAME));
+            return Option::None;
+        }
+        let (i_no, block_lba) = (i_no.unwrap(), block_lba.unwrap());
+
+        let buf = &mut self.bufs[BUF_LOST_FOUND];
+        buf.fill(0);
+        dir_entry::init_dir_block(buf, &DirEntry::new(i_no, ".", FileType::Directory));
+        dir_entry::init_dir_block(buf, &DirEntry::new(root, "..", FileType::Directory));
+        self.write_new_dir_block(block_lba);
+
+        let mut inode = Inode::new(i_no);
+        inode.i_size = (dir_entry::entry_rec_len(".".len()) + dir_entry::entry_rec_len("..".len())) as u32;
+        inode.direct_sectors[0] = block_lba;
+        inode.i_mode = FileType::Directory.to_mode();
+        inode.i_nlink = 2;
+        inode.i_ctime = self.now;
+        inode.i_mtime = self.now;
+        inode.i_atime = self.now;
+        self.write_inode(&inode);
+
+        if !self.add_entry(root, &DirEntry::new(i_no, constant::LOST_FOUND_DIR_NAME, FileType::Directory)) {
+            self.dev.report(format_args!("no room in / to create /{}", constant::LOST_FOUND_DIR_NAME));
+            return Option::None;
+        }
+        let mut root_inode = self.read_inode(root);
+        root_inode.i_nlink += 1;
+        self.write_inode(&root_inode);
+
+        // 根目录中的目录项，以及自己的.；根目录被..引用
+        self.links[i_no.get_data() as usize] += 2;
+        self.links[root.get_data() as usize] += 1;
+        set_bit(self.reached, i_no.get_data() as usize);
+        self.parents[i_no.get_data() as usize] = root.get_data();
+        self.lost_found = Option::Some(i_no);
+        self.lost_found
+    }
+
+    /**
+     * 在目录中加入一个目录项。已有的数据块都放不下了，使用一个空闲的直接块
+     */
+    fn add_entry(&mut self, dir_no: InodeNo, entry: &DirEntry) -> bool {
+        let mut dir_inode = self.read_inode(dir_no);
+        let mut free_idx = Option::None;
+        let mut inserted = false;
+        for block_idx in 0..constant::INODE_CACHED_DATA_SECS {
+            let block_lba = self.get_block(&dir_inode, block_idx);
+            if block_lba.is_empty() {
+                free_idx = free_idx.or(Option::Some(block_idx));
+                continue;
+            }
+            for sec_idx in 0..self.super_block.sec_per_block() {
+                let sec_lba = block_lba.add(sec_idx as u32);
+                self.dev.read_sector(sec_lba, &mut self.bufs[BUF_LOST_FOUND]);
+                if dir_entry::insert_entry(&mut self.bufs[BUF_LOST_FOUND], entry) {
+                    self.dev.write_sector(sec_lba, &self.bufs[BUF_LOST_FOUND]);
+                    inserted = true;
+                    break;
+                }
+            }
+            if inserted {
+                break;
+            }
+        }
+        if !inserted {
+            let free_idx = free_idx.filter(|block_idx| *block_idx < constant::INODE_DIRECT_DATA_SECS);
+            if free_idx.is_none() {
+                return false;
+            }
+            let block_lba = self.apply_block();
+            if block_lba.is_none() {
+                return false;
+            }
+            let block_lba = block_lba.unwrap();
+            let buf = &mut self.bufs[BUF_LOST_FOUND];
+            buf.fill(0);
+            dir_entry::insert_entry(buf, entry);
+            self.write_new_dir_block(block_lba);
+            let mut direct_sectors = dir_inode.direct_sectors;
+            direct_sectors[free_idx.unwrap()] = block_lba;
+            dir_inode.direct_sectors = direct_sectors;
+        }
+        dir_inode.i_size += dir_entry::entry_rec_len(entry.get_name().len()) as u32;
+        self.write_inode(&dir_inode);
+        true
+    }
+
+    /**
+     * 写入一个新的目录块：第一个扇区是BUF_LOST_FOUND中的目录项，其他扇区清零
+     */
+    fn write_new_dir_block(&mut self, block_lba: LbaAddr) {
+        self.dev.write_sector(block_lba, &self.bufs[BUF_LOST_FOUND]);
+        self.bufs[BUF_LOST_FOUND].fill(0);
+        for sec_idx in 1..self.super_block.sec_per_block() {
+            self.dev.write_sector(block_lba.add(sec_idx as u32), &self.bufs[BUF_LOST_FOUND]);
+        }
+    }
+
+    /**
+     * 申请一个inode：遍历结果和硬盘中的位图都是空闲的。两个都标记为占用，新申请的inode不算位图的问题
+     */
+    fn apply_inode(&mut self) -> Option<InodeNo> {
+        let idx = (0..self.super_block.inode_cnt as usize).find(|idx| !get_bit(self.reached, *idx) && !get_bit(self.inode_bitmap, *idx))?;
+        set_bit(self.reached, idx);
+        set_bit(self.inode_bitmap, idx);
+        self.allocated = true;
+        Option::Some(InodeNo::new(idx as u32))
+    }
+
+    /**
+     * 申请一个数据块：遍历结果和硬盘中的位图都是空闲的。两个都标记为占用
+     */
+    fn apply_block(&mut self) -> Option<LbaAddr> {
+        let idx = (0..self.super_block.data_blocks() as usize).find(|idx| !get_bit(self.block_used, *idx) && !get_bit(self.block_bitmap, *idx))?;
+        set_bit(self.block_used, idx);
+        set_bit(self.block_bitmap, idx);
+        self.allocated = true;
+        Option::Some(self.super_block.data_lba_start.add((idx * self.super_block.sec_per_block()) as u32))
+    }
+
+    /**
+     * 检查每个可以访问到的inode，硬链接数量是否等于引用它的目录项数量
+     */
+    fn check_links(&mut self) {
+        for idx in 0..self.super_block.inode_cnt as usize {
+            // 没有找回来的孤儿，不检查
+            if !get_bit(self.reached, idx) || self.links[idx] == 0 {
+                continue;
+            }
+            let mut inode = self.read_inode(InodeNo::new(idx as u32));
+            let i_nlink = inode.i_nlink;
+            if i_nlink as u32 == self.links[idx] {
+                continue;
+            }
+            self.dev.report(format_args!("inode {} has link count {}, should be {}", idx, i_nlink, self.links[idx]));
+            self.report.bad_links += 1;
+            inode.i_nlink = self.links[idx] as u16;
+            self.write_inode(&inode);
+        }
+    }
+
+    /**
+     * inode位图和遍历结果对比，修复的时候使用遍历结果
+     */
+    fn check_inode_bitmap(&mut self) {
+        let (mut unused, mut unmarked) = (0, 0);
+        for idx in 0..self.super_block.inode_cnt as usize {
+            match (get_bit(self.inode_bitmap, idx), get_bit(self.reached, idx)) {
+                (true, false) => unused += 1,
+                (false, true) => unmarked += 1,
+                _ => {},
+            }
+        }
+        if unused + unmarked > 0 {
+            self.dev.report(format_args!("inode bitmap: {} free inodes marked in use, {} used inodes marked free", unused, unmarked));
+            self.report.inode_bitmap_errs += unused + unmarked;
+        }
+        if self.repair && (unused + unmarked > 0 || self.allocated) {
+            // 超出inode数量的位，保持原样
+            for idx in 0..self.super_block.inode_cnt as usize {
+                if get_bit(self.reached, idx) { set_bit(self.inode_bitmap, idx) } else { clear_bit(self.inode_bitmap, idx) }
+            }
+            self.dev.write_sector(self.super_block.inode_bitmap_lba, &self.inode_bitmap[..]);
+        }
+    }
+
+    /**
+     * 块位图和遍历结果对比，修复的时候使用遍历结果。超出数据区的位始终是1
+     */
+    fn check_block_bitmap(&mut self) {
+        let (mut unused, mut unmarked) = (0, 0);
+        for idx in 0..self.block_used.len() * 8 {
+            let used = idx >= self.super_block.data_blocks() as usize || get_bit(self.block_used, idx);
+            match (get_bit(self.block_bitmap, idx), used) {
+                (true, false) => unused += 1,
+                (false, true) => unmarked += 1,
+                _ => {},
+            }
+            if used { set_bit(self.block_used, idx) }
+        }
+        if unused + unmarked > 0 {
+            self.dev.report(format_args!("block bitmap: {} free blocks marked in use, {} used blocks marked free", unused, unmarked));
+            self.report.block_bitmap_errs += unused + unmarked;
+        }
+        if self.repair && (unused + unmarked > 0 || self.allocated) {
+            self.dev.write_sector(self.super_block.block_bitmap_lba, &self.block_used[..]);
+        }
+    }
+
+    /**
+     * 找到inode第block_idx个数据块（只读）。块地址超出数据区，当作没有分配
+     */
+    fn get_block(&mut self, inode: &Inode, block_idx: usize) -> LbaAddr {
+        let block_lba = if block_idx < constant::INODE_DIRECT_DATA_SECS {
+            inode.direct_sectors[block_idx]
+        } else {
+            let idx = block_idx - constant::INODE_DIRECT_DATA_SECS;
+            let indirect_lba = inode.indirect_sector;
+            if idx >= constant::LBA_PER_BLOCK || !self.in_data_area(indirect_lba) {
+                return LbaAddr::empty();
+            }
+            self.dev.read_sector(indirect_lba, &mut self.bufs[BUF_TREE]);
+            read_lba(&self.bufs[BUF_TREE], idx)
+        };
+        if !self.in_data_area(block_lba) {
+            return LbaAddr::empty();
+        }
+        block_lba
+    }
+
+    fn in_data_area(&self, block_lba: LbaAddr) -> bool {
+        self.block_bit(block_lba).is_some()
+    }
+
+    /**
+     * 块block_lba在块位图中是第几位。不在数据区，或者不是某个块的开始，返回None
+     */
+    fn block_bit(&self, block_lba: LbaAddr) -> Option<usize> {
+        let data_start = self.super_block.data_lba_start.get_lba();
+        let lba = block_lba.get_lba();
+        if lba < data_start || lba - data_start >= self.super_block.data_block_secs {
+            return Option::None;
+        }
+        let sec_per_block = self.super_block.sec_per_block();
+        let sec_off = (lba - data_start) as usize;
+        if sec_off % sec_per_block != 0 {
+            return Option::None;
+        }
+        Option::Some(sec_off / sec_per_block)
+    }
+
+    /**
+     * inode所在的扇区，以及在扇区中的偏移量
+     */
+    fn locate_inode(&self, i_no: InodeNo) -> (LbaAddr, usize) {
+        let bytes_off = i_no.get_data() as usize * self.super_block.get_inode_size();
+        (self.super_block.inode_table_lba.add((bytes_off / constants::DISK_SECTOR_SIZE) as u32), bytes_off % constants::DISK_SECTOR_SIZE)
+    }
+
+    fn read_inode(&mut self, i_no: InodeNo) -> Inode {
+        let (lba, off) = self.locate_inode(i_no);
+        let mut inode = Inode::empty();
+        let inode_buf = unsafe { slice::from_raw_parts_mut(&mut inode as *mut Inode as *mut u8, size_of::<Inode>()) };
+        // inode可能跨越两个扇区
+        let first_len = inode_buf.len().min(constants::DISK_SECTOR_SIZE - off);
+        self.dev.read_sector(lba, &mut self.bufs[BUF_INODE]);
+        inode_buf[..first_len].copy_from_slice(&self.bufs[BUF_INODE][off..off + first_len]);
+        if first_len < inode_buf.len() {
+            self.dev.read_sector(lba.add(1), &mut self.bufs[BUF_INODE]);
+            inode_buf[first_len..].copy_from_slice(&self.bufs[BUF_INODE][..size_of::<Inode>() - first_len]);
+        }
+        inode
+    }
+
+    /**
+     * 写入inode。只有修复的时候才会写入
+     */
+    fn write_inode(&mut self, inode: &Inode) {
+        if !self.repair {
+            return;
+        }
+        let (lba, off) = self.locate_inode(inode.i_no);
+        let inode_buf = unsafe { slice::from_raw_parts(inode as *const Inode as *const u8, size_of::<Inode>()) };
+        let first_len = inode_buf.len().min(constants::DISK_SECTOR_SIZE - off);
+        self.dev.read_sector(lba, &mut self.bufs[BUF_INODE]);
+        self.bufs[BUF_INODE][off..off + first_len].copy_from_slice(&inode_buf[..first_len]);
+        self.dev.write_sector(lba, &self.bufs[BUF_INODE]);
+        if first_len < inode_buf.len() {
+            self.dev.read_sector(lba.add(1), &mut self.bufs[BUF_INODE]);
+            self.bufs[BUF_INODE][..inode_buf.len() - first_len].copy_from_slice(&inode_buf[first_len..]);
+            self.dev.write_sector(lba.add(1), &self.bufs[BUF_INODE]);
+        }
+    }
+}
+
+fn as_bytes(words: &mut [u32]) -> &mut [u8] {
+    unsafe { slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, words.len() * size_of::<u32>()) }
+}
+
+fn get_bit(bitmap: &[u8], idx: usize) -> bool {
+    bitmap[idx / 8] & (1 << (idx % 8)) != 0
+}
+
+fn set_bit(bitmap: &mut [u8], idx: usize) {
+    bitmap[idx / 8] |= 1 << (idx % 8);
+}
+
+fn clear_bit(bitmap: &mut [u8], idx: usize) {
+    bitmap[idx / 8] &= !(1 << (idx % 8));
+}
+
+fn read_lba(buf: &Sector, idx: usize) -> LbaAddr {
+    unsafe { (buf.as_ptr() as *const LbaAddr).add(idx).read_unaligned() }
+}
+
+fn write_lba(buf: &mut Sector, idx: usize, lba: LbaAddr) {
+    unsafe { (buf.as_mut_ptr() as *mut LbaAddr).add(idx).write_unaligned(lba) }
+}
+
+/**
+ * 内核中的fsck设备：通过缓冲区读写分区所在的硬盘
+ */
+struct PartitionDevice {
+    disk: *mut Disk,
+}
+
+impl FsckDevice for PartitionDevice {
+    fn read_sector(&mut self, lba: LbaAddr, buf: &mut [u8]) {
+        let disk = unsafe { &mut *self.disk };
+        buffer_cache::read_sectors(disk, lba, buf.len() / constants::DISK_SECTOR_SIZE, buf);
+    }
+
+    fn write_sector(&mut self, lba: LbaAddr, buf: &[u8]) {
+        let disk = unsafe { &mut *self.disk };
+        buffer_cache::write_sector(disk, buf, lba, buf.len() / constants::DISK_SECTOR_SIZE);
+    }
+
+    fn report(&mut self, msg: fmt::Arguments) {
+        printkln!("fsck: {}", msg);
+    }
+}
+
+/**
+ * 挂载分区之前，检查分区中的文件系统。repair：是否修复发现的问题
+ */
+#[inline(never)]
+pub fn check_partition(part: &Partition, super_block: &SuperBlock, repair: bool) -> FsckReport {
+    let words = self::workspace_words(super_block);
+    let workspace = unsafe { slice::from_raw_parts_mut(memory::sys_malloc(words * size_of::<u32>()) as *mut u32, words) };
+    let mut dev = PartitionDevice { disk: part.from_disk };
+    let report = self::check(&mut dev, super_block, workspace, repair, time::get_current_timestamp());
+    memory::sys_free(workspace.as_ptr() as usize);
+
+    if report.problems() == 0 {
+        return report;
+    }
+    if report.repaired {
+        // 修复的内容，立即写回到硬盘
+        buffer_cache::sync_disk(unsafe { &mut *part.from_disk });
+        printkln!("fsck: {}: {} problems repaired", part.get_name(), report.problems());
+    } else {
+        printkln!("fsck: {}: {} problems found", part.get_name(), report.problems());
+    }
+    report
+}
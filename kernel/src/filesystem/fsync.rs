@@ -0,0 +1,41 @@
+use super::{buffer_cache, file::FileError, file_descriptor::FileDescriptor, fs::FileSystem, global_file_table, inode::{self, OpenedInode}, journal, mount};
+
+/**
+ * 把某个已打开文件的数据，写回到硬盘
+ */
+#[inline(never)]
+pub fn fsync(fd: FileDescriptor) -> Result<(), FileError> {
+    let opened_file = global_file_table::get_file_by_fd(fd)?;
+    let file_node = opened_file.get_node();
+    file_node.get_fs().fsync(file_node);
+    Result::Ok(())
+}
+
+/**
+ * LeonFS中，把某个打开的inode的数据，写回到硬盘
+ *  1. 内存中的inode，写入到缓冲区，并且提交日志事务（事务中的元数据扇区，提交之后才能写回）
+ *  2. inode所在的扇区、文件所有的数据块和间接块，写回到硬盘
+ *  3. inode位图和块位图，写回到硬盘（文件申请的inode和块，都记录在位图中）
+ */
+#[inline(never)]
+pub fn fsync_inode(fs: &mut FileSystem, file_inode: &mut OpenedInode) {
+    inode::sync_inode(fs, file_inode);
+    journal::flush(fs);
+    inode::sync_blocks(fs, file_inode);
+
+    let disk = unsafe { &mut *fs.base_part.from_disk };
+    let super_block = fs.super_block;
+    buffer_cache::sync_sectors(disk, super_block.inode_bitmap_lba, super_block.inode_bitmap_secs as usize);
+    buffer_cache::sync_sectors(disk, super_block.block_bitmap_lba, super_block.block_bitmap_secs as usize);
+}
+
+/**
+ * 把所有挂载的文件系统的数据，写回到硬盘
+ *  1. 每个文件系统，把内存中的数据写入到缓冲区
+ *  2. 缓冲区中所有的脏数据，写回到硬盘
+ */
+#[inline(never)]
+pub fn sync() {
+    mount::for_each_mount(|mount_point| mount_point.get_fs().sync());
+    buffer_cache::sync_all();
+}
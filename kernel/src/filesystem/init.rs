@@ -1,11 +1,11 @@
 use core::{mem::{size_of, size_of_val}, slice};
 
-use os_in_rust_common::{constants, domain::InodeNo, utils, ASSERT};
+use os_in_rust_common::{constants, domain::InodeNo, printkln, utils, ASSERT};
 
 use crate::device::{self, Partition};
-use crate::memory;
+use crate::{memory, time};
 
-use super::{dir_entry::{self, DirEntry}, fs::{self, FileSystem}, inode::Inode, superblock::SuperBlock};
+use super::{buffer_cache, dir_entry::{self, DirEntry}, ext2, fat, inode::Inode, journal, superblock::{MkfsOptions, SuperBlock}};
 
 
 /**
@@ -19,50 +19,50 @@ pub fn install_filesystem_for_all_part() {
     // 遍历每个分区，安装文件系统
     for part_tag in all_partition.iter() {
         let part = Partition::parse_by_tag(part_tag);
+        // FAT分区（例如使用mkfs.vfat制作的），由FAT驱动只读挂载，不能格式化
+        if fat::is_fat_volume(part) {
+            printkln!("{} is a FAT partition, skip formatting", part.get_name());
+            continue;
+        }
+        // ext2分区（例如使用mke2fs制作的），由ext2驱动只读挂载，不能格式化
+        if ext2::is_ext2_volume(part) {
+            printkln!("{} is an ext2 partition, skip formatting", part.get_name());
+            continue;
+        }
+        // 已经有文件系统了（例如使用leonfs工具制作的镜像），不再格式化，保留里面的文件
+        if self::is_formatted(part) {
+            printkln!("{} already has a filesystem, skip formatting", part.get_name());
+            continue;
+        }
+        // 是LeonFS，但是格式太旧（例如没有日志区），无法挂载。也不能格式化，否则里面的文件就丢了
+        if self::check_super_block(part, SuperBlock::has_magic) {
+            printkln!("{} has an unsupported LeonFS layout, skip formatting", part.get_name());
+            continue;
+        }
         self::install_filesystem(part);
     }
 }
 
+/**
+ * 分区中，是否已经安装了当前格式的文件系统
+ */
 #[inline(never)]
-pub fn mount_part(part_name: &str) {
-    // 找到所有分区
-    let all_part = device::get_all_partition();
-    // 遍历所有分区
-    all_part.iter()
-        .for_each(|part_tag| {
-            let part = Partition::parse_by_tag(part_tag);
-            // printkln!("part:0x{:x}", part as *const _ as usize);
-            // 找到这个分区了，设置为当前分区
-            if part.get_name() == part_name {
-                let disk = unsafe { &mut *part.from_disk };
-
-                // SuperBlock
-                let super_block: &mut SuperBlock = memory::malloc(size_of::<SuperBlock>());
-                let sb_buf = unsafe { slice::from_raw_parts_mut(super_block as *mut _ as *mut u8, size_of::<SuperBlock>()) };
-                // 读取SuperBlock
-                disk.read_sectors(part.abs_lba_start(1), 1, sb_buf);
-
-
-                // inode位图
-                let inode_bitmap_len = super_block.inode_bitmap_lba.get_lba() as usize * constants::DISK_SECTOR_SIZE;
-                let inode_bitmap_bits = unsafe { slice::from_raw_parts_mut(memory::sys_malloc(inode_bitmap_len) as *mut u8, inode_bitmap_len) };
-                disk.read_sectors(super_block.inode_bitmap_lba, super_block.inode_bitmap_secs as usize, inode_bitmap_bits);
-
-
-                // 块位图
-                let block_bitmap_len = super_block.block_bitmap_secs as usize * constants::DISK_SECTOR_SIZE;
-                let block_bitmap_bits = unsafe { slice::from_raw_parts_mut(memory::sys_malloc(block_bitmap_len) as *mut u8, inode_bitmap_len) };
-                disk.read_sectors(super_block.block_bitmap_lba, super_block.block_bitmap_secs as usize, block_bitmap_bits);
-                
-
-                // 挂载的分区。构建文件系统
-                let fs =  FileSystem::new(part, super_block, inode_bitmap_bits, block_bitmap_bits);
-
-                // 设置当前挂载的分区
-                fs::set_filesystem(fs);
-            }
-        });
-    // printkln!("{} mounted", part_name);
+pub fn is_formatted(part: &Partition) -> bool {
+    self::check_super_block(part, SuperBlock::is_formatted)
+}
+
+/**
+ * 读取分区中的超级块，交给check判断
+ */
+#[inline(never)]
+fn check_super_block(part: &Partition, check: fn(&SuperBlock) -> bool) -> bool {
+    let disk = unsafe { &mut *part.from_disk };
+    let super_block: &mut SuperBlock = memory::malloc(size_of::<SuperBlock>());
+    let sb_buf = unsafe { slice::from_raw_parts_mut(super_block as *mut _ as *mut u8, size_of::<SuperBlock>()) };
+    disk.read_sectors(part.abs_lba_start(1), 1, sb_buf);
+    let result = check(super_block);
+    memory::sys_free(super_block as *const _ as usize);
+    result
 }
 
 pub fn init() {
@@ -93,23 +93,36 @@ pub fn init() {
 /**
  * 安装文件系统
  * 我们文件系统的设计：
- * | 引导块(1扇区) | 超级块(1扇区) | inode位图(x扇区) | inode数组(y扇区)| 空闲数据块位图(z扇区)  | 根目录(1扇区) | 若干个数据块
+ * | 引导块(1扇区) | 超级块(1扇区) | 日志区(j扇区) | inode位图(x扇区) | inode数组(y扇区)| 空闲数据块位图(z扇区)  | 根目录(1块) | 若干个数据块
  * 注意：这里根目录也属于数据块
  */
 #[inline(never)]
 fn install_filesystem(part: &mut Partition) {
 
+    // 安装文件系统是直接写硬盘的，缓冲区中这个分区的数据都作废
+    buffer_cache::invalidate(unsafe { &mut *part.from_disk }, part.abs_lba_start(0), part.sec_cnt as usize);
+
+    // 按照分区大小，使用默认的格式化选项
+    let new_super_block = SuperBlock::new(part.abs_lba_start(0), part.sec_cnt, &MkfsOptions::default_for(part.sec_cnt));
+    if new_super_block.is_err() {
+        printkln!("failed to install filesystem on {}, error: {:?}", part.get_name(), new_super_block.unwrap_err());
+        return;
+    }
     // 申请空间，给超级块
     let super_block: &mut SuperBlock =  memory::malloc(size_of::<SuperBlock>());
-    *super_block = SuperBlock::new(part.abs_lba_start(0), part.sec_cnt);
+    *super_block = new_super_block.unwrap();
 
     // 安装superBlock
     self::install_super_block(part, &super_block);
 
-    // 先创建一个缓冲区，取三者的最大者
+    // 清空日志区
+    journal::install(part, &super_block);
+
+    // 先创建一个缓冲区，取四者的最大者
     let buff_max_secs = super_block.block_bitmap_secs
                         .max(super_block.inode_bitmap_secs)
-                        .max(super_block.inode_table_secs);
+                        .max(super_block.inode_table_secs)
+                        .max(super_block.sec_per_block() as u32);
     let buff_bytes = buff_max_secs as usize * constants::DISK_SECTOR_SIZE;
     let buff = unsafe { slice::from_raw_parts_mut(memory::sys_malloc(buff_bytes) as *mut u8, buff_bytes) };
 
@@ -146,65 +159,120 @@ fn install_super_block(part: &mut Partition, super_block: &SuperBlock) {
 #[inline(never)]
 #[no_mangle]
 fn install_block_bitmap(part: &mut Partition, super_block: &SuperBlock, buff: &mut [u8]) {
+    self::build_block_bitmap(super_block, buff);
+
+    // 块位图写入硬盘
+    let disk = unsafe { &mut *part.from_disk };
+    disk.write_sector(buff, super_block.block_bitmap_lba, super_block.block_bitmap_secs as usize);
+}
+
+
+/**
+ * 安装inode位图
+ */
+#[inline(never)]
+#[no_mangle]
+fn install_inode_bitmap(part: &mut Partition, super_block: &SuperBlock, buff: &mut [u8]) {
+    self::build_inode_bitmap(super_block, buff);
+
+    // printkln!("install_inode_bitmap");
+    let disk = unsafe { &mut *part.from_disk };
+    disk.write_sector(buff, super_block.inode_bitmap_lba, super_block.inode_bitmap_secs as usize);
+}
+
+
+/**
+ * 安装inode列表
+ */
+#[inline(never)]
+#[no_mangle]
+fn install_inode_table(part: &mut Partition, super_block: &SuperBlock, buff: &mut [u8]) {
+    self::build_inode_table(super_block, buff, time::get_current_timestamp());
+
+    // 把inode列表写入到硬盘中
+    let disk = unsafe { &mut *part.from_disk };
+    disk.write_sector(buff, super_block.inode_table_lba, super_block.inode_table_secs as usize);
+}
+
+/**
+ * 安装根目录项
+ *  根目录有两项：.和..，都放在块的数据区
+ */
+#[inline(never)]
+#[no_mangle]
+fn install_root_dir(part: &mut Partition, super_block: &SuperBlock, buff: &mut [u8]) {
+    self::build_root_dir(super_block, buff);
+
+    // 把根目录的数据块（.和..两项）写入到第一个数据块
+    let disk = unsafe { &mut *part.from_disk };
+    disk.write_sector(buff, super_block.data_lba_start, super_block.sec_per_block());
+
+}
+
+/**
+ * 下面的build_xxx，只生成文件系统各个区域初始的数据，不读写硬盘。宿主机上的镜像工具（leonfs）也使用这些函数格式化分区
+ */
+
+/**
+ * 生成空闲块位图。buff至少要有block_bitmap_secs个扇区
+ */
+#[inline(never)]
+pub fn build_block_bitmap(super_block: &SuperBlock, buff: &mut [u8]) {
     ASSERT!(buff.len() > 0);
     // 清零
     unsafe { buff.as_mut_ptr().write_bytes(0x00, buff.len()) };
 
-    // 块位图长度是扇区大小的倍数，当初是向上去整了的
-    // 块位图中，位图的长度（按位计算）
-    let block_bitmap_bit_len = super_block.block_bitmap_secs * constants::DISK_SECTOR_SIZE as u32 * 8;
+    // 实际有这么多个数据块（这才是块位图的有效长度bit），后面用不到的位都设置为已占用
+    self::mark_invalid_bits(buff, super_block.block_bitmap_secs, super_block.data_blocks());
 
-    // 而实际有这么多个数据扇区（这才是块位图的有效长度bit）
-    let data_block_secs = super_block.data_block_secs;
-    
-    // 无效的bit数量 = 位图长度(bits) - 实际数据扇区数量
-    let invalid_bits = block_bitmap_bit_len - data_block_secs;
+    // 位图的第0位设置为1，这一位是给根目录所在块的，设置为已占用
+    buff[0] |= 0x01;
+}
 
-    // 我们把位图中用不到的位都设置为1，表示这些数据扇区不可用
+/**
+ * 位图的长度是扇区大小的倍数，当初是向上去整了的。把位图中用不到的位都设置为1，表示不可用
+ *  - bitmap_secs: 位图占用的扇区数量
+ *  - valid_bits: 位图的有效长度（按位计算）
+ */
+#[inline(never)]
+fn mark_invalid_bits(buff: &mut [u8], bitmap_secs: u32, valid_bits: u32) {
+    // 位图中，位图的长度（按位计算）
+    let bitmap_bit_len = bitmap_secs * constants::DISK_SECTOR_SIZE as u32 * 8;
 
-    // 有效位长度是data_block_secs，后面的位都用不到
+    // 无效的bit数量 = 位图长度(bits) - 有效长度
+    let invalid_bits = bitmap_bit_len - valid_bits;
+
+    // 有效位长度是valid_bits，后面的位都用不到
     // 先按照字节，把位图高字节设置为无效
-    unsafe { buff.as_mut_ptr().add(utils::div_ceil(data_block_secs, 8) as usize).write_bytes(0xFF, invalid_bits as usize / 8); }
+    unsafe { buff.as_mut_ptr().add(utils::div_ceil(valid_bits, 8) as usize).write_bytes(0xFF, invalid_bits as usize / 8); }
 
     // 最后一个有效字节，可能部分位是无效的。把高位的部分位，也设置为1
     for invalid_bit in  0 .. invalid_bits % 8 {
-        buff[data_block_secs as usize / 8] |= 1 << (7 - invalid_bit);
+        buff[valid_bits as usize / 8] |= 1 << (7 - invalid_bit);
     }
-
-    // 位图的第0位设置为1，这一位是给根目录所在块的，设置为已占用
-    buff[0] |= 0x01;
-
-    // 块位图写入硬盘
-    let disk = unsafe { &mut *part.from_disk };
-    disk.write_sector(buff, super_block.block_bitmap_lba, super_block.block_bitmap_secs as usize);
 }
 
-
 /**
- * 安装inode位图
+ * 生成inode位图。buff至少要有inode_bitmap_secs个扇区
  */
 #[inline(never)]
-#[no_mangle]
-fn install_inode_bitmap(part: &mut Partition, super_block: &SuperBlock, buff: &mut [u8]) {
+pub fn build_inode_bitmap(super_block: &SuperBlock, buff: &mut [u8]) {
     ASSERT!(buff.len() > 0);
     // 清零
     unsafe { buff.as_mut_ptr().write_bytes(0x00, buff.len()) };
+    // 超过inode数量的位都用不到，设置为已占用
+    self::mark_invalid_bits(buff, super_block.inode_bitmap_secs, super_block.inode_cnt);
     // buf[0] = 0b00000001
     // 表示位图的0号位是1，被根inode占用了
     buff[0] |= 0x01;
-
-    // printkln!("install_inode_bitmap");
-    let disk = unsafe { &mut *part.from_disk };
-    disk.write_sector(buff, super_block.inode_bitmap_lba, super_block.inode_bitmap_secs as usize);
 }
 
-
 /**
- * 安装inode列表
+ * 生成inode数组，只有根目录的inode。buff至少要有inode_table_secs个扇区
+ *  - now: 根目录的创建时间
  */
 #[inline(never)]
-#[no_mangle]
-fn install_inode_table(part: &mut Partition, super_block: &SuperBlock, buff: &mut [u8]) {
+pub fn build_inode_table(super_block: &SuperBlock, buff: &mut [u8], now: u32) {
     ASSERT!(buff.len() > 0);
     // 清零
     unsafe { buff.as_mut_ptr().write_bytes(0x00, buff.len()) };
@@ -214,40 +282,30 @@ fn install_inode_table(part: &mut Partition, super_block: &SuperBlock, buff: &mu
     // 跟目录inode
     let root_inode = &mut inode_table[0];
     root_inode.i_no = InodeNo::new(0);
-    root_inode.i_size = super_block.dir_entry_size * 2; // 2个目录：.和..
+    root_inode.i_size = (dir_entry::entry_rec_len(".".len()) + dir_entry::entry_rec_len("..".len())) as u32; // 2个目录：.和..
     // 根目录inode，数据区就是在第一个数据扇区
     root_inode.direct_sectors[0] = super_block.data_lba_start;
-
-    // 把inode列表写入到硬盘中
-    let disk = unsafe { &mut *part.from_disk };
-    disk.write_sector(buff, super_block.inode_table_lba, super_block.inode_table_secs as usize);
+    // 根目录的类型和权限
+    root_inode.i_mode = dir_entry::FileType::Directory.to_mode();
+    // 根目录的链接：自己的.和..
+    root_inode.i_nlink = 2;
+    root_inode.i_ctime = now;
+    root_inode.i_mtime = now;
+    root_inode.i_atime = now;
 }
 
 /**
- * 安装根目录项
- *  根目录有两项：.和..，都放在块的数据区
+ * 生成根目录的数据块：.和..两项。buff至少要有1个块
  */
 #[inline(never)]
-#[no_mangle]
-fn install_root_dir(part: &mut Partition, super_block: &SuperBlock, buff: &mut [u8]) {
-    ASSERT!(buff.len() > 0);
+pub fn build_root_dir(super_block: &SuperBlock, buff: &mut [u8]) {
+    ASSERT!(buff.len() >= super_block.get_block_size());
     // 清零
     unsafe { buff.as_mut_ptr().write_bytes(0x00, buff.len()) };
-    // 转成目录项数组
-    let dir_table = unsafe { slice::from_raw_parts_mut(buff as *mut _ as *mut DirEntry, buff.len() / size_of::<DirEntry>()) };
-    {
-        // . 目录项项
-        let cur_dir = &mut dir_table[0];
-        *cur_dir = DirEntry::new(InodeNo::from(0u32), ".", dir_entry::FileType::Directory);
-    }
-
-    {
-        // .. 目录项
-        let last_dir = &mut dir_table[1];
-        *last_dir = DirEntry::new(InodeNo::from(0u32), "..", dir_entry::FileType::Directory);
-    }
-    // 把根目录的两个项：.和..，写入到数据扇区
-    let disk = unsafe { &mut *part.from_disk };
-    disk.write_sector(buff, super_block.data_lba_start, 1 as usize);
-
-}
\ No newline at end of file
+    // 根目录的数据块，两项都放在第一个扇区，其他扇区是空的
+    let dir_block: &mut [u8; constants::DISK_SECTOR_SIZE] = (&mut buff[..constants::DISK_SECTOR_SIZE]).try_into().unwrap();
+    // . 目录项
+    dir_entry::init_dir_block(dir_block, &DirEntry::new(InodeNo::from(0u32), ".", dir_entry::FileType::Directory));
+    // .. 目录项
+    dir_entry::init_dir_block(dir_block, &DirEntry::new(InodeNo::from(0u32), "..", dir_entry::FileType::Directory));
+}
@@ -1,11 +1,11 @@
 use core::{fmt::Display, mem::size_of, ptr, slice};
 
-use os_in_rust_common::{constants, domain::{InodeNo, LbaAddr}, elem2entry, linked_list::LinkedNode, printk, utils, MY_PANIC};
+use os_in_rust_common::{constants, domain::{InodeNo, LbaAddr}, elem2entry, linked_list::LinkedNode, printk, printkln, utils, MY_PANIC};
 use os_in_rust_common::racy_cell::RacyCell;
 
 use crate::{memory, sync::Lock, thread};
 
-use super::{constant, fs::FileSystem};
+use super::{buffer_cache, constant, fs::FileSystem, journal};
 
 
 /**
@@ -35,6 +35,45 @@ pub struct Inode {
      * 该inode数据扇区所在的LBA地址。
      */
     pub indirect_sector: LbaAddr,
+
+    /**
+     * 二级间接块的LBA地址。这个块里面是一级间接块的LBA地址
+     */
+    pub double_indirect_sector: LbaAddr,
+
+    /**
+     * 三级间接块的LBA地址。这个块里面是二级间接块的LBA地址
+     */
+    pub triple_indirect_sector: LbaAddr,
+
+    /**
+     * 文件类型和权限。高4位是文件类型，低9位是权限（rwxrwxrwx）
+     */
+    pub i_mode: u16,
+    /**
+     * 所属用户的id
+     */
+    pub i_uid: u16,
+    /**
+     * 所属用户组的id
+     */
+    pub i_gid: u16,
+    /**
+     * 硬链接的数量（有多少个目录项指向这个inode）
+     */
+    pub i_nlink: u16,
+    /**
+     * 创建时间。时间戳，单位秒
+     */
+    pub i_ctime: u32,
+    /**
+     * 最后修改时间
+     */
+    pub i_mtime: u32,
+    /**
+     * 最后访问时间
+     */
+    pub i_atime: u32,
 }
 
 impl Inode {
@@ -44,6 +83,15 @@ impl Inode {
             i_size: 0,
             direct_sectors: [LbaAddr::empty(); constant::INODE_DIRECT_DATA_SECS],
             indirect_sector: LbaAddr::empty(),
+            double_indirect_sector: LbaAddr::empty(),
+            triple_indirect_sector: LbaAddr::empty(),
+            i_mode: 0,
+            i_uid: 0,
+            i_gid: 0,
+            i_nlink: 0,
+            i_ctime: 0,
+            i_mtime: 0,
+            i_atime: 0,
         }
     }
     pub fn new(i_no: InodeNo) -> Self {
@@ -52,6 +100,15 @@ impl Inode {
             i_size: 0,
             direct_sectors: [LbaAddr::empty(); constant::INODE_DIRECT_DATA_SECS],
             indirect_sector: LbaAddr::empty(),
+            double_indirect_sector: LbaAddr::empty(),
+            triple_indirect_sector: LbaAddr::empty(),
+            i_mode: 0,
+            i_uid: 0,
+            i_gid: 0,
+            i_nlink: 0,
+            i_ctime: 0,
+            i_mtime: 0,
+            i_atime: 0,
         }
     }
 
@@ -60,6 +117,15 @@ impl Inode {
         self.i_size = opened_inode.i_size;
         self.direct_sectors.copy_from_slice(opened_inode.get_direct_data_blocks_ref());
         self.indirect_sector = unsafe {*opened_inode.indirect_block_lba.get_mut()};
+        self.double_indirect_sector = opened_inode.double_indirect_block_lba;
+        self.triple_indirect_sector = opened_inode.triple_indirect_block_lba;
+        self.i_mode = opened_inode.i_mode;
+        self.i_uid = opened_inode.i_uid;
+        self.i_gid = opened_inode.i_gid;
+        self.i_nlink = opened_inode.i_nlink;
+        self.i_ctime = opened_inode.i_ctime;
+        self.i_mtime = opened_inode.i_mtime;
+        self.i_atime = opened_inode.i_atime;
     }
 
 }
@@ -80,10 +146,43 @@ pub struct OpenedInode {
      */
     pub i_size: u32,
 
+    /**
+     * 文件类型和权限
+     */
+    pub i_mode: u16,
+    /**
+     * 所属用户的id
+     */
+    pub i_uid: u16,
+    /**
+     * 所属用户组的id
+     */
+    pub i_gid: u16,
+    /**
+     * 硬链接的数量
+     */
+    pub i_nlink: u16,
+    /**
+     * 创建时间
+     */
+    pub i_ctime: u32,
+    /**
+     * 最后修改时间
+     */
+    pub i_mtime: u32,
+    /**
+     * 最后访问时间
+     */
+    pub i_atime: u32,
+
     /**
      * 该inode打开的次数
      */
     pub open_cnts: u32,
+    /**
+     * 最后一个链接已经被删除了。最后一个打开者关闭的时候，需要释放inode和数据块
+     */
+    pub unlinked: bool,
     /**
      * 写入拒绝（互斥）
      */
@@ -92,6 +191,10 @@ pub struct OpenedInode {
      * 标签
      */
     pub tag: LinkedNode,
+    /**
+     * 该inode所属的文件系统（挂载表中）
+     */
+    fs: *mut FileSystem,
     /**
      * 锁
      */
@@ -101,12 +204,23 @@ pub struct OpenedInode {
      * 数据块的缓存。每个元素是一个LBA地址
      * 不包括间接块（因为间接块没有存放数据）
      */
-    data_block_list: [LbaAddr; constant::INODE_DIRECT_DATA_SECS + (constant::INODE_INDIRECT_DATA_SECS * constants::DISK_SECTOR_SIZE) / size_of::<LbaAddr>()],
+    data_block_list: [LbaAddr; constant::INODE_CACHED_DATA_SECS],
     /**
      * 间接块的地址（这个块内，就是很多的间接数据块的LBA地址）
      */
     pub indirect_block_lba: RacyCell<LbaAddr>,
+    /**
+     * 二级间接块的地址。二级、三级间接块索引的数据块太多了，不做缓存，用到的时候从硬盘读取
+     */
+    pub double_indirect_block_lba: LbaAddr,
+    /**
+     * 三级间接块的地址
+     */
+    pub triple_indirect_block_lba: LbaAddr,
 }
+unsafe impl Sync for OpenedInode {}
+unsafe impl Send for OpenedInode {}
+
 impl Display for OpenedInode {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         printk!("(i_no: {}, i_size: {}, open_cnts: {})", self.i_no, self.i_size, self.open_cnts);
@@ -119,12 +233,23 @@ impl OpenedInode {
         let mut inode = Self {
             i_no: base_inode.i_no,
             i_size: base_inode.i_size,
+            i_mode: base_inode.i_mode,
+            i_uid: base_inode.i_uid,
+            i_gid: base_inode.i_gid,
+            i_nlink: base_inode.i_nlink,
+            i_ctime: base_inode.i_ctime,
+            i_mtime: base_inode.i_mtime,
+            i_atime: base_inode.i_atime,
             open_cnts: 0, // 创建出来认为打开0次，放入到了列表里
+            unlinked: false,
             write_deny: false,
             tag: LinkedNode::new(),
+            fs: ptr::null_mut(),
             lock: Lock::new(),
             indirect_block_lba: RacyCell::new(base_inode.indirect_sector),
-            data_block_list: [LbaAddr::empty(); constant::INODE_DIRECT_DATA_SECS + (constant::INODE_INDIRECT_DATA_SECS * constants::DISK_SECTOR_SIZE) / size_of::<LbaAddr>()],
+            double_indirect_block_lba: base_inode.double_indirect_sector,
+            triple_indirect_block_lba: base_inode.triple_indirect_sector,
+            data_block_list: [LbaAddr::empty(); constant::INODE_CACHED_DATA_SECS],
         };
         // 把硬盘中的该inode数据区，复制到缓冲区中
         inode.data_block_list[0..base_inode.direct_sectors.len()].copy_from_slice(&base_inode.direct_sectors);
@@ -134,6 +259,24 @@ impl OpenedInode {
         unsafe { &mut *elem2entry!(OpenedInode, tag, tag) }
     }
 
+    /**
+     * 设置该inode所属的文件系统
+     */
+    pub fn bind_fs(&mut self, fs: *mut FileSystem) {
+        self.fs = fs;
+    }
+
+    /**
+     * 该inode所属的文件系统。路径可能穿过挂载点，搜索到的inode不一定在根文件系统中
+     */
+    #[inline(never)]
+    pub fn get_fs(&self) -> &'static mut FileSystem {
+        if self.fs.is_null() {
+            MY_PANIC!("inode {} is not bound to a filesystem", self.i_no);
+        }
+        unsafe { &mut *self.fs }
+    }
+
 
     pub fn get_data_blocks_ref(&self) -> &[LbaAddr] {
         &self.data_block_list
@@ -232,6 +375,7 @@ pub fn inode_open(fs: &mut FileSystem, i_no: InodeNo) -> &'static mut OpenedInod
     let inode = self::load_inode(fs, i_no);
     // 把加载的inode，封装为一个打开的结构
     *opened_inode = OpenedInode::new(inode);
+    opened_inode.bind_fs(fs);
     // 打开次数 + 1
     opened_inode.reopen();
 
@@ -249,6 +393,10 @@ pub fn inode_close(fs: &mut FileSystem, inode: &mut OpenedInode) {
         fs.remove_inode(inode);
         // 解锁
         inode.lock.unlock();
+        // 已经没有链接指向这个inode了，释放inode以及数据块
+        if inode.unlinked {
+            self::inode_remove(fs, inode);
+        }
         let cur_task = &mut thread::current_thread().task_struct;
         let pgdir_bak = cur_task.pgdir;
         cur_task.pgdir = ptr::null_mut();
@@ -274,10 +422,13 @@ pub fn load_inode(fs: &FileSystem, i_no: InodeNo) -> Inode {
     let byte_cnt = inode_location.sec_cnt * constants::DISK_SECTOR_SIZE;
     let inode_buf = unsafe { slice::from_raw_parts_mut(memory::sys_malloc(byte_cnt) as *mut u8, byte_cnt) };
     // 从硬盘中读取扇区
-    disk.read_sectors(inode_location.lba, inode_location.sec_cnt, inode_buf);
+    buffer_cache::read_sectors(disk, inode_location.lba, inode_location.sec_cnt, inode_buf);
 
-    // 根据字节偏移量，找到这个inode数据
-    let target_inode = unsafe { *(inode_buf[inode_location.bytes_off .. ].as_ptr() as *const Inode) };
+    // 根据字节偏移量，找到这个inode数据。旧格式的inode比较小，缺少的字段保持为空
+    let mut target_inode = Inode::empty();
+    let inode_size = fs.super_block.get_inode_size().min(size_of::<Inode>());
+    let target_inode_buf = unsafe { slice::from_raw_parts_mut(&mut target_inode as *mut _ as *mut u8, inode_size) };
+    target_inode_buf.copy_from_slice(&inode_buf[inode_location.bytes_off .. inode_location.bytes_off + inode_size]);
     memory::sys_free(inode_buf.as_ptr() as usize);
 
     target_inode
@@ -289,16 +440,18 @@ pub fn load_inode(fs: &FileSystem, i_no: InodeNo) -> Inode {
  */
 #[inline(never)]
 pub fn locate_inode(fs: &FileSystem, i_no: InodeNo) -> InodeLocation {
-    if u32::from(i_no) >  constant::MAX_FILE_PER_FS {
-        MY_PANIC!("failed to locate inode({:?}). exceed maximum({})", i_no, constant::MAX_FILE_PER_FS);
+    if u32::from(i_no) >= fs.super_block.inode_cnt {
+        MY_PANIC!("failed to locate inode({:?}). exceed maximum({})", i_no, fs.super_block.inode_cnt);
     }
+    // 硬盘中inode的大小（旧格式的inode更小）
+    let inode_size = fs.super_block.get_inode_size();
     // inode所在相对inode数组，开始的字节偏移量
-    let i_idx_start = usize::from(i_no) * size_of::<Inode>();
+    let i_idx_start = usize::from(i_no) * inode_size;
     // 换算成扇区偏移数
     let sec_start = i_idx_start / constants::DISK_SECTOR_SIZE;
 
     // inode所在相对inode数组，结束的字节偏移量
-    let i_idx_end = (usize::from(i_no) + 1) as usize * size_of::<Inode>();
+    let i_idx_end = (usize::from(i_no) + 1) as usize * inode_size;
     // inode结束的偏移量，换算成扇区偏移数
     let sec_end: usize = utils::div_ceil(i_idx_end as u32, constants::DISK_SECTOR_SIZE  as u32).try_into().unwrap();
     InodeLocation {
@@ -327,15 +480,17 @@ pub fn sync_inode(fs: &mut FileSystem, opened_inode: &mut OpenedInode) {
     let buf = unsafe { slice::from_raw_parts_mut(buff_addr as *mut u8, buf_size) };
 
     // 读取出inode所在的扇区
-    disk.read_sectors(i_location.lba, i_location.sec_cnt, buf);
+    buffer_cache::read_sectors(disk, i_location.lba, i_location.sec_cnt, buf);
 
-    // 硬盘中的inode结构
-    let inode_from_disk = unsafe { &mut *(buf.as_mut_ptr().add(i_location.bytes_off) as *mut Inode) };
-    // 把内存中的inode结构，复制到硬盘的inode结构中
-    inode_from_disk.from(opened_inode);
+    // 把内存中的inode结构，复制到硬盘的inode结构中（只覆盖硬盘中inode大小的部分，不影响相邻的inode）
+    let mut inode_to_disk = Inode::empty();
+    inode_to_disk.from(opened_inode);
+    let inode_size = fs.super_block.get_inode_size().min(size_of::<Inode>());
+    let inode_to_disk_buf = unsafe { slice::from_raw_parts(&inode_to_disk as *const _ as *const u8, inode_size) };
+    buf[i_location.bytes_off .. i_location.bytes_off + inode_size].copy_from_slice(inode_to_disk_buf);
 
     // 把inode写回到硬盘中
-    disk.write_sector(buf, i_location.lba, i_location.sec_cnt.try_into().unwrap());
+    journal::write_metadata(&mut fs.journal, buf, i_location.lba, i_location.sec_cnt.try_into().unwrap());
 
 
     /*****2. 处理inode的间接块***************/
@@ -352,21 +507,24 @@ pub fn sync_inode(fs: &mut FileSystem, opened_inode: &mut OpenedInode) {
     // 用内存的数据，覆盖硬盘的数据
     indirect_block_sec_lba[..opened_inode.get_indirect_data_blocks_ref().len()].copy_from_slice(opened_inode.get_indirect_data_blocks_ref());
     // 写回到硬盘中
-    disk.write_sector(buf, *indirect_block_lba, 1);
+    journal::write_metadata(&mut fs.journal, buf, *indirect_block_lba, 1);
 }
 
 /**
  * 申请一个间接块
  *  - 如果间接块已经存在，那也不用申请
+ *  - 如果没有空闲的数据块了，返回None
  */
 #[inline(never)]
-pub fn apply_indirect_data_block(fs: &mut FileSystem, opened_inode: &mut OpenedInode) {
+pub fn apply_indirect_data_block(fs: &mut FileSystem, opened_inode: &mut OpenedInode) -> Option<()> {
     // 数组最后一个元素，是间接块的LBA地址。这个块里面，是很多的LBA地址
     let indirect_lba = *unsafe { opened_inode.indirect_block_lba.get_mut() };
     if !indirect_lba.is_empty() {
-        return;
+        return Option::Some(());
     }
-    opened_inode.indirect_block_lba = RacyCell::new(fs.data_block_pool.apply_block(1));
+    let index_block_lba = self::apply_index_block(fs)?;
+    opened_inode.indirect_block_lba = RacyCell::new(index_block_lba);
+    Option::Some(())
 }
 
 /**
@@ -389,38 +547,296 @@ pub fn load_indirect_data_block(fs: &mut FileSystem, opened_inode: &mut OpenedIn
     let left_unfilled_lba = opened_inode.get_indirect_data_blocks();
     let buf = unsafe { slice::from_raw_parts_mut(left_unfilled_lba.as_mut_ptr() as *mut u8, left_unfilled_lba.len() * (size_of::<LbaAddr>() / size_of::<u8>())) };
     // 读取硬盘。把数据写入到数组里。最终也是写入到缓存里了
-    disk.read_sectors(indirect_lba, 1, buf)
+    buffer_cache::read_sectors(disk, indirect_lba, 1, buf)
 }
 
 
 /**
- * 删除一个inode，以及清除该inode下的数据区
+ * 找到inode第block_idx个数据块的LBA地址
+ *  - 直接块和一级间接块，从缓存中取（如果用到了一级间接块，调用方需要先加载间接块）
+ *  - 二级、三级间接块，从硬盘中逐级读取
+ * 返回值：数据块的LBA地址。如果这个数据块还没有分配，那么返回空地址
+ */
+#[inline(never)]
+pub fn get_data_block(fs: &mut FileSystem, opened_inode: &mut OpenedInode, block_idx: usize) -> LbaAddr {
+    if block_idx < constant::INODE_CACHED_DATA_SECS {
+        return opened_inode.get_data_blocks_ref()[block_idx];
+    }
+    let (root_lba, level, idx_in_tree) = self::locate_indirect_tree(opened_inode, block_idx);
+    let mut root_lba = *root_lba;
+    let res = self::walk_indirect_tree(fs, &mut root_lba, level, idx_in_tree, false);
+    if res.is_none() {
+        return LbaAddr::empty();
+    }
+    res.unwrap().0
+}
+
+/**
+ * 找到inode第block_idx个数据块的LBA地址，如果还没有分配，那么申请一个数据块（途经的间接块也会按需申请）
+ *  - 直接块和一级间接块的地址，写入缓存中，由调用方sync_inode同步到硬盘
+ *  - 二级、三级间接块的内容，在申请的时候就同步到硬盘了
+ * 返回值：
+ *  - LbaAddr: 数据块的LBA地址
+ *  - bool: 这个数据块是否是新申请的
+ *  - None: 超过了该文件系统单个文件的最大数据块数量，或者除了保留的数据块，已经没有空闲的数据块（数据块或者途经的间接块）了
+ */
+#[inline(never)]
+pub fn apply_data_block(fs: &mut FileSystem, opened_inode: &mut OpenedInode, block_idx: usize) -> Option<(LbaAddr, bool)> {
+    if block_idx >= fs.super_block.max_data_blocks() {
+        return Option::None;
+    }
+    if block_idx < constant::INODE_CACHED_DATA_SECS {
+        // 用到了一级间接块，先申请间接块
+        if block_idx >= constant::INODE_DIRECT_DATA_SECS && self::apply_indirect_data_block(fs, opened_inode).is_none() {
+            return Option::None;
+        }
+        let data_block_lba = &mut opened_inode.get_data_blocks()[block_idx];
+        if !data_block_lba.is_empty() {
+            return Option::Some((*data_block_lba, false));
+        }
+        let new_block_lba = fs.data_block_pool.try_apply_block(&mut fs.journal);
+        if new_block_lba.is_none() {
+            return Option::None;
+        }
+        *data_block_lba = new_block_lba.unwrap();
+        return Option::Some((*data_block_lba, true));
+    }
+    let (root_lba, level, idx_in_tree) = self::locate_indirect_tree(opened_inode, block_idx);
+    self::walk_indirect_tree(fs, root_lba, level, idx_in_tree, true)
+}
+
+/**
+ * 第block_idx个数据块（超过了缓存的部分），位于哪一棵间接块树中
+ * 返回值：
+ *  - &mut LbaAddr: 这棵树根节点（二级或三级间接块）的地址
+ *  - u32: 树的层级。2：二级间接块；3：三级间接块
+ *  - usize: 数据块在这棵树中的下标
  */
 #[inline(never)]
-pub fn inode_remove(fs: &mut FileSystem, inode: &OpenedInode) {
+fn locate_indirect_tree(opened_inode: &mut OpenedInode, block_idx: usize) -> (&mut LbaAddr, u32, usize) {
+    let idx_in_tree = block_idx - constant::INODE_CACHED_DATA_SECS;
+    let double_indirect_blocks = constant::LBA_PER_BLOCK * constant::LBA_PER_BLOCK;
+    if idx_in_tree < double_indirect_blocks {
+        return (&mut opened_inode.double_indirect_block_lba, 2, idx_in_tree);
+    }
+    (&mut opened_inode.triple_indirect_block_lba, 3, idx_in_tree - double_indirect_blocks)
+}
+
+/**
+ * 在一棵level层的间接块树中，逐级找到第idx_in_tree个数据块
+ *  - apply: 如果途经的间接块、最终的数据块不存在，是否申请
+ * 返回值：数据块的LBA地址，以及是否是新申请的。如果不申请并且不存在，或者数据块、间接块申请不到，返回None
+ */
+#[inline(never)]
+fn walk_indirect_tree(fs: &mut FileSystem, root_lba: &mut LbaAddr, level: u32, idx_in_tree: usize, apply: bool) -> Option<(LbaAddr, bool)> {
+    if root_lba.is_empty() {
+        if !apply {
+            return Option::None;
+        }
+        *root_lba = self::apply_index_block(fs)?;
+    }
     let disk = unsafe { &mut *fs.base_part.from_disk };
     let buf: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(constants::DISK_SECTOR_SIZE);
+    let lba_list = unsafe { slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut LbaAddr, constant::LBA_PER_BLOCK) };
+
+    let mut cur_block_lba = *root_lba;
+    let mut idx_in_tree = idx_in_tree;
+    let mut res = Option::None;
+    // 从根节点开始，每一层往下找一级
+    for cur_level in (1..=level).rev() {
+        // 当前这一层，每一个元素可以索引的数据块数量
+        let blocks_per_entry = constant::LBA_PER_BLOCK.pow(cur_level - 1);
+        let entry_idx = idx_in_tree / blocks_per_entry;
+        idx_in_tree %= blocks_per_entry;
+
+        buffer_cache::read_sectors(disk, cur_block_lba, 1, buf);
+        let mut new_block = false;
+        if lba_list[entry_idx].is_empty() {
+            if !apply {
+                break;
+            }
+            // 最后一层是数据块，其他层是间接块（间接块需要清零）。都不能使用保留的数据块
+            let new_block_lba = if cur_level == 1 { fs.data_block_pool.try_apply_block(&mut fs.journal) } else { self::apply_index_block(fs) };
+            if new_block_lba.is_none() {
+                break;
+            }
+            lba_list[entry_idx] = new_block_lba.unwrap();
+            new_block = true;
+            // 间接块的内容发生了变化，写回到硬盘
+            journal::write_metadata(&mut fs.journal, buf, cur_block_lba, 1);
+        }
+        if cur_level == 1 {
+            res = Option::Some((lba_list[entry_idx], new_block));
+        }
+        cur_block_lba = lba_list[entry_idx];
+    }
+    memory::sys_free(buf.as_ptr() as usize);
+    res
+}
+
+/**
+ * 申请一个间接块，并且清零（间接块里面的LBA地址都是空的）
+ *  - 间接块只使用第一个扇区，块中其他的扇区不读写
+ *  - 跟数据块一样，不能使用保留的数据块。申请不到返回None
+ */
+#[inline(never)]
+fn apply_index_block(fs: &mut FileSystem) -> Option<LbaAddr> {
+    let block_lba = fs.data_block_pool.try_apply_block(&mut fs.journal)?;
+    let buf: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(constants::DISK_SECTOR_SIZE);
+    unsafe { buf.as_mut_ptr().write_bytes(0, buf.len()) };
+    journal::write_metadata(&mut fs.journal, buf, block_lba, 1);
+    memory::sys_free(buf.as_ptr() as usize);
+    Option::Some(block_lba)
+}
+
+/**
+ * 根据文件大小，计算文件占用的块数量：数据块，以及用到的间接块（按照没有空洞的文件计算，不用遍历间接块树）
+ */
+#[inline(never)]
+pub fn size_to_blocks(fs: &FileSystem, i_size: u32) -> u32 {
+    let data_blocks = (i_size as usize).div_ceil(fs.super_block.get_block_size());
+    let mut blocks = data_blocks;
+    // 一级间接块
+    if data_blocks > constant::INODE_DIRECT_DATA_SECS {
+        blocks += 1;
+    }
+    // 二级间接块树：根节点 + 用到的一级间接块
+    let double_indirect_blocks = constant::LBA_PER_BLOCK * constant::LBA_PER_BLOCK;
+    let over_cached = data_blocks.saturating_sub(constant::INODE_CACHED_DATA_SECS);
+    if over_cached > 0 {
+        blocks += 1 + over_cached.min(double_indirect_blocks).div_ceil(constant::LBA_PER_BLOCK);
+    }
+    // 三级间接块树：根节点 + 用到的二级、一级间接块
+    let over_double = over_cached.saturating_sub(double_indirect_blocks);
+    if over_double > 0 {
+        blocks += 1 + over_double.div_ceil(double_indirect_blocks) + over_double.div_ceil(constant::LBA_PER_BLOCK);
+    }
+    blocks as u32
+}
+
+/**
+ * 把inode自身所在的扇区，以及它的所有块（数据块、间接块），在缓冲区中的脏数据写回到硬盘
+ *  - 调用之前，内存中的inode需要先通过sync_inode写入到缓冲区
+ */
+#[inline(never)]
+pub fn sync_blocks(fs: &mut FileSystem, opened_inode: &mut OpenedInode) {
+    let disk = unsafe { &mut *fs.base_part.from_disk };
+    let i_location = self::locate_inode(fs, opened_inode.i_no);
+    buffer_cache::sync_sectors(disk, i_location.lba, i_location.sec_cnt);
+
+    // 直接块和一级间接块中的数据块
+    self::load_indirect_data_block(fs, opened_inode);
+    let sec_per_block = fs.super_block.sec_per_block();
+    for block_lba in opened_inode.get_data_blocks_ref() {
+        if !block_lba.is_empty() {
+            buffer_cache::sync_sectors(disk, *block_lba, sec_per_block);
+        }
+    }
+    let indirect_block_lba = unsafe { *opened_inode.indirect_block_lba.get_mut() };
+    if !indirect_block_lba.is_empty() {
+        buffer_cache::sync_sectors(disk, indirect_block_lba, 1);
+    }
+    // 二级、三级间接块树
+    self::sync_indirect_tree(fs, opened_inode.double_indirect_block_lba, 2);
+    self::sync_indirect_tree(fs, opened_inode.triple_indirect_block_lba, 3);
+}
+
+/**
+ * 把一棵level层的间接块树中所有的块（包括树中的间接块），写回到硬盘
+ */
+#[inline(never)]
+fn sync_indirect_tree(fs: &mut FileSystem, root_lba: LbaAddr, level: u32) {
+    if root_lba.is_empty() {
+        return;
+    }
+    let disk = unsafe { &mut *fs.base_part.from_disk };
+    if level > 0 {
+        let buf: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(constants::DISK_SECTOR_SIZE);
+        buffer_cache::read_sectors(disk, root_lba, 1, buf);
+        let lba_list = unsafe { slice::from_raw_parts(buf.as_ptr() as *const LbaAddr, constant::LBA_PER_BLOCK) };
+        for child_lba in lba_list {
+            self::sync_indirect_tree(fs, *child_lba, level - 1);
+        }
+        memory::sys_free(buf.as_ptr() as usize);
+        buffer_cache::sync_sectors(disk, root_lba, 1);
+        return;
+    }
+    // 最后一层是数据块，整个块都要写回
+    buffer_cache::sync_sectors(disk, root_lba, fs.super_block.sec_per_block());
+}
+
+/**
+ * 清零并且释放一个块
+ *  - zero_buf: 全是0的缓冲区，大小是一个块
+ */
+#[inline(never)]
+fn release_block(fs: &mut FileSystem, block_lba: LbaAddr, zero_buf: &[u8]) {
+    if block_lba.is_empty() {
+        return;
+    }
+    // 日志放不下块位图了，先提交。inode已经释放了，这时候断电，剩下的块只是没有被回收（fsck可以修复）
+    if !journal::has_room(fs, 1) {
+        journal::flush(fs);
+    }
+    let disk = unsafe { &mut *fs.base_part.from_disk };
+    buffer_cache::write_sector(disk, zero_buf, block_lba, zero_buf.len() / constants::DISK_SECTOR_SIZE);
+    fs.data_block_pool.release_block(&mut fs.journal, block_lba);
+}
+
+/**
+ * 释放一棵level层的间接块树：所有的数据块，以及树中所有的间接块
+ */
+#[inline(never)]
+fn release_indirect_tree(fs: &mut FileSystem, root_lba: LbaAddr, level: u32, zero_buf: &[u8]) {
+    if root_lba.is_empty() {
+        return;
+    }
+    if level > 0 {
+        let disk = unsafe { &mut *fs.base_part.from_disk };
+        let buf: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(constants::DISK_SECTOR_SIZE);
+        buffer_cache::read_sectors(disk, root_lba, 1, buf);
+        let lba_list = unsafe { slice::from_raw_parts(buf.as_ptr() as *const LbaAddr, constant::LBA_PER_BLOCK) };
+        for child_lba in lba_list {
+            self::release_indirect_tree(fs, *child_lba, level - 1, zero_buf);
+        }
+        memory::sys_free(buf.as_ptr() as usize);
+    }
+    self::release_block(fs, root_lba, zero_buf);
+}
+
+/**
+ * 删除一个inode，以及清除该inode下的数据区
+ */
+#[inline(never)]
+pub fn inode_remove(fs: &mut FileSystem, inode: &mut OpenedInode) {
+    // 释放inode和数据块，放在一个日志事务中
+    let trans = journal::begin(fs, constant::JOURNAL_REMOVE_INODE_CREDITS);
+    // 外层事务预留的扇区不够了。先不释放，inode和数据块只是没有被回收（fsck可以修复）
+    if trans.is_err() {
+        printkln!("failed to remove inode {}, journal transaction is full", inode.i_no.get_data());
+        return;
+    }
+    let _trans = trans.unwrap();
+    // 把这个inode的数据扇区LBA地址都加载出来（间接扇区）
+    self::load_indirect_data_block(fs, inode);
+    let block_size = fs.super_block.get_block_size();
+    let buf = unsafe { slice::from_raw_parts_mut(memory::sys_malloc(block_size) as *mut u8, block_size) };
 
     // 在inode位图中释放这个inode
-    fs.inode_pool.release_inode(inode.i_no);
+    fs.inode_pool.release_inode(&mut fs.journal, inode.i_no);
 
-    // 把该inode下的所有数据区扇区清零
+    // 把该inode下的所有数据区扇区清零，并且释放（直接块和一级间接块的数据块，在缓存中）
+    unsafe { buf.as_mut_ptr().write_bytes(0, buf.len()) };
     for block_lba in inode.get_data_blocks_ref() {
-        // 清零
-        unsafe { buf.as_mut_ptr().write_bytes(0, buf.len()) };
-        // 写入到硬盘
-        disk.write_sector(buf, *block_lba, 1)
+        self::release_block(fs, *block_lba, buf);
     }
+    // 一级间接块自身
+    self::release_block(fs, unsafe { *inode.indirect_block_lba.get_mut() }, buf);
+
+    // 二级、三级间接块树
+    self::release_indirect_tree(fs, inode.double_indirect_block_lba, 2, buf);
+    self::release_indirect_tree(fs, inode.triple_indirect_block_lba, 3, buf);
 
     // 释放缓冲区
     memory::sys_free(buf.as_ptr() as usize);
-
-    // 释放 该inode的所有数据区
-    for block_lba in inode.get_data_blocks_ref() {
-        if block_lba.is_empty() {
-            continue;
-        }
-        // 释放这个数据区
-        fs.data_block_pool.release_block(*block_lba);
-    }
-}
\ No newline at end of file
+}
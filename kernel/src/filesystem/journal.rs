@@ -0,0 +1,303 @@
+use core::mem::size_of;
+
+use os_in_rust_common::{constants, domain::LbaAddr, printkln};
+
+use crate::{device::{Disk, Partition}, memory, sync::Lock};
+
+use super::{buffer_cache, constant, file::FileError, fs::FileSystem, superblock::SuperBlock};
+
+/**
+ * 元数据日志（预写日志）。保证元数据修改的崩溃一致性
+ * 日志区的结构：
+ * | 日志头(1扇区) | 元数据扇区的副本(JOURNAL_MAX_BLOCKS扇区) |
+ *
+ * 每个文件系统有自己的日志状态（FileSystem.journal），一个事务的流程：
+ *  1. begin：开始事务，并且预留credits个元数据扇区。放不下的时候返回TransactionTooLarge
+ *     - 事务加了这个文件系统的事务锁，直到最外层的事务结束才释放。其他任务的事务、元数据写入，都要等待
+ *     - 事务可以嵌套（同一个任务重入事务锁），最外层的事务结束的时候才提交。外层事务预留的扇区，要包括嵌套的事务
+ *     - 写大文件、删除大文件这种修改量没有上限的操作，自己在元数据一致的地方，通过has_room判断、flush提交
+ *  2. write_metadata：元数据写入缓冲区，并且钉住（提交之前不会写回到原本的位置），记录扇区的LBA地址
+ *  3. 提交：把元数据扇区的副本写入日志区，再写日志头（写入日志头之后，事务就提交成功了）
+ *  4. 检查点：把元数据扇区写回到原本的位置，然后清空日志头
+ *
+ * 挂载的时候，如果日志头中有已经提交的事务（上次没有完成检查点就断电了），重放日志
+ */
+
+/**
+ * 日志头。位于日志区的第一个扇区
+ */
+#[repr(C, align(512))]
+pub struct JournalHeader {
+    magic: u32,
+    /**
+     * 事务的序号
+     */
+    seq: u32,
+    /**
+     * 事务中元数据扇区的数量。0表示日志区中没有需要重放的事务
+     */
+    block_cnt: u32,
+    /**
+     * 每一个元数据扇区，原本的LBA地址。第i个扇区的副本，位于日志区的第i+1个扇区
+     */
+    blocks: [LbaAddr; constant::JOURNAL_MAX_BLOCKS],
+}
+
+impl JournalHeader {
+    /**
+     * 日志区中，是否有已经提交、但是还没有完成检查点的事务（需要重放）
+     */
+    #[inline(never)]
+    pub fn is_pending(&self) -> bool {
+        self.magic == constant::JOURNAL_MAGIC && self.block_cnt > 0 && self.block_cnt as usize <= constant::JOURNAL_MAX_BLOCKS
+    }
+}
+
+/**
+ * 一个文件系统的日志状态（当前的事务）
+ */
+pub struct Journal {
+    /**
+     * 文件系统所在的硬盘
+     */
+    disk: *mut Disk,
+    /**
+     * 文件系统日志区的起始LBA地址
+     */
+    journal_lba: LbaAddr,
+    /**
+     * 事务嵌套的层数。0表示当前没有事务
+     */
+    depth: u32,
+    /**
+     * 事务的序号
+     */
+    seq: u32,
+    /**
+     * 事务中修改过的元数据扇区的数量
+     */
+    block_cnt: usize,
+    /**
+     * 事务中修改过的元数据扇区
+     */
+    blocks: [LbaAddr; constant::JOURNAL_MAX_BLOCKS],
+    /**
+     * 事务锁。从最外层的begin，一直持有到最外层的事务结束
+     */
+    lock: Lock,
+}
+
+impl Journal {
+    #[inline(never)]
+    pub fn new(disk: *mut Disk, journal_lba: LbaAddr) -> Self {
+        Self {
+            disk,
+            journal_lba,
+            depth: 0,
+            seq: 0,
+            block_cnt: 0,
+            blocks: [LbaAddr::new(0); constant::JOURNAL_MAX_BLOCKS],
+            lock: Lock::new(),
+        }
+    }
+
+    /**
+     * 事务中是否已经记录了这个扇区
+     */
+    #[inline(never)]
+    fn contains(&self, lba: LbaAddr) -> bool {
+        self.blocks[..self.block_cnt].iter().any(|block| block.get_lba() == lba.get_lba())
+    }
+
+    /**
+     * 提交当前事务，并且做检查点
+     */
+    #[inline(never)]
+    fn commit(&mut self) {
+        if self.block_cnt == 0 {
+            return;
+        }
+        let disk = unsafe { &mut *self.disk };
+        let header: &mut JournalHeader = memory::malloc(size_of::<JournalHeader>());
+        let buf: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(constants::DISK_SECTOR_SIZE);
+
+        // 1. 元数据扇区的副本，写入日志区（直接写硬盘，不经过缓冲区）
+        for (idx, block_lba) in self.blocks[..self.block_cnt].iter().enumerate() {
+            buffer_cache::read_sectors(disk, *block_lba, 1, buf);
+            disk.write_sector(buf, self.journal_lba.add(1 + idx as u32), 1);
+        }
+
+        // 2. 写入日志头。写完之后，这个事务就提交成功了
+        self.seq = self.seq.wrapping_add(1);
+        header.magic = constant::JOURNAL_MAGIC;
+        header.seq = self.seq;
+        header.block_cnt = self.block_cnt as u32;
+        header.blocks[..self.block_cnt].copy_from_slice(&self.blocks[..self.block_cnt]);
+        self::write_header(disk, self.journal_lba, header);
+
+        // 3. 检查点：元数据写回到原本的位置
+        for block_lba in self.blocks[..self.block_cnt].iter() {
+            buffer_cache::unpin_sectors(disk, *block_lba, 1);
+            buffer_cache::sync_sectors(disk, *block_lba, 1);
+        }
+
+        // 4. 清空日志头，日志区中没有需要重放的事务了
+        header.block_cnt = 0;
+        self::write_header(disk, self.journal_lba, header);
+        self.block_cnt = 0;
+
+        memory::sys_free(buf.as_ptr() as usize);
+        memory::sys_free(header as *const _ as usize);
+    }
+}
+
+/**
+ * 一个事务。离开作用域的时候（drop），结束这个事务
+ */
+pub struct Transaction {
+    journal: *mut Journal,
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        let journal = unsafe { &mut *self.journal };
+        journal.depth -= 1;
+        // 最外层的事务结束了，提交
+        if journal.depth == 0 {
+            journal.commit();
+        }
+        // 释放begin的时候加的事务锁
+        journal.lock.unlock();
+    }
+}
+
+/**
+ * 在文件系统fs上，开始一个事务，并且预留credits个元数据扇区。返回的事务离开作用域的时候结束
+ *  - 事务锁一直持有到事务结束，预留的扇区不会被其他任务用掉
+ *  - 事务可以嵌套，最外层的事务结束的时候才提交
+ *  - 当前事务剩余的扇区，放不下credits个扇区，返回TransactionTooLarge（事务没有开始）
+ */
+#[inline(never)]
+pub fn begin(fs: &mut FileSystem, credits: usize) -> Result<Transaction, FileError> {
+    let journal = &mut fs.journal;
+    journal.lock.lock();
+    if journal.block_cnt + credits > constant::JOURNAL_MAX_BLOCKS {
+        printkln!("journal transaction too large. blocks:{}, credits:{}", journal.block_cnt, credits);
+        journal.lock.unlock();
+        return Result::Err(FileError::TransactionTooLarge);
+    }
+    journal.depth += 1;
+    Result::Ok(Transaction { journal: journal as *mut Journal })
+}
+
+/**
+ * 把元数据写入到lba_start开始的连续sec_cnt个扇区中
+ *  - 在事务中：写入缓冲区并且钉住，等事务提交之后才写回到硬盘
+ *  - 不在事务中：等待其他任务的事务结束，直接写入缓冲区
+ */
+#[inline(never)]
+pub fn write_metadata(journal: &mut Journal, buf: &[u8], lba_start: LbaAddr, sec_cnt: usize) {
+    journal.lock.lock();
+    let disk = unsafe { &mut *journal.disk };
+    if journal.depth == 0 {
+        buffer_cache::write_sector(disk, buf, lba_start, sec_cnt);
+        journal.lock.unlock();
+        return;
+    }
+    // 新增的扇区数量
+    let new_blocks = (0..sec_cnt).filter(|sec_idx| !journal.contains(lba_start.add(*sec_idx as u32))).count();
+    // 超过了begin预留的扇区（预留的数量算少了）。不能在操作的中途提交（会把一个操作拆成两半），只能不经过日志直接写入
+    if journal.block_cnt + new_blocks > constant::JOURNAL_MAX_BLOCKS {
+        printkln!("journal transaction overflow, write through. blocks:{}, new blocks:{}", journal.block_cnt, new_blocks);
+        buffer_cache::write_sector(disk, buf, lba_start, sec_cnt);
+        journal.lock.unlock();
+        return;
+    }
+    buffer_cache::write_sector_pinned(disk, buf, lba_start, sec_cnt);
+    for sec_idx in 0..sec_cnt {
+        let sec_lba = lba_start.add(sec_idx as u32);
+        if !journal.contains(sec_lba) {
+            journal.blocks[journal.block_cnt] = sec_lba;
+            journal.block_cnt += 1;
+        }
+    }
+    journal.lock.unlock();
+}
+
+/**
+ * 文件系统fs当前的事务中，是否还能再记录credits个元数据扇区
+ *  - 没有事务的时候，元数据直接写入缓冲区，不受限制
+ */
+#[inline(never)]
+pub fn has_room(fs: &mut FileSystem, credits: usize) -> bool {
+    let journal = &mut fs.journal;
+    journal.lock.lock();
+    let room = journal.depth == 0 || journal.block_cnt + credits <= constant::JOURNAL_MAX_BLOCKS;
+    journal.lock.unlock();
+    room
+}
+
+/**
+ * 提交文件系统fs当前事务中，已经记录的修改
+ *  - 在事务的中途调用，调用方需要保证此时已经记录的元数据是一致的
+ *  - 其他任务正在事务中，等待它的事务结束（结束的时候已经提交了）
+ */
+#[inline(never)]
+pub fn flush(fs: &mut FileSystem) {
+    let journal = &mut fs.journal;
+    journal.lock.lock();
+    journal.commit();
+    journal.lock.unlock();
+}
+
+/**
+ * 安装文件系统的时候，清空日志区
+ */
+#[inline(never)]
+pub fn install(part: &Partition, super_block: &SuperBlock) {
+    let disk = unsafe { &mut *part.from_disk };
+    let header: &mut JournalHeader = memory::malloc(size_of::<JournalHeader>());
+    unsafe { (header as *mut JournalHeader).write_bytes(0, 1) };
+    header.magic = constant::JOURNAL_MAGIC;
+    self::write_header(disk, super_block.journal_lba, header);
+    memory::sys_free(header as *const _ as usize);
+}
+
+/**
+ * 挂载的时候，重放日志区中已经提交的事务
+ *  - 日志头中有已经提交的事务，说明上次提交之后，没有完成检查点（断电或者重启了）
+ *  - 把日志区中元数据扇区的副本，写回到原本的位置，再清空日志头
+ */
+#[inline(never)]
+pub fn replay(part: &Partition, super_block: &SuperBlock) {
+    let disk = unsafe { &mut *part.from_disk };
+    let header: &mut JournalHeader = memory::malloc(size_of::<JournalHeader>());
+    let header_buf = unsafe { core::slice::from_raw_parts_mut(header as *mut _ as *mut u8, size_of::<JournalHeader>()) };
+    disk.read_sectors(super_block.journal_lba, 1, header_buf);
+
+    let block_cnt = header.block_cnt as usize;
+    if header.is_pending() {
+        printkln!("replaying journal of {}: seq {}, {} blocks", part.get_name(), header.seq, block_cnt);
+        let buf: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(constants::DISK_SECTOR_SIZE);
+        for idx in 0..block_cnt {
+            let block_lba = header.blocks[idx];
+            disk.read_sectors(super_block.journal_lba.add(1 + idx as u32), 1, buf);
+            disk.write_sector(buf, block_lba, 1);
+            // 缓冲区中这个扇区的数据是旧的
+            buffer_cache::invalidate(disk, block_lba, 1);
+        }
+        memory::sys_free(buf.as_ptr() as usize);
+        header.block_cnt = 0;
+        self::write_header(disk, super_block.journal_lba, header);
+    }
+    memory::sys_free(header as *const _ as usize);
+}
+
+/**
+ * 把日志头写入到日志区的第一个扇区（直接写硬盘，不经过缓冲区）
+ */
+#[inline(never)]
+fn write_header(disk: &mut Disk, journal_lba: LbaAddr, header: &JournalHeader) {
+    let header_buf = unsafe { core::slice::from_raw_parts(header as *const _ as *const u8, size_of::<JournalHeader>()) };
+    disk.write_sector(header_buf, journal_lba, 1);
+}
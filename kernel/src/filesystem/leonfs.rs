@@ -0,0 +1,275 @@
+use core::{mem::size_of, slice};
+
+use os_in_rust_common::{constants, domain::InodeNo};
+
+use crate::{device::Partition, memory};
+
+use super::{
+    buffer_cache, constant, dir, dir_entry::{self, DirEntry, DirEntryBlockIter, DirEntrySearchReq, FileType}, file::{self, FileError}, fs::FileSystem, fsck, fsync, inode::{self, OpenedInode}, journal, link, rename, stat::{FileStat, FsStat}, superblock::SuperBlock, vfs::{Vfs, Vnode}
+};
+
+/**
+ * LeonFS：本系统自己的文件系统，VFS的第一个实现
+ *  - Vnode的数据，是打开的inode（OpenedInode）的地址
+ *  - 具体的操作，还是由inode、dir_entry、file等模块完成，这里只是把它们适配到Vfs
+ */
+
+/**
+ * 从分区中加载文件系统：读取超级块，重放日志，检查一致性，再加载inode位图、块位图以及根目录
+ * 文件系统的结构放在堆中，卸载之前地址不会变化，打开的inode可以用指针找到所属的文件系统
+ */
+#[inline(never)]
+pub fn load(part: &'static Partition) -> &'static mut FileSystem {
+    let disk = unsafe { &mut *part.from_disk };
+
+    // SuperBlock
+    let super_block: &mut SuperBlock = memory::malloc(size_of::<SuperBlock>());
+    let sb_buf = unsafe { slice::from_raw_parts_mut(super_block as *mut _ as *mut u8, size_of::<SuperBlock>()) };
+    // 读取SuperBlock
+    buffer_cache::read_sectors(disk, part.abs_lba_start(1), 1, sb_buf);
+
+    // 上次没有正常关机的话，重放日志中已经提交的事务
+    journal::replay(part, super_block);
+
+    // 挂载之前检查文件系统的一致性，发现问题就修复（旧格式的inode中没有文件类型，无法检查）
+    if !super_block.has_legacy_inode() {
+        fsck::check_partition(part, super_block, true);
+    }
+
+    // inode位图
+    let inode_bitmap_len = super_block.inode_bitmap_secs as usize * constants::DISK_SECTOR_SIZE;
+    let inode_bitmap_bits = unsafe { slice::from_raw_parts_mut(memory::sys_malloc(inode_bitmap_len) as *mut u8, inode_bitmap_len) };
+    buffer_cache::read_sectors(disk, super_block.inode_bitmap_lba, super_block.inode_bitmap_secs as usize, inode_bitmap_bits);
+
+    // 块位图
+    let block_bitmap_len = super_block.block_bitmap_secs as usize * constants::DISK_SECTOR_SIZE;
+    let block_bitmap_bits = unsafe { slice::from_raw_parts_mut(memory::sys_malloc(block_bitmap_len) as *mut u8, block_bitmap_len) };
+    buffer_cache::read_sectors(disk, super_block.block_bitmap_lba, super_block.block_bitmap_secs as usize, block_bitmap_bits);
+
+    // 挂载的分区。构建文件系统
+    let fs: &mut FileSystem = memory::malloc_system(size_of::<FileSystem>());
+    *fs = FileSystem::new(part, super_block, inode_bitmap_bits, block_bitmap_bits);
+
+    // 文件系统的地址已经固定了，这时候才能加载根目录
+    let root_inode = inode::load_inode(fs, fs.super_block.root_inode_no);
+    fs.set_root_inode(root_inode);
+    fs
+}
+
+/**
+ * 节点对应的打开的inode
+ */
+fn opened_inode(node: &Vnode) -> &'static mut OpenedInode {
+    unsafe { &mut *(node.get_data() as *mut OpenedInode) }
+}
+
+impl FileSystem {
+    /**
+     * 把打开的inode，包装为Vnode
+     *  - inode中没有文件类型（旧格式的inode），使用default_type
+     */
+    #[inline(never)]
+    fn to_vnode(&mut self, inode: &'static mut OpenedInode, default_type: FileType) -> Vnode {
+        let inode_file_type = FileType::from_mode(inode.i_mode);
+        let file_type = if inode_file_type == FileType::Unknown { default_type } else { inode_file_type };
+        Vnode::new(self as *mut FileSystem, inode.i_no, file_type, inode as *mut OpenedInode as usize)
+    }
+}
+
+impl Vfs for FileSystem {
+    fn root_ino(&self) -> InodeNo {
+        self.super_block.root_inode_no
+    }
+
+    #[inline(never)]
+    fn open_dir(&mut self, ino: InodeNo) -> Vnode {
+        let dir_inode = inode::inode_open(self, ino);
+        self.to_vnode(dir_inode, FileType::Directory)
+    }
+
+    #[inline(never)]
+    fn lookup(&mut self, dir: &Vnode, name: &str) -> Option<Vnode> {
+        let entry = dir_entry::do_search_dir_entry(self, opened_inode(dir), DirEntrySearchReq::build().entry_name(name))?;
+        let entry_inode = inode::inode_open(self, entry.i_no);
+        Option::Some(self.to_vnode(entry_inode, entry.file_type))
+    }
+
+    #[inline(never)]
+    fn create(&mut self, dir: &Vnode, name: &str, file_type: FileType) -> Result<Vnode, FileError> {
+        // 元数据的修改，放在一个日志事务中
+        let credits = if file_type == FileType::Directory { constant::JOURNAL_MKDIR_CREDITS } else { constant::JOURNAL_CREATE_CREDITS };
+        let _trans = journal::begin(self, credits)?;
+        let dir_inode = opened_inode(dir);
+        if dir_entry::do_search_dir_entry(self, dir_inode, DirEntrySearchReq::build().entry_name(name)).is_some() {
+            return Result::Err(FileError::AlreadyExists);
+        }
+        let i_no = if file_type == FileType::Directory {
+            dir::mkdir(self, dir_inode, name)?
+        } else {
+            dir_entry::create_dir_entry(self, dir_inode, name, file_type)?
+        };
+        let created_inode = inode::inode_open(self, i_no);
+        Result::Ok(self.to_vnode(created_inode, file_type))
+    }
+
+    #[inline(never)]
+    fn read(&mut self, node: &Vnode, off: u32, buff: &mut [u8]) -> usize {
+        file::read_file(self, opened_inode(node), off, buff)
+    }
+
+    #[inline(never)]
+    fn write(&mut self, node: &Vnode, off: u32, buff: &[u8]) -> Result<usize, FileError> {
+        file::write_file(self, opened_inode(node), off, buff)
+    }
+
+    /**
+     * 目录项的偏移量 = 目录扇区的下标（数据块的下标 * 每块的扇区数量 + 块内扇区的下标） * 扇区大小 + 目录项在扇区内的偏移量
+     */
+    #[inline(never)]
+    fn readdir(&mut self, dir: &Vnode, off: u32) -> Option<(DirEntry, u32)> {
+        let dir_inode = opened_inode(dir);
+        // 如果直接块都满了，那么就需要加载间接块
+        if dir_inode.get_direct_data_blocks_ref().iter().all(|block| !block.is_empty()) {
+            inode::load_indirect_data_block(self, dir_inode);
+        }
+        let disk = unsafe { &mut *self.base_part.from_disk };
+        let buf: &mut [u8; constants::DISK_SECTOR_SIZE] = memory::malloc(constants::DISK_SECTOR_SIZE);
+
+        let data_blocks = dir_inode.get_data_blocks_ref();
+        let sec_per_block = self.super_block.sec_per_block();
+        let mut dir_sec_idx = off as usize / constants::DISK_SECTOR_SIZE;
+        let mut entry_off = off as usize % constants::DISK_SECTOR_SIZE;
+        let mut found = Option::None;
+        // 遍历数据块中的扇区，直到遇到空的数据块
+        while dir_sec_idx / sec_per_block < data_blocks.len() && !data_blocks[dir_sec_idx / sec_per_block].is_empty() {
+            let sec_lba = data_blocks[dir_sec_idx / sec_per_block].add((dir_sec_idx % sec_per_block) as u32);
+            buffer_cache::read_sectors(disk, sec_lba, 1, buf);
+            let mut block_iter = DirEntryBlockIter::new(buf);
+            block_iter.skip_to(entry_off);
+            let next_entry = block_iter.find(|(_, _, entry)| !entry.is_empty());
+            if next_entry.is_some() {
+                let (cur_off, rec_len, entry) = next_entry.unwrap();
+                let next_off = dir_sec_idx * constants::DISK_SECTOR_SIZE + cur_off + rec_len;
+                found = Option::Some((entry, next_off as u32));
+                break;
+            }
+            dir_sec_idx += 1;
+            entry_off = 0;
+        }
+        memory::sys_free(buf.as_ptr() as usize);
+        found
+    }
+
+    #[inline(never)]
+    fn unlink(&mut self, dir: &Vnode, name: &str) -> Result<(), FileError> {
+        // 元数据的修改，放在一个日志事务中
+        let _trans = journal::begin(self, constant::JOURNAL_UNLINK_CREDITS)?;
+        let dir_inode = opened_inode(dir);
+        let entry = dir_entry::do_search_dir_entry(self, dir_inode, DirEntrySearchReq::build().entry_name(name));
+        if entry.is_none() {
+            return Result::Err(FileError::NotFound);
+        }
+        let entry = entry.unwrap();
+        let entry_inode = inode::inode_open(self, entry.i_no);
+        let res = if entry.file_type == FileType::Directory {
+            dir::rmdir(self, dir_inode, entry_inode)
+        } else {
+            file::remove_file(self, dir_inode, name, entry_inode)
+        };
+        // 关闭inode。如果这是最后一个链接，并且没有其他打开者，关闭的时候会释放inode和数据块
+        inode::inode_close(self, entry_inode);
+        res
+    }
+
+    #[inline(never)]
+    fn stat(&mut self, node: &Vnode) -> FileStat {
+        FileStat::from(self, opened_inode(node), node.file_type)
+    }
+
+    fn reopen(&mut self, node: &Vnode) {
+        opened_inode(node).reopen();
+    }
+
+    #[inline(never)]
+    fn close(&mut self, node: &Vnode) {
+        inode::inode_close(self, opened_inode(node));
+    }
+
+    #[inline(never)]
+    fn rename(&mut self, old_dir: &Vnode, old_name: &str, new_dir: &Vnode, new_name: &str) -> Result<(), FileError> {
+        // 元数据的修改，放在一个日志事务中
+        let _trans = journal::begin(self, constant::JOURNAL_RENAME_CREDITS)?;
+        let old_parent_inode = opened_inode(old_dir);
+        let entry = dir_entry::do_search_dir_entry(self, old_parent_inode, DirEntrySearchReq::build().entry_name(old_name));
+        if entry.is_none() {
+            return Result::Err(FileError::NotFound);
+        }
+        rename::do_rename(self, old_parent_inode, &entry.unwrap(), old_name, opened_inode(new_dir), new_name)
+    }
+
+    #[inline(never)]
+    fn link(&mut self, node: &Vnode, dir: &Vnode, name: &str) -> Result<(), FileError> {
+        // 元数据的修改，放在一个日志事务中
+        let _trans = journal::begin(self, constant::JOURNAL_LINK_CREDITS)?;
+        link::do_link(self, opened_inode(node), node.file_type, opened_inode(dir), name)
+    }
+
+    #[inline(never)]
+    fn fsync(&mut self, node: &Vnode) {
+        fsync::fsync_inode(self, opened_inode(node));
+    }
+
+    /**
+     * 所有打开的inode，写入到缓冲区，并且提交日志事务
+     */
+    #[inline(never)]
+    fn sync(&mut self) {
+        let fs_ptr = self as *mut FileSystem;
+        self.iter_open_nodes(|opened_inode| inode::sync_inode(unsafe { &mut *fs_ptr }, opened_inode));
+        // 提交这个文件系统当前的事务
+        journal::flush(self);
+    }
+
+    /**
+     * 一个数据块就是一个扇区。空闲数量由inode池和数据块池维护
+     */
+    #[inline(never)]
+    fn statfs(&mut self) -> FsStat {
+        FsStat {
+            block_size: self.super_block.get_block_size() as u32,
+            total_blocks: self.data_block_pool.total_blocks(),
+            free_blocks: self.data_block_pool.free_blocks(),
+            avail_blocks: self.data_block_pool.avail_blocks(),
+            total_inodes: self.inode_pool.total_inodes(),
+            free_inodes: self.inode_pool.free_inodes(),
+        }
+    }
+
+    /**
+     * 除了根目录之外，还有打开的inode；或者根目录还被别人打开着
+     */
+    #[inline(never)]
+    fn is_busy(&mut self) -> bool {
+        let mut open_cnt = 0;
+        self.iter_open_nodes(|inode| open_cnt += inode.open_cnts);
+        open_cnt > 1
+    }
+
+    #[inline(never)]
+    fn unmount(&mut self) {
+        // 把还没有提交的修改，以及缓冲区中这个分区的数据，都写回到硬盘
+        journal::flush(self);
+        let part = self.base_part;
+        let disk = unsafe { &mut *part.from_disk };
+        buffer_cache::sync_sectors(disk, part.abs_lba_start(0), part.sec_cnt as usize);
+        buffer_cache::invalidate(disk, part.abs_lba_start(0), part.sec_cnt as usize);
+
+        // 释放文件系统的结构
+        let root_inode = self.get_root_inode() as *mut OpenedInode;
+        self.remove_inode(unsafe { &mut *root_inode });
+        memory::sys_free(self.inode_pool.bitmap_addr());
+        memory::sys_free(self.data_block_pool.bitmap_addr());
+        memory::sys_free(self.super_block as *const _ as usize);
+        memory::free_system(self as *const FileSystem);
+    }
+}
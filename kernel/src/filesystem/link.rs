@@ -0,0 +1,75 @@
+use super::{dir_entry::{self, DirEntrySearchReq, FileType}, file::FileError, file_util, fs::FileSystem, inode::{self, OpenedInode}, vfs};
+
+/**
+ * 创建一个硬链接：在new_path所在的目录下，添加一个目录项，指向existing_path的inode
+ *  - 不允许给目录创建硬链接
+ *  - inode的链接数量 + 1，并且同步到硬盘
+ */
+#[inline(never)]
+pub fn link(existing_path: &str, new_path: &str) -> Result<(), FileError> {
+    if !existing_path.starts_with("/") || !file_util::is_path_legal(existing_path) || new_path == "/" {
+        return Result::Err(FileError::FilePathIllegal);
+    }
+    let new_split = file_util::split_file_path(new_path);
+    if new_split.is_none() {
+        return Result::Err(FileError::FilePathIllegal);
+    }
+    let (_, new_name) = new_split.unwrap();
+    if new_name.is_empty() || new_name == "." || new_name == ".." {
+        return Result::Err(FileError::FilePathIllegal);
+    }
+
+    // 已存在的文件
+    let existing = vfs::lookup_path(existing_path, true);
+    if existing.is_none() {
+        return Result::Err(FileError::NotFound);
+    }
+    let existing = existing.unwrap();
+    if existing.is_dir() {
+        existing.close();
+        return Result::Err(FileError::IsADirectory);
+    }
+
+    // 新链接所在的目录
+    let new_parent = vfs::lookup_parent(new_path);
+    if new_parent.is_err() {
+        existing.close();
+        return Result::Err(FileError::ParentDirNotExists);
+    }
+    let (new_parent, _) = new_parent.unwrap();
+    // 硬链接只能指向同一个文件系统中的节点
+    if !vfs::same_fs(new_parent.get_fs_ptr(), existing.get_fs_ptr()) {
+        new_parent.close();
+        existing.close();
+        return Result::Err(FileError::CrossDevice);
+    }
+
+    let res = existing.get_fs().link(&existing, &new_parent, new_name);
+    new_parent.close();
+    existing.close();
+    res
+}
+
+/**
+ * LeonFS中，在new_parent_inode目录下创建名为new_name的目录项，指向existing_inode
+ */
+#[inline(never)]
+pub fn do_link(fs: &mut FileSystem, existing_inode: &mut OpenedInode, file_type: FileType, new_parent_inode: &mut OpenedInode, new_name: &str) -> Result<(), FileError> {
+    // 新的名称已经存在了
+    if dir_entry::do_search_dir_entry(fs, new_parent_inode, DirEntrySearchReq::build().entry_name(new_name)).is_some() {
+        return Result::Err(FileError::AlreadyExists);
+    }
+
+    // 先增加链接数量，再创建目录项。中途出错，最多是链接数量多了，不会出现目录项指向被释放的inode
+    // 旧格式的inode没有链接数量（是0），当作只有1个链接
+    let old_nlink = existing_inode.i_nlink;
+    existing_inode.i_nlink = old_nlink.max(1) + 1;
+    inode::sync_inode(fs, existing_inode);
+    let res = dir_entry::do_create_dir_entry_with_inode(fs, new_parent_inode, existing_inode.i_no, new_name, file_type);
+    // 目录项没有创建成功，链接数量改回去
+    if res.is_err() {
+        existing_inode.i_nlink = old_nlink;
+        inode::sync_inode(fs, existing_inode);
+    }
+    res
+}
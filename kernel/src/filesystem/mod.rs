@@ -1,4 +1,5 @@
-mod constant;
+pub mod constant;
+mod buffer_cache;
 pub mod superblock;
 pub mod inode;
 mod dir;
@@ -8,11 +9,27 @@ mod dir_entry;
 mod file_descriptor;
 mod global_file_table;
 mod fs;
+mod mount;
+mod vfs;
+mod leonfs;
+mod procfs;
+mod devfs;
+mod tmpfs;
+pub mod fat;
+pub mod ext2;
 mod file_api;
 mod dir_api;
 mod file_util;
+mod stat;
+mod rename;
+mod link;
+mod symlink;
+mod fsync;
+mod journal;
+pub mod fsck;
 
-pub use fs::get_filesystem;
+pub use vfs::Vfs;
+pub use vfs::Vnode;
 
 pub use file_descriptor::TaskFileDescriptorTable;
 pub use file_descriptor::FileDescriptor;
@@ -20,20 +37,27 @@ pub use file_descriptor::StdFileDescriptor;
 pub use file_descriptor::FileDescriptorType;
 
 pub use file::FileError;
-pub use file::read_file;
-pub use file::write_file;
 
 
 
 pub use init::init;
 pub use init::install_filesystem_for_all_part;
-pub use init::mount_part;
+pub use init::build_block_bitmap;
+pub use init::build_inode_bitmap;
+pub use init::build_inode_table;
+pub use init::build_root_dir;
 pub use dir::init_root_dir;
 pub use dir::change_dir;
 pub use dir::get_cwd;
 
 pub use dir_entry::FileType;
 pub use dir_entry::DirEntry;
+pub use dir_entry::DirEntryBlockIter;
+pub use dir_entry::DirEntryHeader;
+pub use dir_entry::entry_rec_len;
+pub use dir_entry::insert_entry;
+pub use dir_entry::replace_entry;
+pub use dir_entry::DirEntrySearchReq;
 pub use dir_entry::current_inode_entry;
 
 pub use dir_api::create_dir;
@@ -50,6 +74,29 @@ pub use file_api::SeekFrom;
 pub use file_api::OpenOptions;
 pub use file_api::remove_file;
 
+pub use stat::FileStat;
+pub use stat::stat;
+pub use stat::fstat;
+pub use stat::lstat;
+pub use stat::FsStat;
+pub use stat::statfs;
+
+pub use rename::rename;
+pub use link::link;
+pub use symlink::symlink;
+pub use symlink::readlink;
+pub use fsync::fsync;
+pub use fsync::sync;
+pub use journal::JournalHeader;
+pub use mount::mount;
+pub use mount::umount;
+pub use mount::mount_root;
+pub use mount::mount_all_part;
+pub use mount::MountError;
+pub use procfs::mount_procfs;
+pub use devfs::mount_devfs;
+pub use tmpfs::mount_tmpfs;
+
 
 pub use global_file_table::get_opened_file;
 pub use global_file_table::get_file_by_fd;
@@ -0,0 +1,381 @@
+use os_in_rust_common::{cstr_write, cstring_utils, domain::InodeNo, instruction, printkln, racy_cell::RacyCell, MY_PANIC};
+
+use crate::{device::{self, Partition}, memory, thread::{self, TaskStruct}};
+
+use super::{constant, devfs, dir_api, ext2, fat, file_util, init, leonfs, procfs, tmpfs, vfs::{self, Vfs}};
+
+/**
+ * 挂载表。每一项是一个挂载了的文件系统
+ *  - 第0项是根文件系统，挂载在"/"
+ *  - 其他的文件系统，挂载在某个目录上（覆盖这个目录）
+ * 文件系统的结构放在堆中，挂载期间地址不会变化，打开的节点可以用指针找到所属的文件系统
+ */
+static MOUNT_TABLE: RacyCell<[Option<MountPoint>; constant::MAX_MOUNT_CNT]> = RacyCell::new([EMPTY_MOUNT_POINT; constant::MAX_MOUNT_CNT]);
+const EMPTY_MOUNT_POINT: Option<MountPoint> = Option::None;
+
+/**
+ * 挂载点。一个挂载在某个目录上的文件系统
+ */
+pub struct MountPoint {
+    /**
+     * 挂载的文件系统
+     */
+    fs: *mut dyn Vfs,
+    /**
+     * 挂载点目录所在的文件系统。根文件系统没有
+     */
+    parent_fs: Option<*mut dyn Vfs>,
+    /**
+     * 挂载点目录（被覆盖的目录），在parent_fs中的编号
+     */
+    covered_ino: InodeNo,
+    /**
+     * 文件系统的来源。例如分区的名称
+     */
+    source: [u8; constant::MAX_FILE_NAME],
+    /**
+     * 挂载的路径
+     */
+    path: [u8; constant::MAX_FILE_PATH_LEN],
+}
+
+unsafe impl Sync for MountPoint {}
+unsafe impl Send for MountPoint {}
+
+impl MountPoint {
+    #[inline(never)]
+    fn new(fs: *mut dyn Vfs, parent_fs: Option<*mut dyn Vfs>, covered_ino: InodeNo, source: &str, path: &str) -> Self {
+        let mut mount_point = Self {
+            fs,
+            parent_fs,
+            covered_ino,
+            source: [0; constant::MAX_FILE_NAME],
+            path: [0; constant::MAX_FILE_PATH_LEN],
+        };
+        cstr_write!(&mut mount_point.source, "{}", source);
+        cstr_write!(&mut mount_point.path, "{}", path);
+        mount_point
+    }
+
+    pub fn get_fs(&self) -> &'static mut dyn Vfs {
+        unsafe { &mut *self.fs }
+    }
+
+    #[inline(never)]
+    pub fn get_source(&self) -> &str {
+        let source = cstring_utils::read_from_bytes(&self.source);
+        source.unwrap_or("")
+    }
+
+    #[inline(never)]
+    pub fn get_path(&self) -> &str {
+        let path = cstring_utils::read_from_bytes(&self.path);
+        path.unwrap_or("")
+    }
+}
+
+#[derive(Debug)]
+pub enum MountError {
+    /**
+     * 挂载路径不合法
+     */
+    PathIllegal,
+    /**
+     * 分区不存在
+     */
+    PartitionNotFound,
+    /**
+     * 分区上没有文件系统
+     */
+    NotFormatted,
+    /**
+     * 分区已经挂载了
+     */
+    AlreadyMounted,
+    /**
+     * 挂载点目录不存在
+     */
+    MountPointNotFound,
+    /**
+     * 挂载点不是目录
+     */
+    NotADirectory,
+    /**
+     * 目录上已经挂载了文件系统（或者是根目录）
+     */
+    MountPointBusy,
+    /**
+     * 挂载表满了
+     */
+    MountTableFull,
+    /**
+     * 这个路径上没有挂载文件系统（根文件系统不能卸载）
+     */
+    NotMounted,
+    /**
+     * 文件系统还在使用中：有打开的文件、作为工作目录，或者下面还挂载了其他文件系统
+     */
+    Busy,
+}
+
+/**
+ * 根文件系统
+ */
+#[inline(never)]
+pub fn root_fs() -> &'static mut dyn Vfs {
+    let root = unsafe { MOUNT_TABLE.get_mut() }[0].as_ref();
+    if root.is_none() {
+        MY_PANIC!("fs system not exist");
+    }
+    root.unwrap().get_fs()
+}
+
+/**
+ * 挂载根文件系统。系统启动的时候调用
+ */
+#[inline(never)]
+pub fn mount_root(part_name: &str) {
+    let part = self::find_partition(part_name);
+    if part.is_none() {
+        MY_PANIC!("failed to mount root filesystem, partition {} not found", part_name);
+    }
+    let fs = leonfs::load(part.unwrap());
+    let table = unsafe { MOUNT_TABLE.get_mut() };
+    table[0] = Option::Some(MountPoint::new(fs, Option::None, InodeNo::new(0), part_name, "/"));
+    printkln!("{} mounted on /", part_name);
+}
+
+/**
+ * 把根文件系统之外，所有有文件系统的分区，挂载到 dir_path/分区名 下。例如：/mnt/sdb5
+ */
+#[inline(never)]
+pub fn mount_all_part(dir_path: &str) {
+    let buf: &mut [u8; constant::MAX_FILE_PATH_LEN] = memory::malloc(constant::MAX_FILE_PATH_LEN);
+    for part_tag in device::get_all_partition().iter() {
+        let part = Partition::parse_by_tag(part_tag);
+        if self::is_mounted(part.get_name()) || !self::has_filesystem(part) {
+            continue;
+        }
+        cstr_write!(buf, "{}/{}", dir_path, part.get_name());
+        let path = cstring_utils::read_from_bytes(buf).unwrap();
+        let res = dir_api::create_dir_all(path).map_err(|_| MountError::MountPointNotFound)
+            .and_then(|_| self::mount(part.get_name(), path));
+        if res.is_err() {
+            printkln!("failed to mount {} on {}, error:{:?}", part.get_name(), path, res.unwrap_err());
+        }
+    }
+    memory::sys_free(buf.as_ptr() as usize);
+}
+
+/**
+ * 把分区part_name的文件系统（LeonFS，或者只读的FAT、ext2），挂载到目录path上
+ *  - part_name是proc、dev、tmpfs的话，挂载procfs、devfs、一个新的tmpfs
+ */
+#[inline(never)]
+pub fn mount(part_name: &str, path: &str) -> Result<(), MountError> {
+    if !file_util::is_path_legal(path) || path.len() >= constant::MAX_FILE_PATH_LEN {
+        return Result::Err(MountError::PathIllegal);
+    }
+    if part_name == procfs::PROC_FS_SOURCE {
+        return self::mount_fs(procfs::get_proc_fs(), part_name, path);
+    }
+    if part_name == devfs::DEV_FS_SOURCE {
+        return self::mount_fs(devfs::get_dev_fs(), part_name, path);
+    }
+    if part_name == tmpfs::TMP_FS_SOURCE {
+        return tmpfs::mount(path);
+    }
+    let part = self::find_partition(part_name).ok_or(MountError::PartitionNotFound)?;
+    if self::is_mounted(part_name) {
+        return Result::Err(MountError::AlreadyMounted);
+    }
+    if !self::has_filesystem(part) {
+        return Result::Err(MountError::NotFormatted);
+    }
+    let (parent_fs, covered_ino) = self::find_mount_point(path)?;
+    let slot_idx = self::find_free_slot()?;
+
+    let fs = self::load_fs(part);
+    let table = unsafe { MOUNT_TABLE.get_mut() };
+    table[slot_idx] = Option::Some(MountPoint::new(fs, Option::Some(parent_fs), covered_ino, part_name, path));
+    printkln!("{} mounted on {}", part_name, path);
+    Result::Ok(())
+}
+
+/**
+ * 把一个不在分区上的文件系统（例如procfs），挂载到目录path上。source是文件系统的来源名称
+ */
+#[inline(never)]
+pub fn mount_fs(fs: *mut dyn Vfs, source: &str, path: &str) -> Result<(), MountError> {
+    if !file_util::is_path_legal(path) || path.len() >= constant::MAX_FILE_PATH_LEN {
+        return Result::Err(MountError::PathIllegal);
+    }
+    if self::is_mounted(source) {
+        return Result::Err(MountError::AlreadyMounted);
+    }
+    let (parent_fs, covered_ino) = self::find_mount_point(path)?;
+    let slot_idx = self::find_free_slot()?;
+
+    let table = unsafe { MOUNT_TABLE.get_mut() };
+    table[slot_idx] = Option::Some(MountPoint::new(fs, Option::Some(parent_fs), covered_ino, source, path));
+    printkln!("{} mounted on {}", source, path);
+    Result::Ok(())
+}
+
+/**
+ * 卸载挂载在目录path上的文件系统
+ */
+#[inline(never)]
+pub fn umount(path: &str) -> Result<(), MountError> {
+    if !file_util::is_path_legal(path) {
+        return Result::Err(MountError::PathIllegal);
+    }
+    let dir = vfs::lookup_path(path, true).ok_or(MountError::MountPointNotFound)?;
+    let fs = dir.get_fs_ptr();
+    let is_fs_root = dir.is_fs_root();
+    dir.close();
+
+    let table = unsafe { MOUNT_TABLE.get_mut() };
+    // 路径要刚好是某个文件系统（不能是根文件系统）的根目录
+    let mount_idx = table.iter().position(|mount_point| mount_point.as_ref().is_some_and(|mount_point| vfs::same_fs(mount_point.fs, fs)));
+    if !is_fs_root || mount_idx.is_none() || mount_idx.unwrap() == 0 {
+        return Result::Err(MountError::NotMounted);
+    }
+    if self::is_busy(fs) {
+        return Result::Err(MountError::Busy);
+    }
+
+    // 写回数据，释放文件系统的结构，然后从挂载表中移除
+    let mount_point = table[mount_idx.unwrap()].take().unwrap();
+    mount_point.get_fs().unmount();
+    printkln!("{} unmounted from {}", mount_point.get_source(), mount_point.get_path());
+    Result::Ok(())
+}
+
+/**
+ * 找到挂载在 parent_fs中的目录dir_ino 上的文件系统
+ */
+#[inline(never)]
+pub fn find_mounted(parent_fs: *const dyn Vfs, dir_ino: InodeNo) -> Option<&'static mut dyn Vfs> {
+    unsafe { MOUNT_TABLE.get_mut() }.iter()
+        .filter_map(|mount_point| mount_point.as_ref())
+        .find(|mount_point| mount_point.parent_fs.is_some_and(|fs| vfs::same_fs(fs, parent_fs)) && mount_point.covered_ino == dir_ino)
+        .map(|mount_point| mount_point.get_fs())
+}
+
+/**
+ * 文件系统fs被挂载在哪里：返回挂载点目录所在的文件系统，以及挂载点目录的编号。根文件系统返回None
+ */
+#[inline(never)]
+pub fn find_covered(fs: *const dyn Vfs) -> Option<(&'static mut dyn Vfs, InodeNo)> {
+    unsafe { MOUNT_TABLE.get_mut() }.iter()
+        .filter_map(|mount_point| mount_point.as_ref())
+        .find(|mount_point| vfs::same_fs(mount_point.fs, fs) && mount_point.parent_fs.is_some())
+        .map(|mount_point| (unsafe { &mut *mount_point.parent_fs.unwrap() }, mount_point.covered_ino))
+}
+
+/**
+ * 遍历所有挂载了的文件系统
+ */
+#[inline(never)]
+pub fn for_each_mount<F>(mut f: F) where F: FnMut(&mut MountPoint) {
+    unsafe { MOUNT_TABLE.get_mut() }.iter_mut()
+        .filter_map(|mount_point| mount_point.as_mut())
+        .for_each(|mount_point| f(mount_point));
+}
+
+/**
+ * 找到挂载点目录。返回(目录所在的文件系统, 目录的编号)
+ *  - 如果路径穿过了其他挂载点，拿到的是最里层文件系统中的目录
+ *  - 不能是某个文件系统的根目录：要么是"/"，要么上面已经挂载了文件系统
+ */
+#[inline(never)]
+fn find_mount_point(path: &str) -> Result<(*mut dyn Vfs, InodeNo), MountError> {
+    let dir = vfs::lookup_path(path, true).ok_or(MountError::MountPointNotFound)?;
+    dir.close();
+    if !dir.is_dir() {
+        return Result::Err(MountError::NotADirectory);
+    }
+    if dir.is_fs_root() {
+        return Result::Err(MountError::MountPointBusy);
+    }
+    Result::Ok((dir.get_fs_ptr(), dir.ino))
+}
+
+/**
+ * 挂载表中空闲的位置（第0项是根文件系统）
+ */
+#[inline(never)]
+fn find_free_slot() -> Result<usize, MountError> {
+    unsafe { MOUNT_TABLE.get_mut() }.iter()
+        .skip(1)
+        .position(|mount_point| mount_point.is_none())
+        .map(|idx| idx + 1)
+        .ok_or(MountError::MountTableFull)
+}
+
+/**
+ * 根据名称找到分区
+ */
+#[inline(never)]
+fn find_partition(part_name: &str) -> Option<&'static Partition> {
+    device::get_all_partition().iter()
+        .map(|part_tag| &*Partition::parse_by_tag(part_tag))
+        .find(|part| part.get_name() == part_name)
+}
+
+/**
+ * 分区上是否有可以挂载的文件系统：FAT分区、ext2分区，或者安装了LeonFS
+ */
+#[inline(never)]
+fn has_filesystem(part: &Partition) -> bool {
+    fat::is_fat_volume(part) || ext2::is_ext2_volume(part) || init::is_formatted(part)
+}
+
+/**
+ * 加载分区上的文件系统。FAT、ext2分区使用对应的驱动（只读），其他的是LeonFS
+ */
+#[inline(never)]
+fn load_fs(part: &'static Partition) -> *mut dyn Vfs {
+    if fat::is_fat_volume(part) {
+        return fat::load(part);
+    }
+    if ext2::is_ext2_volume(part) {
+        return ext2::load(part);
+    }
+    leonfs::load(part)
+}
+
+/**
+ * 来源为source（分区名称）的文件系统，是否已经挂载了
+ */
+#[inline(never)]
+fn is_mounted(source: &str) -> bool {
+    unsafe { MOUNT_TABLE.get_mut() }.iter()
+        .filter_map(|mount_point| mount_point.as_ref())
+        .any(|mount_point| mount_point.get_source() == source)
+}
+
+/**
+ * 文件系统fs是否还在使用中
+ *  - 文件系统中还有打开的节点
+ *  - 某个任务的工作目录在这个文件系统中
+ *  - 这个文件系统中的目录上，还挂载了其他文件系统
+ */
+#[inline(never)]
+fn is_busy(fs: *mut dyn Vfs) -> bool {
+    if unsafe { &mut *fs }.is_busy() {
+        return true;
+    }
+    let old = instruction::disable_interrupt();
+    let cwd_busy = thread::get_all_thread().iter()
+        .map(|tag| unsafe { &*TaskStruct::parse_by_all_tag(&*tag) })
+        .any(|task| task.cwd_inode.is_some() && task.cwd_fs.is_some_and(|cwd_fs| vfs::same_fs(cwd_fs, fs)));
+    instruction::set_interrupt(old);
+    if cwd_busy {
+        return true;
+    }
+    unsafe { MOUNT_TABLE.get_mut() }.iter()
+        .filter_map(|mount_point| mount_point.as_ref())
+        .any(|mount_point| mount_point.parent_fs.is_some_and(|parent_fs| vfs::same_fs(parent_fs, fs)))
+}
@@ -0,0 +1,472 @@
+use core::fmt::{self, Write};
+
+use os_in_rust_common::{constants, cstr_write, cstring_utils, domain::InodeNo, instruction, pool::MemPool, printkln, racy_cell::RacyCell};
+
+use crate::{device::{self, Partition}, memory, thread::{self, TaskStruct}, time, version};
+
+use super::{constant, dir, dir_api, dir_entry::{DirEntry, FileType}, file::FileError, mount::{self, MountError}, stat::FileStat, vfs::{Vfs, Vnode}};
+
+/**
+ * procfs：把内核的实时状态，以文件的形式展示出来。文件的内容在读取的时候生成，不占用硬盘
+ *  - /proc/meminfo、/proc/mounts、/proc/partitions、/proc/uptime、/proc/version
+ *  - /proc/<pid>/ 下面是每个任务的 status、name、ppid、ticks、cwd（符号链接）、fds
+ * 所有的文件都是只读的
+ */
+
+/**
+ * procfs的来源名称。mount proc <path> 挂载procfs
+ */
+pub const PROC_FS_SOURCE: &str = "proc";
+
+/**
+ * 根目录的编号
+ */
+const ROOT_INO: u32 = 1;
+
+/**
+ * 根目录下的全局文件。编号从GLOBAL_INO_START开始，按顺序排列
+ */
+const GLOBAL_FILES: [&str; 5] = ["meminfo", "mounts", "partitions", "uptime", "version"];
+const GLOBAL_INO_START: u32 = 2;
+
+/**
+ * 每个任务目录下的文件
+ *  - 任务目录的编号 = PID_INO_START + pid * PID_INO_STRIDE
+ *  - 任务目录下第idx个文件的编号 = 任务目录的编号 + 1 + idx
+ */
+const PID_FILES: [&str; 6] = ["status", "name", "ppid", "ticks", "cwd", "fds"];
+const PID_INO_START: u32 = 0x100;
+const PID_INO_STRIDE: u32 = 0x10;
+
+/**
+ * 生成的文件内容的最大长度。超过的部分会被截断
+ */
+const PROC_FILE_MAX_LEN: usize = 1024;
+
+/**
+ * procfs中的节点
+ */
+#[derive(Clone, Copy)]
+enum ProcNode {
+    /**
+     * 根目录
+     */
+    Root,
+    /**
+     * 根目录下的全局文件。GLOBAL_FILES的下标
+     */
+    Global(usize),
+    /**
+     * 任务的目录
+     */
+    PidDir(u8),
+    /**
+     * 任务目录下的文件。(pid, PID_FILES的下标)
+     */
+    PidFile(u8, usize),
+}
+
+impl ProcNode {
+    #[inline(never)]
+    fn from_ino(ino: InodeNo) -> Option<Self> {
+        let ino = ino.get_data();
+        if ino == ROOT_INO {
+            return Option::Some(Self::Root);
+        }
+        if ino >= GLOBAL_INO_START && ino < GLOBAL_INO_START + GLOBAL_FILES.len() as u32 {
+            return Option::Some(Self::Global((ino - GLOBAL_INO_START) as usize));
+        }
+        if ino < PID_INO_START {
+            return Option::None;
+        }
+        let pid = (ino - PID_INO_START) / PID_INO_STRIDE;
+        let file_idx = (ino - PID_INO_START) % PID_INO_STRIDE;
+        if pid > u8::MAX as u32 || file_idx > PID_FILES.len() as u32 {
+            return Option::None;
+        }
+        if file_idx == 0 {
+            return Option::Some(Self::PidDir(pid as u8));
+        }
+        Option::Some(Self::PidFile(pid as u8, file_idx as usize - 1))
+    }
+
+    fn to_ino(&self) -> InodeNo {
+        let ino = match self {
+            Self::Root => ROOT_INO,
+            Self::Global(idx) => GLOBAL_INO_START + *idx as u32,
+            Self::PidDir(pid) => PID_INO_START + *pid as u32 * PID_INO_STRIDE,
+            Self::PidFile(pid, idx) => PID_INO_START + *pid as u32 * PID_INO_STRIDE + 1 + *idx as u32,
+        };
+        InodeNo::new(ino)
+    }
+
+    fn file_type(&self) -> FileType {
+        match self {
+            Self::Root | Self::PidDir(_) => FileType::Directory,
+            // 工作目录是一个符号链接，可以直接 cd /proc/<pid>/cwd
+            Self::PidFile(_, idx) if PID_FILES[*idx] == "cwd" => FileType::Symlink,
+            _ => FileType::Regular,
+        }
+    }
+}
+
+/**
+ * 把格式化的内容写入到缓冲区。写满之后，剩下的内容丢弃（不会panic）
+ */
+struct ProcWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Write for ProcWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let copy_len = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + copy_len].copy_from_slice(&s.as_bytes()[..copy_len]);
+        self.len += copy_len;
+        Result::Ok(())
+    }
+}
+
+/**
+ * procfs。没有任何数据需要保存，只记录打开的节点数量
+ */
+pub struct ProcFs {
+    open_cnt: u32,
+}
+
+static PROC_FS: RacyCell<ProcFs> = RacyCell::new(ProcFs { open_cnt: 0 });
+
+/**
+ * procfs的实例。全局只有一个，卸载之后可以再次挂载
+ */
+pub fn get_proc_fs() -> *mut dyn Vfs {
+    unsafe { PROC_FS.get_mut() as *mut ProcFs }
+}
+
+/**
+ * 创建目录path，把procfs挂载上去。系统启动的时候调用
+ */
+#[inline(never)]
+pub fn mount_procfs(path: &str) {
+    let res = dir_api::create_dir_all(path).map_err(|_| MountError::MountPointNotFound)
+        .and_then(|_| mount::mount_fs(self::get_proc_fs(), PROC_FS_SOURCE, path));
+    if res.is_err() {
+        printkln!("failed to mount {} on {}, error:{:?}", PROC_FS_SOURCE, path, res.unwrap_err());
+    }
+}
+
+impl ProcFs {
+    #[inline(never)]
+    fn open_node(&mut self, node: ProcNode) -> Vnode {
+        self.open_cnt += 1;
+        Vnode::new(self as *mut ProcFs, node.to_ino(), node.file_type(), 0)
+    }
+
+    /**
+     * 生成节点的内容，写入到buf中。返回内容的长度
+     */
+    #[inline(never)]
+    fn generate(&self, node: &ProcNode, buf: &mut [u8]) -> usize {
+        let mut writer = ProcWriter { buf, len: 0 };
+        let _ = match node {
+            ProcNode::Global(idx) => match GLOBAL_FILES[*idx] {
+                "meminfo" => self::write_meminfo(&mut writer),
+                "mounts" => self::write_mounts(&mut writer),
+                "partitions" => self::write_partitions(&mut writer),
+                "uptime" => self::write_uptime(&mut writer),
+                _ => self::write_version(&mut writer),
+            },
+            ProcNode::PidFile(pid, idx) => {
+                // 任务已经结束了，文件是空的
+                let task = self::find_task(*pid);
+                if task.is_none() {
+                    return 0;
+                }
+                self::write_pid_file(task.unwrap(), PID_FILES[*idx], &mut writer)
+            },
+            _ => Result::Ok(()),
+        };
+        writer.len
+    }
+}
+
+impl Vfs for ProcFs {
+    fn root_ino(&self) -> InodeNo {
+        InodeNo::new(ROOT_INO)
+    }
+
+    #[inline(never)]
+    fn open_dir(&mut self, ino: InodeNo) -> Vnode {
+        let node = ProcNode::from_ino(ino).unwrap_or(ProcNode::Root);
+        self.open_node(node)
+    }
+
+    #[inline(never)]
+    fn lookup(&mut self, dir: &Vnode, name: &str) -> Option<Vnode> {
+        let node = match ProcNode::from_ino(dir.ino)? {
+            ProcNode::Root => {
+                let global_idx = GLOBAL_FILES.iter().position(|file_name| *file_name == name);
+                if name == "." || name == ".." {
+                    ProcNode::Root
+                } else if global_idx.is_some() {
+                    ProcNode::Global(global_idx.unwrap())
+                } else {
+                    // 任务的目录，名称是pid
+                    let pid = name.parse::<u8>().ok()?;
+                    self::find_task(pid)?;
+                    ProcNode::PidDir(pid)
+                }
+            },
+            ProcNode::PidDir(pid) => {
+                if name == "." {
+                    ProcNode::PidDir(pid)
+                } else if name == ".." {
+                    ProcNode::Root
+                } else {
+                    let idx = PID_FILES.iter().position(|file_name| *file_name == name)?;
+                    self::find_task(pid)?;
+                    ProcNode::PidFile(pid, idx)
+                }
+            },
+            _ => return Option::None,
+        };
+        Option::Some(self.open_node(node))
+    }
+
+    fn create(&mut self, _dir: &Vnode, _name: &str, _file_type: FileType) -> Result<Vnode, FileError> {
+        Result::Err(FileError::PermissionDenied)
+    }
+
+    #[inline(never)]
+    fn read(&mut self, node: &Vnode, off: u32, buff: &mut [u8]) -> usize {
+        let proc_node = ProcNode::from_ino(node.ino);
+        if proc_node.is_none() || node.is_dir() {
+            return 0;
+        }
+        // 每次读取，都重新生成内容
+        let content: &mut [u8; PROC_FILE_MAX_LEN] = memory::malloc(PROC_FILE_MAX_LEN);
+        let content_len = self.generate(&proc_node.unwrap(), content);
+        let start = (off as usize).min(content_len);
+        let read_len = (content_len - start).min(buff.len());
+        buff[..read_len].copy_from_slice(&content[start..start + read_len]);
+        memory::sys_free(content.as_ptr() as usize);
+        read_len
+    }
+
+    fn write(&mut self, _node: &Vnode, _off: u32, _buff: &[u8]) -> Result<usize, FileError> {
+        Result::Err(FileError::PermissionDenied)
+    }
+
+    /**
+     * 目录项的偏移量，就是目录项的下标
+     *  - 根目录：.、..、全局文件、每个任务的目录
+     *  - 任务目录：.、..、任务的文件
+     */
+    #[inline(never)]
+    fn readdir(&mut self, dir: &Vnode, off: u32) -> Option<(DirEntry, u32)> {
+        let idx = off as usize;
+        let (self_node, parent_node, files_cnt) = match ProcNode::from_ino(dir.ino)? {
+            ProcNode::Root => (ProcNode::Root, ProcNode::Root, GLOBAL_FILES.len()),
+            ProcNode::PidDir(pid) => (ProcNode::PidDir(pid), ProcNode::Root, PID_FILES.len()),
+            _ => return Option::None,
+        };
+        let entry = if idx == 0 {
+            DirEntry::new(self_node.to_ino(), ".", FileType::Directory)
+        } else if idx == 1 {
+            DirEntry::new(parent_node.to_ino(), "..", FileType::Directory)
+        } else if idx < 2 + files_cnt {
+            let file_node = match self_node {
+                ProcNode::PidDir(pid) => ProcNode::PidFile(pid, idx - 2),
+                _ => ProcNode::Global(idx - 2),
+            };
+            let file_name = match file_node {
+                ProcNode::PidFile(_, file_idx) => PID_FILES[file_idx],
+                _ => GLOBAL_FILES[idx - 2],
+            };
+            DirEntry::new(file_node.to_ino(), file_name, file_node.file_type())
+        } else {
+            // 任务目录下，没有更多的目录项了
+            if matches!(self_node, ProcNode::PidDir(_)) {
+                return Option::None;
+            }
+            let pid = self::nth_task_pid(idx - 2 - files_cnt)?;
+            let mut name_buf = [0u8; 4];
+            cstr_write!(&mut name_buf, "{}", pid);
+            DirEntry::new(ProcNode::PidDir(pid).to_ino(), cstring_utils::read_from_bytes(&name_buf).unwrap(), FileType::Directory)
+        };
+        Option::Some((entry, off + 1))
+    }
+
+    fn unlink(&mut self, _dir: &Vnode, _name: &str) -> Result<(), FileError> {
+        Result::Err(FileError::PermissionDenied)
+    }
+
+    /**
+     * 文件的大小，是当前生成的内容的长度
+     */
+    #[inline(never)]
+    fn stat(&mut self, node: &Vnode) -> FileStat {
+        let size = if node.is_dir() {
+            0
+        } else {
+            let content: &mut [u8; PROC_FILE_MAX_LEN] = memory::malloc(PROC_FILE_MAX_LEN);
+            let content_len = ProcNode::from_ino(node.ino).map_or(0, |proc_node| self.generate(&proc_node, content));
+            memory::sys_free(content.as_ptr() as usize);
+            content_len
+        };
+        let perm = if node.is_dir() { 0o555 } else if node.file_type == FileType::Symlink { constant::DEFAULT_SYMLINK_PERM } else { 0o444 };
+        FileStat {
+            i_no: node.ino.get_data(),
+            mode: ((node.file_type.to_mode() & constant::INODE_MODE_TYPE_MASK) | perm) as u32,
+            nlink: if node.is_dir() { 2 } else { 1 },
+            uid: 0,
+            gid: 0,
+            size: size as u32,
+            blocks: 0,
+            flags: 0,
+            ctime: 0,
+            mtime: 0,
+            atime: 0,
+        }
+    }
+
+    fn reopen(&mut self, _node: &Vnode) {
+        self.open_cnt += 1;
+    }
+
+    fn close(&mut self, _node: &Vnode) {
+        self.open_cnt = self.open_cnt.saturating_sub(1);
+    }
+
+    fn is_busy(&mut self) -> bool {
+        self.open_cnt > 0
+    }
+
+    /**
+     * procfs没有需要写回的数据，也不需要释放内存
+     */
+    fn unmount(&mut self) {
+        self.open_cnt = 0;
+    }
+}
+
+/**
+ * 根据pid找到任务
+ */
+#[inline(never)]
+fn find_task(pid: u8) -> Option<&'static mut TaskStruct> {
+    let old = instruction::disable_interrupt();
+    let task = thread::get_all_thread().iter()
+        .map(|tag| unsafe { &mut *TaskStruct::parse_by_all_tag(&*tag) })
+        .find(|task| task.pid.get_data() == pid);
+    instruction::set_interrupt(old);
+    task
+}
+
+/**
+ * 全部任务列表中，第idx个任务的pid
+ */
+#[inline(never)]
+fn nth_task_pid(idx: usize) -> Option<u8> {
+    let old = instruction::disable_interrupt();
+    let pid = thread::get_all_thread().iter()
+        .nth(idx)
+        .map(|tag| unsafe { &*TaskStruct::parse_by_all_tag(&*tag) }.pid.get_data());
+    instruction::set_interrupt(old);
+    pid
+}
+
+/**
+ * 内核、用户物理内存池的总量和剩余量
+ */
+#[inline(never)]
+fn write_meminfo(writer: &mut ProcWriter) -> fmt::Result {
+    self::write_mem_pool(writer, "Kernel", memory::get_kernel_mem_pool())?;
+    self::write_mem_pool(writer, "User", memory::get_user_mem_pool())
+}
+
+#[inline(never)]
+fn write_mem_pool(writer: &mut ProcWriter, pool_name: &str, pool: &MemPool) -> fmt::Result {
+    let granularity = pool.granularity;
+    let total_cnt = pool.bitmap.bits_len();
+    let used_cnt = pool.iter_valid().filter(|(_, used)| *used).count();
+    writeln!(writer, "{}Total: {} kB", pool_name, total_cnt * granularity / 1024)?;
+    writeln!(writer, "{}Free: {} kB", pool_name, (total_cnt - used_cnt) * granularity / 1024)
+}
+
+/**
+ * 所有挂载了的文件系统。每行一个：来源 挂载点
+ */
+#[inline(never)]
+fn write_mounts(writer: &mut ProcWriter) -> fmt::Result {
+    let mut res = Result::Ok(());
+    mount::for_each_mount(|mount_point| {
+        if res.is_ok() {
+            res = writeln!(writer, "{} {}", mount_point.get_source(), mount_point.get_path());
+        }
+    });
+    res
+}
+
+/**
+ * 所有的分区：名称、所在的硬盘、起始扇区、扇区数量
+ */
+#[inline(never)]
+fn write_partitions(writer: &mut ProcWriter) -> fmt::Result {
+    writeln!(writer, "NAME  DISK  START_LBA  SECTORS  SIZE_KB")?;
+    for part_tag in device::get_all_partition().iter() {
+        let part = Partition::parse_by_tag(part_tag);
+        let disk = unsafe { &*part.from_disk };
+        let size_kb = part.sec_cnt as usize * constants::DISK_SECTOR_SIZE / 1024;
+        writeln!(writer, "{:<5} {:<5} {:<10} {:<8} {}", part.get_name(), disk.get_name(), part.abs_lba_start(0).get_lba(), part.sec_cnt, size_kb)?;
+    }
+    Result::Ok(())
+}
+
+/**
+ * 系统启动之后经过的时间。单位秒，保留两位小数
+ */
+#[inline(never)]
+fn write_uptime(writer: &mut ProcWriter) -> fmt::Result {
+    let ticks = time::get_system_ticks();
+    let frequency = constants::TIMER_INTR_FREQUENCY as u64;
+    writeln!(writer, "{}.{:02}", ticks / frequency, ticks % frequency * 100 / frequency)
+}
+
+#[inline(never)]
+fn write_version(writer: &mut ProcWriter) -> fmt::Result {
+    writeln!(writer, "LeonOS version {} ({})", version::VERSION_STRING, version::VERSION_NAME)
+}
+
+/**
+ * 任务目录下的文件
+ *  - ticks：已经执行的滴答数 剩余的滴答数
+ *  - cwd：工作目录的路径（符号链接的目标，没有换行）
+ *  - fds：每行一个打开的文件描述符：fd 类型 全局文件表的下标
+ */
+#[inline(never)]
+fn write_pid_file(task: &TaskStruct, file_name: &str, writer: &mut ProcWriter) -> fmt::Result {
+    match file_name {
+        "status" => writeln!(writer, "{}", task.task_status.get_name()),
+        "name" => writeln!(writer, "{}", task.get_name()),
+        "ppid" => writeln!(writer, "{}", task.parent_pid.map_or(0, |pid| pid.get_data())),
+        "ticks" => writeln!(writer, "{} {}", task.elapsed_ticks, task.left_ticks),
+        "cwd" => {
+            let path_buf: &mut [u8; constant::MAX_FILE_PATH_LEN] = memory::malloc(constant::MAX_FILE_PATH_LEN);
+            let cwd = dir::get_cwd(task, path_buf);
+            let res = if cwd.is_some() { write!(writer, "{}", cwd.unwrap()) } else { Result::Ok(()) };
+            memory::sys_free(path_buf.as_ptr() as usize);
+            res
+        },
+        _ => {
+            for (fd, task_fd) in task.fd_table.get_file_descriptors().iter().enumerate() {
+                if task_fd.is_none() {
+                    continue;
+                }
+                let task_fd = task_fd.unwrap();
+                writeln!(writer, "{} {:?} {}", fd, task_fd.get_fd_type(), task_fd.get_global_idx())?;
+            }
+            Result::Ok(())
+        },
+    }
+}
@@ -0,0 +1,141 @@
+use os_in_rust_common::domain::InodeNo;
+
+use super::{dir_entry::{self, DirEntry, DirEntrySearchReq, FileType}, file::FileError, file_util, fs::FileSystem, inode::{self, OpenedInode}, mount, vfs};
+
+/**
+ * 重命名（移动）一个文件或者目录
+ *  - 不复制数据，只是把目录项从旧的父目录，挪到新的父目录中
+ *  - 先在新的父目录中创建目录项，再删除旧的目录项。中途出错，最多是多了一个目录项，不会丢失文件
+ *  - 如果移动的是目录，还需要修改它的..目录项，并且不允许移动到它自己的子目录中
+ */
+#[inline(never)]
+pub fn rename(old_path: &str, new_path: &str) -> Result<(), FileError> {
+    if old_path == "/" || new_path == "/" {
+        return Result::Err(FileError::FilePathIllegal);
+    }
+    let old_split = file_util::split_file_path(old_path);
+    let new_split = file_util::split_file_path(new_path);
+    if old_split.is_none() || new_split.is_none() {
+        return Result::Err(FileError::FilePathIllegal);
+    }
+    let (_, old_name) = old_split.unwrap();
+    let (_, new_name) = new_split.unwrap();
+    // .和..不允许重命名，也不能作为新的名称
+    if old_name == "." || old_name == ".." || new_name == "." || new_name == ".." || new_name.is_empty() {
+        return Result::Err(FileError::FilePathIllegal);
+    }
+
+    // 旧的父目录
+    let (old_parent, _) = vfs::lookup_parent(old_path)?;
+    // 要移动的节点
+    let node = old_parent.get_fs().lookup(&old_parent, old_name);
+    if node.is_none() {
+        old_parent.close();
+        return Result::Err(FileError::NotFound);
+    }
+    let node = node.unwrap();
+    node.close();
+    // 挂载点目录不能移动
+    if mount::find_mounted(node.get_fs_ptr(), node.ino).is_some() {
+        old_parent.close();
+        return Result::Err(FileError::MountPointBusy);
+    }
+
+    // 新的父目录
+    let new_parent = vfs::lookup_parent(new_path);
+    if new_parent.is_err() {
+        old_parent.close();
+        return Result::Err(FileError::ParentDirNotExists);
+    }
+    let (new_parent, _) = new_parent.unwrap();
+    // 只能在同一个文件系统中移动
+    if !vfs::same_fs(new_parent.get_fs_ptr(), old_parent.get_fs_ptr()) {
+        new_parent.close();
+        old_parent.close();
+        return Result::Err(FileError::CrossDevice);
+    }
+
+    let res = old_parent.get_fs().rename(&old_parent, old_name, &new_parent, new_name);
+
+    new_parent.close();
+    old_parent.close();
+    res
+}
+
+/**
+ * LeonFS中，把old_parent_inode目录下的目录项entry（名称为old_name），移动到new_parent_inode目录下，名称为new_name
+ */
+#[inline(never)]
+pub fn do_rename(fs: &mut FileSystem, old_parent_inode: &mut OpenedInode, entry: &DirEntry, old_name: &str, new_parent_inode: &mut OpenedInode, new_name: &str) -> Result<(), FileError> {
+    let is_dir = entry.file_type as FileType == FileType::Directory;
+    let same_parent = old_parent_inode.i_no == new_parent_inode.i_no;
+
+    // 新的名称已经存在了
+    let exist_entry = dir_entry::do_search_dir_entry(fs, new_parent_inode, DirEntrySearchReq::build().entry_name(new_name));
+    if exist_entry.is_some() {
+        // 重命名为自己，什么都不用做
+        if exist_entry.unwrap().i_no == entry.i_no {
+            return Result::Ok(());
+        }
+        return Result::Err(FileError::AlreadyExists);
+    }
+
+    // 目录不能移动到自己（或者自己的子目录）中
+    if is_dir && !same_parent && self::is_in_subtree(fs, new_parent_inode.i_no, entry.i_no) {
+        return Result::Err(FileError::MoveIntoSubDirectory);
+    }
+
+    // 1. 在新的父目录中，创建目录项（会同步新父目录的inode）
+    dir_entry::do_create_dir_entry_with_inode(fs, new_parent_inode, entry.i_no, new_name, entry.file_type)?;
+
+    // 2. 删除旧的父目录中的目录项。名称和inode号都要匹配，同一个目录下重命名的时候，不会删掉新的目录项
+    let removed = dir_entry::remove_dir_entry(fs, old_parent_inode, DirEntrySearchReq::build().entry_name(old_name).i_no(entry.i_no));
+    if !removed {
+        return Result::Err(FileError::NotFound);
+    }
+    inode::sync_inode(fs, old_parent_inode);
+
+    if !is_dir || same_parent {
+        return Result::Ok(());
+    }
+
+    // 3. 移动的是目录，它的..要指向新的父目录
+    let moved_inode = inode::inode_open(fs, entry.i_no);
+    let parent_entry = DirEntry::new(new_parent_inode.i_no, "..", FileType::Directory);
+    dir_entry::replace_dir_entry(fs, moved_inode, DirEntrySearchReq::build().entry_name(".."), &parent_entry);
+    inode::inode_close(fs, moved_inode);
+
+    // 4. 旧的父目录少了一个..的链接，新的父目录多了一个
+    old_parent_inode.i_nlink = old_parent_inode.i_nlink.saturating_sub(1);
+    inode::sync_inode(fs, old_parent_inode);
+    new_parent_inode.i_nlink += 1;
+    inode::sync_inode(fs, new_parent_inode);
+
+    Result::Ok(())
+}
+
+/**
+ * 目录dir_ino，是否是目录ancestor_ino自身，或者位于它的子树中
+ * 从dir_ino开始，沿着..一直往上找，直到根目录
+ */
+#[inline(never)]
+fn is_in_subtree(fs: &mut FileSystem, dir_ino: InodeNo, ancestor_ino: InodeNo) -> bool {
+    let root_ino = fs.super_block.root_inode_no;
+    let mut cur_ino = dir_ino;
+    loop {
+        if cur_ino == ancestor_ino {
+            return true;
+        }
+        if cur_ino == root_ino {
+            return false;
+        }
+        let cur_inode = inode::inode_open(fs, cur_ino);
+        let parent_ino = dir_entry::parent_entry(cur_inode);
+        inode::inode_close(fs, cur_inode);
+        // 找不到上一级了
+        if parent_ino == cur_ino {
+            return false;
+        }
+        cur_ino = parent_ino;
+    }
+}
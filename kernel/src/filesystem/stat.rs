@@ -0,0 +1,218 @@
+use super::{dir_entry::FileType, file::FileError, file_descriptor::FileDescriptor, file_util, fs::FileSystem, global_file_table, inode::{self, OpenedInode}, vfs};
+
+/**
+ * 文件的元信息。stat、fstat系统调用的返回值
+ * 用户进程直接读取这个结构，因此使用C的内存布局，并且只包含定长的整数
+ */
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct FileStat {
+    /**
+     * inode号
+     */
+    pub i_no: u32,
+    /**
+     * 文件类型和权限。高4位是文件类型（跟inode的i_mode一样），低9位是权限（rwxrwxrwx）
+     */
+    pub mode: u32,
+    /**
+     * 硬链接的数量
+     */
+    pub nlink: u32,
+    pub uid: u32,
+    pub gid: u32,
+    /**
+     * 文件大小。单位字节
+     */
+    pub size: u32,
+    /**
+     * 占用的扇区数量（512字节）
+     */
+    pub blocks: u32,
+    /**
+     * 标志位。FLAG_HAS_TIME：有时间信息（没有的话，下面三个字段都是0）
+     */
+    pub flags: u32,
+    /**
+     * 创建、修改、访问时间。时间戳，单位秒
+     */
+    pub ctime: u32,
+    pub mtime: u32,
+    pub atime: u32,
+}
+
+impl FileStat {
+    /**
+     * 有时间信息
+     */
+    pub const FLAG_HAS_TIME: u32 = 0b1;
+
+    pub const fn empty() -> Self {
+        Self {
+            i_no: 0,
+            mode: 0,
+            nlink: 0,
+            uid: 0,
+            gid: 0,
+            size: 0,
+            blocks: 0,
+            flags: 0,
+            ctime: 0,
+            mtime: 0,
+            atime: 0,
+        }
+    }
+
+    #[inline(never)]
+    pub fn from(fs: &mut FileSystem, inode: &mut OpenedInode, file_type: FileType) -> Self {
+        // inode中没有文件类型（旧格式的inode），使用目录项中的类型
+        let inode_file_type = FileType::from_mode(inode.i_mode);
+        let mode = if inode_file_type == FileType::Unknown { file_type.to_mode() } else { inode.i_mode };
+        let has_time = inode.i_ctime != 0 || inode.i_mtime != 0 || inode.i_atime != 0;
+        Self {
+            i_no: inode.i_no.get_data(),
+            mode: mode as u32,
+            nlink: inode.i_nlink as u32,
+            uid: inode.i_uid as u32,
+            gid: inode.i_gid as u32,
+            size: inode.i_size,
+            blocks: inode::size_to_blocks(fs, inode.i_size) * fs.super_block.sec_per_block() as u32,
+            flags: if has_time { Self::FLAG_HAS_TIME } else { 0 },
+            ctime: inode.i_ctime,
+            mtime: inode.i_mtime,
+            atime: inode.i_atime,
+        }
+    }
+
+    /**
+     * 文件类型（mode的高4位）
+     */
+    pub fn file_type(&self) -> FileType {
+        FileType::from_mode(self.mode as u16)
+    }
+
+    pub fn has_time(&self) -> bool {
+        self.flags & Self::FLAG_HAS_TIME != 0
+    }
+}
+
+/**
+ * 文件系统的容量信息。statfs系统调用的返回值
+ * 用户进程直接读取这个结构，因此使用C的内存布局
+ */
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct FsStat {
+    /**
+     * 块的大小。单位字节
+     */
+    pub block_size: u32,
+    /**
+     * 块的总数量、空闲的块数量
+     */
+    pub total_blocks: u32,
+    pub free_blocks: u32,
+    /**
+     * 可以申请的块数量（空闲的块，去掉保留的块）
+     */
+    pub avail_blocks: u32,
+    /**
+     * inode的总数量、空闲的inode数量。不限制inode数量的文件系统，都是0
+     */
+    pub total_inodes: u32,
+    pub free_inodes: u32,
+}
+
+impl FsStat {
+    /**
+     * 没有容量信息的文件系统（例如procfs、devfs）
+     */
+    pub const fn empty() -> Self {
+        Self {
+            block_size: 0,
+            total_blocks: 0,
+            free_blocks: 0,
+            avail_blocks: 0,
+            total_inodes: 0,
+            free_inodes: 0,
+        }
+    }
+
+    /**
+     * 已经使用的块数量
+     */
+    pub fn used_blocks(&self) -> u32 {
+        self.total_blocks - self.free_blocks
+    }
+
+    /**
+     * 已经使用的inode数量
+     */
+    pub fn used_inodes(&self) -> u32 {
+        self.total_inodes - self.free_inodes
+    }
+}
+
+/**
+ * 根据文件路径，读取文件的元信息
+ */
+#[inline(never)]
+pub fn stat(file_path: &str) -> Result<FileStat, FileError> {
+    if !file_path.starts_with("/") || !file_util::is_path_legal(file_path) {
+        return Result::Err(FileError::FilePathIllegal);
+    }
+    let file_node = vfs::lookup_path(file_path, true);
+    if file_node.is_none() {
+        return Result::Err(FileError::NotFound);
+    }
+    let file_node = file_node.unwrap();
+    let file_stat = file_node.get_fs().stat(&file_node);
+    file_node.close();
+    Result::Ok(file_stat)
+}
+
+/**
+ * 根据文件路径，读取文件的元信息。路径的最后一项如果是符号链接，读取符号链接自身的元信息
+ */
+#[inline(never)]
+pub fn lstat(file_path: &str) -> Result<FileStat, FileError> {
+    if !file_path.starts_with("/") || !file_util::is_path_legal(file_path) {
+        return Result::Err(FileError::FilePathIllegal);
+    }
+    let file_node = vfs::lookup_path(file_path, false);
+    if file_node.is_none() {
+        return Result::Err(FileError::NotFound);
+    }
+    let file_node = file_node.unwrap();
+    let file_stat = file_node.get_fs().stat(&file_node);
+    file_node.close();
+    Result::Ok(file_stat)
+}
+
+/**
+ * 根据文件描述符，读取已打开文件的元信息
+ */
+#[inline(never)]
+pub fn fstat(fd: FileDescriptor) -> Result<FileStat, FileError> {
+    let opened_file = global_file_table::get_file_by_fd(fd)?;
+    let file_node = opened_file.get_node();
+    Result::Ok(file_node.get_fs().stat(file_node))
+}
+
+/**
+ * 读取路径所在的文件系统的容量信息
+ */
+#[inline(never)]
+pub fn statfs(file_path: &str) -> Result<FsStat, FileError> {
+    if !file_path.starts_with("/") || !file_util::is_path_legal(file_path) {
+        return Result::Err(FileError::FilePathIllegal);
+    }
+    let file_node = vfs::lookup_path(file_path, true);
+    if file_node.is_none() {
+        return Result::Err(FileError::NotFound);
+    }
+    let file_node = file_node.unwrap();
+    let fs_stat = file_node.get_fs().statfs();
+    file_node.close();
+    Result::Ok(fs_stat)
+}
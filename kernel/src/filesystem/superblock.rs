@@ -3,18 +3,80 @@ use core::mem::size_of;
 use os_in_rust_common::{constants, domain::LbaAddr, utils};
 use os_in_rust_common::domain::InodeNo;
 
-use super::{constant, dir_entry::DirEntry, inode::Inode};
+use super::{constant, dir_entry::DirEntryHeader, inode::Inode};
 
 /**
  * 文件系统的超级块
  * 文件系统结构：
- * | 引导块(1扇区) | 超级块(1扇区) | inode位图(x扇区) | inode数组(y扇区)| 空闲数据块位图(z扇区)  | 根目录(1扇区) | 若干个数据块
+ * | 引导块(1扇区) | 超级块(1扇区) | 日志区(j扇区) | inode位图(x扇区) | inode数组(y扇区)| 空闲数据块位图(z扇区)  | 根目录(1块) | 若干个数据块
  */
 
+/**
+ * 格式化文件系统的选项
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct MkfsOptions {
+    /**
+     * inode的数量，也就是最多可以创建的文件数量
+     */
+    pub inode_cnt: u32,
+    /**
+     * 块大小（单位字节）。目前只能是1KB，见MAX_MKFS_BLOCK_SIZE
+     */
+    pub block_size: u32,
+    /**
+     * 保留的数据块数量。剩余的数据块不多于保留数量的时候，不能再申请数据块
+     */
+    pub reserved_blocks: u32,
+}
+
+impl MkfsOptions {
+    /**
+     * 根据分区大小，生成默认的格式化选项
+     *  - inode数量：每DEFAULT_BYTES_PER_INODE个字节一个inode
+     *  - 块大小：DEFAULT_BLOCK_SIZE
+     *  - 不保留数据块
+     */
+    #[inline(never)]
+    pub fn default_for(part_secs: u32) -> Self {
+        let part_bytes = part_secs as u64 * constants::DISK_SECTOR_SIZE as u64;
+        let inode_cnt = (part_bytes / constant::DEFAULT_BYTES_PER_INODE as u64) as u32;
+        Self {
+            inode_cnt: inode_cnt.clamp(constant::MIN_INODE_CNT, constant::MAX_INODE_CNT),
+            block_size: constant::DEFAULT_BLOCK_SIZE,
+            reserved_blocks: 0,
+        }
+    }
+}
+
+/**
+ * 格式化文件系统的错误
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MkfsError {
+    /**
+     * 块大小不是格式化支持的大小（目前只有1KB）
+     */
+    InvalidBlockSize,
+    /**
+     * inode数量不在[MIN_INODE_CNT, MAX_INODE_CNT]之间
+     */
+    InvalidInodeCount,
+    /**
+     * 保留的数据块，不少于全部的数据块
+     */
+    TooManyReservedBlocks,
+    /**
+     * 分区太小，放不下元数据和根目录
+     */
+    PartitionTooSmall,
+}
+
 /**
  * 文件系统超级块的结构。物理结构。512个字节
  * 超级块是文件系统元数据（块位图、inode位图）的元数据，目前文件系统的元数据结构位置是这样的：
- * | 引导块(1扇区) | 超级块(1扇区) | inode位图(x扇区) | inode数组(y扇区)| 空闲数据块位图(z扇区)  | 根目录(1扇区) | 若干个数据块
+ * | 引导块(1扇区) | 超级块(1扇区) | 日志区(j扇区) | inode位图(x扇区) | inode数组(y扇区)| 空闲数据块位图(z扇区)  | 根目录(1块) | 若干个数据块
+ * 数据区按照块来分配，一个块是连续的block_size / 扇区大小个扇区
  */
 #[derive(Debug)]
 #[repr(C, align(512))]
@@ -42,7 +104,7 @@ pub struct SuperBlock {
      */
     pub root_inode_no: InodeNo,
     /**
-     * 目录项的大小
+     * 目录项头部的大小。目录项是变长的：头部 + 名称
      */
     pub dir_entry_size: u32, 
    
@@ -68,7 +130,7 @@ pub struct SuperBlock {
     /**
      * 空闲块位图自身所在扇区的LBA地址
      *  - 块位图是描述空闲块的使用情况
-     *  - 一位表示某一个块（扇区）的使用情况
+     *  - 一位表示某一个块的使用情况
      */
     pub block_bitmap_lba: LbaAddr,
     /**
@@ -82,47 +144,95 @@ pub struct SuperBlock {
     pub data_lba_start: LbaAddr,
 
     /**
-     * 数据扇区的数量。实际真正可用的数据扇区（根目录所在扇区也算可用的数据扇区）
+     * 数据扇区的数量。实际真正可用的数据扇区（根目录所在扇区也算可用的数据扇区）。是每个块扇区数量的整数倍
      */
     pub data_block_secs: u32,
+
+    /**
+     * 每个inode的大小（单位字节）
+     * 旧版本的文件系统没有这个字段（读出来是0），inode是旧的格式
+     */
+    pub inode_size: u32,
+
+    /**
+     * 日志区所在的LBA起始地址。元数据的修改，先写入日志区，再写入原本的位置
+     */
+    pub journal_lba: LbaAddr,
+    /**
+     * 日志区占用的扇区数量。更早的文件系统没有日志区（读出来是0），不支持挂载
+     */
+    pub journal_secs: u32,
+
+    /**
+     * 块大小（单位字节）
+     * 旧版本的文件系统没有这个字段（读出来是0），一个块就是一个扇区
+     */
+    pub block_size: u32,
+    /**
+     * 保留的数据块数量。剩余的数据块不多于保留数量的时候，内核不能再申请数据块
+     */
+    pub reserved_blocks: u32,
 }
 
 impl SuperBlock {
     /**
      * 构建超级块。超级块是文件系统的元数据的元数据。
      * 我们的文件系统数据占据的扇区的结构这样的：
-     * | 引导块(1扇区) | 超级块(1扇区) | inode位图(x扇区) | inode数组(y扇区)| 空闲数据块位图(z扇区) | 根目录(1扇区) | 数据块
+     * | 引导块(1扇区) | 超级块(1扇区) | 日志区(j扇区) | inode位图(x扇区) | inode数组(y扇区)| 空闲数据块位图(z扇区) | 根目录(1块) | 数据块
+     *  - options: inode数量、块大小、保留块数量
      */
     #[inline(never)]
-    pub fn new(part_lba: LbaAddr, part_secs: u32) -> Self {
+    pub fn new(part_lba: LbaAddr, part_secs: u32, options: &MkfsOptions) -> Result<Self, MkfsError> {
+        if !Self::is_valid_mkfs_block_size(options.block_size) {
+            return Result::Err(MkfsError::InvalidBlockSize);
+        }
+        if options.inode_cnt < constant::MIN_INODE_CNT || options.inode_cnt > constant::MAX_INODE_CNT {
+            return Result::Err(MkfsError::InvalidInodeCount);
+        }
+        let sec_per_block = options.block_size / constants::DISK_SECTOR_SIZE as u32;
+
+        // 日志区所在扇区的起始LBA = 开始LBA + 引导块 + 超级块
+        let journal_lba = part_lba.get_lba() + 1 + 1;
+        let journal_secs = constant::JOURNAL_SECS;
 
-        // inode位图所在扇区的起始LBA= 开始LBA + 引导块 + 超级块
-        let inode_bitmap_lba = part_lba.get_lba() + 1 + 1;
-        // inode位图占用的扇区数量 = inode最大数量 / 一个扇区的位数
-        let inode_bitmap_sec = utils::div_ceil(constant::MAX_FILE_PER_FS, constants::DISK_SECTOR_SIZE as u32 * 8) as u32;
+        // inode位图所在扇区的起始LBA= 日志区之后
+        let inode_bitmap_lba = journal_lba + journal_secs;
+        // inode位图占用的扇区数量 = inode数量 / 一个扇区的位数
+        let inode_bitmap_sec = utils::div_ceil(options.inode_cnt, constants::DISK_SECTOR_SIZE as u32 * 8) as u32;
         
         let inode_table_lba = inode_bitmap_lba + inode_bitmap_sec;
         // inode数组占用的扇区数量 = inode数量 * inode大小 / 一个扇区的大小
-        let inode_table_sec = utils::div_ceil(constant::MAX_FILE_PER_FS * size_of::<Inode>() as u32, constants::DISK_SECTOR_SIZE as u32) as u32;
+        let inode_table_sec = utils::div_ceil(options.inode_cnt * size_of::<Inode>() as u32, constants::DISK_SECTOR_SIZE as u32) as u32;
 
         let block_bitmap_lba  = inode_table_lba + inode_table_sec;
-        // 剩余可用扇区的数量 = 该分区总扇区数量 - 引导块（1扇区） - 超级块（1扇区） - inode位图占扇区数量 - inode数组占扇区数量
-        let left_secs = part_secs - 1 - size_of::<SuperBlock>() as u32 - inode_bitmap_sec - inode_table_sec;
-        // 空闲块位图 占用的扇区数量 = 剩余扇区 / 每个扇区包含的位数
-        let block_bitmap_secs =  utils::div_ceil(left_secs, constants::DISK_SECTOR_SIZE as u32 * 8) as u32;
-        // 数据块的扇区数量 = 原本空闲的块扇区数量 - 块位图占用的扇区数量
-        let data_block_secs = left_secs - block_bitmap_secs;
-        // 空闲块位图 占用的扇区数量 =  数据块扇区数量 / 每个扇区包含的位数
-        let block_bitmap_secs = utils::div_ceil(data_block_secs, constants::DISK_SECTOR_SIZE as u32 * 8) as u32;
+        // 剩余可用扇区的数量 = 该分区总扇区数量 - 引导块（1扇区） - 超级块（1扇区） - 日志区 - inode位图占扇区数量 - inode数组占扇区数量
+        let meta_secs = 1 + 1 + journal_secs + inode_bitmap_sec + inode_table_sec;
+        if part_secs <= meta_secs {
+            return Result::Err(MkfsError::PartitionTooSmall);
+        }
+        let left_secs = part_secs - meta_secs;
+        // 空闲块位图 占用的扇区数量 = 剩余的块数量 / 每个扇区包含的位数
+        let block_bitmap_secs =  utils::div_ceil(left_secs / sec_per_block, constants::DISK_SECTOR_SIZE as u32 * 8) as u32;
+        // 数据块的数量 = (原本空闲的扇区数量 - 块位图占用的扇区数量) / 每个块的扇区数量
+        let data_blocks = (left_secs - block_bitmap_secs) / sec_per_block;
+        // 至少要放下根目录
+        if data_blocks == 0 {
+            return Result::Err(MkfsError::PartitionTooSmall);
+        }
+        if options.reserved_blocks >= data_blocks {
+            return Result::Err(MkfsError::TooManyReservedBlocks);
+        }
+        // 空闲块位图 占用的扇区数量 =  数据块数量 / 每个扇区包含的位数
+        let block_bitmap_secs = utils::div_ceil(data_blocks, constants::DISK_SECTOR_SIZE as u32 * 8) as u32;
 
 
-        Self {
+        Result::Ok(Self {
             magic: constant::FILESYSTEM_MAGIC,
             lba_start: part_lba, // 分区的起始扇区LBA地址
             sec_cnt: part_secs, // 该分区的扇区数量
-            inode_cnt: constant::MAX_FILE_PER_FS,
+            inode_cnt: options.inode_cnt,
             root_inode_no: InodeNo::new(0), // 根目录的inode号就是0，位于inode数据的首个元素
-            dir_entry_size: size_of::<DirEntry>().try_into().unwrap(), // 目录项的大小
+            dir_entry_size: size_of::<DirEntryHeader>().try_into().unwrap(), // 目录项头部的大小
             // inode位图
             inode_bitmap_lba: LbaAddr::new(inode_bitmap_lba), // inode位图所在扇区的起始LBA
             inode_bitmap_secs: inode_bitmap_sec,// inode位图占用扇区数量
@@ -134,8 +244,108 @@ impl SuperBlock {
             block_bitmap_secs: block_bitmap_secs, // 空闲块位图 占用扇区数量
             // 空闲块起始LBA地址，跳过前面的所有块
             data_lba_start: LbaAddr::new(block_bitmap_lba + block_bitmap_secs),
-            data_block_secs: data_block_secs, // 数据块占用的扇区的数量
+            data_block_secs: data_blocks * sec_per_block, // 数据块占用的扇区的数量
+            inode_size: size_of::<Inode>().try_into().unwrap(), // inode的大小
+            // 日志区
+            journal_lba: LbaAddr::new(journal_lba), // 日志区所在扇区的起始LBA
+            journal_secs: journal_secs, // 日志区占用扇区的数量
+            block_size: options.block_size,
+            reserved_blocks: options.reserved_blocks,
+        })
+    }
+
+    /**
+     * 挂载的时候，是否支持这个块大小：1KB、2KB、4KB
+     */
+    pub fn is_valid_block_size(block_size: u32) -> bool {
+        block_size >= constant::MIN_BLOCK_SIZE && block_size <= constant::MAX_BLOCK_SIZE && block_size.is_power_of_two()
+    }
+
+    /**
+     * 格式化的时候，是否可以使用这个块大小：不超过MAX_MKFS_BLOCK_SIZE
+     */
+    pub fn is_valid_mkfs_block_size(block_size: u32) -> bool {
+        Self::is_valid_block_size(block_size) && block_size <= constant::MAX_MKFS_BLOCK_SIZE
+    }
+
+    /**
+     * 分区中是否是LeonFS（魔数正确）。格式不一定支持
+     */
+    #[inline(never)]
+    pub fn has_magic(&self) -> bool {
+        self.magic == constant::FILESYSTEM_MAGIC
+    }
+
+    /**
+     * 是否是可以挂载的文件系统：魔数正确，使用变长的目录项，有日志区
+     *  - inode可以是旧的格式（inode_size是0），块大小可以是旧的一个扇区（block_size是0）
+     */
+    #[inline(never)]
+    pub fn is_formatted(&self) -> bool {
+        self.has_magic()
+            && self.dir_entry_size as usize == size_of::<DirEntryHeader>()
+            && self.journal_secs == constant::JOURNAL_SECS
+            && (self.inode_size == 0 || self.inode_size as usize == size_of::<Inode>())
+            && (self.block_size == 0 || Self::is_valid_block_size(self.block_size))
+    }
+
+    /**
+     * inode是否是旧的格式（没有二级、三级间接块，也没有mode、链接数量、时间）
+     */
+    #[inline(never)]
+    pub fn has_legacy_inode(&self) -> bool {
+        self.get_inode_size() < size_of::<Inode>()
+    }
+
+    /**
+     * 该文件系统中，块的大小（单位字节）
+     */
+    #[inline(never)]
+    pub fn get_block_size(&self) -> usize {
+        if self.block_size == 0 {
+            return constants::DISK_SECTOR_SIZE;
+        }
+        self.block_size as usize
+    }
+
+    /**
+     * 该文件系统中，一个块包含的扇区数量
+     */
+    #[inline(never)]
+    pub fn sec_per_block(&self) -> usize {
+        self.get_block_size() / constants::DISK_SECTOR_SIZE
+    }
+
+    /**
+     * 该文件系统中，数据块的数量（也就是块位图的有效位数）
+     */
+    #[inline(never)]
+    pub fn data_blocks(&self) -> u32 {
+        self.data_block_secs / self.sec_per_block() as u32
+    }
+
+    /**
+     * 该文件系统中，每个inode在硬盘中的大小（单位字节）
+     */
+    #[inline(never)]
+    pub fn get_inode_size(&self) -> usize {
+        if self.inode_size == 0 {
+            return constant::LEGACY_INODE_SIZE;
+        }
+        self.inode_size as usize
+    }
+
+    /**
+     * 该文件系统中，一个文件最多可以有多少个数据块
+     *  - 旧格式的inode中没有二级、三级间接块，只能使用直接块和一级间接块
+     *  - 间接块只使用第一个扇区，所以和块大小无关
+     */
+    #[inline(never)]
+    pub fn max_data_blocks(&self) -> usize {
+        if self.has_legacy_inode() {
+            return constant::INODE_CACHED_DATA_SECS;
         }
+        constant::INODE_MAX_DATA_SECS
     }
 
 }
\ No newline at end of file
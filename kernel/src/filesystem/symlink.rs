@@ -0,0 +1,64 @@
+use super::{constant, dir_entry::FileType, file::FileError, file_util, vfs};
+
+/**
+ * 创建一个符号链接：在link_path所在的目录下，添加一个符号链接类型的目录项，数据区存放target_path
+ *  - target_path原样保存，可以是绝对路径或者相对路径（相对于符号链接所在的目录），也不要求目标存在
+ *  - 目标路径最多MAX_FILE_PATH_LEN个字节，只占用一个数据块
+ */
+#[inline(never)]
+pub fn symlink(target_path: &str, link_path: &str) -> Result<(), FileError> {
+    if target_path.is_empty() || target_path.len() > constant::MAX_FILE_PATH_LEN {
+        return Result::Err(FileError::FilePathIllegal);
+    }
+    if !link_path.starts_with("/") || link_path.ends_with("/") {
+        return Result::Err(FileError::FilePathIllegal);
+    }
+    let link_split = file_util::split_file_path(link_path);
+    if link_split.is_none() {
+        return Result::Err(FileError::FilePathIllegal);
+    }
+    let (_, link_name) = link_split.unwrap();
+    if link_name.is_empty() || link_name == "." || link_name == ".." {
+        return Result::Err(FileError::FilePathIllegal);
+    }
+
+    // 符号链接所在的目录
+    let (parent_dir, _) = vfs::lookup_parent(link_path)?;
+    let link_node = parent_dir.get_fs().create(&parent_dir, link_name, FileType::Symlink);
+    parent_dir.close();
+    let link_node = link_node?;
+
+    // 把目标路径，写入到符号链接的数据区
+    let written = link_node.get_fs().write(&link_node, 0, target_path.as_bytes());
+    link_node.close();
+    if written? != target_path.len() {
+        return Result::Err(FileError::Uncategorized);
+    }
+    Result::Ok(())
+}
+
+/**
+ * 读取符号链接link_path的目标路径，写入到buff中。返回目标路径的字节数
+ *  - link_path本身不跟随。如果link_path不是符号链接，返回NotASymlink
+ */
+#[inline(never)]
+pub fn readlink(link_path: &str, buff: &mut [u8]) -> Result<usize, FileError> {
+    if !link_path.starts_with("/") || !file_util::is_path_legal(link_path) {
+        return Result::Err(FileError::FilePathIllegal);
+    }
+    let link_node = vfs::lookup_path(link_path, false);
+    if link_node.is_none() {
+        return Result::Err(FileError::NotFound);
+    }
+    let link_node = link_node.unwrap();
+    if link_node.file_type != FileType::Symlink {
+        link_node.close();
+        return Result::Err(FileError::NotASymlink);
+    }
+    let target_len = link_node.get_fs().read(&link_node, 0, buff);
+    link_node.close();
+    if target_len == 0 || core::str::from_utf8(&buff[..target_len]).is_err() {
+        return Result::Err(FileError::Uncategorized);
+    }
+    Result::Ok(target_len)
+}
@@ -0,0 +1,562 @@
+use core::{mem::size_of, ptr, slice, str};
+
+use os_in_rust_common::{constants, domain::InodeNo, printkln};
+
+use crate::{memory, time};
+
+use super::{constant, dir_api, dir_entry::{DirEntry, FileType}, file::FileError, mount::{self, MountError}, stat::{FileStat, FsStat}, vfs::{Vfs, Vnode}};
+
+/**
+ * tmpfs：数据放在内存中的文件系统，用来存放临时文件。卸载（或者关机）之后数据就没有了
+ *  - 节点、目录项，放在内核堆中
+ *  - 文件的数据，放在内核页中。每个节点有一个页表（也是一个内核页），记录每个数据页的地址
+ *  - 数据页和页表，一共最多使用TMPFS_MAX_PAGES个内核页
+ */
+
+/**
+ * tmpfs的来源名称。mount tmpfs <path> 挂载tmpfs
+ */
+pub const TMP_FS_SOURCE: &str = "tmpfs";
+
+/**
+ * 根目录的编号
+ */
+const ROOT_INO: u32 = 1;
+
+/**
+ * 一个页表中数据页的数量。文件最大 = PAGE_TABLE_ENTRIES * PAGE_SIZE
+ */
+const PAGE_TABLE_ENTRIES: usize = constants::PAGE_SIZE as usize / size_of::<usize>();
+
+/**
+ * tmpfs中的节点（文件、目录、符号链接）
+ */
+struct TmpNode {
+    ino: InodeNo,
+    file_type: FileType,
+    nlink: u16,
+    /**
+     * 打开的次数。没有链接、也没有打开的时候，释放这个节点
+     */
+    open_cnt: u32,
+    size: u32,
+    ctime: u32,
+    mtime: u32,
+    atime: u32,
+    /**
+     * 页表的地址。还没有数据的时候是0
+     */
+    page_table: usize,
+    /**
+     * 父目录。根目录的父目录是它自己
+     */
+    parent: *mut TmpNode,
+    /**
+     * 目录的第一个目录项
+     */
+    first_entry: *mut TmpDirEntry,
+    /**
+     * 文件系统中的所有节点，组成一个链表
+     */
+    next_node: *mut TmpNode,
+}
+
+impl TmpNode {
+    fn get_page_table(&self) -> Option<&'static mut [usize]> {
+        if self.page_table == 0 {
+            return Option::None;
+        }
+        Option::Some(unsafe { slice::from_raw_parts_mut(self.page_table as *mut usize, PAGE_TABLE_ENTRIES) })
+    }
+
+    fn is_dir(&self) -> bool {
+        self.file_type == FileType::Directory
+    }
+}
+
+/**
+ * tmpfs中的目录项。一个目录的所有目录项，组成一个链表
+ */
+struct TmpDirEntry {
+    node: *mut TmpNode,
+    name_len: u8,
+    name: [u8; constant::MAX_FILE_NAME],
+    next: *mut TmpDirEntry,
+}
+
+impl TmpDirEntry {
+    fn get_name(&self) -> &str {
+        str::from_utf8(&self.name[..self.name_len as usize]).unwrap_or("")
+    }
+}
+
+/**
+ * tmpfs。每次挂载都创建一个新的，卸载的时候释放所有的内存
+ */
+pub struct TmpFs {
+    root: *mut TmpNode,
+    /**
+     * 所有节点组成的链表
+     */
+    all_nodes: *mut TmpNode,
+    /**
+     * 下一个节点的编号
+     */
+    next_ino: u32,
+    /**
+     * 已经使用的内核页数量
+     */
+    used_pages: usize,
+}
+
+/**
+ * 创建一个空的tmpfs（只有根目录）
+ */
+#[inline(never)]
+pub fn create() -> *mut dyn Vfs {
+    let fs: &mut TmpFs = memory::malloc_system(size_of::<TmpFs>());
+    *fs = TmpFs {
+        root: ptr::null_mut(),
+        all_nodes: ptr::null_mut(),
+        next_ino: ROOT_INO,
+        used_pages: 0,
+    };
+    let root = fs.new_node(FileType::Directory, ptr::null_mut());
+    root.parent = root as *mut TmpNode;
+    fs.root = root as *mut TmpNode;
+    fs as *mut TmpFs
+}
+
+/**
+ * 创建目录path，挂载一个新的tmpfs。系统启动的时候调用
+ */
+#[inline(never)]
+pub fn mount_tmpfs(path: &str) {
+    let res = dir_api::create_dir_all(path).map_err(|_| MountError::MountPointNotFound)
+        .and_then(|_| self::mount(path));
+    if res.is_err() {
+        printkln!("failed to mount {} on {}, error:{:?}", TMP_FS_SOURCE, path, res.unwrap_err());
+    }
+}
+
+/**
+ * 创建一个新的tmpfs，挂载到目录path上。挂载失败的话，释放掉
+ */
+#[inline(never)]
+pub fn mount(path: &str) -> Result<(), MountError> {
+    let fs = self::create();
+    let res = mount::mount_fs(fs, TMP_FS_SOURCE, path);
+    if res.is_err() {
+        unsafe { &mut *fs }.unmount();
+    }
+    res
+}
+
+/**
+ * 节点对应的tmpfs节点
+ */
+fn tmp_node(node: &Vnode) -> &'static mut TmpNode {
+    unsafe { &mut *(node.get_data() as *mut TmpNode) }
+}
+
+impl TmpFs {
+    /**
+     * 创建一个节点，放到节点链表中
+     */
+    #[inline(never)]
+    fn new_node(&mut self, file_type: FileType, parent: *mut TmpNode) -> &'static mut TmpNode {
+        let node: &mut TmpNode = memory::malloc_system(size_of::<TmpNode>());
+        let now = time::get_current_timestamp();
+        *node = TmpNode {
+            ino: InodeNo::new(self.next_ino),
+            file_type,
+            // 目录有两个链接：父目录中的目录项，以及自己的.
+            nlink: if file_type == FileType::Directory { 2 } else { 1 },
+            open_cnt: 0,
+            size: 0,
+            ctime: now,
+            mtime: now,
+            atime: now,
+            page_table: 0,
+            parent,
+            first_entry: ptr::null_mut(),
+            next_node: self.all_nodes,
+        };
+        self.next_ino += 1;
+        self.all_nodes = node as *mut TmpNode;
+        node
+    }
+
+    /**
+     * 释放节点：释放数据页、页表，从节点链表中移除
+     */
+    #[inline(never)]
+    fn free_node(&mut self, node: *mut TmpNode) {
+        let node_ref = unsafe { &mut *node };
+        self.free_pages(node_ref);
+        if self.all_nodes == node {
+            self.all_nodes = node_ref.next_node;
+        } else {
+            let mut cur = self.all_nodes;
+            while !cur.is_null() {
+                let cur_ref = unsafe { &mut *cur };
+                if cur_ref.next_node == node {
+                    cur_ref.next_node = node_ref.next_node;
+                    break;
+                }
+                cur = cur_ref.next_node;
+            }
+        }
+        memory::free_system(node);
+    }
+
+    /**
+     * 释放节点所有的数据页以及页表
+     */
+    #[inline(never)]
+    fn free_pages(&mut self, node: &mut TmpNode) {
+        let page_table = node.get_page_table();
+        if page_table.is_none() {
+            return;
+        }
+        for page in page_table.unwrap().iter().filter(|page| **page != 0) {
+            memory::free_kernel_page(*page, 1, true);
+            self.used_pages -= 1;
+        }
+        memory::free_kernel_page(node.page_table, 1, true);
+        self.used_pages -= 1;
+        node.page_table = 0;
+    }
+
+    /**
+     * 节点的第page_idx个数据页的地址
+     *  - alloc: 数据页不存在的时候，是否申请。超过文件系统的大小限制，就申请不到
+     */
+    #[inline(never)]
+    fn get_page(&mut self, node: &mut TmpNode, page_idx: usize, alloc: bool) -> Option<usize> {
+        if page_idx >= PAGE_TABLE_ENTRIES {
+            return Option::None;
+        }
+        if node.page_table == 0 {
+            // 页表和数据页，至少要能申请到两个页
+            if !alloc || self.used_pages + 2 > constant::TMPFS_MAX_PAGES {
+                return Option::None;
+            }
+            node.page_table = memory::malloc_kernel_page(1);
+            self.used_pages += 1;
+        }
+        let page_table = node.get_page_table().unwrap();
+        if page_table[page_idx] == 0 {
+            if !alloc || self.used_pages + 1 > constant::TMPFS_MAX_PAGES {
+                return Option::None;
+            }
+            page_table[page_idx] = memory::malloc_kernel_page(1);
+            self.used_pages += 1;
+        }
+        Option::Some(page_table[page_idx])
+    }
+
+    /**
+     * 在目录dir中，找到名为name的目录项。返回(上一个目录项, 这个目录项)，第一个目录项的上一个是null
+     */
+    #[inline(never)]
+    fn find_entry(&self, dir: &TmpNode, name: &str) -> Option<(*mut TmpDirEntry, *mut TmpDirEntry)> {
+        let mut prev = ptr::null_mut();
+        let mut cur = dir.first_entry;
+        while !cur.is_null() {
+            let cur_ref = unsafe { &*cur };
+            if cur_ref.get_name() == name {
+                return Option::Some((prev, cur));
+            }
+            prev = cur;
+            cur = cur_ref.next;
+        }
+        Option::None
+    }
+
+    /**
+     * 在目录dir的最后，添加一个名为name、指向node的目录项
+     */
+    #[inline(never)]
+    fn add_entry(&mut self, dir: &mut TmpNode, name: &str, node: *mut TmpNode) {
+        let entry: &mut TmpDirEntry = memory::malloc_system(size_of::<TmpDirEntry>());
+        entry.node = node;
+        entry.name_len = name.len() as u8;
+        entry.name[..name.len()].copy_from_slice(name.as_bytes());
+        entry.next = ptr::null_mut();
+
+        if dir.first_entry.is_null() {
+            dir.first_entry = entry;
+            return;
+        }
+        let mut last = unsafe { &mut *dir.first_entry };
+        while !last.next.is_null() {
+            last = unsafe { &mut *last.next };
+        }
+        last.next = entry;
+    }
+
+    #[inline(never)]
+    fn open_node(&mut self, node: &mut TmpNode) -> Vnode {
+        node.open_cnt += 1;
+        Vnode::new(self as *mut TmpFs, node.ino, node.file_type, node as *mut TmpNode as usize)
+    }
+}
+
+impl Vfs for TmpFs {
+    fn root_ino(&self) -> InodeNo {
+        InodeNo::new(ROOT_INO)
+    }
+
+    /**
+     * 目录已经被删除了的话，打开根目录
+     */
+    #[inline(never)]
+    fn open_dir(&mut self, ino: InodeNo) -> Vnode {
+        let mut cur = self.all_nodes;
+        while !cur.is_null() {
+            let cur_ref = unsafe { &mut *cur };
+            if cur_ref.ino == ino && cur_ref.is_dir() {
+                return self.open_node(cur_ref);
+            }
+            cur = cur_ref.next_node;
+        }
+        let root = self.root;
+        self.open_node(unsafe { &mut *root })
+    }
+
+    #[inline(never)]
+    fn lookup(&mut self, dir: &Vnode, name: &str) -> Option<Vnode> {
+        let dir_node = tmp_node(dir);
+        if name == "." {
+            return Option::Some(self.open_node(dir_node));
+        }
+        if name == ".." {
+            return Option::Some(self.open_node(unsafe { &mut *dir_node.parent }));
+        }
+        let (_, entry) = self.find_entry(dir_node, name)?;
+        Option::Some(self.open_node(unsafe { &mut *(*entry).node }))
+    }
+
+    #[inline(never)]
+    fn create(&mut self, dir: &Vnode, name: &str, file_type: FileType) -> Result<Vnode, FileError> {
+        let dir_node = tmp_node(dir);
+        if name.is_empty() || name.len() > constant::MAX_FILE_NAME {
+            return Result::Err(FileError::FilePathIllegal);
+        }
+        if self.find_entry(dir_node, name).is_some() {
+            return Result::Err(FileError::AlreadyExists);
+        }
+        let node = self.new_node(file_type, dir_node as *mut TmpNode);
+        // 子目录的..，是父目录的一个链接
+        if file_type == FileType::Directory {
+            dir_node.nlink += 1;
+        }
+        self.add_entry(dir_node, name, node as *mut TmpNode);
+        dir_node.mtime = time::get_current_timestamp();
+        Result::Ok(self.open_node(node))
+    }
+
+    /**
+     * 没有数据页的地方（空洞），读取出来是0
+     */
+    #[inline(never)]
+    fn read(&mut self, node: &Vnode, off: u32, buff: &mut [u8]) -> usize {
+        let file_node = tmp_node(node);
+        if file_node.is_dir() {
+            return 0;
+        }
+        let page_size = constants::PAGE_SIZE as usize;
+        let total_len = buff.len().min((file_node.size as usize).saturating_sub(off as usize));
+        let mut done_len = 0;
+        while done_len < total_len {
+            let cur_off = off as usize + done_len;
+            let page_off = cur_off % page_size;
+            let copy_len = (page_size - page_off).min(total_len - done_len);
+            let page = self.get_page(file_node, cur_off / page_size, false);
+            if page.is_some() {
+                let page_data = unsafe { slice::from_raw_parts((page.unwrap() + page_off) as *const u8, copy_len) };
+                buff[done_len..done_len + copy_len].copy_from_slice(page_data);
+            } else {
+                buff[done_len..done_len + copy_len].fill(0);
+            }
+            done_len += copy_len;
+        }
+        file_node.atime = time::get_current_timestamp();
+        total_len
+    }
+
+    /**
+     * 申请不到数据页（超过文件系统的大小，或者超过文件的最大大小），就只写入前面的部分
+     */
+    #[inline(never)]
+    fn write(&mut self, node: &Vnode, off: u32, buff: &[u8]) -> Result<usize, FileError> {
+        let file_node = tmp_node(node);
+        if file_node.is_dir() {
+            return Result::Err(FileError::IsADirectory);
+        }
+        let page_size = constants::PAGE_SIZE as usize;
+        let mut done_len = 0;
+        while done_len < buff.len() {
+            let cur_off = off as usize + done_len;
+            let page_off = cur_off % page_size;
+            let copy_len = (page_size - page_off).min(buff.len() - done_len);
+            let page = self.get_page(file_node, cur_off / page_size, true);
+            if page.is_none() {
+                break;
+            }
+            let page_data = unsafe { slice::from_raw_parts_mut((page.unwrap() + page_off) as *mut u8, copy_len) };
+            page_data.copy_from_slice(&buff[done_len..done_len + copy_len]);
+            done_len += copy_len;
+        }
+        if done_len == 0 && !buff.is_empty() {
+            return Result::Err(FileError::NoSpace);
+        }
+        file_node.size = file_node.size.max(off + done_len as u32);
+        file_node.mtime = time::get_current_timestamp();
+        Result::Ok(done_len)
+    }
+
+    /**
+     * 目录项的偏移量，就是目录项的下标：.、..、目录项链表
+     */
+    #[inline(never)]
+    fn readdir(&mut self, dir: &Vnode, off: u32) -> Option<(DirEntry, u32)> {
+        let dir_node = tmp_node(dir);
+        let entry = if off == 0 {
+            DirEntry::new(dir_node.ino, ".", FileType::Directory)
+        } else if off == 1 {
+            DirEntry::new(unsafe { &*dir_node.parent }.ino, "..", FileType::Directory)
+        } else {
+            let mut cur = dir_node.first_entry;
+            for _ in 2..off {
+                if cur.is_null() {
+                    break;
+                }
+                cur = unsafe { &*cur }.next;
+            }
+            if cur.is_null() {
+                return Option::None;
+            }
+            let cur_ref = unsafe { &*cur };
+            let entry_node = unsafe { &*cur_ref.node };
+            DirEntry::new(entry_node.ino, cur_ref.get_name(), entry_node.file_type)
+        };
+        Option::Some((entry, off + 1))
+    }
+
+    /**
+     * 删除目录项。节点没有链接、也没有打开的话，直接释放；否则等到最后一次关闭的时候释放
+     */
+    #[inline(never)]
+    fn unlink(&mut self, dir: &Vnode, name: &str) -> Result<(), FileError> {
+        let dir_node = tmp_node(dir);
+        let (prev, entry) = self.find_entry(dir_node, name).ok_or(FileError::NotFound)?;
+        let entry_ref = unsafe { &mut *entry };
+        let target = unsafe { &mut *entry_ref.node };
+        if target.is_dir() && !target.first_entry.is_null() {
+            return Result::Err(FileError::DirectoryNotEmpty);
+        }
+
+        // 从目录项链表中移除
+        if prev.is_null() {
+            dir_node.first_entry = entry_ref.next;
+        } else {
+            unsafe { &mut *prev }.next = entry_ref.next;
+        }
+        let target_ptr = entry_ref.node;
+        memory::free_system(entry);
+
+        if target.is_dir() {
+            target.nlink = 0;
+            dir_node.nlink -= 1;
+        } else {
+            target.nlink -= 1;
+        }
+        dir_node.mtime = time::get_current_timestamp();
+        if target.nlink == 0 && target.open_cnt == 0 {
+            self.free_node(target_ptr);
+        }
+        Result::Ok(())
+    }
+
+    #[inline(never)]
+    fn stat(&mut self, node: &Vnode) -> FileStat {
+        let file_node = tmp_node(node);
+        // 占用的块数量，按照扇区计算
+        let page_cnt = file_node.get_page_table().map_or(0, |page_table| page_table.iter().filter(|page| **page != 0).count() + 1);
+        FileStat {
+            i_no: file_node.ino.get_data(),
+            mode: file_node.file_type.to_mode() as u32,
+            nlink: file_node.nlink as u32,
+            uid: 0,
+            gid: 0,
+            size: file_node.size,
+            blocks: (page_cnt * constants::PAGE_SIZE as usize / constants::DISK_SECTOR_SIZE) as u32,
+            flags: FileStat::FLAG_HAS_TIME,
+            ctime: file_node.ctime,
+            mtime: file_node.mtime,
+            atime: file_node.atime,
+        }
+    }
+
+    fn reopen(&mut self, node: &Vnode) {
+        tmp_node(node).open_cnt += 1;
+    }
+
+    #[inline(never)]
+    fn close(&mut self, node: &Vnode) {
+        let file_node = tmp_node(node);
+        file_node.open_cnt -= 1;
+        // 已经被删除了，最后一次关闭的时候释放
+        if file_node.open_cnt == 0 && file_node.nlink == 0 {
+            self.free_node(file_node as *mut TmpNode);
+        }
+    }
+
+    /**
+     * 一个块就是一个内核页（数据页和页表都算）。节点的数量没有限制
+     */
+    #[inline(never)]
+    fn statfs(&mut self) -> FsStat {
+        FsStat {
+            block_size: constants::PAGE_SIZE,
+            total_blocks: constant::TMPFS_MAX_PAGES as u32,
+            free_blocks: (constant::TMPFS_MAX_PAGES - self.used_pages) as u32,
+            avail_blocks: (constant::TMPFS_MAX_PAGES - self.used_pages) as u32,
+            total_inodes: 0,
+            free_inodes: 0,
+        }
+    }
+
+    #[inline(never)]
+    fn is_busy(&mut self) -> bool {
+        let mut cur = self.all_nodes;
+        while !cur.is_null() {
+            let cur_ref = unsafe { &*cur };
+            if cur_ref.open_cnt > 0 {
+                return true;
+            }
+            cur = cur_ref.next_node;
+        }
+        false
+    }
+
+    /**
+     * 释放所有的节点、目录项、数据页，以及文件系统自己
+     */
+    #[inline(never)]
+    fn unmount(&mut self) {
+        while !self.all_nodes.is_null() {
+            let node = unsafe { &mut *self.all_nodes };
+            let mut entry = node.first_entry;
+            while !entry.is_null() {
+                let next = unsafe { &*entry }.next;
+                memory::free_system(entry);
+                entry = next;
+            }
+            self.free_node(node as *mut TmpNode);
+        }
+        memory::free_system(self as *const TmpFs);
+    }
+}
@@ -0,0 +1,368 @@
+use core::ptr;
+
+use os_in_rust_common::domain::InodeNo;
+
+use crate::memory;
+
+use super::{constant, dir_entry::{DirEntry, FileType}, file::FileError, file_util, mount, stat::{FileStat, FsStat}};
+
+/**
+ * 虚拟文件系统（VFS）
+ *  - 每种文件系统都实现Vfs，上层的文件、目录、路径解析、挂载，都只通过Vfs来操作文件系统
+ *  - 文件系统中打开的一个节点（文件、目录、符号链接），使用Vnode表示
+ */
+
+/**
+ * 打开的节点。可以复制，复制出来的还是同一个打开的节点（打开次数不变）
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct Vnode {
+    /**
+     * 节点所在的文件系统
+     */
+    fs: *mut dyn Vfs,
+    /**
+     * 节点在文件系统中的编号
+     */
+    pub ino: InodeNo,
+    /**
+     * 节点的类型
+     */
+    pub file_type: FileType,
+    /**
+     * 文件系统自己使用的数据。例如LeonFS中，是打开的inode的地址
+     */
+    data: usize,
+}
+
+unsafe impl Sync for Vnode {}
+unsafe impl Send for Vnode {}
+
+impl Vnode {
+    pub fn new(fs: *mut dyn Vfs, ino: InodeNo, file_type: FileType, data: usize) -> Self {
+        Self {
+            fs,
+            ino,
+            file_type,
+            data,
+        }
+    }
+
+    pub fn get_fs(&self) -> &'static mut dyn Vfs {
+        unsafe { &mut *self.fs }
+    }
+
+    pub fn get_fs_ptr(&self) -> *mut dyn Vfs {
+        self.fs
+    }
+
+    pub fn get_data(&self) -> usize {
+        self.data
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type == FileType::Directory
+    }
+
+    /**
+     * 是否是所在文件系统的根目录
+     */
+    #[inline(never)]
+    pub fn is_fs_root(&self) -> bool {
+        self.ino == self.get_fs().root_ino()
+    }
+
+    /**
+     * 关闭这个节点
+     */
+    #[inline(never)]
+    pub fn close(&self) {
+        self.get_fs().close(self);
+    }
+}
+
+/**
+ * 文件系统的操作。每种文件系统实现这些操作
+ *  - 返回Vnode的操作，都会打开这个节点，使用完之后需要close
+ *  - 修改元数据的操作（create、write、unlink、rename、link），由文件系统自己保证一致性（例如LeonFS的日志事务）
+ */
+pub trait Vfs {
+    /**
+     * 根目录的编号
+     */
+    fn root_ino(&self) -> InodeNo;
+
+    /**
+     * 根据编号打开一个目录。只用来打开根目录、工作目录、挂载点目录
+     */
+    fn open_dir(&mut self, ino: InodeNo) -> Vnode;
+
+    /**
+     * 打开根目录
+     */
+    fn root(&mut self) -> Vnode {
+        let root_ino = self.root_ino();
+        self.open_dir(root_ino)
+    }
+
+    /**
+     * 在目录dir下，查找名为name的节点并且打开。name可以是..（根目录的..是它自己）
+     */
+    fn lookup(&mut self, dir: &Vnode, name: &str) -> Option<Vnode>;
+
+    /**
+     * 在目录dir下，创建一个名为name、类型为file_type的节点并且打开
+     */
+    fn create(&mut self, dir: &Vnode, name: &str, file_type: FileType) -> Result<Vnode, FileError>;
+
+    /**
+     * 从节点数据偏移量为off（单位字节）的地方开始，读取数据到buff中。返回读取到的字节数
+     */
+    fn read(&mut self, node: &Vnode, off: u32, buff: &mut [u8]) -> usize;
+
+    /**
+     * 把buff写入到节点数据偏移量为off的地方。返回写入的字节数
+     */
+    fn write(&mut self, node: &Vnode, off: u32, buff: &[u8]) -> Result<usize, FileError>;
+
+    /**
+     * 读取目录dir中，偏移量off（包括）之后的第一个目录项。返回(目录项, 下一个目录项的偏移量)
+     *  - 偏移量由文件系统自己定义，从0开始
+     */
+    fn readdir(&mut self, dir: &Vnode, off: u32) -> Option<(DirEntry, u32)>;
+
+    /**
+     * 删除目录dir下名为name的目录项。如果是目录，必须是空的
+     */
+    fn unlink(&mut self, dir: &Vnode, name: &str) -> Result<(), FileError>;
+
+    /**
+     * 读取节点的元信息
+     */
+    fn stat(&mut self, node: &Vnode) -> FileStat;
+
+    /**
+     * 节点再打开一次
+     */
+    fn reopen(&mut self, node: &Vnode);
+
+    /**
+     * 关闭节点
+     */
+    fn close(&mut self, node: &Vnode);
+
+    /**
+     * 把old_dir下的old_name，移动到new_dir下，名称为new_name。两个目录都在这个文件系统中
+     */
+    fn rename(&mut self, _old_dir: &Vnode, _old_name: &str, _new_dir: &Vnode, _new_name: &str) -> Result<(), FileError> {
+        Result::Err(FileError::Unsupported)
+    }
+
+    /**
+     * 在目录dir下，创建一个名为name的硬链接，指向node
+     */
+    fn link(&mut self, _node: &Vnode, _dir: &Vnode, _name: &str) -> Result<(), FileError> {
+        Result::Err(FileError::Unsupported)
+    }
+
+    /**
+     * 把节点的数据写回到硬盘
+     */
+    fn fsync(&mut self, _node: &Vnode) {}
+
+    /**
+     * 把整个文件系统的数据写回到硬盘
+     */
+    fn sync(&mut self) {}
+
+    /**
+     * 文件系统的容量信息。没有容量限制的文件系统（例如procfs），都是0
+     */
+    fn statfs(&mut self) -> FsStat {
+        FsStat::empty()
+    }
+
+    /**
+     * 是否还有打开的节点（根目录除外）。卸载之前检查
+     */
+    fn is_busy(&mut self) -> bool;
+
+    /**
+     * 卸载文件系统：写回数据，释放文件系统占用的内存。之后不能再使用
+     */
+    fn unmount(&mut self);
+}
+
+/**
+ * 两个文件系统是否是同一个
+ */
+pub fn same_fs(fs: *const dyn Vfs, other: *const dyn Vfs) -> bool {
+    ptr::addr_eq(fs, other)
+}
+
+/**
+ * 根据绝对路径，找到并且打开这个节点
+ *  - follow_last: 路径的最后一项是符号链接的时候，是否跟随
+ */
+#[inline(never)]
+pub fn lookup_path(path: &str, follow_last: bool) -> Option<Vnode> {
+    if !path.starts_with("/") {
+        return Option::None;
+    }
+    let root = mount::root_fs().root();
+    // 已经跟随过的符号链接次数
+    let mut symlink_cnt = 0;
+    self::resolve(root, path, follow_last, &mut symlink_cnt)
+}
+
+/**
+ * 把绝对路径分为父目录和最后一项名称，打开父目录
+ */
+#[inline(never)]
+pub fn lookup_parent(path: &str) -> Result<(Vnode, &str), FileError> {
+    let (dir_path, name) = file_util::split_file_path(path).ok_or(FileError::FilePathIllegal)?;
+    let dir = self::lookup_path(dir_path, true).ok_or(FileError::ParentDirNotExists)?;
+    if !dir.is_dir() {
+        dir.close();
+        return Result::Err(FileError::ParentDirNotExists);
+    }
+    Result::Ok((dir, name))
+}
+
+/**
+ * 从目录base开始，逐级解析路径path。base会被关闭，返回打开的最后一项
+ *  - 绝对路径从根文件系统的根目录开始解析，相对路径从base开始解析
+ *  - 遇到符号链接，从符号链接所在的目录开始，解析链接的目标路径，然后继续解析剩下的路径
+ *  - 遇到挂载点，进入挂载的文件系统的根目录；在挂载的文件系统的根目录下访问..，回到挂载点目录的上一级
+ */
+#[inline(never)]
+fn resolve(base: Vnode, path: &str, follow_last: bool, symlink_cnt: &mut u32) -> Option<Vnode> {
+    let mut cur = base;
+    if path.starts_with("/") {
+        cur.close();
+        cur = mount::root_fs().root();
+    }
+
+    // 剩下还没有解析的路径
+    let mut left_path = path;
+    loop {
+        left_path = left_path.trim_start_matches("/");
+        if left_path.is_empty() {
+            break;
+        }
+        // 取出下一个目录项名称
+        let (entry_name, next_path) = match left_path.find("/") {
+            Option::Some(idx) => (&left_path[..idx], &left_path[idx..]),
+            Option::None => (left_path, ""),
+        };
+        left_path = next_path;
+
+        // 只有目录下面，才能继续搜索
+        if !cur.is_dir() {
+            cur.close();
+            return Option::None;
+        }
+        if entry_name == "." {
+            continue;
+        }
+        // 挂载的文件系统的根目录下的..，要先回到挂载点目录（在上一层文件系统中），再找挂载点目录的..
+        if entry_name == ".." && cur.is_fs_root() {
+            let covered = mount::find_covered(cur.get_fs_ptr());
+            if covered.is_some() {
+                let (parent_fs, covered_ino) = covered.unwrap();
+                cur.close();
+                cur = parent_fs.open_dir(covered_ino);
+            }
+        }
+        let fs = cur.get_fs();
+
+        // 根据名称搜索
+        let next = fs.lookup(&cur, entry_name);
+        if next.is_none() {
+            cur.close();
+            return Option::None;
+        }
+        let next = next.unwrap();
+
+        // 是符号链接，并且需要跟随（不是最后一项，或者最后一项也要跟随）
+        let is_last = left_path.trim_start_matches("/").is_empty();
+        if next.file_type == FileType::Symlink && (!is_last || follow_last) {
+            *symlink_cnt += 1;
+            // 跟随的次数太多了，说明符号链接有环
+            if *symlink_cnt > constant::MAX_SYMLINK_FOLLOW {
+                next.close();
+                cur.close();
+                return Option::None;
+            }
+            // 读取链接的目标路径，从链接所在的目录开始解析
+            let target_buf: &mut [u8; constant::MAX_FILE_PATH_LEN] = memory::malloc(constant::MAX_FILE_PATH_LEN);
+            let target_len = fs.read(&next, 0, target_buf);
+            next.close();
+            let target_path = core::str::from_utf8(&target_buf[..target_len]);
+            let resolved = if target_len > 0 && target_path.is_ok() {
+                self::resolve(cur, target_path.unwrap(), true, symlink_cnt)
+            } else {
+                cur.close();
+                Option::None
+            };
+            memory::sys_free(target_buf.as_ptr() as usize);
+
+            cur = resolved?;
+            continue;
+        }
+
+        cur.close();
+        // 挂载点会进入挂载的文件系统的根目录
+        cur = self::enter_mount(next);
+    }
+    Option::Some(cur)
+}
+
+/**
+ * 如果目录node上挂载了文件系统，关闭node，打开挂载的文件系统的根目录
+ */
+#[inline(never)]
+pub fn enter_mount(node: Vnode) -> Vnode {
+    let mounted_fs = mount::find_mounted(node.get_fs_ptr(), node.ino);
+    if mounted_fs.is_none() {
+        return node;
+    }
+    node.close();
+    mounted_fs.unwrap().root()
+}
+
+/**
+ * 在目录dir中，找到编号为ino的目录项（.和..除外）
+ */
+#[inline(never)]
+pub fn find_entry(dir: &Vnode, ino: InodeNo) -> Option<DirEntry> {
+    let fs = dir.get_fs();
+    let mut off = 0;
+    loop {
+        let (entry, next_off) = fs.readdir(dir, off)?;
+        if entry.i_no == ino && entry.get_name() != "." && entry.get_name() != ".." {
+            return Option::Some(entry);
+        }
+        off = next_off;
+    }
+}
+
+/**
+ * 目录dir是否是空的（只有.和..）
+ */
+#[inline(never)]
+pub fn is_empty_dir(dir: &Vnode) -> bool {
+    let fs = dir.get_fs();
+    let mut off = 0;
+    loop {
+        let next = fs.readdir(dir, off);
+        if next.is_none() {
+            return true;
+        }
+        let (entry, next_off) = next.unwrap();
+        if entry.get_name() != "." && entry.get_name() != ".." {
+            return false;
+        }
+        off = next_off;
+    }
+}
@@ -2,11 +2,11 @@ use core::{mem, ptr};
 use core::mem::size_of;
 
 use os_in_rust_common::{cstr_write, printk, MY_PANIC};
-use os_in_rust_common::{constants, linked_list::LinkedNode, paging::PageTable, printkln, ASSERT};
+use os_in_rust_common::{linked_list::LinkedNode, paging::PageTable, printkln, ASSERT};
 
 use crate::filesystem::FileDescriptorType;
 use crate::process;
-use crate::{filesystem::{self}, memory::{self, MemBlockAllocator}, pid_allocator::{self, Pid}, thread::{self, PcbPage, TaskStatus, TaskStruct}, thread_management};
+use crate::{filesystem::{self}, memory::{self, page_util, MemBlockAllocator}, pid_allocator::{self, Pid}, thread::{self, PcbPage, TaskStatus, TaskStruct}, thread_management};
 
 
 #[inline(never)]
@@ -34,10 +34,10 @@ pub fn fork() -> Pid {
     self::vaddr_pool_copy(&cur_pcb.task_struct, &mut sub_pcb.task_struct);
     thread::check_task_stack("failed to fork, copy vaddr pool error");
     
-    // 拷贝 堆内存（该任务的页表映射了的所有内存）
+    // 共享 堆内存（该任务的页表映射了的所有内存）。写时复制，谁先写入谁再复制
     let to_task_dir_table = unsafe { &mut *(sub_pcb.task_struct.pgdir) };
-    self::heap_memory_copy(to_task_dir_table);
-    thread::check_task_stack("failed to fork, copy heap memory error");
+    self::heap_memory_share(to_task_dir_table);
+    thread::check_task_stack("failed to fork, share heap memory error");
     
     printkln!();
 
@@ -104,16 +104,17 @@ fn vaddr_pool_copy(from_task: &TaskStruct, to_task: &mut TaskStruct) {
 }
 
 /**
- * 堆内存拷贝
+ * 堆内存共享（写时复制）
+ * 父子任务的页表都指向同一个物理页，并且都设置为只读。当任何一方写入的时候，在缺页异常中再复制
  */
 #[inline(never)]
-fn heap_memory_copy(to_task_dir_table: &mut PageTable) {
+fn heap_memory_share(to_task_dir_table: &mut PageTable) {
     let from_task = &thread::current_thread().task_struct;
 
-    // 要拷贝的任务的虚拟地址池
+    // 要共享的任务的虚拟地址池
     let from_task_addr_pool = &from_task.vaddr_pool;
 
-    // 作为复制页表的入参，默认是None
+    // 作为共享页表的入参，默认是None
     let mut page_table_req = Option::None;
     // 遍历 虚拟地址池，里面所有被set过的地址
     for (vaddr, set) in from_task_addr_pool.iter_valid() {
@@ -121,11 +122,12 @@ fn heap_memory_copy(to_task_dir_table: &mut PageTable) {
         if !set {
             continue;
         }
-        // 这个地址指向页的数据
-        let page_data = unsafe { core::slice::from_raw_parts(vaddr as *const u8, constants::PAGE_SIZE as usize) };
-        
-        // 把这个页的数据，拷贝到另一个任务的页目录表中。得到操作的页表，用于下次循环
-        let page_table  = memory::copy_single_user_page(page_data, to_task_dir_table, page_table_req);
+        // 这个地址还没有映射物理页，那么也不需要
+        if !page_util::is_mapped(vaddr) {
+            continue;
+        }
+        // 把这个页共享到另一个任务的页目录表中。得到操作的页表，用于下次循环
+        let page_table = memory::share_single_user_page(vaddr, to_task_dir_table, page_table_req);
 
         // 把得到的页表，作为下次循环的参数
         page_table_req = Option::Some(unsafe { &mut *(page_table as *mut PageTable) });
@@ -1,4 +1,4 @@
-use os_in_rust_common::{bios_mem::{ARDSType, AddressRangeDescriptorStructure}, context::BootContext, instruction, printkln, ASSERT};
+use os_in_rust_common::{bios_mem::{ARDSType, AddressRangeDescriptorStructure}, context::BootContext, instruction, printkln, reg_cr0::{self, CR0}, ASSERT};
 
 use crate::{device, filesystem, interrupt, memory, process, sys_call, thread, thread_management, tss};
 
@@ -31,6 +31,9 @@ pub fn init_all(boot_info: &BootContext) {
     .unwrap();
     
     memory::mem_pool_init(os_memory_size);
+
+    // 开启写保护。内核写入只读的用户页（写时复制的页）也要触发缺页异常
+    reg_cr0::set_on(CR0::WP);
     
     printkln!("LEONOS: init process");
     // init进程初始化
@@ -62,13 +65,29 @@ pub fn init_all(boot_info: &BootContext) {
     filesystem::install_filesystem_for_all_part();
     thread::check_task_stack("overflow after fs init");
     
-    // 初始化文件系统
-    filesystem::mount_part("sdb4");
+    // 挂载根文件系统
+    filesystem::mount_root("sdb4");
     thread::check_task_stack("overflow after fs mounted");
     
     // 初始化根目录
     filesystem::init_root_dir();
     thread::check_task_stack("overflow after root dir init");
+
+    // 其他分区的文件系统，挂载到/mnt下
+    filesystem::mount_all_part("/mnt");
+    thread::check_task_stack("overflow after all fs mounted");
+
+    // 内核状态，挂载到/proc下
+    filesystem::mount_procfs("/proc");
+    thread::check_task_stack("overflow after procfs mounted");
+
+    // 设备，挂载到/dev下
+    filesystem::mount_devfs("/dev");
+    thread::check_task_stack("overflow after devfs mounted");
+
+    // 临时文件，放在内存中，挂载到/tmp下
+    filesystem::mount_tmpfs("/tmp");
+    thread::check_task_stack("overflow after tmpfs mounted");
     
     // 系统启动成功
 }
\ No newline at end of file
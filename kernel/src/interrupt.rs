@@ -1,9 +1,9 @@
 
 use core::{arch::asm, ptr::{self, addr_of}};
 
-use os_in_rust_common::{idt::{self, InterruptStackFrame, InterruptTypeEnum}, pic, pit, port::Port, sd::SegmentDPL, ASSERT, MY_PANIC};
+use os_in_rust_common::{idt::{self, InterruptStackFrame, InterruptTypeEnum}, pic, pit, port::Port, printkln, reg_cr2, sd::SegmentDPL, ASSERT, MY_PANIC};
 
-use crate::{device::{self, ChannelIrqNoEnum, StatusRegister}, keyboard::{self, ScanCodeCombinator}, pid_allocator::Pid, scheduler, sys_call::{self, HandlerType}, thread};
+use crate::{device::{self, ChannelIrqNoEnum, StatusRegister}, keyboard::{self, ScanCodeCombinator}, memory, pid_allocator::Pid, scheduler, sys_call::{self, HandlerType}, thread, time, userprog::{self, ExceptionExitStatus}};
 
 /**
  * exceptions and codes: <https://wiki.osdev.org/Exceptions>
@@ -47,6 +47,11 @@ pub fn init() {
  */
 #[cfg(all(not(test), target_arch = "x86"))]
 extern "x86-interrupt" fn invalid_opcode_handler(frame: InterruptStackFrame) {
+    // 用户进程引发的异常，终止该用户进程
+    if self::from_user_mode(&frame) {
+        self::kill_user_process(ExceptionExitStatus::InvalidOpcode, &frame);
+        return;
+    }
     MY_PANIC!("invalid opcode eip: 0x{:x}, cs:0x{:x}, eflags:0x{:x}, sp: 0x{:x}, ss:{:x}", frame.ip as u32, frame.cs as u32, frame.eflags as u32, frame.sp as u32, frame.ss as u32);
 }
 
@@ -62,6 +67,11 @@ fn invalid_opcode_handler(frame: InterruptStackFrame) {
  */
 #[cfg(all(not(test), target_arch = "x86"))]
 extern "x86-interrupt" fn general_protection_handler(frame: InterruptStackFrame, error_code: u32) {
+    // 用户进程引发的异常，终止该用户进程
+    if self::from_user_mode(&frame) {
+        self::kill_user_process(ExceptionExitStatus::GeneralProtection, &frame);
+        return;
+    }
     MY_PANIC!("!!!!general protection exception occur, error code:0x{:x}!!!eip: 0x{:x}, cs:0x{:x}, eflags:0x{:x}, sp: 0x{:x}, ss:{:x}", error_code, frame.ip as u32, frame.cs as u32, frame.eflags as u32, frame.sp as u32, frame.ss as u32);
 }
 
@@ -83,9 +93,31 @@ fn double_fault_handler(frame: InterruptStackFrame, error_code: u32) {
     todo!()
 }
 
+/**
+ * 缺页异常
+ * error code的结构：<https://wiki.osdev.org/Exceptions#Page_Fault>
+ *   - 第0位：1表示页存在（保护异常），0表示页不存在
+ *   - 第1位：1表示写入引发的异常，0表示读取
+ */
 #[cfg(all(not(test), target_arch = "x86"))]
 extern "x86-interrupt" fn page_fault_handler(frame: InterruptStackFrame, error_code: u32) {
-    MY_PANIC!("page fault, code:0x{:x}. eip: 0x{:x}, cs:0x{:x}, eflags:0x{:x}, sp: 0x{:x}, ss:{:x}", error_code, frame.ip as u32, frame.cs as u32, frame.eflags as u32, frame.sp as u32, frame.ss as u32);
+    // 引发缺页异常的虚拟地址
+    let fault_addr = reg_cr2::get_fault_addr();
+    // 写入了一个存在的只读页，看看是不是写时复制的页
+    if error_code & 0b11 == 0b11 && memory::copy_on_write(fault_addr as usize) {
+        return;
+    }
+    // 访问了一个不存在的页，看看是不是可以按需分配
+    if error_code & 0b1 == 0 && memory::demand_page(fault_addr as usize) {
+        return;
+    }
+    // 用户进程访问了非法的地址，终止该用户进程
+    if self::from_user_mode(&frame) {
+        printkln!("segmentation fault, addr:0x{:x}", fault_addr);
+        self::kill_user_process(ExceptionExitStatus::PageFault, &frame);
+        return;
+    }
+    MY_PANIC!("page fault, code:0x{:x}, addr:0x{:x}. eip: 0x{:x}, cs:0x{:x}, eflags:0x{:x}, sp: 0x{:x}, ss:{:x}", error_code, fault_addr, frame.ip as u32, frame.cs as u32, frame.eflags as u32, frame.sp as u32, frame.ss as u32);
 }
 #[cfg(all(not(target_arch = "x86")))]
 fn page_fault_handler(frame: InterruptStackFrame, error_code: u32) {
@@ -93,6 +125,24 @@ fn page_fault_handler(frame: InterruptStackFrame, error_code: u32) {
 }
 
 
+/**
+ * 异常是否是在用户态（特权级3）引发的。看被打断的代码段选择子的RPL
+ */
+#[inline(never)]
+fn from_user_mode(frame: &InterruptStackFrame) -> bool {
+    frame.cs as u8 & 0b11 == SegmentDPL::LEVEL3 as u8
+}
+
+/**
+ * 用户进程引发了异常，终止当前用户进程
+ */
+#[inline(never)]
+fn kill_user_process(exception: ExceptionExitStatus, frame: &InterruptStackFrame) {
+    let cur_task = &thread::current_thread().task_struct;
+    printkln!("process {}({}) killed by {}. eip: 0x{:x}", cur_task.pid.get_data(), cur_task.get_name(), exception.get_name(), frame.ip as u32);
+    userprog::exit_by_exception(exception);
+}
+
 fn alert(error_msg: &str) {
     let vga_buffer = 0xC00b8000 as *mut u8;
     for (i, &e) in error_msg.as_bytes().iter().enumerate() {
@@ -129,6 +179,9 @@ pub extern "x86-interrupt" fn timer_handler(frame: InterruptStackFrame) {
 
     pic::send_end_of_interrupt();
 
+    // 更新系统时间
+    time::update_system_time();
+
     // 检查任务的调度。时间片耗尽则调度
     scheduler::check_task_schedule();
 
@@ -162,6 +162,30 @@ pub fn get_keycode_queue() -> &'static mut ArrayBlockingQueue<'static, AsciiKey>
     unsafe { KEYCODE_BLOCKING_QUEUE.get_mut() }
 }
 
+/**
+ * 从键码队列中逐个取出输入的键，直到填满key_buff。队列空的时候会阻塞。返回读取到的键的数量
+ */
+#[inline(never)]
+pub fn read_keys(key_buff: &mut [AsciiKey]) -> usize {
+    let keyboard_queue = self::get_keycode_queue();
+    let mut idx = 0;
+    while idx < key_buff.len() {
+        let ascii_key = keyboard_queue.take();
+        // 如果队列里空了
+        if ascii_key.is_none() {
+            break;
+        }
+        let ascii_key = ascii_key.unwrap();
+        // 无效的键，忽略
+        if ascii_key == AsciiKey::NUL {
+            continue;
+        }
+        key_buff[idx] = ascii_key;
+        idx += 1;
+    }
+    idx
+}
+
 /**
  * 扫描码处理
  */
@@ -30,7 +30,7 @@ pub mod filesystem;
 pub mod fork;
 pub mod shell;
 pub mod exec;
-pub mod program_loader;
+pub mod elf;
 mod common;
 pub mod userprog;
 pub mod pipe;
@@ -8,9 +8,7 @@
 
 
 use core::panic::PanicInfo;
-use kernel::{init, program_loader, thread_management, version};
-use os_in_rust_common::domain::LbaAddr;
-use os_in_rust_common::constants;
+use kernel::{init, thread_management, version};
 use os_in_rust_common::{context::BootContext, printkln};
 
 
@@ -41,13 +39,6 @@ pub extern "C" fn _start(boot_info: &BootContext) {
     // 初始化一切
     init::init_all(boot_info);
 
-
-    // 读取并且写入用户进程
-    // program_loader::sync_program(LbaAddr::new(250), 100 * constants::DISK_SECTOR_SIZE, "/cat");
-    // program_loader::sync_program(LbaAddr::new(350), 50 * constants::DISK_SECTOR_SIZE, "/grep");
-    // program_loader::sync_program(LbaAddr::new(400), 10 * constants::DISK_SECTOR_SIZE, "/echo");
-    // program_loader::sync_program(LbaAddr::new(410), 1443, "/main.rs");
-
     loop {
         thread_management::thread_yield();
     }
@@ -5,7 +5,7 @@ use os_in_rust_common::{constants, pool::MemPool, utils, ASSERT, MY_PANIC};
 
 use crate::memory::page_util;
 
-use super::mem_block::{Arena, MemBlockAllocator};
+use super::{mem_block::{Arena, MemBlockAllocator}, memory_poll};
 
 /**
  * ************************************************************
@@ -22,8 +22,16 @@ pub fn malloc_bytes(vaddr_pool: &mut MemPool, phy_mem_pool: &mut MemPool, alloca
     if bytes > constants::MINIMAL_BLOCK_SIZE * 2usize.pow(constants::MEM_BLOCK_CONTAINER_CNT as u32 - 1) {
         // 计算需要申请多少个页
         let pages = utils::div_ceil((size_of::<Arena>() + bytes) as u32, constants::PAGE_SIZE) as usize;
-        // 开始申请页
-        let page_addr = malloc_page(vaddr_pool, phy_mem_pool, pages as usize);
+        // 开始申请页。用户进程只预留虚拟地址，用到的时候再在缺页异常中分配物理页
+        let page_addr = if ptr::eq(phy_mem_pool, memory_poll::get_user_mem_pool()) {
+            let page_addr = reserve_page(vaddr_pool, pages);
+            // 第一页马上要写入arena的头部，现在就分配物理页。调用方已经持有用户内存池的锁，不能等到缺页异常中再分配
+            malloc_phy_by_vaddr(page_addr, phy_mem_pool);
+            unsafe { (page_addr as *mut u8).write_bytes(0, constants::PAGE_SIZE as usize) };
+            page_addr
+        } else {
+            malloc_page(vaddr_pool, phy_mem_pool, pages)
+        };
         // 申请到的页，转成一个Arena
         let arena = unsafe { &mut *(page_addr as *mut Arena) };
         // 初始化arena
@@ -107,6 +115,19 @@ pub fn malloc_page(addr_pool: &mut MemPool, mem_pool: &mut MemPool, page_cnt: us
 }
 
 
+/**
+ * 从addr_pool地址池中预留连续的page_cnt页虚拟地址，不分配物理页（访问的时候，缺页异常中再按需分配）。返回虚拟起始地址
+ */
+#[inline(never)]
+pub fn reserve_page(addr_pool: &mut MemPool, page_cnt: usize) -> usize {
+    let addr_apply_res = addr_pool.apply(page_cnt);
+    if addr_apply_res.is_err() {
+        MY_PANIC!("failed to reserve virtual address.  res: {:?}", addr_apply_res);
+    }
+    addr_apply_res.unwrap()
+}
+
+
 /**
  * 已知虚拟地址virtual_addr，然后前往mem_pool物理空间池申请1页空间，并且返回物理空间池的物理地址
  */
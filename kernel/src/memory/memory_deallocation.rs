@@ -6,7 +6,7 @@ use os_in_rust_common::{constants, pool::MemPool, ASSERT, MY_PANIC};
 
 use crate::memory::page_util;
 
-use super::mem_block::MemBlock;
+use super::{mem_block::MemBlock, memory_poll};
 
 
 
@@ -84,19 +84,18 @@ pub fn free_page(addr_pool: &mut MemPool, mem_pool: &mut MemPool, vaddr_start: u
     ASSERT!(addr_pool.in_pool(vaddr_start + constants::PAGE_SIZE as usize * page_cnt));
     ASSERT!(page_cnt >= 1 && vaddr_start % constants::PAGE_SIZE as usize == 0);
 
-    // 确保释放的物理地址，也在物理地址池中
-    let phy_addr_start = page_util::get_phy_from_virtual_addr(vaddr_start);
-    if !mem_pool.in_pool(phy_addr_start) {
-        MY_PANIC!("phy addr(0x{:x}) not in mem pool", phy_addr_start);
-    }
-    ASSERT!(phy_addr_start % constants::PAGE_SIZE as usize == 0);
-
     // 遍历每一页
     for page_idx in 0 .. page_cnt {
         // 虚拟地址
         let vaddr = vaddr_start + page_idx * constants::PAGE_SIZE as usize;
         ASSERT!(addr_pool.in_pool(vaddr));
 
+        // 只预留了虚拟地址，还没有分配物理页（按需分配），那么只需要放回虚拟地址
+        if !page_util::is_mapped(vaddr) {
+            addr_pool.restore(vaddr);
+            continue;
+        }
+
         // 物理地址
         let phy_addr = page_util::get_phy_from_virtual_addr(vaddr);
         if !mem_pool.in_pool(phy_addr) {
@@ -105,7 +104,12 @@ pub fn free_page(addr_pool: &mut MemPool, mem_pool: &mut MemPool, vaddr_start: u
 
         // 把物理地址放回池子中
         if phy_free {
-            mem_pool.restore(phy_addr);
+            // 用户物理页可能被多个任务共享（写时复制），需要看引用数量
+            if ptr::eq(mem_pool, memory_poll::get_user_mem_pool()) {
+                memory_poll::release_user_frame(phy_addr);
+            } else {
+                mem_pool.restore(phy_addr);
+            }
         }
 
         // 取消该虚拟地址页表项的p位
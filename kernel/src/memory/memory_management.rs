@@ -1,11 +1,11 @@
 
 use core::ptr;
 
-use os_in_rust_common::{constants, paging::{PageTable, PageTableEntry}, pool::MemPool, racy_cell::RacyCell, ASSERT, MY_PANIC};
+use os_in_rust_common::{constants, instruction, paging::{PageTable, PageTableEntry}, pool::MemPool, printkln, racy_cell::RacyCell, ASSERT, MY_PANIC};
 
 use crate::{memory::page_util, sync::Lock, thread::{self, TaskStruct}};
 
-use super::{mem_block, memory_allocation, memory_deallocation, memory_poll};
+use super::{mem_block, memory_allocation, memory_deallocation, memory_poll, vm_region::VmRegionType};
 
 
 
@@ -156,11 +156,11 @@ pub fn free_kernel_page(vaddr: usize, page_cnt: usize, phy_free: bool) {
 }
 
 /**
- * 已知栈顶，分配一个物理页
+ * 已知虚拟地址，分配一个物理页（该虚拟地址可以是已经预留了的，但是不能已经映射了物理页）
  */
 #[inline(never)]
 pub fn malloc_user_page_by_vaddr(vaddr_pool: &mut MemPool, vaddr: usize) {
-    if vaddr_pool.is_set(vaddr) {
+    if vaddr_pool.is_set(vaddr) && page_util::is_mapped(vaddr) {
         MY_PANIC!("addr: 0x{:x} set", vaddr);
     }
     let vaddr_pool_set = vaddr_pool.addr_set(vaddr);
@@ -174,6 +174,36 @@ pub fn malloc_user_page_by_vaddr(vaddr_pool: &mut MemPool, vaddr: usize) {
 }
 
 
+/**
+ * 按需分配物理页。当前任务访问了一个没有映射物理页的地址（缺页异常：页不存在）
+ *   - 找到该地址所在的虚拟内存区域，找不到说明是非法地址
+ *   - 堆区域，只有已经在虚拟地址池中预留了的地址才是合法的
+ *  @param fault_vaddr: 引发异常的虚拟地址
+ *  @return 是否处理成功
+ */
+#[inline(never)]
+pub fn demand_page(fault_vaddr: usize) -> bool {
+    let task = &mut thread::current_thread().task_struct;
+    // 内核线程，没有用户空间
+    if task.pgdir.is_null() {
+        return false;
+    }
+    let vaddr = fault_vaddr & 0xfffff000;
+    let region = task.vm_regions.find(vaddr);
+    if region.is_none() {
+        return false;
+    }
+    if region.unwrap().region_type == VmRegionType::Heap && !task.vaddr_pool.is_set(vaddr) {
+        return false;
+    }
+    // 分配一个物理页
+    self::malloc_user_page_by_vaddr(&mut task.vaddr_pool, vaddr);
+
+    // 清空申请到的内存空间
+    unsafe { (vaddr as *mut u8).write_bytes(0, constants::PAGE_SIZE as usize) };
+    true
+}
+
 /**
  * 释放某个虚拟地址池的所有内存
  *    - 根据虚拟地址池的开始地址和结束地址
@@ -206,8 +236,8 @@ pub fn free_by_addr_pool(vaddr_pool: &MemPool) {
             }
             // 得到这个页表项中记录的物理地址
             let phy_addr = pte.get_phy_addr();
-            // 用户内存池释放
-            memory_poll::get_user_mem_pool().restore(phy_addr.try_into().unwrap());
+            // 用户内存池释放（如果该物理页还被其他任务共享，那么只是减少引用）
+            memory_poll::release_user_frame(phy_addr.try_into().unwrap());
             pte.set_present(false);
         }
 
@@ -218,37 +248,36 @@ pub fn free_by_addr_pool(vaddr_pool: &MemPool) {
 }
 
 /**
- * 已知空间page_data，把这块数据复制一份，并且让to_dir_table页目录表可以访问到
- *  @param page_data: 需要复制的数据（当前程序可以访问，经过了当前任务页表的映射）
- *  @param to_dir_table: 数据要拷贝到的页目录表（此时该地址，是当前任务可以访问的虚拟地址），这是其他任务的页目录表
- *  @param page_table: 该数据拷贝到页目录表后，所在的页表（因为可能页表不存在的话就需要申请空间，因此可以为空）（当前任务可访问的虚拟地址）
- *
+ * 已知当前任务的虚拟地址vaddr，把该地址指向的物理页共享给to_dir_table页目录表（写时复制）
+ *   - 可写的页：两个任务的页表项都设置为只读，并且标记为写时复制。谁先写入，谁就在缺页异常中复制一份
+ *   - 本来就只读的页（例如ELF的代码段、只读数据段）：保持只读，不标记写时复制。写入的时候终止进程
+ *  @param vaddr: 要共享的页的虚拟地址（当前任务已经映射了该地址）
+ *  @param to_dir_table: 要共享给的页目录表（此时该地址，是当前任务可以访问的虚拟地址），这是其他任务的页目录表
+ *  @param to_page_table: 该页共享到页目录表后，所在的页表（因为可能页表不存在的话就需要申请空间，因此可以为空）（当前任务可访问的虚拟地址）
  */
 #[inline(never)]
-pub fn copy_single_user_page<'a>(page_data: &[u8], to_dir_table: &mut PageTable, to_page_table: Option<&'a mut PageTable>) -> &'a mut PageTable {
-    
-    // // 为什么这样就可以？
-    // let page_table = if to_page_table.is_some() {
-    //     to_page_table.unwrap()
-    // } else {
-    //     unsafe { &mut *(self::malloc_kernel_page(1) as *mut PageTable) }
-    // };
-    // return page_table;
-
-    ASSERT!(page_data.len() == constants::PAGE_SIZE as usize);
-    let cur_task = &mut thread::current_thread().task_struct;
-
-    /*** 1. 把这一页的数据，先复制一份出来  */
-    // 申请一块用户空间，用于存放我们要复制的数据
-    let new_page_data_addr = self::malloc_user_page(cur_task, 1);
-    let new_page_data = unsafe { core::slice::from_raw_parts_mut(new_page_data_addr as *mut u8, constants::PAGE_SIZE as usize) };
-    // 那一页的数据，复制到新的空间中
-    new_page_data.copy_from_slice(page_data);
+pub fn share_single_user_page<'a>(vaddr: usize, to_dir_table: &mut PageTable, to_page_table: Option<&'a mut PageTable>) -> &'a mut PageTable {
+    ASSERT!(vaddr % constants::PAGE_SIZE as usize == 0);
+
+    /*** 1. 当前任务的页表项，如果是可写的，改成只读，并且标记为写时复制  */
+    let pte = page_util::addr_to_pte(vaddr);
+    ASSERT!(pte.present());
+    // 已经是写时复制的页（之前fork过），或者本来就只读的页，页表项保持不变
+    if pte.writable() {
+        pte.set_writable(false);
+        pte.set_cow(true);
+        // 刷新TLB缓存，否则当前任务还是可以写入
+        instruction::invalidate_page(vaddr);
+    }
 
+    // 这个物理页，多了一个引用
+    unsafe { USER_MEM_POOL_LOCK.get_mut().lock() };
+    memory_poll::user_frame_ref_inc(pte.get_phy_addr() as usize);
+    unsafe { USER_MEM_POOL_LOCK.get_mut().unlock() };
 
-    // /**** 2. 填充页表，页表指向数据页的物理地址  */
-    let pde_idx = page_util::locate_pde(page_data.as_ptr() as usize);
-    let pte_idx = page_util::locate_pte(page_data.as_ptr() as usize);
+    /**** 2. 填充页表，页表项指向同一个物理页  */
+    let pde_idx = page_util::locate_pde(vaddr);
+    let pte_idx = page_util::locate_pte(vaddr);
 
     // 页目录项，指向的是页表。看看页目录项有没有值
     // 如果页目录项有值，那么我们是不知道页表的虚拟地址的（只有知道虚拟地址才可以操作），因此需要使用入参的页表地址
@@ -264,9 +293,8 @@ pub fn copy_single_user_page<'a>(page_data: &[u8], to_dir_table: &mut PageTable,
         unsafe { &mut *(self::malloc_kernel_page(1) as *mut PageTable) }
     };
 
-    // 填充页表。页表项指向物理页的物理地址
-    page_table.set_entry(pte_idx, PageTableEntry::new_default(page_util::get_phy_from_virtual_addr(new_page_data_addr)));
-
+    // 填充页表。跟当前任务的页表项一模一样（只读，可写的页还会标记写时复制）
+    page_table.set_entry(pte_idx, *pte);
 
     /**** 3. 填充页目录表，页目录表该项指向页表的物理地址 *****/
     if !pde.present() {
@@ -274,8 +302,65 @@ pub fn copy_single_user_page<'a>(page_data: &[u8], to_dir_table: &mut PageTable,
         to_dir_table.set_entry(pde_idx, PageTableEntry::new_default(page_util::get_phy_from_virtual_addr(page_table as *const _ as usize)));
     }
 
-    /**** 4. 释放申请的内存（不要释放物理地址，只是释放该内存空间跟当前任务的链接关系） */
-    self::free_user_page(cur_task, new_page_data_addr, 1, false);
-
     page_table
 }
+
+/**
+ * 处理写时复制的页。当前任务写入了一个只读的页，如果这个页是写时复制的页，那么：
+ *   - 如果这个物理页还被其他任务共享，那么复制一份新的物理页给当前任务
+ *   - 如果这个物理页只剩下当前任务在用，那么直接改成可写
+ *  @param fault_vaddr: 引发异常的虚拟地址
+ *  @return 是否处理成功。如果这个地址不是写时复制的页，或者没有空闲的物理页可以复制了，那么返回false（由缺页异常终止进程）
+ */
+#[inline(never)]
+pub fn copy_on_write(fault_vaddr: usize) -> bool {
+    let vaddr = fault_vaddr & 0xfffff000;
+    // 页目录项不存在，那么不可能是写时复制的页
+    if !page_util::addr_to_pde(vaddr).present() {
+        return false;
+    }
+    let pte = page_util::addr_to_pte(vaddr);
+    if !pte.present() || !pte.is_cow() {
+        return false;
+    }
+    let phy_addr = pte.get_phy_addr() as usize;
+
+    unsafe { USER_MEM_POOL_LOCK.get_mut().lock() };
+    // 只剩下当前任务在使用这个物理页，那么直接改成可写
+    if memory_poll::user_frame_ref_cnt(phy_addr) <= 1 {
+        pte.set_writable(true);
+        pte.set_cow(false);
+        instruction::invalidate_page(vaddr);
+        unsafe { USER_MEM_POOL_LOCK.get_mut().unlock() };
+        return true;
+    }
+    unsafe { USER_MEM_POOL_LOCK.get_mut().unlock() };
+
+    // 先把这一页的数据，暂存到内核空间中
+    let buf_addr = self::malloc_kernel_page(1);
+    let buf = unsafe { core::slice::from_raw_parts_mut(buf_addr as *mut u8, constants::PAGE_SIZE as usize) };
+    buf.copy_from_slice(unsafe { core::slice::from_raw_parts(vaddr as *const u8, constants::PAGE_SIZE as usize) });
+
+    unsafe { USER_MEM_POOL_LOCK.get_mut().lock() };
+    // 申请一个新的物理页
+    let apply_res = memory_poll::get_user_mem_pool().apply_one();
+    // 用户内存用完了，复制不了。页表项保持不变
+    if apply_res.is_err() {
+        unsafe { USER_MEM_POOL_LOCK.get_mut().unlock() };
+        printkln!("failed to apply user memory for copy on write, error:{:?}", apply_res.as_ref().err());
+        self::free_kernel_page(buf_addr, 1, true);
+        return false;
+    }
+    // 原本的物理页，少了一个引用
+    memory_poll::user_frame_ref_dec(phy_addr);
+    unsafe { USER_MEM_POOL_LOCK.get_mut().unlock() };
+
+    // 页表项指向新的物理页（可写）
+    *pte = PageTableEntry::new_default(apply_res.unwrap());
+    instruction::invalidate_page(vaddr);
+
+    // 把暂存的数据，复制到新的物理页中
+    unsafe { core::slice::from_raw_parts_mut(vaddr as *mut u8, constants::PAGE_SIZE as usize) }.copy_from_slice(buf);
+    self::free_kernel_page(buf_addr, 1, true);
+    true
+}
@@ -1,6 +1,8 @@
 use core::{mem::{self}, slice};
 
-use os_in_rust_common::{constants, paging::PageTable, pool::MemPool, printkln, racy_cell::RacyCell};
+use os_in_rust_common::{constants, paging::PageTable, pool::MemPool, printkln, racy_cell::RacyCell, utils, ASSERT};
+
+use super::memory_allocation;
 
 
 /**
@@ -26,6 +28,13 @@ static USER_MEM_POOL: RacyCell<MemPool> = RacyCell::new(MemPool::empty());
 static KERNEL_ADDR_POOL: RacyCell<MemPool> = RacyCell::new(MemPool::empty());
 
 
+/**
+ * 用户物理页的引用计数（跟用户物理内存池一一对应，下标就是物理页在用户物理内存池位图中的位下标）
+ * 为了不改动申请物理页的逻辑，0表示该物理页只有1个引用（没有被共享）；被共享之后，记录的就是真实的引用数
+ */
+static USER_FRAME_REF_CNT: RacyCell<&'static mut [u16]> = RacyCell::new(&mut []);
+
+
 /**
  * 获取内核物理内存池
  */
@@ -113,6 +122,9 @@ pub fn mem_pool_init(all_mem: u32) {
         bit_map_addr
     );
 
+    /* 4. 用户物理页的引用计数。放在内核的内存空间中 */
+    self::user_frame_ref_init(pages_for_pool as usize);
+
     // printkln!("kernel_mem_pool  addr_start: 0x{:x}", kernel_mem_pool.addr_start as u32);
     // printkln!("kernel_mem_pool  bitmap addr: 0x{:x}", kernel_mem_pool.bitmap.map_ptr as u32);
     // printkln!("kernel_mem_pool  bitmap len: 0x{:x}", kernel_mem_pool.bitmap.size as u32);
@@ -148,4 +160,76 @@ fn compose_pool(addr_start: usize, mem_page_num: u32, bitmap_base_addr: usize) -
     };
     mem_pool.init(addr_start, constants::PAGE_SIZE as usize, bitmap);
     mem_pool
-}
\ No newline at end of file
+}
+
+/**
+ * 初始化用户物理页的引用计数
+ * frame_cnt: 用户物理内存池的页数量
+ */
+#[inline(never)]
+fn user_frame_ref_init(frame_cnt: usize) {
+    // 需要的内核页数量
+    let pages = utils::div_ceil((frame_cnt * mem::size_of::<u16>()) as u32, constants::PAGE_SIZE) as usize;
+    let addr = memory_allocation::malloc_page(self::get_kernel_addr_pool(), self::get_kernel_mem_pool(), pages);
+    let ref_cnt = unsafe { slice::from_raw_parts_mut(addr as *mut u16, frame_cnt) };
+    // 清零。所有物理页都只有1个引用
+    ref_cnt.fill(0);
+    *unsafe { USER_FRAME_REF_CNT.get_mut() } = ref_cnt;
+}
+
+/**
+ * 用户物理页的物理地址，找到在引用计数数组中的下标
+ */
+#[inline(never)]
+fn user_frame_idx(phy_addr: usize) -> usize {
+    let user_mem_pool = self::get_user_mem_pool();
+    ASSERT!(user_mem_pool.in_pool(phy_addr));
+    (phy_addr - user_mem_pool.addr_start) / user_mem_pool.granularity
+}
+
+/**
+ * 查询某个用户物理页的引用数量
+ */
+#[inline(never)]
+pub fn user_frame_ref_cnt(phy_addr: usize) -> u16 {
+    let ref_cnt = unsafe { USER_FRAME_REF_CNT.get_mut() }[self::user_frame_idx(phy_addr)];
+    if ref_cnt == 0 {
+        1
+    } else {
+        ref_cnt
+    }
+}
+
+/**
+ * 某个用户物理页，多了一个引用（被共享给了另一个任务）
+ */
+#[inline(never)]
+pub fn user_frame_ref_inc(phy_addr: usize) {
+    let ref_cnt = self::user_frame_ref_cnt(phy_addr) + 1;
+    let ref_cnt_list = unsafe { USER_FRAME_REF_CNT.get_mut() };
+    ref_cnt_list[self::user_frame_idx(phy_addr)] = ref_cnt;
+}
+
+/**
+ * 某个用户物理页，少了一个引用。返回剩余的引用数量
+ */
+#[inline(never)]
+pub fn user_frame_ref_dec(phy_addr: usize) -> u16 {
+    let left_cnt = self::user_frame_ref_cnt(phy_addr) - 1;
+    let ref_cnt_list = unsafe { USER_FRAME_REF_CNT.get_mut() };
+    // 只剩下1个引用（或者没有引用了），那么恢复成0
+    ref_cnt_list[self::user_frame_idx(phy_addr)] = if left_cnt > 1 { left_cnt } else { 0 };
+    left_cnt
+}
+
+/**
+ * 释放某个用户物理页的一个引用。只有当没有任何引用的时候，才会把这个物理页放回用户物理内存池
+ * 返回是否真正放回了池子中
+ */
+#[inline(never)]
+pub fn release_user_frame(phy_addr: usize) -> bool {
+    if self::user_frame_ref_dec(phy_addr) > 0 {
+        return false;
+    }
+    self::get_user_mem_pool().restore(phy_addr)
+}
@@ -3,6 +3,7 @@ mod memory_deallocation;
 mod mem_block;
 mod memory_poll;
 mod memory_management;
+mod vm_region;
 pub mod page_util;
 
 // 初始化内存池
@@ -20,11 +21,18 @@ pub use memory_management::free_by_addr_pool;
 // 释放内存
 pub use memory_management::sys_free;
 
-pub use memory_management::copy_single_user_page;
+pub use memory_management::share_single_user_page;
+pub use memory_management::copy_on_write;
+pub use memory_management::demand_page;
+
+pub use vm_region::VmRegion;
+pub use vm_region::VmRegionList;
+pub use vm_region::VmRegionType;
 
 
 pub use mem_block::MemBlockAllocator;
 
 
+pub use memory_poll::get_kernel_mem_pool;
 pub use memory_poll::get_user_mem_pool;
 pub use memory_poll::mem_pool_init;
@@ -58,6 +58,15 @@ pub fn do_add_page_connection(pde: &mut PageTableEntry, pte: &mut PageTableEntry
     *pte = PageTableEntry::new_default(physical_addr);
 }
 
+/**
+ * 某个虚拟地址，在当前页表中是否已经映射了物理页
+ */
+#[inline(never)]
+pub fn is_mapped(virtual_addr: usize) -> bool {
+    // 页目录项不存在的话，是不能访问页表项的（会引发缺页异常）
+    addr_to_pde(virtual_addr).present() && addr_to_pte(virtual_addr).present()
+}
+
 /**
  * 已知当前的虚拟地址，把该虚拟地址在当前PTE中的连接取消
  * （页表项的P位设置为0）
@@ -0,0 +1,135 @@
+use os_in_rust_common::constants;
+
+/**
+ * 这里是用户进程的虚拟内存区域（每个任务都有一个区域列表）
+ * 缺页异常的时候，根据引发异常的地址找到所在的区域，只有在区域内的地址，才会按需分配物理页
+ */
+
+/**
+ * 每个任务，最多的虚拟内存区域数量
+ */
+const MAX_VM_REGION_PER_TASK: usize = 8;
+
+/**
+ * 虚拟内存区域的类型
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum VmRegionType {
+    /**
+     * 堆。只有已经在虚拟地址池中预留了的地址，才可以按需分配
+     */
+    Heap,
+    /**
+     * 栈。区域内的地址都可以按需分配
+     */
+    Stack,
+    /**
+     * 加载的程序映像。区域内的地址都可以按需分配
+     */
+    Image,
+}
+
+/**
+ * 一个虚拟内存区域。地址范围[start, end)
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct VmRegion {
+    pub start: usize,
+    pub end: usize,
+    pub region_type: VmRegionType,
+}
+
+impl VmRegion {
+    pub fn new(start: usize, end: usize, region_type: VmRegionType) -> Self {
+        Self {
+            start,
+            end,
+            region_type,
+        }
+    }
+
+    /**
+     * 某个地址，是否在这个区域内
+     */
+    #[inline(never)]
+    pub fn contains(&self, addr: usize) -> bool {
+        addr >= self.start && addr < self.end
+    }
+}
+
+/**
+ * 一个任务的虚拟内存区域列表
+ */
+#[derive(Clone, Copy)]
+pub struct VmRegionList {
+    regions: [Option<VmRegion>; MAX_VM_REGION_PER_TASK],
+}
+
+impl VmRegionList {
+    pub const fn new() -> Self {
+        Self {
+            regions: [Option::None; MAX_VM_REGION_PER_TASK],
+        }
+    }
+
+    /**
+     * 用户进程默认的区域：堆 + 栈
+     */
+    #[inline(never)]
+    pub fn new_user() -> Self {
+        let mut region_list = Self::new();
+        // 栈的最低地址
+        let stack_limit = constants::USER_STACK_BASE_ADDR - constants::USER_STACK_MAX_SIZE;
+        region_list.add(VmRegion::new(constants::USER_PROCESS_ADDR_START, stack_limit, VmRegionType::Heap));
+        region_list.add(VmRegion::new(stack_limit, constants::USER_STACK_BASE_ADDR, VmRegionType::Stack));
+        region_list
+    }
+
+    /**
+     * 添加一个区域。返回是否添加成功（列表满了就失败）
+     */
+    #[inline(never)]
+    pub fn add(&mut self, region: VmRegion) -> bool {
+        let empty_slot = self.regions.iter_mut().find(|region| region.is_none());
+        if empty_slot.is_none() {
+            return false;
+        }
+        *empty_slot.unwrap() = Option::Some(region);
+        true
+    }
+
+    /**
+     * 删除某种类型的所有区域
+     */
+    #[inline(never)]
+    pub fn remove_by_type(&mut self, region_type: VmRegionType) {
+        for region in self.regions.iter_mut() {
+            if region.is_some() && region.unwrap().region_type == region_type {
+                *region = Option::None;
+            }
+        }
+    }
+
+    /**
+     * 找到地址addr所在的区域。
+     * 堆区域的范围很大，会覆盖程序映像，因此优先返回非堆的区域
+     */
+    #[inline(never)]
+    pub fn find(&self, addr: usize) -> Option<VmRegion> {
+        let mut heap_region = Option::None;
+        for region in self.regions.iter() {
+            if region.is_none() {
+                continue;
+            }
+            let region = region.unwrap();
+            if !region.contains(addr) {
+                continue;
+            }
+            if region.region_type != VmRegionType::Heap {
+                return Option::Some(region);
+            }
+            heap_region = Option::Some(region);
+        }
+        heap_region
+    }
+}
@@ -9,9 +9,7 @@ use crate::{interrupt, memory::{self, page_util}, pid_allocator, println, shell,
 pub extern "C" fn start_process(func_addr: ThreadArg) {
     let pcb_page = thread::current_thread();
 
-    // 申请一个用户页，作为栈空间
-    memory::malloc_user_page_by_vaddr(&mut pcb_page.task_struct.vaddr_pool, constants::USER_STACK_TOP_ADDR);
-
+    // 栈空间不需要提前申请，用到的时候在缺页异常中按需分配
     pcb_page.init_intr_stack(func_addr, constants::USER_STACK_BASE_ADDR as u32);
 
     let pcb_intr_stack_addr = &(pcb_page.interrupt_stack) as *const _ as u32;
@@ -58,6 +56,9 @@ pub fn process_execute(process_name: &'static str, func: extern "C" fn()) {
     // 设置用户地址池
     pcb_page.task_struct.vaddr_pool = thread_management::apply_user_addr_pool();
 
+    // 用户进程的虚拟内存区域（堆、栈）
+    pcb_page.task_struct.vm_regions = memory::VmRegionList::new_user();
+
     // 设置线程栈
     pcb_page.init_thread_stack(start_process, func as u32);
 
@@ -10,6 +10,12 @@ pub enum Cmd<'a> {
     Rmdir,
     Touch,
     Rm,
+    Mv,
+    Ln,
+    Sync,
+    Mount,
+    Umount,
+    Df,
     Shutdown,
     Help,
     Echo,
@@ -41,6 +47,12 @@ impl <'a> Cmd<'a> {
             "rmdir" => Self::Rmdir,
             "touch" => Self::Touch,
             "rm" => Self::Rm,
+            "mv" => Self::Mv,
+            "ln" => Self::Ln,
+            "sync" => Self::Sync,
+            "mount" => Self::Mount,
+            "umount" => Self::Umount,
+            "df" => Self::Df,
             "shutdown" => Self::Shutdown,
             "help" => Self::Help,
             "echo" => Self::Echo,
@@ -66,6 +78,12 @@ impl <'a> Cmd<'a> {
             ("rmdir", "Remove directory"),
             ("touch", "Create new file"),
             ("rm", "Remove file"),
+            ("mv", "Move or rename a file or directory"),
+            ("ln", "Create a hard link, or a symbolic link with -s"),
+            ("sync", "Flush all filesystem data to disk"),
+            ("mount", "Mount a partition on a directory"),
+            ("umount", "Unmount the filesystem mounted on a directory"),
+            ("df", "Show disk space and inode usage of mounted filesystems"),
             ("shutdown", "Shutdown system"),
             ("help", "Show all available commands"),
             ("echo", "Print arguments to stdout"),
@@ -0,0 +1,60 @@
+use crate::{filesystem::FsStat, println, sys_call};
+
+/**
+ * df命令：显示每个挂载了的文件系统的容量。挂载信息从/proc/mounts中读取
+ */
+#[inline(never)]
+pub fn df(param: Option<&str>) {
+    // 不支持任何参数
+    if param.is_some() && !param.unwrap().trim().is_empty() {
+        println!("Usage: df");
+        return;
+    }
+    let mounts = sys_call::File::open("/proc/mounts");
+    if mounts.is_err() {
+        println!("failed to read /proc/mounts, error: {:?}", mounts.unwrap_err());
+        return;
+    }
+    let mounts = mounts.unwrap();
+    let mut buf = [0u8; 1024];
+    let mut len = 0;
+    while len < buf.len() {
+        let read_len = mounts.read(&mut buf[len..]);
+        if read_len == 0 {
+            break;
+        }
+        len += read_len;
+    }
+
+    println!("{:<10} {:>10} {:>10} {:>10} {:>5} {:>8} {:>8} {:>8}  MOUNTED_ON", "FILESYSTEM", "SIZE_KB", "USED_KB", "AVAIL_KB", "USE%", "INODES", "IUSED", "IFREE");
+    // 每行是：来源 挂载点
+    for line in core::str::from_utf8(&buf[..len]).unwrap_or("").lines() {
+        let (source, path) = line.split_once(" ").unwrap_or((line, ""));
+        let fs_stat = sys_call::statfs(path);
+        if fs_stat.is_err() {
+            println!("failed to statfs {}, error: {:?}", path, fs_stat.unwrap_err());
+            continue;
+        }
+        let fs_stat = fs_stat.unwrap();
+        let size_kb = self::blocks_to_kb(&fs_stat, fs_stat.total_blocks);
+        let used_kb = self::blocks_to_kb(&fs_stat, fs_stat.used_blocks());
+        let avail_kb = self::blocks_to_kb(&fs_stat, fs_stat.avail_blocks);
+        // 没有容量限制的文件系统，使用率显示为"-"
+        if fs_stat.total_blocks == 0 {
+            println!("{:<10} {:>10} {:>10} {:>10} {:>5} {:>8} {:>8} {:>8}  {}", source, size_kb, used_kb, avail_kb, "-",
+                fs_stat.total_inodes, fs_stat.used_inodes(), fs_stat.free_inodes, path);
+            continue;
+        }
+        // 使用率向上取整
+        let use_percent = (fs_stat.used_blocks() as u64 * 100).div_ceil(fs_stat.total_blocks as u64);
+        println!("{:<10} {:>10} {:>10} {:>10} {:>4}% {:>8} {:>8} {:>8}  {}", source, size_kb, used_kb, avail_kb, use_percent,
+            fs_stat.total_inodes, fs_stat.used_inodes(), fs_stat.free_inodes, path);
+    }
+}
+
+/**
+ * 块的数量，换算成KB
+ */
+fn blocks_to_kb(fs_stat: &FsStat, blocks: u32) -> u64 {
+    blocks as u64 * fs_stat.block_size as u64 / 1024
+}
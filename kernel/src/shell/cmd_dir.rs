@@ -73,6 +73,14 @@ pub fn rmdir(cwd: &str, param: Option<&str>, buf: &mut [u8]) {
                     println!("dir not empty: {}", dir_path);
                     return;
                 },
+                filesystem::DirError::NotADirectory => {
+                    println!("not a directory: {}", dir_path);
+                    return;
+                },
+                filesystem::DirError::DirectoryBusy => {
+                    println!("dir is a mount point: {}", dir_path);
+                    return;
+                },
             }
         },
     }
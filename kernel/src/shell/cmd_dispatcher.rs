@@ -1,4 +1,4 @@
-use crate::{filesystem::{FileDescriptor, FileError, StdFileDescriptor}, pipe::{self, PipeError}, println, shell::shell_util::PathError, sys_call};
+use crate::{filesystem::{FileDescriptor, FileError, StdFileDescriptor}, pid_allocator::Pid, pipe::{self, PipeError}, println, shell::shell_util::PathError, sys_call, userprog::TaskWaitStatus};
 
 use super::{cmd_executor, shell_util};
 
@@ -37,10 +37,11 @@ pub fn dispatch_cmd(cwd: &str, input: &str, buf: &mut [u8]) {
                 if wait_res.is_none() {
                     println!("child process does not exit");
                 }
-                let (chpid, exit_status) = wait_res.unwrap();
+                let (chpid, wait_status) = wait_res.unwrap();
                 // println!("child process exit");
                 println!("");
-                println!("child process exit. cur pid:{}, child pid:{}, child status:{:?}", sys_call::get_pid().get_data(), chpid.get_data(), exit_status);
+                println!("child process exit. cur pid:{}, child pid:{}, child status:{:?}", sys_call::get_pid().get_data(), chpid.get_data(), wait_status.exit_status);
+                self::report_killed_child(chpid, wait_status);
             },
             sys_call::ForkResult::Child => {
                 let (cmd, param) = shell_util::parse_cmd(input);
@@ -122,7 +123,11 @@ pub fn dispatch_cmd(cwd: &str, input: &str, buf: &mut [u8]) {
 
     // 父进程在这里统一循环等待
     for _ in 0..cmd_cnt {
-        sys_call::wait();
+        let wait_res = sys_call::wait();
+        if wait_res.is_some() {
+            let (chpid, wait_status) = wait_res.unwrap();
+            self::report_killed_child(chpid, wait_status);
+        }
     }
 
     // 释放所有管道
@@ -134,6 +139,17 @@ pub fn dispatch_cmd(cwd: &str, input: &str, buf: &mut [u8]) {
     sys_call::free(pipes.as_ptr());
 }
 
+/**
+ * 如果子进程是因为CPU异常被终止的，那么打印出来
+ */
+#[inline(never)]
+fn report_killed_child(chpid: Pid, wait_status: TaskWaitStatus) {
+    if wait_status.killed_by.is_none() {
+        return;
+    }
+    println!("process {} was killed by {}", chpid.get_data(), wait_status.killed_by.unwrap().get_name());
+}
+
 #[inline(never)]
 fn batch_create_pipe(pipe_cnt: usize) -> &'static mut [FileDescriptor] {
     let pipe_res_size: usize = size_of::<FileDescriptor>() * pipe_cnt;
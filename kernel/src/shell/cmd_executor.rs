@@ -1,5 +1,5 @@
 use super::{cmd_custom, cmd_dir, cmd_echo, cmd_file, cmd_grep, cmd_cat, cmd_version, cmd_date, cmd_hello};
-use super::{cmd::Cmd, cmd_cd, cmd_ls, cmd_ps, cmd_psend};
+use super::{cmd::Cmd, cmd_cd, cmd_ln, cmd_ls, cmd_mv, cmd_ps, cmd_psend, cmd_sync, cmd_mount, cmd_df};
 
 use crate::{print, println};
 use crate::sys_call;
@@ -54,8 +54,33 @@ pub fn execute_cmd(cwd: &str, cmd: Cmd, param: Option<&str>, buf: &mut [u8]) {
         Cmd::Rm => {
             cmd_file::remove_file(cwd, param, buf);
         },
+        // 移动（重命名）文件或者目录
+        Cmd::Mv => {
+            cmd_mv::mv(cwd, param, buf);
+        },
+        // 创建链接
+        Cmd::Ln => {
+            cmd_ln::ln(cwd, param, buf);
+        },
+        // 把数据写回到硬盘
+        Cmd::Sync => {
+            cmd_sync::sync(param);
+        },
+        // 挂载、卸载文件系统
+        Cmd::Mount => {
+            cmd_mount::mount(cwd, param, buf);
+        },
+        Cmd::Umount => {
+            cmd_mount::umount(cwd, param, buf);
+        },
+        // 显示文件系统的容量
+        Cmd::Df => {
+            cmd_df::df(param);
+        },
         Cmd::Shutdown => {
             println!("Shutting down the system...");
+            // 关机之前，把文件系统的数据写回到硬盘，避免丢失
+            sys_call::sync();
             sys_call::shutdown();
         },
         Cmd::Echo => {
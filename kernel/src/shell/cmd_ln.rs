@@ -0,0 +1,77 @@
+use crate::{println, sys_call};
+
+use super::shell_util;
+
+/**
+ * ln命令：创建硬链接或者符号链接
+ *  ln <目标文件> <链接名称>
+ *  ln -s <目标路径> <链接名称>
+ */
+#[inline(never)]
+pub fn ln(cwd: &str, param: Option<&str>, buff: &mut [u8]) {
+    if param.is_none() || param.unwrap().trim().is_empty() {
+        self::print_usage();
+        return;
+    }
+    let mut param_split = param.unwrap().split_whitespace().peekable();
+    // -s参数，创建符号链接
+    let symbolic = param_split.peek() == Option::Some(&"-s");
+    if symbolic {
+        param_split.next();
+    }
+    let target_name = param_split.next();
+    let link_name = param_split.next();
+    // 必须是两个参数
+    if target_name.is_none() || link_name.is_none() || param_split.next().is_some() {
+        self::print_usage();
+        return;
+    }
+    let target_name = target_name.unwrap();
+    let link_name = link_name.unwrap();
+    if symbolic {
+        self::symlink(cwd, target_name, link_name, buff);
+        return;
+    }
+
+    let target_path = shell_util::get_abs_path(cwd, target_name, buff);
+    if target_path.is_err() {
+        println!("failed to link {}, error:{:?}", target_name, target_path.unwrap_err());
+        return;
+    }
+    let target_path = target_path.unwrap();
+
+    // 链接的路径，需要另外一个缓冲区
+    let link_buff: &mut [u8; 100] = sys_call::malloc(100);
+    let link_path = shell_util::get_abs_path(cwd, link_name, link_buff);
+    if link_path.is_err() {
+        println!("failed to link {}, error:{:?}", link_name, link_path.unwrap_err());
+        sys_call::free(link_buff.as_ptr());
+        return;
+    }
+
+    let res = sys_call::link(target_path, link_path.unwrap());
+    if res.is_err() {
+        println!("failed to link {} to {}, error:{:?}", link_name, target_name, res.unwrap_err());
+    }
+    sys_call::free(link_buff.as_ptr());
+}
+
+/**
+ * 创建符号链接。目标路径原样保存（相对路径是相对于链接所在的目录），只有链接的路径需要转成绝对路径
+ */
+#[inline(never)]
+fn symlink(cwd: &str, target_name: &str, link_name: &str, buff: &mut [u8]) {
+    let link_path = shell_util::get_abs_path(cwd, link_name, buff);
+    if link_path.is_err() {
+        println!("failed to link {}, error:{:?}", link_name, link_path.unwrap_err());
+        return;
+    }
+    let res = sys_call::symlink(target_name, link_path.unwrap());
+    if res.is_err() {
+        println!("failed to link {} to {}, error:{:?}", link_name, target_name, res.unwrap_err());
+    }
+}
+
+fn print_usage() {
+    println!("Usage: ln [-s] <target> <link_name>");
+}
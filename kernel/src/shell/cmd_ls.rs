@@ -3,7 +3,7 @@ use os_in_rust_common::{cstr_write, cstring_utils, ASSERT};
 use crate::filesystem::{self, inode};
 use crate::shell::shell_util;
 use crate::sys_call::{self};
-use crate::{print, println};
+use crate::{print, println, time};
 
 /**
  * ls命令
@@ -40,7 +40,7 @@ pub fn ls(cwd: &str, param: Option<&str>, buff: &mut [u8]) {
     }
 
     println!("total: {}", dir.get_file_size());
-    println!("file_type inode_no file_size file_name");
+    println!("mode       inode_no nlink uid gid file_size blocks modify_time         file_name");
     
     // 如果是-l参数
     for dir_entry in dir.iter() {
@@ -53,12 +53,33 @@ pub fn ls(cwd: &str, param: Option<&str>, buff: &mut [u8]) {
         }
         
         let file_type = &(dir_entry.file_type as filesystem::FileType);
-        let file_type_sign = self::get_file_type_sign(file_type);
         let file_inode = dir_entry.i_no;
 
-        let file_size = self::get_file_size(shell_util::get_abs_path(cwd, entry_name, buff).unwrap());
+        let file_path = shell_util::get_abs_path(cwd, entry_name, buff).unwrap();
+        let file_stat = self::get_stat(file_path);
+        if file_stat.is_none() {
+            continue;
+        }
+        let file_stat = file_stat.unwrap();
 
-        println!("{:^9} {:^8} {:^9} {:^9}", file_type_sign, file_inode.get_data(), file_size, dir_entry.get_name());
+        // 文件类型 + 权限。例如：drwxr-xr-x
+        let mut mode_buf = [0u8; 10];
+        let mode = self::get_mode_str(file_type, file_stat.mode, &mut mode_buf);
+        // 修改时间。例如：2024-01-01 00:00:00。旧格式的inode没有时间
+        let mtime = time::Time::from_timestamp(file_stat.mtime).to_string();
+        let mtime = if file_stat.has_time() { cstring_utils::read_from_bytes(&mtime).unwrap_or("") } else { "-" };
+
+        print!("{} {:^8} {:^5} {:^3} {:^3} {:^9} {:^6} {:19} {}", mode, file_inode.get_data(), file_stat.nlink, file_stat.uid, file_stat.gid, file_stat.size, file_stat.blocks, mtime, dir_entry.get_name());
+        // 符号链接，展示链接的目标。例如：link -> /a/b
+        if *file_type == filesystem::FileType::Symlink {
+            let target_buff: &mut [u8; 100] = sys_call::malloc(100);
+            let target_len = sys_call::readlink(file_path, target_buff);
+            if target_len.is_ok() {
+                print!(" -> {}", core::str::from_utf8(&target_buff[..target_len.unwrap()]).unwrap_or(""));
+            }
+            sys_call::free(target_buff.as_ptr());
+        }
+        println!();
     }
     return;
 }
@@ -68,32 +89,46 @@ pub fn ls(cwd: &str, param: Option<&str>, buff: &mut [u8]) {
  * 得到文件类型的标识：
  *  - 普通文件：使用"-"标识
  *  - 目录文件：使用"d"标识
+ *  - 符号链接：使用"l"标识
+ *  - 字符设备、块设备：使用"c"、"b"标识
  * 
  */
 fn get_file_type_sign(ft: &filesystem::FileType) -> &str {
     match ft {
         filesystem::FileType::Regular => "-",
         filesystem::FileType::Directory => "d",
+        filesystem::FileType::Symlink => "l",
+        filesystem::FileType::CharDevice => "c",
+        filesystem::FileType::BlockDevice => "b",
         filesystem::FileType::Unknown => "*",
     }
 }
 
-
 /**
- * 得到文件的大小
+ * 把文件类型和权限，转成ls -l展示的格式。例如：-rw-r--r--
  */
 #[inline(never)]
-fn get_file_size(file_path: &str) -> usize {
-    let file = sys_call::File::open(file_path);
-    if file.is_err() {
-        println!("{:?}", file.unwrap_err());
-        return 0;
+fn get_mode_str<'a>(ft: &filesystem::FileType, mode: u32, buf: &'a mut [u8; 10]) -> &'a str {
+    buf[0] = self::get_file_type_sign(ft).as_bytes()[0];
+    let perm_sign = b"rwxrwxrwx";
+    for (idx, sign) in perm_sign.iter().enumerate() {
+        // 权限位从高到低：rwx(用户) rwx(用户组) rwx(其他)
+        let perm_bit = 1u32 << (perm_sign.len() - 1 - idx);
+        buf[idx + 1] = if mode & perm_bit != 0 { *sign } else { b'-' };
     }
-    let file = file.unwrap();
-    let file_size = file.get_size();
-    if file_size.is_err() {
-        println!("{:?}", file_size.unwrap_err());
-        return 0;
+    core::str::from_utf8(buf).unwrap_or("")
+}
+
+
+/**
+ * 得到文件的元信息。符号链接展示它自身的信息，不跟随
+ */
+#[inline(never)]
+fn get_stat(file_path: &str) -> Option<filesystem::FileStat> {
+    let file_stat = sys_call::lstat(file_path);
+    if file_stat.is_err() {
+        println!("{:?}", file_stat.unwrap_err());
+        return Option::None;
     }
-    file_size.unwrap()
-}
\ No newline at end of file
+    Option::Some(file_stat.unwrap())
+}
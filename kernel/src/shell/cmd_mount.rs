@@ -0,0 +1,72 @@
+use crate::{println, sys_call};
+
+use super::shell_util;
+
+/**
+ * mount命令：把分区的文件系统挂载到某个目录上
+ *  mount <分区名称> <目录>
+ */
+#[inline(never)]
+pub fn mount(cwd: &str, param: Option<&str>, buff: &mut [u8]) {
+    if param.is_none() || param.unwrap().trim().is_empty() {
+        self::print_mount_usage();
+        return;
+    }
+    let mut param_split = param.unwrap().split_whitespace();
+    let part_name = param_split.next();
+    let dir_name = param_split.next();
+    // 必须是两个参数
+    if part_name.is_none() || dir_name.is_none() || param_split.next().is_some() {
+        self::print_mount_usage();
+        return;
+    }
+    let part_name = part_name.unwrap();
+    let dir_name = dir_name.unwrap();
+
+    let dir_path = shell_util::get_abs_path(cwd, dir_name, buff);
+    if dir_path.is_err() {
+        println!("failed to mount {}, error:{:?}", part_name, dir_path.unwrap_err());
+        return;
+    }
+    let res = sys_call::mount(part_name, dir_path.unwrap());
+    if res.is_err() {
+        println!("failed to mount {} on {}, error:{:?}", part_name, dir_name, res.unwrap_err());
+    }
+}
+
+/**
+ * umount命令：卸载挂载在某个目录上的文件系统
+ *  umount <目录>
+ */
+#[inline(never)]
+pub fn umount(cwd: &str, param: Option<&str>, buff: &mut [u8]) {
+    if param.is_none() || param.unwrap().trim().is_empty() {
+        self::print_umount_usage();
+        return;
+    }
+    let mut param_split = param.unwrap().split_whitespace();
+    let dir_name = param_split.next().unwrap();
+    // 只能是一个参数
+    if param_split.next().is_some() {
+        self::print_umount_usage();
+        return;
+    }
+    let dir_path = shell_util::get_abs_path(cwd, dir_name, buff);
+    if dir_path.is_err() {
+        println!("failed to umount {}, error:{:?}", dir_name, dir_path.unwrap_err());
+        return;
+    }
+    let res = sys_call::umount(dir_path.unwrap());
+    if res.is_err() {
+        println!("failed to umount {}, error:{:?}", dir_name, res.unwrap_err());
+    }
+}
+
+fn print_mount_usage() {
+    println!("Usage: mount <partition> <dir>");
+    println!("       mount proc|dev|tmpfs <dir>");
+}
+
+fn print_umount_usage() {
+    println!("Usage: umount <dir>");
+}
@@ -0,0 +1,66 @@
+use crate::{filesystem, println, sys_call};
+
+use super::shell_util;
+
+/**
+ * mv命令：移动（重命名）文件或者目录
+ *  mv <源路径> <目标路径>
+ */
+#[inline(never)]
+pub fn mv(cwd: &str, param: Option<&str>, buff: &mut [u8]) {
+    if param.is_none() || param.unwrap().trim().is_empty() {
+        self::print_usage();
+        return;
+    }
+    let mut param_split = param.unwrap().split_whitespace();
+    let old_name = param_split.next();
+    let new_name = param_split.next();
+    // 必须是两个参数
+    if old_name.is_none() || new_name.is_none() || param_split.next().is_some() {
+        self::print_usage();
+        return;
+    }
+    let old_name = old_name.unwrap();
+    let new_name = new_name.unwrap();
+
+    let old_path = shell_util::get_abs_path(cwd, old_name, buff);
+    if old_path.is_err() {
+        println!("failed to move {}, error:{:?}", old_name, old_path.unwrap_err());
+        return;
+    }
+    let old_path = old_path.unwrap();
+
+    // 目标路径，需要另外的缓冲区
+    let new_buff: &mut [u8; 200] = sys_call::malloc(200);
+    let (target_buff, join_buff) = new_buff.split_at_mut(100);
+    let new_path = shell_util::get_abs_path(cwd, new_name, target_buff);
+    if new_path.is_err() {
+        println!("failed to move {}, error:{:?}", old_name, new_path.unwrap_err());
+        sys_call::free(new_buff.as_ptr());
+        return;
+    }
+    let mut new_path = new_path.unwrap();
+
+    // 如果目标是一个已存在的目录，那么移动到这个目录下，名称不变
+    let target_stat = sys_call::stat(new_path);
+    if target_stat.is_ok() && target_stat.unwrap().file_type() == filesystem::FileType::Directory {
+        let base_name = old_path.rsplit("/").next().unwrap_or("");
+        let join_path = shell_util::get_abs_path(new_path, base_name, join_buff);
+        if join_path.is_err() {
+            println!("failed to move {}, error:{:?}", old_name, join_path.unwrap_err());
+            sys_call::free(new_buff.as_ptr());
+            return;
+        }
+        new_path = join_path.unwrap();
+    }
+
+    let res = sys_call::rename(old_path, new_path);
+    if res.is_err() {
+        println!("failed to move {} to {}, error:{:?}", old_name, new_name, res.unwrap_err());
+    }
+    sys_call::free(new_buff.as_ptr());
+}
+
+fn print_usage() {
+    println!("Usage: mv <source> <target>");
+}
@@ -1,14 +1,52 @@
-use crate::thread::{self, TaskStruct};
+use os_in_rust_common::{cstr_write, cstring_utils};
+
 use crate::println;
+use crate::sys_call;
 
 /**
- * ps命令的效果
+ * ps命令的效果。每个进程的信息，都从/proc/<pid>/下的文件中读取
  */
+#[inline(never)]
 pub fn ps() {
+    let proc_dir = sys_call::read_dir("/proc");
+    if proc_dir.is_err() {
+        println!("failed to read /proc, error: {:?}", proc_dir.unwrap_err());
+        return;
+    }
+    let mut proc_dir = proc_dir.unwrap();
+
     println!("PID  PPID    STAT    TICKS  LEFT_TICKS  TASK_NAME ");
-    let all_thread_iter = thread::get_all_thread().iter();
-    for task_node in all_thread_iter {
-        let task = unsafe {&*TaskStruct::parse_by_all_tag(&*task_node)};
-        println!("{:^3}  {:^5} {:^8} {:^6} {:^12} {:^12}", task.pid.get_data(), task.parent_pid.map_or(0, |pid| pid.get_data()), task.task_status.get_name(), task.elapsed_ticks, task.left_ticks, task.get_name());
+    let mut status_buf = [0u8; 16];
+    let mut ppid_buf = [0u8; 8];
+    let mut ticks_buf = [0u8; 16];
+    let mut name_buf = [0u8; 32];
+    for dir_entry in proc_dir.iter() {
+        // 只有名称是pid的目录，才是进程
+        let pid = dir_entry.get_name();
+        if pid.parse::<u8>().is_err() {
+            continue;
+        }
+        let status = self::read_proc_file(pid, "status", &mut status_buf);
+        let ppid = self::read_proc_file(pid, "ppid", &mut ppid_buf);
+        let name = self::read_proc_file(pid, "name", &mut name_buf);
+        // ticks文件的内容：已经执行的滴答数 剩余的滴答数
+        let ticks = self::read_proc_file(pid, "ticks", &mut ticks_buf);
+        let (elapsed_ticks, left_ticks) = ticks.split_once(" ").unwrap_or((ticks, ""));
+        println!("{:^3}  {:^5} {:^8} {:^6} {:^12} {:^12}", pid, ppid, status, elapsed_ticks, left_ticks, name);
+    }
+}
+
+/**
+ * 读取/proc/<pid>/<file_name>的内容，去掉结尾的换行。进程已经结束的话，返回空字符串
+ */
+#[inline(never)]
+fn read_proc_file<'a>(pid: &str, file_name: &str, buf: &'a mut [u8]) -> &'a str {
+    let mut path_buf = [0u8; 32];
+    cstr_write!(&mut path_buf, "/proc/{}/{}", pid, file_name);
+    let file = sys_call::File::open(cstring_utils::read_from_bytes(&path_buf).unwrap());
+    if file.is_err() {
+        return "";
     }
-}
\ No newline at end of file
+    let read_len = file.unwrap().read(buf);
+    core::str::from_utf8(&buf[..read_len]).unwrap_or("").trim_end()
+}
@@ -0,0 +1,14 @@
+use crate::{println, sys_call};
+
+/**
+ * sync命令：把文件系统中所有的数据，写回到硬盘
+ */
+#[inline(never)]
+pub fn sync(param: Option<&str>) {
+    // 不支持任何参数
+    if param.is_some() && !param.unwrap().trim().is_empty() {
+        println!("Usage: sync");
+        return;
+    }
+    sys_call::sync();
+}
@@ -17,6 +17,11 @@ mod cmd_version;
 mod cmd_date;
 mod cmd_psend;
 mod cmd_hello;
+mod cmd_mv;
+mod cmd_ln;
+mod cmd_sync;
+mod cmd_mount;
+mod cmd_df;
 
 pub use my_shell::shell_start;
 pub use shell::Shell;
\ No newline at end of file
@@ -150,7 +150,8 @@ impl Lock {
         self.semaphore.down();
         // 当前任务为该锁的持有者。能运行到这里，那么说明必定没有阻塞
         self.holder = current_task as *mut _;
-        self.repeat += 0;
+        // 第一次加锁。之后每重入一次加1，unlock的次数和lock一样多才真正释放
+        self.repeat = 1;
     }
 
     /**
@@ -114,6 +114,22 @@ impl File {
         sys_call_proxy::file_size(&self.file)
     }
 
+    /**
+     * 读取该文件的元信息
+     */
+    #[inline(never)]
+    pub fn stat(&self) -> Result<filesystem::FileStat, filesystem::FileError> {
+        sys_call_proxy::fstat(self.file.get_file_descriptor())
+    }
+
+    /**
+     * 把该文件的数据，写回到硬盘
+     */
+    #[inline(never)]
+    pub fn sync_all(&self) -> Result<(), filesystem::FileError> {
+        sys_call_proxy::fsync(self.file.get_file_descriptor())
+    }
+
     pub fn get_fd(&self) -> FileDescriptor {
         self.file.get_file_descriptor()
     }
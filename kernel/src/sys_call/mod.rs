@@ -31,6 +31,18 @@ pub use sys_call_proxy::release_pipe;
 pub use sys_call_proxy::set_producer;
 pub use sys_call_proxy::set_consumer;
 pub use sys_call_proxy::shutdown;
+pub use sys_call_proxy::stat;
+pub use sys_call_proxy::fstat;
+pub use sys_call_proxy::rename;
+pub use sys_call_proxy::link;
+pub use sys_call_proxy::lstat;
+pub use sys_call_proxy::symlink;
+pub use sys_call_proxy::readlink;
+pub use sys_call_proxy::fsync;
+pub use sys_call_proxy::sync;
+pub use sys_call_proxy::mount;
+pub use sys_call_proxy::umount;
+pub use sys_call_proxy::statfs;
 pub use crate::println;
 pub use crate::print;
 
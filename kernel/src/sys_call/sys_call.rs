@@ -188,6 +188,66 @@ pub enum SystemCallNo {
      * 系统关机
      */
     Shutdown,
+
+    /**
+     * 根据路径，读取文件的元信息
+     */
+    Stat,
+
+    /**
+     * 根据文件描述符，读取文件的元信息
+     */
+    FStat,
+
+    /**
+     * 重命名（移动）文件或者目录
+     */
+    Rename,
+
+    /**
+     * 创建硬链接
+     */
+    Link,
+
+    /**
+     * 根据路径，读取文件的元信息（不跟随最后一级的符号链接）
+     */
+    LStat,
+
+    /**
+     * 创建符号链接
+     */
+    Symlink,
+
+    /**
+     * 读取符号链接的目标路径
+     */
+    ReadLink,
+
+    /**
+     * 把某个文件的数据，写回到硬盘
+     */
+    FSync,
+
+    /**
+     * 把整个文件系统的数据，写回到硬盘
+     */
+    Sync,
+
+    /**
+     * 把分区的文件系统，挂载到某个目录上
+     */
+    Mount,
+
+    /**
+     * 卸载挂载在某个目录上的文件系统
+     */
+    Umount,
+
+    /**
+     * 读取路径所在的文件系统的容量信息
+     */
+    StatFs,
 }
 
 /**
@@ -2,7 +2,7 @@ use core::{fmt, mem::{size_of, take}, str, task};
 
 use os_in_rust_common::{printkln, vga::{self}, ASSERT, MY_PANIC};
 
-use crate::{ascii::AsciiKey, blocking_queue::BlockingQueue, common::{cwd_dto::CwdDto, exec_dto::ExecParam, open_file_dto::OpenFileDto}, console, console_print, exec, filesystem::{self, DirError, FileDescriptor, FileDescriptorType, StdFileDescriptor}, fork, keyboard, memory, pid_allocator::Pid, pipe::{self, PipeError, PipeReader, PipeWriter}, scancode::KeyCode, thread, thread_management, userprog::{self, TaskExitStatus}};
+use crate::{ascii::AsciiKey, common::{cwd_dto::CwdDto, exec_dto::ExecParam, mount_dto::MountDto, open_file_dto::OpenFileDto, path_pair_dto::PathPairDto, read_link_dto::ReadLinkDto}, console, console_print, exec, filesystem::{self, DirError, FileDescriptor, FileDescriptorType, StdFileDescriptor}, fork, keyboard, memory, pid_allocator::Pid, pipe::{self, PipeError, PipeReader, PipeWriter}, scancode::KeyCode, thread, thread_management, userprog::{self, TaskWaitStatus}};
 use super::sys_call::{self, HandlerType, SystemCallNo};
 
 /**
@@ -68,6 +68,42 @@ pub fn init() {
     // 获取文件大小
     sys_call::register_handler(SystemCallNo::FileSize, HandlerType::TwoParams(file_size));
 
+    // 根据路径读取文件元信息
+    sys_call::register_handler(SystemCallNo::Stat, HandlerType::ThreeParams(stat));
+
+    // 根据文件描述符读取文件元信息
+    sys_call::register_handler(SystemCallNo::FStat, HandlerType::TwoParams(fstat));
+
+    // 重命名
+    sys_call::register_handler(SystemCallNo::Rename, HandlerType::TwoParams(rename));
+
+    // 硬链接
+    sys_call::register_handler(SystemCallNo::Link, HandlerType::TwoParams(link));
+
+    // 根据路径读取文件元信息（不跟随符号链接）
+    sys_call::register_handler(SystemCallNo::LStat, HandlerType::ThreeParams(lstat));
+
+    // 符号链接
+    sys_call::register_handler(SystemCallNo::Symlink, HandlerType::TwoParams(symlink));
+
+    // 读取符号链接
+    sys_call::register_handler(SystemCallNo::ReadLink, HandlerType::TwoParams(read_link));
+
+    // 把文件的数据写回到硬盘
+    sys_call::register_handler(SystemCallNo::FSync, HandlerType::TwoParams(fsync));
+
+    // 把整个文件系统的数据写回到硬盘
+    sys_call::register_handler(SystemCallNo::Sync, HandlerType::NoneParam(sync));
+
+    // 挂载文件系统
+    sys_call::register_handler(SystemCallNo::Mount, HandlerType::TwoParams(mount));
+
+    // 卸载文件系统
+    sys_call::register_handler(SystemCallNo::Umount, HandlerType::ThreeParams(umount));
+
+    // 读取文件系统的容量信息
+    sys_call::register_handler(SystemCallNo::StatFs, HandlerType::ThreeParams(statfs));
+
     // 关闭文件
     sys_call::register_handler(SystemCallNo::CloseFile, HandlerType::TwoParams(close_file));
     
@@ -112,6 +148,9 @@ pub fn init() {
 #[inline(never)]
 fn shutdown() -> u32 {
     printkln!("System is shutting down...");
+
+    // 关机之前，把文件系统的数据写回到硬盘
+    filesystem::sync();
     
     // 在x86架构中，实现ATX电源关机
     unsafe {
@@ -189,8 +228,7 @@ fn write(fd_addr: u32, addr: u32, len: u32) -> u32 {
     // 普通文件
     if task_file_descriptor.get_fd_type() == FileDescriptorType::File {
         let file = filesystem::get_file_by_fd(fd).unwrap();
-        let fs = filesystem::get_filesystem();
-        return filesystem::write_file(fs, file, buf).try_into().unwrap()
+        return file.write(buf).unwrap_or(0).try_into().unwrap()
     }
     return 0;
 }
@@ -211,25 +249,8 @@ fn read(fd_addr: u32, buf: u32, len: u32) -> u32 {
         // 如果是标准输入
         if filesystem::StdFileDescriptor::StdInputNo as usize == fd.get_value() {
             let key_buff = unsafe { core::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut AsciiKey, buf.len() / size_of::<AsciiKey>()) };
-            let mut idx = 0;
-            // 键盘队列
-            let keyboard_queue = keyboard::get_keycode_queue();
-            while idx < key_buff.len() {
-                // 逐个取出输入的键，直到满了
-                let ascii_key = keyboard_queue.take();
-                // 如果队列里空了
-                if ascii_key.is_none() {
-                    break;
-                }
-                let ascii_key = ascii_key.unwrap();
-                // 无效的键，忽略
-                if ascii_key == AsciiKey::NUL {
-                    continue;
-                }
-                key_buff[idx] = ascii_key;
-                idx += 1;
-            }
-            return idx.try_into().unwrap();
+            // 从键盘队列中读取
+            return keyboard::read_keys(key_buff).try_into().unwrap();
         }
         return 0;
     }
@@ -249,9 +270,8 @@ fn read(fd_addr: u32, buf: u32, len: u32) -> u32 {
     if task_file_descriptor.get_fd_type() == FileDescriptorType::File {
         // 根据文件描述符，得到这个文件
         let file = filesystem::get_file_by_fd(fd).unwrap();
-        let fs = filesystem::get_filesystem();
         // 读取文件
-        return filesystem::read_file(fs, file, buf).try_into().unwrap();
+        return file.read(buf).try_into().unwrap();
     }
     return 0;
 }
@@ -391,6 +411,149 @@ fn file_size(file_addr: u32, res_addr: u32) -> u32 {
     0
 }
 
+/**
+ * 根据路径，读取文件的元信息，写入到stat_addr的结构中。返回值：0表示成功，否则是错误码
+ */
+#[inline(never)]
+fn stat(addr: u32, len: u32, stat_addr: u32) -> u32 {
+    let file_stat = unsafe {&mut *(stat_addr as *mut filesystem::FileStat)};
+    let file_path = unsafe { core::str::from_utf8(core::slice::from_raw_parts(addr as *const u8, len.try_into().unwrap())) };
+    ASSERT!(file_path.is_ok());
+    self::write_stat(filesystem::stat(file_path.unwrap()), file_stat)
+}
+
+/**
+ * 根据文件描述符，读取文件的元信息，写入到stat_addr的结构中。返回值：0表示成功，否则是错误码
+ */
+#[inline(never)]
+fn fstat(fd_addr: u32, stat_addr: u32) -> u32 {
+    let fd  = unsafe { *(fd_addr as *const FileDescriptor) };
+    let file_stat = unsafe {&mut *(stat_addr as *mut filesystem::FileStat)};
+    self::write_stat(filesystem::fstat(fd), file_stat)
+}
+
+/**
+ * 把读取到的元信息，写入到用户进程的结构中。失败的话，返回错误码
+ */
+#[inline(never)]
+fn write_stat(res: Result<filesystem::FileStat, filesystem::FileError>, file_stat: &mut filesystem::FileStat) -> u32 {
+    if res.is_err() {
+        return res.unwrap_err().to_code();
+    }
+    *file_stat = res.unwrap();
+    0
+}
+
+
+/**
+ * 重命名（移动）文件或者目录
+ */
+#[inline(never)]
+fn rename(req_addr: u32, res_addr: u32) -> u32 {
+    let req = unsafe { &*(req_addr as *const PathPairDto) };
+    let res = unsafe {&mut *(res_addr as *mut Result<(), filesystem::FileError>)};
+    *res = filesystem::rename(req.src_path, req.dst_path);
+    0
+}
+
+/**
+ * 创建硬链接
+ */
+#[inline(never)]
+fn link(req_addr: u32, res_addr: u32) -> u32 {
+    let req = unsafe { &*(req_addr as *const PathPairDto) };
+    let res = unsafe {&mut *(res_addr as *mut Result<(), filesystem::FileError>)};
+    *res = filesystem::link(req.src_path, req.dst_path);
+    0
+}
+
+/**
+ * 根据路径，读取文件的元信息。不跟随最后一级的符号链接。返回值：0表示成功，否则是错误码
+ */
+#[inline(never)]
+fn lstat(addr: u32, len: u32, stat_addr: u32) -> u32 {
+    let file_stat = unsafe {&mut *(stat_addr as *mut filesystem::FileStat)};
+    let file_path = unsafe { core::str::from_utf8(core::slice::from_raw_parts(addr as *const u8, len.try_into().unwrap())) };
+    ASSERT!(file_path.is_ok());
+    self::write_stat(filesystem::lstat(file_path.unwrap()), file_stat)
+}
+
+/**
+ * 创建符号链接。src_path是链接的目标，dst_path是符号链接的路径
+ */
+#[inline(never)]
+fn symlink(req_addr: u32, res_addr: u32) -> u32 {
+    let req = unsafe { &*(req_addr as *const PathPairDto) };
+    let res = unsafe {&mut *(res_addr as *mut Result<(), filesystem::FileError>)};
+    *res = filesystem::symlink(req.src_path, req.dst_path);
+    0
+}
+
+/**
+ * 读取符号链接的目标路径
+ */
+#[inline(never)]
+fn read_link(req_addr: u32, res_addr: u32) -> u32 {
+    let req = unsafe { &mut *(req_addr as *mut ReadLinkDto) };
+    let res = unsafe {&mut *(res_addr as *mut Result<usize, filesystem::FileError>)};
+    *res = filesystem::readlink(req.link_path, req.buff);
+    0
+}
+
+/**
+ * 把某个文件的数据，写回到硬盘
+ */
+#[inline(never)]
+fn fsync(fd_addr: u32, res_addr: u32) -> u32 {
+    let fd  = unsafe { *(fd_addr as *const FileDescriptor) };
+    let res = unsafe {&mut *(res_addr as *mut Result<(), filesystem::FileError>)};
+    *res = filesystem::fsync(fd);
+    0
+}
+
+/**
+ * 把整个文件系统的数据，写回到硬盘
+ */
+#[inline(never)]
+fn sync() -> u32 {
+    filesystem::sync();
+    0
+}
+
+/**
+ * 把分区的文件系统，挂载到某个目录上
+ */
+#[inline(never)]
+fn mount(req_addr: u32, res_addr: u32) -> u32 {
+    let req = unsafe { &*(req_addr as *const MountDto) };
+    let res = unsafe {&mut *(res_addr as *mut Result<(), filesystem::MountError>)};
+    *res = filesystem::mount(req.part_name, req.path);
+    0
+}
+
+/**
+ * 卸载挂载在某个目录上的文件系统
+ */
+#[inline(never)]
+fn umount(addr: u32, len: u32, res_addr: u32) -> u32 {
+    let res = unsafe {&mut *(res_addr as *mut Result<(), filesystem::MountError>)};
+    let path = unsafe { core::str::from_utf8(core::slice::from_raw_parts(addr as *const u8, len.try_into().unwrap())) };
+    ASSERT!(path.is_ok());
+    *res = filesystem::umount(path.unwrap());
+    0
+}
+
+/**
+ * 读取路径所在的文件系统的容量信息
+ */
+#[inline(never)]
+fn statfs(addr: u32, len: u32, res_addr: u32) -> u32 {
+    let res = unsafe {&mut *(res_addr as *mut Result<filesystem::FsStat, filesystem::FileError>)};
+    let path = unsafe { core::str::from_utf8(core::slice::from_raw_parts(addr as *const u8, len.try_into().unwrap())) };
+    ASSERT!(path.is_ok());
+    *res = filesystem::statfs(path.unwrap());
+    0
+}
 
 #[inline(never)]
 fn seek_file(file_addr: u32, seek_addr: u32, res_addr: u32) -> u32 {
@@ -439,7 +602,7 @@ fn exit(status: u32) -> u32 {
 
 #[inline(never)]
 fn wait(res_addr: u32) -> u32 {
-    let res = unsafe { &mut *(res_addr as *mut Option<(Pid, Option<TaskExitStatus>)>) };
+    let res = unsafe { &mut *(res_addr as *mut Option<(Pid, TaskWaitStatus)>) };
     *res = userprog::wait();
     0
 }
@@ -7,11 +7,14 @@ use crate::ascii::AsciiKey;
 use crate::common::cwd_dto::CwdDto;
 use crate::common::exec_dto::ExecParam;
 use crate::common::open_file_dto::OpenFileDto;
+use crate::common::mount_dto::MountDto;
+use crate::common::path_pair_dto::PathPairDto;
+use crate::common::read_link_dto::ReadLinkDto;
 use crate::exec;
 use crate::filesystem::{self, FileDescriptor, SeekFrom, StdFileDescriptor};
 use crate::pid_allocator::Pid;
 use crate::pipe::PipeError;
-use crate::userprog::TaskExitStatus;
+use crate::userprog::{TaskExitStatus, TaskWaitStatus};
 
 use super::sys_call::SystemCallNo;
 
@@ -183,6 +186,140 @@ pub fn file_size(file: &filesystem::File) -> Result<usize, filesystem::FileError
     res
 }
 
+/**
+ * 根据路径，读取文件的元信息
+ */
+#[inline(never)]
+pub fn stat(path: &str) -> Result<filesystem::FileStat, filesystem::FileError> {
+    let mut file_stat = filesystem::FileStat::empty();
+    let code = self::do_sys_call(SystemCallNo::Stat, Option::Some(path.as_ptr() as u32), Option::Some(path.len() as u32), Option::Some(&mut file_stat as *mut _ as u32));
+    self::stat_result(code, file_stat)
+}
+
+/**
+ * 根据文件描述符，读取文件的元信息
+ */
+#[inline(never)]
+pub fn fstat(fd: FileDescriptor) -> Result<filesystem::FileStat, filesystem::FileError> {
+    let mut file_stat = filesystem::FileStat::empty();
+    let code = self::do_sys_call(SystemCallNo::FStat, Option::Some(&fd as *const _ as u32), Option::Some(&mut file_stat as *mut _ as u32), Option::None);
+    self::stat_result(code, file_stat)
+}
+
+/**
+ * stat系列系统调用，根据返回的错误码（0表示成功），得到结果
+ */
+#[inline(never)]
+fn stat_result(code: u32, file_stat: filesystem::FileStat) -> Result<filesystem::FileStat, filesystem::FileError> {
+    if code != 0 {
+        return Result::Err(filesystem::FileError::from_code(code).unwrap_or(filesystem::FileError::Uncategorized));
+    }
+    Result::Ok(file_stat)
+}
+
+/**
+ * 重命名（移动）文件或者目录
+ */
+#[inline(never)]
+pub fn rename(old_path: &str, new_path: &str) -> Result<(), filesystem::FileError> {
+    let req = PathPairDto::new(old_path, new_path);
+    let mut res: Result<(), filesystem::FileError> = Result::Err(filesystem::FileError::NotFound);
+    self::do_sys_call(SystemCallNo::Rename, Option::Some(&req as *const _ as u32), Option::Some(&mut res as *mut _ as u32), Option::None);
+    res
+}
+
+/**
+ * 创建硬链接：new_path指向existing_path的文件
+ */
+#[inline(never)]
+pub fn link(existing_path: &str, new_path: &str) -> Result<(), filesystem::FileError> {
+    let req = PathPairDto::new(existing_path, new_path);
+    let mut res: Result<(), filesystem::FileError> = Result::Err(filesystem::FileError::NotFound);
+    self::do_sys_call(SystemCallNo::Link, Option::Some(&req as *const _ as u32), Option::Some(&mut res as *mut _ as u32), Option::None);
+    res
+}
+
+/**
+ * 根据路径，读取文件的元信息。路径的最后一级是符号链接的话，读取符号链接自身
+ */
+#[inline(never)]
+pub fn lstat(path: &str) -> Result<filesystem::FileStat, filesystem::FileError> {
+    let mut file_stat = filesystem::FileStat::empty();
+    let code = self::do_sys_call(SystemCallNo::LStat, Option::Some(path.as_ptr() as u32), Option::Some(path.len() as u32), Option::Some(&mut file_stat as *mut _ as u32));
+    self::stat_result(code, file_stat)
+}
+
+/**
+ * 创建符号链接：link_path指向target_path
+ */
+#[inline(never)]
+pub fn symlink(target_path: &str, link_path: &str) -> Result<(), filesystem::FileError> {
+    let req = PathPairDto::new(target_path, link_path);
+    let mut res: Result<(), filesystem::FileError> = Result::Err(filesystem::FileError::NotFound);
+    self::do_sys_call(SystemCallNo::Symlink, Option::Some(&req as *const _ as u32), Option::Some(&mut res as *mut _ as u32), Option::None);
+    res
+}
+
+/**
+ * 读取符号链接的目标路径，写入到buff中。返回目标路径的字节数
+ */
+#[inline(never)]
+pub fn readlink(link_path: &str, buff: &mut [u8]) -> Result<usize, filesystem::FileError> {
+    let mut req = ReadLinkDto::new(link_path, buff);
+    let mut res: Result<usize, filesystem::FileError> = Result::Err(filesystem::FileError::NotFound);
+    self::do_sys_call(SystemCallNo::ReadLink, Option::Some(&mut req as *mut _ as u32), Option::Some(&mut res as *mut _ as u32), Option::None);
+    res
+}
+
+/**
+ * 把某个文件的数据，写回到硬盘
+ */
+#[inline(never)]
+pub fn fsync(fd: FileDescriptor) -> Result<(), filesystem::FileError> {
+    let mut res: Result<(), filesystem::FileError> = Result::Err(filesystem::FileError::NotFound);
+    self::do_sys_call(SystemCallNo::FSync, Option::Some(&fd as *const _ as u32), Option::Some(&mut res as *mut _ as u32), Option::None);
+    res
+}
+
+/**
+ * 把整个文件系统的数据，写回到硬盘
+ */
+#[inline(never)]
+pub fn sync() {
+    self::do_sys_call(SystemCallNo::Sync, Option::None, Option::None, Option::None);
+}
+
+/**
+ * 把分区part_name的文件系统，挂载到目录path上
+ */
+#[inline(never)]
+pub fn mount(part_name: &str, path: &str) -> Result<(), filesystem::MountError> {
+    let req = MountDto::new(part_name, path);
+    let mut res: Result<(), filesystem::MountError> = Result::Err(filesystem::MountError::PartitionNotFound);
+    self::do_sys_call(SystemCallNo::Mount, Option::Some(&req as *const _ as u32), Option::Some(&mut res as *mut _ as u32), Option::None);
+    res
+}
+
+/**
+ * 卸载挂载在目录path上的文件系统
+ */
+#[inline(never)]
+pub fn umount(path: &str) -> Result<(), filesystem::MountError> {
+    let mut res: Result<(), filesystem::MountError> = Result::Err(filesystem::MountError::NotMounted);
+    self::do_sys_call(SystemCallNo::Umount, Option::Some(path.as_ptr() as u32), Option::Some(path.len() as u32), Option::Some(&mut res as *mut _ as u32));
+    res
+}
+
+/**
+ * 读取路径所在的文件系统的容量信息
+ */
+#[inline(never)]
+pub fn statfs(path: &str) -> Result<filesystem::FsStat, filesystem::FileError> {
+    let mut res: Result<filesystem::FsStat, filesystem::FileError> = Result::Err(filesystem::FileError::NotFound);
+    self::do_sys_call(SystemCallNo::StatFs, Option::Some(path.as_ptr() as u32), Option::Some(path.len() as u32), Option::Some(&mut res as *mut _ as u32));
+    res
+}
+
 #[inline(never)]
 pub fn remove_file(path: &str) -> Result<(), filesystem::FileError> {
     let mut res: Result<(), filesystem::FileError> = Result::Err(filesystem::FileError::NotFound);
@@ -205,8 +342,8 @@ pub fn exit(status: TaskExitStatus) {
 }
 
 #[inline(never)]
-pub fn wait() -> Option<(Pid, Option<u8>)> {
-    let mut res: Option<(Pid, Option<u8>)> = Option::None;
+pub fn wait() -> Option<(Pid, TaskWaitStatus)> {
+    let mut res: Option<(Pid, TaskWaitStatus)> = Option::None;
     self::do_sys_call(SystemCallNo::Wait, Option::Some(&mut res as *mut _ as u32), Option::None,  Option::None);
     res
 }
@@ -2,7 +2,7 @@ use core::{arch::asm, fmt::{write, Display}, mem::size_of, ptr};
 
 use os_in_rust_common::{constants, cstr_write, cstring_utils, domain::InodeNo, elem2entry, instruction::{self, enable_interrupt}, linked_list::{LinkedList, LinkedNode, LinkedNodeIterator}, paging::{self, PageTable}, pool::MemPool, printkln, racy_cell::RacyCell, reg_cr3::{self, CR3}, reg_eflags::{self, EFlags, FlagEnum}, selector::SegmentSelector, utils, ASSERT, MY_PANIC};
 
-use crate::{console_println, filesystem::TaskFileDescriptorTable, interrupt, memory::{page_util, MemBlockAllocator}, pid_allocator::Pid, tss, userprog::TaskExitStatus};
+use crate::{console_println, filesystem::{TaskFileDescriptorTable, Vfs}, interrupt, memory::{page_util, MemBlockAllocator, VmRegionList}, pid_allocator::Pid, tss, userprog::{ExceptionExitStatus, TaskExitStatus}};
 
 
 /**
@@ -295,6 +295,11 @@ pub struct TaskStruct {
      */
     pub vaddr_pool: MemPool,
 
+    /**
+     * 该进程的虚拟内存区域列表（堆、栈、程序映像）。缺页异常的时候，用来判断地址是否合法
+     */
+    pub vm_regions: VmRegionList,
+
     /**
      * PCB页的地址
      */
@@ -310,11 +315,21 @@ pub struct TaskStruct {
      */
     pub cwd_inode: Option<InodeNo>,
 
+    /**
+     * 工作目录所在的文件系统
+     */
+    pub cwd_fs: Option<*mut dyn Vfs>,
+
     /**
      * 该任务退出时，指定的状态
      */
     pub exit_status: Option<TaskExitStatus>,
 
+    /**
+     * 该任务如果是被CPU异常终止的，记录是哪个异常
+     */
+    pub killed_by: Option<ExceptionExitStatus>,
+
     /**
      * 栈边界的魔数
      */
@@ -348,6 +363,11 @@ impl TaskStruct {
         self.all_tag = LinkedNode::new();
         self.pcb_page_addr = pcb_page_addr;
         self.fd_table = TaskFileDescriptorTable::new();
+        self.vm_regions = VmRegionList::new();
+        self.cwd_inode = Option::None;
+        self.cwd_fs = Option::None;
+        self.exit_status = Option::None;
+        self.killed_by = Option::None;
     }
 
     #[inline(never)]
@@ -9,6 +9,9 @@ use core::mem::size_of;
 // 系统启动时间计数器（以秒为单位）
 static SYSTEM_TIME_SECONDS: AtomicU64 = AtomicU64::new(0);
 
+// 系统启动之后，定时器中断的次数
+static SYSTEM_TICKS: AtomicU64 = AtomicU64::new(0);
+
 // 系统启动时的默认时间（2024-01-01 00:00:00）
 const DEFAULT_YEAR: u16 = 2024;
 const DEFAULT_MONTH: u8 = 1;
@@ -16,6 +19,8 @@ const DEFAULT_DAY: u8 = 1;
 const DEFAULT_HOUR: u8 = 0;
 const DEFAULT_MINUTE: u8 = 0;
 const DEFAULT_SECOND: u8 = 0;
+// 默认时间的Unix时间戳
+const DEFAULT_UNIX_TIMESTAMP: u32 = 1_704_067_200;
 
 /**
  * 时间结构体
@@ -83,6 +88,26 @@ impl Time {
         
         buffer
     }
+
+    /**
+     * 把时间戳（系统时间的秒数）转成具体的日期时间
+     */
+    pub fn from_timestamp(timestamp: u32) -> Self {
+        calculate_datetime(timestamp as u64)
+    }
+
+    /**
+     * 把具体的日期时间转成时间戳（from_timestamp的逆运算，每月按30天计算）
+     * 早于默认时间的，时间戳是0
+     */
+    pub fn to_timestamp(&self) -> u32 {
+        if self.year < DEFAULT_YEAR {
+            return 0;
+        }
+        let months = (self.year - DEFAULT_YEAR) as u32 * 12 + (self.month as u32).saturating_sub(DEFAULT_MONTH as u32);
+        let days = months * 30 + (self.day as u32).saturating_sub(DEFAULT_DAY as u32);
+        days * 24 * 3600 + self.hour as u32 * 3600 + self.minute as u32 * 60 + self.second as u32
+    }
 }
 
 /**
@@ -140,6 +165,22 @@ pub fn get_current_time() -> Time {
     calculate_datetime(seconds)
 }
 
+/**
+ * 获取当前系统时间的时间戳（从默认时间开始经过的秒数）
+ * 文件系统的inode中保存的是这个时间戳
+ */
+pub fn get_current_timestamp() -> u32 {
+    SYSTEM_TIME_SECONDS.load(Ordering::SeqCst) as u32
+}
+
+/**
+ * 把Unix时间戳（从1970-01-01 00:00:00开始的秒数），转成系统的时间戳（从默认时间开始经过的秒数）
+ * 早于默认时间的，时间戳是0
+ */
+pub fn from_unix_timestamp(unix_timestamp: u32) -> u32 {
+    unix_timestamp.saturating_sub(DEFAULT_UNIX_TIMESTAMP)
+}
+
 /**
  * 更新系统时间（由定时器中断调用）
  * 由于TIMER_INTR_FREQUENCY是100Hz，每100次中断为1秒
@@ -148,6 +189,7 @@ pub fn update_system_time() {
     static mut TICK_COUNT: u32 = 0;
     const TICKS_PER_SECOND: u32 = 100; // 与TIMER_INTR_FREQUENCY一致
     
+    SYSTEM_TICKS.fetch_add(1, Ordering::SeqCst);
     unsafe {
         TICK_COUNT += 1;
         if TICK_COUNT >= TICKS_PER_SECOND {
@@ -157,6 +199,13 @@ pub fn update_system_time() {
     }
 }
 
+/**
+ * 系统启动之后，经过的定时器中断次数（每秒100次）
+ */
+pub fn get_system_ticks() -> u64 {
+    SYSTEM_TICKS.load(Ordering::SeqCst)
+}
+
 /**
  * 初始化时间模块
  */
@@ -6,6 +6,66 @@ use crate::{filesystem::{FileDescriptor, FileDescriptorType, StdFileDescriptor},
 
 pub type TaskExitStatus = u8;
 
+/**
+ * 导致用户进程被终止的CPU异常（值为异常号）。跟exit的退出码分开记录，不会跟正常的exit(N)混淆
+ * exceptions: <https://wiki.osdev.org/Exceptions>
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExceptionExitStatus {
+    /**
+     * 非法操作码
+     */
+    InvalidOpcode = 6,
+    /**
+     * 保护异常
+     */
+    GeneralProtection = 13,
+    /**
+     * 缺页异常（访问了非法的地址）
+     */
+    PageFault = 14,
+}
+
+impl ExceptionExitStatus {
+    pub fn get_name(&self) -> &str {
+        match self {
+            ExceptionExitStatus::InvalidOpcode => "invalid opcode",
+            ExceptionExitStatus::GeneralProtection => "general protection fault",
+            ExceptionExitStatus::PageFault => "page fault",
+        }
+    }
+}
+
+/**
+ * 父进程wait到的子进程状态
+ *   - exit_status: 子进程调用exit时指定的退出码
+ *   - killed_by: 子进程被CPU异常终止时，是哪个异常（此时没有退出码）
+ */
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TaskWaitStatus {
+    pub exit_status: Option<TaskExitStatus>,
+    pub killed_by: Option<ExceptionExitStatus>,
+}
+
+/**
+ * 当前用户进程引发了CPU异常，那么终止该进程（跟exit系统调用一样的退出流程）
+ *   异常记录在killed_by里，不占用退出码
+ *   不会返回到引发异常的地方（返回之后会再次引发同一个异常）
+ */
+#[inline(never)]
+pub fn exit_by_exception(exception: ExceptionExitStatus) {
+    let cur_task = &mut thread::current_thread().task_struct;
+    // 已经退出过了，资源不再重复释放
+    if cur_task.task_status != TaskStatus::TaskHanging {
+        cur_task.killed_by = Option::Some(exception);
+        self::do_exit(cur_task);
+    }
+    // 一直挂起，等待父进程回收。即使被唤醒了，也不能回到用户态
+    loop {
+        scheduler::block_thread(cur_task, TaskStatus::TaskHanging);
+    }
+}
+
 /**
  * exit系统调用。当某个用户进程调用exit，那么就需要释放这个用户进程的空间
  *   当前任务的所有资源都释放，只剩下当前任务的PCB还在
@@ -18,7 +78,14 @@ pub fn exit(status: TaskExitStatus) {
         return;
     }
     cur_task.exit_status = Option::Some(status);
-    
+    self::do_exit(cur_task);
+}
+
+/**
+ * 释放当前任务的资源，并且把任务挂起，等待父进程回收
+ */
+#[inline(never)]
+fn do_exit(cur_task: &mut TaskStruct) {
     cur_task.check_stack_magic("failed to exit");
 
     // 把管道关掉
@@ -3,5 +3,8 @@ mod wait;
 
 
 pub use exit::exit;
+pub use exit::exit_by_exception;
+pub use exit::ExceptionExitStatus;
 pub use exit::TaskExitStatus;
+pub use exit::TaskWaitStatus;
 pub use wait::wait;
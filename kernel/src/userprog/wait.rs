@@ -1,12 +1,12 @@
 use crate::{pid_allocator::Pid, scheduler, thread::{self, TaskStatus, TaskStruct}, thread_management};
 
-use super::TaskExitStatus;
+use super::TaskWaitStatus;
 
 /**
  * 父进程等待子进程，使用wait等待，然后给子进程“收尸”
  */
 #[inline(never)]
-pub fn wait() -> Option<(Pid, Option<TaskExitStatus>)> {
+pub fn wait() -> Option<(Pid, TaskWaitStatus)> {
     let cur_task = &mut thread::current_thread().task_struct;
     
     loop {
@@ -15,13 +15,16 @@ pub fn wait() -> Option<(Pid, Option<TaskExitStatus>)> {
         if hanging_child.is_some() {
             let hanging_child = hanging_child.unwrap();
             // 子进程的信息
-            let child_exit_status = hanging_child.exit_status;
+            let child_status = TaskWaitStatus {
+                exit_status: hanging_child.exit_status,
+                killed_by: hanging_child.killed_by,
+            };
             let child_pid = hanging_child.pid;
 
             // 把child给回收掉（收尸）
             thread_management::free_thread(hanging_child);
 
-            return Option::Some((child_pid, child_exit_status));
+            return Option::Some((child_pid, child_status));
         }
 
         // 如果连子进程都没有，结束
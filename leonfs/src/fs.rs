@@ -0,0 +1,670 @@
+use std::{fmt, mem::size_of, slice, time::{SystemTime, UNIX_EPOCH}};
+
+use kernel::filesystem::{self, constant, fsck::{self, FsckDevice, FsckReport}, inode::Inode, superblock::{MkfsError, MkfsOptions, SuperBlock}, DirEntry, DirEntryBlockIter, FileType, JournalHeader};
+use os_in_rust_common::{constants, domain::{InodeNo, LbaAddr}, utils};
+
+use crate::image::DiskImage;
+
+/**
+ * 一个扇区的数据
+ */
+type Sector = [u8; constants::DISK_SECTOR_SIZE];
+
+/**
+ * 内核的时间戳，是从2024-01-01 00:00:00开始的秒数（见kernel::time）
+ */
+const KERNEL_EPOCH_UNIX_SECS: u64 = 1704067200;
+
+/**
+ * 当前时间，转成内核的时间戳
+ */
+pub fn now() -> u32 {
+    let unix_secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    unix_secs.saturating_sub(KERNEL_EPOCH_UNIX_SECS) as u32
+}
+
+/**
+ * 把分区格式化成LeonFS。使用默认选项的时候，和内核安装文件系统（filesystem::install_filesystem_for_all_part）的结果一致
+ */
+pub fn mkfs(image: &mut DiskImage, part_lba: LbaAddr, part_secs: u32, options: &MkfsOptions) -> Result<Box<SuperBlock>, String> {
+    let super_block = Box::new(SuperBlock::new(part_lba, part_secs, options).map_err(|err| self::mkfs_error_msg(err, options))?);
+
+    // 超级块，位于引导块之后
+    image.write_sectors(part_lba.add(1), as_bytes(super_block.as_ref()))?;
+
+    // 日志区全部清零，并且写入空的日志头
+    let mut journal_buf = vec![0u8; super_block.journal_secs as usize * constants::DISK_SECTOR_SIZE];
+    journal_buf[..size_of::<u32>()].copy_from_slice(&constant::JOURNAL_MAGIC.to_le_bytes());
+    image.write_sectors(super_block.journal_lba, &journal_buf)?;
+
+    let mut buff = vec![0u8; super_block.inode_bitmap_secs as usize * constants::DISK_SECTOR_SIZE];
+    filesystem::build_inode_bitmap(&super_block, &mut buff);
+    image.write_sectors(super_block.inode_bitmap_lba, &buff)?;
+
+    let mut buff = vec![0u8; super_block.inode_table_secs as usize * constants::DISK_SECTOR_SIZE];
+    filesystem::build_inode_table(&super_block, &mut buff, self::now());
+    image.write_sectors(super_block.inode_table_lba, &buff)?;
+
+    let mut buff = vec![0u8; super_block.block_bitmap_secs as usize * constants::DISK_SECTOR_SIZE];
+    filesystem::build_block_bitmap(&super_block, &mut buff);
+    image.write_sectors(super_block.block_bitmap_lba, &buff)?;
+
+    let mut buff = vec![0u8; super_block.get_block_size()];
+    filesystem::build_root_dir(&super_block, &mut buff);
+    image.write_sectors(super_block.data_lba_start, &buff)?;
+    Result::Ok(super_block)
+}
+
+fn mkfs_error_msg(err: MkfsError, options: &MkfsOptions) -> String {
+    match err {
+        MkfsError::InvalidBlockSize => format!("invalid block size {}, only {} is supported", options.block_size, constant::MAX_MKFS_BLOCK_SIZE),
+        MkfsError::InvalidInodeCount => format!("invalid inode count {}, must be between {} and {}", options.inode_cnt, constant::MIN_INODE_CNT, constant::MAX_INODE_CNT),
+        MkfsError::TooManyReservedBlocks => format!("{} reserved blocks is not less than the number of data blocks", options.reserved_blocks),
+        MkfsError::PartitionTooSmall => "partition is too small for the filesystem metadata".to_string(),
+    }
+}
+
+/**
+ * 镜像文件中，一个已经格式化的LeonFS分区
+ * 位图读到内存中修改，调用flush之后才写回到镜像
+ */
+pub struct LeonFs {
+    image: DiskImage,
+    super_block: Box<SuperBlock>,
+    inode_bitmap: Vec<u8>,
+    block_bitmap: Vec<u8>,
+}
+
+impl LeonFs {
+    /**
+     * 打开分区中的文件系统
+     *  - 分区没有格式化（或者不是当前的格式），返回错误
+     *  - 日志区中有还没有完成检查点的事务，返回错误（需要先让内核挂载一次，重放日志）
+     */
+    pub fn open(mut image: DiskImage, part_lba: LbaAddr) -> Result<Self, String> {
+        let mut super_block: Box<SuperBlock> = Box::new(unsafe { std::mem::zeroed() });
+        image.read_sectors(part_lba.add(1), as_bytes_mut(super_block.as_mut()))?;
+        if !super_block.is_formatted() {
+            return Result::Err("partition does not contain a LeonFS filesystem, run mkfs first".to_string());
+        }
+        // 旧格式的inode只有内核支持（挂载的时候兼容），宿主机工具按照当前的inode格式读写
+        if super_block.has_legacy_inode() {
+            return Result::Err("partition uses the legacy inode format, which leonfs does not support".to_string());
+        }
+
+        let mut header: Box<JournalHeader> = Box::new(unsafe { std::mem::zeroed() });
+        image.read_sectors(super_block.journal_lba, as_bytes_mut(header.as_mut()))?;
+        if header.is_pending() {
+            return Result::Err("journal has a pending transaction, boot LeonOS once to replay it".to_string());
+        }
+
+        let mut inode_bitmap = vec![0u8; super_block.inode_bitmap_secs as usize * constants::DISK_SECTOR_SIZE];
+        image.read_sectors(super_block.inode_bitmap_lba, &mut inode_bitmap)?;
+        let mut block_bitmap = vec![0u8; super_block.block_bitmap_secs as usize * constants::DISK_SECTOR_SIZE];
+        image.read_sectors(super_block.block_bitmap_lba, &mut block_bitmap)?;
+        Result::Ok(Self { image, super_block, inode_bitmap, block_bitmap })
+    }
+
+    /**
+     * 位图写回到镜像
+     */
+    pub fn flush(&mut self) -> Result<(), String> {
+        self.image.write_sectors(self.super_block.inode_bitmap_lba, &self.inode_bitmap)?;
+        self.image.write_sectors(self.super_block.block_bitmap_lba, &self.block_bitmap)
+    }
+
+    /**
+     * 检查文件系统的一致性。repair：是否修复发现的问题
+     */
+    pub fn fsck(&mut self, repair: bool) -> Result<FsckReport, String> {
+        let mut workspace = vec![0u32; fsck::workspace_words(&self.super_block)];
+        let mut dev = ImageDevice { image: &mut self.image, error: Option::None };
+        let report = fsck::check(&mut dev, &self.super_block, &mut workspace, repair, self::now());
+        if let Option::Some(err) = dev.error {
+            return Result::Err(err);
+        }
+        // 修复的时候重建了位图，重新读取
+        if report.repaired {
+            self.image.read_sectors(self.super_block.inode_bitmap_lba, &mut self.inode_bitmap)?;
+            self.image.read_sectors(self.super_block.block_bitmap_lba, &mut self.block_bitmap)?;
+        }
+        Result::Ok(report)
+    }
+
+    /**
+     * 根据绝对路径，找到对应的inode。不跟随符号链接
+     */
+    pub fn lookup(&mut self, path: &str) -> Result<Inode, String> {
+        if !path.starts_with('/') {
+            return Result::Err(format!("{}: path must be absolute", path));
+        }
+        let mut inode = self.read_inode(self.super_block.root_inode_no)?;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            if FileType::from_mode(inode.i_mode) != FileType::Directory {
+                return Result::Err(format!("{}: not a directory", path));
+            }
+            let entry = self.find_entry(&mut inode, name)?;
+            if entry.is_none() {
+                return Result::Err(format!("{}: no such file or directory", path));
+            }
+            inode = self.read_inode(entry.unwrap().i_no)?;
+        }
+        Result::Ok(inode)
+    }
+
+    /**
+     * 读取目录中所有的目录项（包括.和..）。数据块中的每个扇区，都独立存放目录项
+     */
+    pub fn read_dir(&mut self, dir_inode: &mut Inode) -> Result<Vec<DirEntry>, String> {
+        let mut entries = Vec::new();
+        let mut buf: Sector = [0; constants::DISK_SECTOR_SIZE];
+        for block_idx in 0..constant::INODE_CACHED_DATA_SECS {
+            let block_lba = self.get_block(dir_inode, block_idx, false)?;
+            if block_lba.is_empty() {
+                continue;
+            }
+            for sec_idx in 0..self.super_block.sec_per_block() {
+                self.image.read_sectors(block_lba.add(sec_idx as u32), &mut buf)?;
+                entries.extend(DirEntryBlockIter::new(&buf).map(|(_, _, entry)| entry).filter(|entry| !entry.is_empty()));
+            }
+        }
+        Result::Ok(entries)
+    }
+
+    /**
+     * 读取文件的全部数据。没有分配的数据块（空洞）读出来是0
+     */
+    pub fn read_file(&mut self, inode: &mut Inode) -> Result<Vec<u8>, String> {
+        let file_size = inode.i_size as usize;
+        let block_size = self.super_block.get_block_size();
+        let mut data = vec![0u8; utils::div_ceil(file_size as u32, block_size as u32) as usize * block_size];
+        for (block_idx, block) in data.chunks_mut(block_size).enumerate() {
+            let block_lba = self.get_block(inode, block_idx, false)?;
+            if !block_lba.is_empty() {
+                self.image.read_sectors(block_lba, block)?;
+            }
+        }
+        data.truncate(file_size);
+        Result::Ok(data)
+    }
+
+    /**
+     * 在路径file_path上，创建一个普通文件，内容是data。文件已经存在，返回错误
+     */
+    pub fn create_file(&mut self, file_path: &str, data: &[u8]) -> Result<(), String> {
+        let (parent_path, file_name) = match file_path.trim_end_matches('/').rsplit_once('/') {
+            Option::Some((parent, name)) => (if parent.is_empty() { "/" } else { parent }, name),
+            Option::None => return Result::Err(format!("{}: path must be absolute", file_path)),
+        };
+        if file_name.is_empty() || file_name == "." || file_name == ".." || file_name.len() > constant::MAX_FILE_NAME {
+            return Result::Err(format!("{}: invalid file name", file_path));
+        }
+        let mut parent_inode = self.lookup(parent_path)?;
+        if FileType::from_mode(parent_inode.i_mode) != FileType::Directory {
+            return Result::Err(format!("{}: not a directory", parent_path));
+        }
+        if self.find_entry(&mut parent_inode, file_name)?.is_some() {
+            return Result::Err(format!("{}: file exists", file_path));
+        }
+        let block_size = self.super_block.get_block_size();
+        let max_size = self.super_block.max_data_blocks() * block_size;
+        if data.len() > max_size || data.len() > u32::MAX as usize {
+            return Result::Err(format!("{}: file too large, at most {} bytes", file_path, max_size));
+        }
+
+        let i_no = self.apply_inode()?;
+        let mut inode = Inode::new(i_no);
+        inode.i_mode = FileType::Regular.to_mode();
+        inode.i_nlink = 1;
+        let now = self::now();
+        inode.i_ctime = now;
+        inode.i_mtime = now;
+        inode.i_atime = now;
+        let mut buf = vec![0u8; block_size];
+        for (block_idx, chunk) in data.chunks(block_size).enumerate() {
+            let block_lba = self.get_block(&mut inode, block_idx, true)?;
+            buf.fill(0);
+            buf[..chunk.len()].copy_from_slice(chunk);
+            self.image.write_sectors(block_lba, &buf)?;
+        }
+        inode.i_size = data.len() as u32;
+        self.write_inode(&inode)?;
+
+        self.add_entry(&mut parent_inode, &DirEntry::new(i_no, file_name, FileType::Regular))?;
+        self.flush()
+    }
+
+    /**
+     * 在目录中加入一个目录项。目录的数据块都放不下了，申请一个新的数据块
+     *  - 和内核一样，目录项放在数据块的某个扇区中，不跨扇区
+     *  - 宿主机工具不受保留数据块的限制（保留数据块只限制内核）
+     */
+    fn add_entry(&mut self, dir_inode: &mut Inode, entry: &DirEntry) -> Result<(), String> {
+        let mut buf: Sector = [0; constants::DISK_SECTOR_SIZE];
+        let mut free_idx = Option::None;
+        let mut inserted = false;
+        for block_idx in 0..constant::INODE_CACHED_DATA_SECS {
+            let block_lba = self.get_block(dir_inode, block_idx, false)?;
+            if block_lba.is_empty() {
+                free_idx = free_idx.or(Option::Some(block_idx));
+                continue;
+            }
+            for sec_idx in 0..self.super_block.sec_per_block() {
+                let sec_lba = block_lba.add(sec_idx as u32);
+                self.image.read_sectors(sec_lba, &mut buf)?;
+                if filesystem::insert_entry(&mut buf, entry) {
+                    self.image.write_sectors(sec_lba, &buf)?;
+                    inserted = true;
+                    break;
+                }
+            }
+            if inserted {
+                break;
+            }
+        }
+        if !inserted {
+            let block_idx = free_idx.ok_or_else(|| "directory is full".to_string())?;
+            let block_lba = self.get_block(dir_inode, block_idx, true)?;
+            // 新的数据块，目录项放在第一个扇区，其他扇区清零
+            let mut block_buf = vec![0u8; self.super_block.get_block_size()];
+            filesystem::insert_entry((&mut block_buf[..constants::DISK_SECTOR_SIZE]).try_into().unwrap(), entry);
+            self.image.write_sectors(block_lba, &block_buf)?;
+        }
+        dir_inode.i_size += filesystem::entry_rec_len(entry.get_name().len()) as u32;
+        self.write_inode(dir_inode)
+    }
+
+    /**
+     * 在目录中找名称为name的目录项
+     */
+    fn find_entry(&mut self, dir_inode: &mut Inode, name: &str) -> Result<Option<DirEntry>, String> {
+        Result::Ok(self.read_dir(dir_inode)?.into_iter().find(|entry| entry.get_name() == name))
+    }
+
+    /**
+     * inode在inode数组中的位置：(所在扇区的LBA地址, 在扇区中的偏移量, 跨越的扇区数量)
+     */
+    fn locate_inode(&self, i_no: InodeNo) -> (LbaAddr, usize, usize) {
+        let bytes_off = i_no.get_data() as usize * self.super_block.get_inode_size();
+        let lba = self.super_block.inode_table_lba.add((bytes_off / constants::DISK_SECTOR_SIZE) as u32);
+        let off_in_sec = bytes_off % constants::DISK_SECTOR_SIZE;
+        let sec_cnt = if off_in_sec + size_of::<Inode>() > constants::DISK_SECTOR_SIZE { 2 } else { 1 };
+        (lba, off_in_sec, sec_cnt)
+    }
+
+    fn read_inode(&mut self, i_no: InodeNo) -> Result<Inode, String> {
+        let (lba, off, sec_cnt) = self.locate_inode(i_no);
+        let mut buf = vec![0u8; sec_cnt * constants::DISK_SECTOR_SIZE];
+        self.image.read_sectors(lba, &mut buf)?;
+        Result::Ok(unsafe { (buf.as_ptr().add(off) as *const Inode).read_unaligned() })
+    }
+
+    fn write_inode(&mut self, inode: &Inode) -> Result<(), String> {
+        let (lba, off, sec_cnt) = self.locate_inode(inode.i_no);
+        let mut buf = vec![0u8; sec_cnt * constants::DISK_SECTOR_SIZE];
+        self.image.read_sectors(lba, &mut buf)?;
+        unsafe { (buf.as_mut_ptr().add(off) as *mut Inode).write_unaligned(*inode) };
+        self.image.write_sectors(lba, &buf)
+    }
+
+    /**
+     * 找到inode第block_idx个数据块的LBA地址
+     *  - apply: 如果途经的间接块、最终的数据块不存在，是否申请。不申请并且不存在，返回空地址
+     */
+    fn get_block(&mut self, inode: &mut Inode, block_idx: usize, apply: bool) -> Result<LbaAddr, String> {
+        if block_idx >= self.super_block.max_data_blocks() {
+            return Result::Err("file too large".to_string());
+        }
+        if block_idx < constant::INODE_DIRECT_DATA_SECS {
+            let mut direct_sectors = inode.direct_sectors;
+            if direct_sectors[block_idx].is_empty() && apply {
+                direct_sectors[block_idx] = self.apply_block()?;
+                inode.direct_sectors = direct_sectors;
+            }
+            return Result::Ok(direct_sectors[block_idx]);
+        }
+        // 一级、二级、三级间接块树，依次往后排
+        let mut idx_in_tree = block_idx - constant::INODE_DIRECT_DATA_SECS;
+        if idx_in_tree < constant::LBA_PER_BLOCK {
+            let mut root_lba = inode.indirect_sector;
+            let res = self.walk_indirect_tree(&mut root_lba, 1, idx_in_tree, apply);
+            inode.indirect_sector = root_lba;
+            return res;
+        }
+        idx_in_tree -= constant::LBA_PER_BLOCK;
+        if idx_in_tree < constant::LBA_PER_BLOCK * constant::LBA_PER_BLOCK {
+            let mut root_lba = inode.double_indirect_sector;
+            let res = self.walk_indirect_tree(&mut root_lba, 2, idx_in_tree, apply);
+            inode.double_indirect_sector = root_lba;
+            return res;
+        }
+        idx_in_tree -= constant::LBA_PER_BLOCK * constant::LBA_PER_BLOCK;
+        let mut root_lba = inode.triple_indirect_sector;
+        let res = self.walk_indirect_tree(&mut root_lba, 3, idx_in_tree, apply);
+        inode.triple_indirect_sector = root_lba;
+        res
+    }
+
+    /**
+     * 在一棵level层的间接块树中，逐级找到第idx_in_tree个数据块。和内核inode::walk_indirect_tree一致
+     */
+    fn walk_indirect_tree(&mut self, root_lba: &mut LbaAddr, level: u32, idx_in_tree: usize, apply: bool) -> Result<LbaAddr, String> {
+        if root_lba.is_empty() {
+            if !apply {
+                return Result::Ok(LbaAddr::empty());
+            }
+            *root_lba = self.apply_index_block()?;
+        }
+        let mut buf: Sector = [0; constants::DISK_SECTOR_SIZE];
+        let mut cur_block_lba = *root_lba;
+        let mut idx_in_tree = idx_in_tree;
+        for cur_level in (1..=level).rev() {
+            let blocks_per_entry = constant::LBA_PER_BLOCK.pow(cur_level - 1);
+            let entry_idx = idx_in_tree / blocks_per_entry;
+            idx_in_tree %= blocks_per_entry;
+
+            self.image.read_sectors(cur_block_lba, &mut buf)?;
+            let lba_list = unsafe { slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut LbaAddr, constant::LBA_PER_BLOCK) };
+            if lba_list[entry_idx].is_empty() {
+                if !apply {
+                    return Result::Ok(LbaAddr::empty());
+                }
+                // 最后一层是数据块，其他层是间接块
+                lba_list[entry_idx] = if cur_level == 1 { self.apply_block()? } else { self.apply_index_block()? };
+                self.image.write_sectors(cur_block_lba, &buf)?;
+            }
+            cur_block_lba = lba_list[entry_idx];
+        }
+        Result::Ok(cur_block_lba)
+    }
+
+    /**
+     * 申请一个间接块，并且清零。和内核一样，间接块只使用第一个扇区
+     */
+    fn apply_index_block(&mut self) -> Result<LbaAddr, String> {
+        let block_lba = self.apply_block()?;
+        self.image.write_sectors(block_lba, &[0u8; constants::DISK_SECTOR_SIZE])?;
+        Result::Ok(block_lba)
+    }
+
+    /**
+     * 从块位图中申请一个数据块
+     */
+    fn apply_block(&mut self) -> Result<LbaAddr, String> {
+        let bit_idx = apply_bit(&mut self.block_bitmap, self.super_block.data_blocks() as usize)
+            .ok_or_else(|| "no free data block".to_string())?;
+        Result::Ok(self.super_block.data_lba_start.add((bit_idx * self.super_block.sec_per_block()) as u32))
+    }
+
+    /**
+     * 从inode位图中申请一个inode
+     */
+    fn apply_inode(&mut self) -> Result<InodeNo, String> {
+        let bit_idx = apply_bit(&mut self.inode_bitmap, self.super_block.inode_cnt as usize)
+            .ok_or_else(|| "no free inode".to_string())?;
+        Result::Ok(InodeNo::new(bit_idx as u32))
+    }
+}
+
+/**
+ * fsck读写镜像文件。读写失败的时候记录第一个错误，检查结束之后返回给调用方
+ */
+struct ImageDevice<'a> {
+    image: &'a mut DiskImage,
+    error: Option<String>,
+}
+
+impl FsckDevice for ImageDevice<'_> {
+    fn read_sector(&mut self, lba: LbaAddr, buf: &mut [u8]) {
+        if let Result::Err(err) = self.image.read_sectors(lba, buf) {
+            buf.fill(0);
+            self.error.get_or_insert(err);
+        }
+    }
+
+    fn write_sector(&mut self, lba: LbaAddr, buf: &[u8]) {
+        if let Result::Err(err) = self.image.write_sectors(lba, buf) {
+            self.error.get_or_insert(err);
+        }
+    }
+
+    fn report(&mut self, msg: fmt::Arguments) {
+        println!("{}", msg);
+    }
+}
+
+/**
+ * 在位图的前bit_cnt位中，找一个空闲位，设置为已占用
+ */
+fn apply_bit(bitmap: &mut [u8], bit_cnt: usize) -> Option<usize> {
+    let bit_idx = (0..bit_cnt.min(bitmap.len() * 8)).find(|bit_idx| bitmap[bit_idx / 8] & (1 << (bit_idx % 8)) == 0)?;
+    bitmap[bit_idx / 8] |= 1 << (bit_idx % 8);
+    Option::Some(bit_idx)
+}
+
+fn as_bytes<T>(value: &T) -> &[u8] {
+    unsafe { slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+}
+
+fn as_bytes_mut<T>(value: &mut T) -> &mut [u8] {
+    unsafe { slice::from_raw_parts_mut(value as *mut T as *mut u8, size_of::<T>()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use kernel::filesystem::{constant, fsck::FsckReport, inode::Inode, superblock::{MkfsOptions, SuperBlock}, FileType};
+    use os_in_rust_common::domain::LbaAddr;
+
+    use crate::image::DiskImage;
+
+    use super::LeonFs;
+
+    const PART_LBA: u32 = 63;
+    const PART_SECS: u32 = 8192;
+
+    /**
+     * 在内存中的镜像上格式化一个分区，并且打开
+     */
+    fn new_fs(block_size: u32) -> LeonFs {
+        let mut image = DiskImage::in_memory(PART_LBA + PART_SECS);
+        let mut options = MkfsOptions::default_for(PART_SECS);
+        options.block_size = block_size;
+        super::mkfs(&mut image, LbaAddr::new(PART_LBA), PART_SECS, &options).unwrap();
+        LeonFs::open(image, LbaAddr::new(PART_LBA)).unwrap()
+    }
+
+    /**
+     * 先只检查（两次结果一样，说明没有修改镜像），再修复，最后再检查一次应该没有问题了。返回只检查的结果
+     */
+    fn check_and_repair(fs: &mut LeonFs) -> FsckReport {
+        let report = fs.fsck(false).unwrap();
+        assert!(!report.repaired);
+        assert_eq!(fs.fsck(false).unwrap().problems(), report.problems());
+
+        let repaired = fs.fsck(true).unwrap();
+        assert!(repaired.repaired);
+        assert_eq!(repaired.problems(), report.problems());
+
+        let after = fs.fsck(false).unwrap();
+        assert_eq!(after.problems(), 0, "{:?}", after);
+        report
+    }
+
+    fn read(fs: &mut LeonFs, path: &str) -> Vec<u8> {
+        let mut inode = fs.lookup(path).unwrap();
+        fs.read_file(&mut inode).unwrap()
+    }
+
+    /**
+     * 数据块在块位图中是第几位
+     */
+    fn block_bit(fs: &LeonFs, block_lba: LbaAddr) -> usize {
+        (block_lba.get_lba() - fs.super_block.data_lba_start.get_lba()) as usize / fs.super_block.sec_per_block()
+    }
+
+    fn get_bit(bitmap: &[u8], idx: usize) -> bool {
+        bitmap[idx / 8] & (1 << (idx % 8)) != 0
+    }
+
+    fn set_bit(bitmap: &mut [u8], idx: usize, value: bool) {
+        if value { bitmap[idx / 8] |= 1 << (idx % 8) } else { bitmap[idx / 8] &= !(1 << (idx % 8)) }
+    }
+
+    /**
+     * 旧格式的inode（超级块中inode_size是0）可以挂载，只能使用直接块和一级间接块
+     * 没有日志区的更早的格式，魔数正确但是不能挂载（启动的时候也不会被格式化）
+     */
+    #[test]
+    fn test_legacy_layout() {
+        let fs = new_fs(1024);
+        let mut super_block: SuperBlock = unsafe { std::ptr::read(fs.super_block.as_ref()) };
+        assert!(super_block.is_formatted() && !super_block.has_legacy_inode());
+        assert_eq!(super_block.max_data_blocks(), constant::INODE_MAX_DATA_SECS);
+
+        super_block.inode_size = 0;
+        assert!(super_block.is_formatted() && super_block.has_legacy_inode());
+        assert_eq!(super_block.get_inode_size(), constant::LEGACY_INODE_SIZE);
+        assert_eq!(super_block.max_data_blocks(), constant::INODE_CACHED_DATA_SECS);
+
+        super_block.journal_secs = 0;
+        assert!(super_block.has_magic() && !super_block.is_formatted());
+    }
+
+    /**
+     * 刚格式化、拷贝了文件的文件系统，没有问题
+     */
+    #[test]
+    fn test_fsck_clean() {
+        let mut fs = new_fs(1024);
+        fs.create_file("/a.txt", &[b'a'; 3000]).unwrap();
+        let report = fs.fsck(true).unwrap();
+        assert_eq!(report.problems(), 0, "{:?}", report);
+        assert!(!report.repaired);
+    }
+
+    /**
+     * 位图中占用、但是没有目录项引用的inode，修复之后放到/lost+found下，数据不变
+     */
+    #[test]
+    fn test_fsck_orphan() {
+        let mut fs = new_fs(1024);
+        fs.create_file("/kept.txt", b"kept").unwrap();
+
+        let data = [b'o'; 1500];
+        let i_no = fs.apply_inode().unwrap();
+        let mut inode = Inode::new(i_no);
+        inode.i_mode = FileType::Regular.to_mode();
+        inode.i_nlink = 1;
+        inode.i_size = data.len() as u32;
+        for (block_idx, chunk) in data.chunks(fs.super_block.get_block_size()).enumerate() {
+            let block_lba = fs.get_block(&mut inode, block_idx, true).unwrap();
+            let mut buf = vec![0u8; fs.super_block.get_block_size()];
+            buf[..chunk.len()].copy_from_slice(chunk);
+            fs.image.write_sectors(block_lba, &buf).unwrap();
+        }
+        fs.write_inode(&inode).unwrap();
+        fs.flush().unwrap();
+
+        let report = check_and_repair(&mut fs);
+        assert_eq!(report.orphans, 1);
+        assert_eq!(report.problems(), 1, "{:?}", report);
+
+        let path = format!("/{}/#{}", constant::LOST_FOUND_DIR_NAME, i_no.get_data());
+        let found = fs.lookup(&path).unwrap();
+        let i_nlink = found.i_nlink;
+        assert_eq!(i_nlink, 1);
+        assert_eq!(read(&mut fs, &path), data);
+        assert_eq!(read(&mut fs, "/kept.txt"), b"kept");
+    }
+
+    /**
+     * 两个文件引用同一个数据块：先访问到的文件保留，后一个文件的块地址被清掉，它原来的块在位图中释放
+     */
+    #[test]
+    fn test_fsck_dup_blocks() {
+        let mut fs = new_fs(1024);
+        fs.create_file("/a.txt", &[b'a'; 1024]).unwrap();
+        fs.create_file("/b.txt", &[b'b'; 1024]).unwrap();
+        let a_inode = fs.lookup("/a.txt").unwrap();
+        let mut b_inode = fs.lookup("/b.txt").unwrap();
+        let (a_sectors, mut b_sectors) = (a_inode.direct_sectors, b_inode.direct_sectors);
+        let b_old_block = b_sectors[0];
+        b_sectors[0] = a_sectors[0];
+        b_inode.direct_sectors = b_sectors;
+        fs.write_inode(&b_inode).unwrap();
+
+        let report = check_and_repair(&mut fs);
+        assert_eq!(report.dup_blocks, 1);
+        // b原来的块没有被引用了，但是在位图中还是占用的
+        assert_eq!(report.block_bitmap_errs, 1);
+        assert_eq!(report.problems(), 2, "{:?}", report);
+
+        assert_eq!(read(&mut fs, "/a.txt"), [b'a'; 1024]);
+        let b_sectors = fs.lookup("/b.txt").unwrap().direct_sectors;
+        assert!(b_sectors[0].is_empty());
+        assert!(get_bit(&fs.block_bitmap, block_bit(&fs, a_sectors[0])));
+        assert!(!get_bit(&fs.block_bitmap, block_bit(&fs, b_old_block)));
+    }
+
+    /**
+     * 普通文件的i_size没有覆盖最后一个数据块，目录的i_size和目录项不一致，修复之后都改正
+     */
+    #[test]
+    fn test_fsck_bad_size() {
+        let mut fs = new_fs(1024);
+        fs.create_file("/a.txt", &[b'a'; 3000]).unwrap();
+        let mut file_inode = fs.lookup("/a.txt").unwrap();
+        file_inode.i_size = 100;
+        fs.write_inode(&file_inode).unwrap();
+        let mut root_inode = fs.lookup("/").unwrap();
+        let root_size = root_inode.i_size;
+        root_inode.i_size = root_size + 7;
+        fs.write_inode(&root_inode).unwrap();
+
+        let report = check_and_repair(&mut fs);
+        assert_eq!(report.bad_sizes, 2);
+        assert_eq!(report.problems(), 2, "{:?}", report);
+
+        // 文件的大小，改成覆盖到最后一个数据块
+        let file_size = fs.lookup("/a.txt").unwrap().i_size;
+        assert_eq!(file_size, 3 * 1024);
+        let repaired_root_size = fs.lookup("/").unwrap().i_size;
+        assert_eq!(repaired_root_size, root_size);
+        assert_eq!(&read(&mut fs, "/a.txt")[..3000], [b'a'; 3000]);
+    }
+
+    /**
+     * 位图和实际的使用情况不一致：占用的inode、块被标记为空闲，空闲的被标记为占用。修复之后按照遍历结果重建
+     */
+    #[test]
+    fn test_fsck_stale_bitmaps() {
+        let mut fs = new_fs(1024);
+        fs.create_file("/a.txt", &[b'a'; 1024]).unwrap();
+        let a_inode = fs.lookup("/a.txt").unwrap();
+        let (a_no, a_sectors) = (a_inode.i_no.get_data() as usize, a_inode.direct_sectors);
+        let a_block = block_bit(&fs, a_sectors[0]);
+        let free_inode = fs.super_block.inode_cnt as usize - 1;
+        let free_block = fs.super_block.data_blocks() as usize - 1;
+        assert!(!get_bit(&fs.inode_bitmap, free_inode) && !get_bit(&fs.block_bitmap, free_block));
+
+        set_bit(&mut fs.inode_bitmap, a_no, false);
+        set_bit(&mut fs.inode_bitmap, free_inode, true);
+        set_bit(&mut fs.block_bitmap, a_block, false);
+        set_bit(&mut fs.block_bitmap, free_block, true);
+        fs.flush().unwrap();
+
+        let report = check_and_repair(&mut fs);
+        assert_eq!(report.inode_bitmap_errs, 2);
+        assert_eq!(report.block_bitmap_errs, 2);
+        assert_eq!(report.orphans, 0);
+        assert_eq!(report.problems(), 4, "{:?}", report);
+
+        assert!(get_bit(&fs.inode_bitmap, a_no) && !get_bit(&fs.inode_bitmap, free_inode));
+        assert!(get_bit(&fs.block_bitmap, a_block) && !get_bit(&fs.block_bitmap, free_block));
+        // 修复之后，新文件不会再占用a.txt的inode和数据块
+        fs.create_file("/b.txt", &[b'b'; 1024]).unwrap();
+        assert_ne!(fs.lookup("/b.txt").unwrap().i_no.get_data() as usize, a_no);
+        assert_eq!(read(&mut fs, "/a.txt"), [b'a'; 1024]);
+        assert_eq!(read(&mut fs, "/b.txt"), [b'b'; 1024]);
+    }
+}
@@ -0,0 +1,115 @@
+use std::{fs::File, io::{Read, Seek, SeekFrom, Write}, mem::size_of};
+#[cfg(test)]
+use std::io::Cursor;
+
+use kernel::device::BootSector;
+use os_in_rust_common::{constants, domain::LbaAddr};
+
+/**
+ * 镜像的存储：镜像文件，或者内存中的一段数据（测试用）
+ */
+trait Storage: Read + Write + Seek {}
+
+impl<T: Read + Write + Seek> Storage for T {}
+
+/**
+ * 硬盘镜像文件。按照扇区读写
+ */
+pub struct DiskImage {
+    file: Box<dyn Storage>,
+}
+
+impl DiskImage {
+    /**
+     * 以读写的方式打开一个镜像文件
+     */
+    pub fn open(path: &str) -> Result<Self, String> {
+        let file = File::options().read(true).write(true).open(path)
+            .map_err(|err| format!("failed to open image {}: {}", path, err))?;
+        Result::Ok(Self { file: Box::new(file) })
+    }
+
+    /**
+     * 在内存中创建一个全是0的镜像，大小是sec_cnt个扇区
+     */
+    #[cfg(test)]
+    pub fn in_memory(sec_cnt: u32) -> Self {
+        Self { file: Box::new(Cursor::new(vec![0u8; sec_cnt as usize * constants::DISK_SECTOR_SIZE])) }
+    }
+
+    /**
+     * 从lba开始，读取buf.len()个字节（扇区大小的整数倍）
+     */
+    pub fn read_sectors(&mut self, lba: LbaAddr, buf: &mut [u8]) -> Result<(), String> {
+        self.file.seek(SeekFrom::Start(lba.get_lba() as u64 * constants::DISK_SECTOR_SIZE as u64))
+            .and_then(|_| self.file.read_exact(buf))
+            .map_err(|err| format!("failed to read sector {}: {}", lba.get_lba(), err))
+    }
+
+    /**
+     * 把buf写入到lba开始的扇区（buf的长度是扇区大小的整数倍）
+     */
+    pub fn write_sectors(&mut self, lba: LbaAddr, buf: &[u8]) -> Result<(), String> {
+        self.file.seek(SeekFrom::Start(lba.get_lba() as u64 * constants::DISK_SECTOR_SIZE as u64))
+            .and_then(|_| self.file.write_all(buf))
+            .map_err(|err| format!("failed to write sector {}: {}", lba.get_lba(), err))
+    }
+
+    /**
+     * 找到编号为part_no的分区，返回(分区起始的LBA地址, 分区的扇区数量)
+     * 分区的编号和内核一致：0~3是主分区（分区表中的下标），逻辑分区从4开始编号
+     */
+    pub fn find_partition(&mut self, part_no: usize) -> Result<(LbaAddr, u32), String> {
+        let mbr = self.read_boot_sector(LbaAddr::new(0))?;
+        for (idx, part_entry) in mbr.part_table.iter().enumerate() {
+            if part_entry.is_empty() {
+                continue;
+            }
+            if !part_entry.is_extended() {
+                if idx == part_no {
+                    return Result::Ok((part_entry.start_lba, part_entry.sec_cnt));
+                }
+                continue;
+            }
+            let found = self.find_logical_partition(part_entry.start_lba, part_no)?;
+            if found.is_some() {
+                return Result::Ok(found.unwrap());
+            }
+        }
+        Result::Err(format!("partition {} not found", part_no))
+    }
+
+    /**
+     * 在总扩展分区中，按照内核扫描的顺序，找编号为part_no的逻辑分区
+     *  - main_ext_lba: 总扩展分区的起始地址。所有子扩展分区的LBA地址都基于该地址
+     */
+    fn find_logical_partition(&mut self, main_ext_lba: LbaAddr, part_no: usize) -> Result<Option<(LbaAddr, u32)>, String> {
+        let mut stack = vec![(main_ext_lba, 4usize)];
+        while let Option::Some((extend_part_lba, mut cur_part_no)) = stack.pop() {
+            let ebr = self.read_boot_sector(extend_part_lba)?;
+            for part_entry in ebr.part_table.iter() {
+                if part_entry.is_empty() {
+                    continue;
+                }
+                if !part_entry.is_extended() {
+                    if cur_part_no == part_no {
+                        return Result::Ok(Option::Some((extend_part_lba + part_entry.start_lba, part_entry.sec_cnt)));
+                    }
+                    cur_part_no += 1;
+                    continue;
+                }
+                stack.push((part_entry.start_lba + main_ext_lba, cur_part_no));
+            }
+        }
+        Result::Ok(Option::None)
+    }
+
+    /**
+     * 读取lba所在的引导扇区（MBR或者EBR）
+     */
+    fn read_boot_sector(&mut self, lba: LbaAddr) -> Result<Box<BootSector>, String> {
+        let mut buf = [0u8; size_of::<BootSector>()];
+        self.read_sectors(lba, &mut buf)?;
+        Result::Ok(Box::new(unsafe { (buf.as_ptr() as *const BootSector).read_unaligned() }))
+    }
+}
@@ -0,0 +1,283 @@
+mod image;
+mod fs;
+
+use std::process::ExitCode;
+
+use kernel::filesystem::{superblock::MkfsOptions, FileType};
+
+use crate::{fs::LeonFs, image::DiskImage};
+
+/**
+ * 宿主机上的LeonFS镜像工具：格式化镜像中的分区，列出目录，把文件拷贝进、拷贝出镜像
+ * 分区的编号和内核一致：0~3是主分区，逻辑分区从4开始（内核挂载的sdb4，就是4号分区）
+ */
+const USAGE: &str = "Usage: leonfs <image> <part_no> <command> [args]
+  part_no: partition number, same as the kernel: 0-3 primary, 4+ logical (sdb4 is 4)
+Commands:
+  mkfs [options]              format the partition
+    --inodes <n>              number of inodes (default: one per 16 KiB)
+    --block-size <bytes>      block size: only 1024 for now (default 1024)
+    --reserved <n>            data blocks the kernel never allocates (default 0)
+  fsck [--repair]             check the filesystem, optionally repair it
+  ls [path]                   list a directory (default /)
+  cp-in <host_file> <path>    copy a host file into the image
+  cp-out <path> <host_file>   copy a file out of the image";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match self::run(&args) {
+        Result::Ok(()) => ExitCode::SUCCESS,
+        Result::Err(err) => {
+            eprintln!("leonfs: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    if args.len() < 3 {
+        return Result::Err(USAGE.to_string());
+    }
+    let part_no: usize = args[1].parse().map_err(|_| USAGE.to_string())?;
+    let cmd_args: Vec<&str> = args[3..].iter().map(|arg| arg.as_str()).collect();
+    let mut image = DiskImage::open(&args[0])?;
+    let (part_lba, part_secs) = image.find_partition(part_no)?;
+
+    match (args[2].as_str(), cmd_args.as_slice()) {
+        ("mkfs", options) => {
+            let options = self::parse_mkfs_options(options, part_secs)?;
+            let super_block = fs::mkfs(&mut image, part_lba, part_secs, &options)?;
+            println!("formatted partition {}: lba {}, {} sectors, block size {}, {} blocks ({} reserved), {} inodes",
+                part_no, part_lba.get_lba(), part_secs, super_block.get_block_size(), super_block.data_blocks(), options.reserved_blocks, options.inode_cnt);
+            Result::Ok(())
+        },
+        ("fsck", []) => self::fsck(LeonFs::open(image, part_lba)?, false),
+        ("fsck", ["--repair"]) => self::fsck(LeonFs::open(image, part_lba)?, true),
+        ("ls", []) => self::ls(LeonFs::open(image, part_lba)?, "/"),
+        ("ls", [path]) => self::ls(LeonFs::open(image, part_lba)?, path),
+        ("cp-in", [host_file, path]) => self::copy_in(LeonFs::open(image, part_lba)?, host_file, path),
+        ("cp-out", [path, host_file]) => self::copy_out(LeonFs::open(image, part_lba)?, path, host_file),
+        _ => Result::Err(USAGE.to_string()),
+    }
+}
+
+/**
+ * 解析mkfs的选项。没有指定的选项，使用按照分区大小计算的默认值
+ */
+fn parse_mkfs_options(args: &[&str], part_secs: u32) -> Result<MkfsOptions, String> {
+    let mut options = MkfsOptions::default_for(part_secs);
+    if args.len() % 2 != 0 {
+        return Result::Err(USAGE.to_string());
+    }
+    for pair in args.chunks(2) {
+        let value: u32 = pair[1].parse().map_err(|_| format!("{}: invalid number {}", pair[0], pair[1]))?;
+        match pair[0] {
+            "--inodes" => options.inode_cnt = value,
+            "--block-size" => options.block_size = value,
+            "--reserved" => options.reserved_blocks = value,
+            _ => return Result::Err(USAGE.to_string()),
+        }
+    }
+    Result::Ok(options)
+}
+
+/**
+ * 检查文件系统。有没有修复的问题，返回错误（退出码非0）
+ */
+fn fsck(mut fs: LeonFs, repair: bool) -> Result<(), String> {
+    let report = fs.fsck(repair)?;
+    if report.problems() == 0 {
+        println!("filesystem is clean");
+        return Result::Ok(());
+    }
+    if report.repaired {
+        println!("{} problems repaired", report.problems());
+        return Result::Ok(());
+    }
+    Result::Err(format!("{} problems found, run fsck --repair to fix them", report.problems()))
+}
+
+/**
+ * 列出目录下的目录项。如果是文件，只列出文件自己
+ */
+fn ls(mut fs: LeonFs, path: &str) -> Result<(), String> {
+    let mut inode = fs.lookup(path)?;
+    if FileType::from_mode(inode.i_mode) != FileType::Directory {
+        let (i_no, i_size) = (inode.i_no.get_data(), inode.i_size);
+        println!("{} {:>6} {:>10} {}", self::type_sign(FileType::from_mode(inode.i_mode)), i_no, i_size, path);
+        return Result::Ok(());
+    }
+    for entry in fs.read_dir(&mut inode)? {
+        let name = entry.get_name();
+        if name == "." || name == ".." {
+            continue;
+        }
+        let entry_inode = fs.lookup(&format!("{}/{}", path.trim_end_matches('/'), name))?;
+        let i_size = entry_inode.i_size;
+        println!("{} {:>6} {:>10} {}", self::type_sign(entry.file_type), entry.i_no.get_data(), i_size, name);
+    }
+    Result::Ok(())
+}
+
+/**
+ * 把宿主机的文件拷贝到镜像中。path是已经存在的目录，那么拷贝到这个目录下，名称不变
+ */
+fn copy_in(mut fs: LeonFs, host_file: &str, path: &str) -> Result<(), String> {
+    let data = std::fs::read(host_file).map_err(|err| format!("failed to read {}: {}", host_file, err))?;
+    let mut target = path.to_string();
+    if let Result::Ok(inode) = fs.lookup(path) {
+        if FileType::from_mode(inode.i_mode) == FileType::Directory {
+            let file_name = std::path::Path::new(host_file).file_name().and_then(|name| name.to_str())
+                .ok_or_else(|| format!("{}: invalid file name", host_file))?;
+            target = format!("{}/{}", path.trim_end_matches('/'), file_name);
+        }
+    }
+    fs.create_file(&target, &data)?;
+    println!("copied {} -> {} ({} bytes)", host_file, target, data.len());
+    Result::Ok(())
+}
+
+/**
+ * 把镜像中的普通文件，拷贝到宿主机
+ */
+fn copy_out(mut fs: LeonFs, path: &str, host_file: &str) -> Result<(), String> {
+    let mut inode = fs.lookup(path)?;
+    if FileType::from_mode(inode.i_mode) != FileType::Regular {
+        return Result::Err(format!("{}: not a regular file", path));
+    }
+    let data = fs.read_file(&mut inode)?;
+    std::fs::write(host_file, &data).map_err(|err| format!("failed to write {}: {}", host_file, err))?;
+    println!("copied {} -> {} ({} bytes)", path, host_file, data.len());
+    Result::Ok(())
+}
+
+fn type_sign(file_type: FileType) -> char {
+    match file_type {
+        FileType::Directory => 'd',
+        FileType::Symlink => 'l',
+        FileType::CharDevice => 'c',
+        FileType::BlockDevice => 'b',
+        FileType::Regular => '-',
+        FileType::Unknown => '?',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use os_in_rust_common::{constants, domain::LbaAddr};
+
+    use crate::{fs::LeonFs, image::DiskImage};
+
+    const PART_LBA: u32 = 63;
+    const PART_SECS: u32 = 16384;
+
+    /**
+     * 临时目录，存放镜像文件和宿主机上的文件。测试结束的时候删除
+     */
+    struct TempDir {
+        path: PathBuf,
+    }
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("leonfs-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            Self { path }
+        }
+
+        fn file(&self, name: &str) -> String {
+            self.path.join(name).to_str().unwrap().to_string()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+
+    /**
+     * 创建一个镜像文件，MBR中只有一个主分区（0号分区）
+     */
+    fn create_image(path: &str) {
+        let mut data = vec![0u8; (PART_LBA + PART_SECS) as usize * constants::DISK_SECTOR_SIZE];
+        // 第一个分区表项：类型、起始的LBA地址、扇区数量
+        let entry = &mut data[446..446 + 16];
+        entry[4] = 0x83;
+        entry[8..12].copy_from_slice(&PART_LBA.to_le_bytes());
+        entry[12..16].copy_from_slice(&PART_SECS.to_le_bytes());
+        data[510] = 0x55;
+        data[511] = 0xaa;
+        fs::write(path, data).unwrap();
+    }
+
+    /**
+     * 执行一条leonfs命令，操作镜像中的0号分区
+     */
+    fn leonfs(image: &str, args: &[&str]) -> Result<(), String> {
+        let mut all_args = vec![image.to_string(), "0".to_string()];
+        all_args.extend(args.iter().map(|arg| arg.to_string()));
+        super::run(&all_args)
+    }
+
+    /**
+     * mkfs -> cp-in -> ls -> cp-out，拷贝出来的文件和拷贝进去的一样
+     *  - 大文件超过了直接块的数量，用到了间接块
+     */
+    fn round_trip(block_size: u32) {
+        let dir = TempDir::new(&format!("round-trip-{}", block_size));
+        let image = dir.file("disk.img");
+        create_image(&image);
+        leonfs(&image, &["mkfs", "--block-size", &block_size.to_string()]).unwrap();
+
+        let small: Vec<u8> = b"hello, leonos\n".to_vec();
+        let big: Vec<u8> = (0..block_size as usize * 20 + 123).map(|idx| (idx * 7 % 251) as u8).collect();
+        fs::write(dir.file("hello.txt"), &small).unwrap();
+        fs::write(dir.file("big.bin"), &big).unwrap();
+        fs::write(dir.file("empty"), []).unwrap();
+
+        // 目标是目录，使用宿主机文件的名称；否则使用指定的路径
+        leonfs(&image, &["cp-in", &dir.file("hello.txt"), "/"]).unwrap();
+        leonfs(&image, &["cp-in", &dir.file("big.bin"), "/copy.bin"]).unwrap();
+        leonfs(&image, &["cp-in", &dir.file("empty"), "/"]).unwrap();
+        assert!(leonfs(&image, &["cp-in", &dir.file("hello.txt"), "/hello.txt"]).is_err());
+
+        leonfs(&image, &["ls"]).unwrap();
+        leonfs(&image, &["ls", "/copy.bin"]).unwrap();
+        let mut fs = LeonFs::open(DiskImage::open(&image).unwrap(), LbaAddr::new(PART_LBA)).unwrap();
+        let mut root_inode = fs.lookup("/").unwrap();
+        let mut names: Vec<String> = fs.read_dir(&mut root_inode).unwrap().iter().map(|entry| entry.get_name().to_string()).collect();
+        names.sort();
+        assert_eq!(names, [".", "..", "copy.bin", "empty", "hello.txt"]);
+
+        for (path, expected) in [("/hello.txt", &small), ("/copy.bin", &big), ("/empty", &Vec::new())] {
+            let host_file = dir.file("out");
+            leonfs(&image, &["cp-out", path, &host_file]).unwrap();
+            assert_eq!(&fs::read(&host_file).unwrap(), expected, "{}", path);
+        }
+        assert!(leonfs(&image, &["cp-out", "/", &dir.file("out")]).is_err());
+        leonfs(&image, &["fsck"]).unwrap();
+    }
+
+    #[test]
+    fn test_round_trip_1k_blocks() {
+        round_trip(1024);
+    }
+
+    /**
+     * 间接块、目录块按照块大小组织之前，不能格式化更大的块
+     */
+    #[test]
+    fn test_mkfs_rejects_large_blocks() {
+        let dir = TempDir::new("large-blocks");
+        let image = dir.file("disk.img");
+        create_image(&image);
+        for block_size in ["512", "2048", "4096"] {
+            assert!(leonfs(&image, &["mkfs", "--block-size", block_size]).is_err());
+        }
+        leonfs(&image, &["mkfs", "--block-size", "1024"]).unwrap();
+    }
+}
@@ -5,9 +5,9 @@ use std::{
     mem::size_of,
 };
 
-use kernel::filesystem::{DirEntry, inode::{Inode, OpenedInode}, superblock::SuperBlock};
+use kernel::filesystem::{DirEntry, DirEntryBlockIter, inode::{Inode, OpenedInode}, superblock::SuperBlock};
 use os_in_rust_common::{constants, domain::LbaAddr};
-pub const DISK_FILE_PATH: &str = "/Users/jackson/MyProjects/rust/os-in-rust/build/hd80M.img";
+pub const DISK_FILE_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../build/hd80M.img");
 use lazy_static::lazy_static;
 
 const MAX_FILE_PER_FS: u32 = 4096;
@@ -136,11 +136,10 @@ fn find_entry_in_data_block(data_blocks: &[LbaAddr], entry_name: &str, disk_file
         }
         // 读取出数据块内容
         let indirect_data = self::read_disk(disk_file, *data_block_lba);
-        // 转成目录项
-        let dir_entry_list: &[DirEntry] = unsafe { slice::from_raw_parts(indirect_data.as_ptr() as *const DirEntry, indirect_data.len() / size_of::<DirEntry>()) };
-        let found_entry = dir_entry_list.iter().find(|entry| entry.get_name().eq(entry_name));
+        // 遍历扇区内的目录项
+        let found_entry = DirEntryBlockIter::new(&indirect_data).map(|(_, _, entry)| entry).find(|entry| !entry.is_empty() && entry.get_name().eq(entry_name));
         if found_entry.is_some() {
-            return Option::Some(*found_entry.unwrap());
+            return found_entry;
         }
     }
     return Option::None;
@@ -191,9 +190,7 @@ pub fn read_dir_entry(parent_dir: &str) -> Option<Vec<DirEntry>> {
         }
         // 读取出硬盘数据
         let block_data = self::read_disk(&mut disk_file, *data_block_lba);
-        let dir_entry_list = unsafe { slice::from_raw_parts(block_data.as_ptr() as *const DirEntry, block_data.len() / size_of::<DirEntry>()) };
-
-        all_dir_entry_list.append(&mut dir_entry_list.to_vec());
+        all_dir_entry_list.extend(DirEntryBlockIter::new(&block_data).map(|(_, _, entry)| entry));
     }
 
     return Option::Some(all_dir_entry_list);
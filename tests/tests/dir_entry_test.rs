@@ -0,0 +1,195 @@
+#[cfg(test)]
+mod tests {
+    use std::mem::size_of;
+
+    use kernel::filesystem::{constant, entry_rec_len, insert_entry, replace_entry, DirEntry, DirEntryBlockIter, DirEntryHeader, DirEntrySearchReq, FileType};
+    use os_in_rust_common::{constants, domain::InodeNo};
+
+    type Sector = [u8; constants::DISK_SECTOR_SIZE];
+
+    /**
+     * 扇区内所有的目录项：(偏移量, rec_len, 目录项)
+     */
+    fn entries(buf: &Sector) -> Vec<(usize, usize, DirEntry)> {
+        DirEntryBlockIter::new(buf).collect()
+    }
+
+    /**
+     * 扇区内正在使用的目录项名称
+     */
+    fn names(buf: &Sector) -> Vec<String> {
+        DirEntryBlockIter::new(buf).filter(|(_, _, entry)| !entry.is_empty()).map(|(_, _, entry)| entry.get_name().to_string()).collect()
+    }
+
+    /**
+     * 每个目录项都是对齐的，所有目录项的rec_len加起来正好是一个扇区
+     */
+    fn assert_well_formed(buf: &Sector) {
+        let mut expect_off = 0;
+        for (off, rec_len, entry) in entries(buf) {
+            assert_eq!(off, expect_off);
+            assert_eq!(rec_len % constant::DIR_ENTRY_ALIGN, 0);
+            if !entry.is_empty() {
+                assert!(rec_len >= entry_rec_len(entry.get_name().len()));
+            }
+            expect_off += rec_len;
+        }
+        assert_eq!(expect_off, constants::DISK_SECTOR_SIZE);
+    }
+
+    fn remove(buf: &mut Sector, name: &str) -> Option<DirEntry> {
+        replace_entry(buf, DirEntrySearchReq::build().entry_name(name), Option::None)
+    }
+
+    /**
+     * 名称为4个字节的第idx个文件
+     */
+    fn file_name(idx: usize) -> String {
+        format!("f{:03}", idx)
+    }
+
+    /**
+     * 用名称为4个字节的目录项，把扇区填满。返回放入的数量
+     */
+    fn fill_sector(buf: &mut Sector) -> usize {
+        let mut cnt = 0;
+        while insert_entry(buf, &DirEntry::new(InodeNo::new(cnt as u32 + 1), &file_name(cnt), FileType::Regular)) {
+            cnt += 1;
+        }
+        cnt
+    }
+
+    /**
+     * 目录项占用的字节数：头部 + 名称，按照DIR_ENTRY_ALIGN向上对齐
+     */
+    #[test]
+    fn test_rec_len_padding() {
+        let header_len = size_of::<DirEntryHeader>();
+        assert_eq!(header_len % constant::DIR_ENTRY_ALIGN, 0);
+        assert_eq!(entry_rec_len(1), header_len + constant::DIR_ENTRY_ALIGN);
+        assert_eq!(entry_rec_len(constant::DIR_ENTRY_ALIGN), header_len + constant::DIR_ENTRY_ALIGN);
+        assert_eq!(entry_rec_len(constant::DIR_ENTRY_ALIGN + 1), header_len + constant::DIR_ENTRY_ALIGN * 2);
+        for name_len in 1..=constant::MAX_FILE_NAME {
+            let rec_len = entry_rec_len(name_len);
+            assert_eq!(rec_len % constant::DIR_ENTRY_ALIGN, 0);
+            assert!(rec_len >= header_len + name_len && rec_len < header_len + name_len + constant::DIR_ENTRY_ALIGN);
+        }
+
+        // 最后一个目录项的rec_len，包括扇区后面所有空闲的空间
+        let mut buf: Sector = [0; constants::DISK_SECTOR_SIZE];
+        insert_entry(&mut buf, &DirEntry::new(InodeNo::new(0), ".", FileType::Directory));
+        insert_entry(&mut buf, &DirEntry::new(InodeNo::new(0), "..", FileType::Directory));
+        insert_entry(&mut buf, &DirEntry::new(InodeNo::new(1), "hello", FileType::Regular));
+        let list = entries(&buf);
+        assert_eq!(list.iter().map(|(off, rec_len, _)| (*off, *rec_len)).collect::<Vec<_>>(),
+            [(0, entry_rec_len(1)), (entry_rec_len(1), entry_rec_len(2)), (entry_rec_len(1) + entry_rec_len(2), constants::DISK_SECTOR_SIZE - entry_rec_len(1) - entry_rec_len(2))]);
+        assert_eq!(list[2].2.get_name(), "hello");
+        assert_eq!(list[2].2.file_type, FileType::Regular);
+        assert_well_formed(&buf);
+    }
+
+    /**
+     * 全是0的扇区，整个扇区是一个空闲的目录项
+     */
+    #[test]
+    fn test_empty_sector() {
+        let buf: Sector = [0; constants::DISK_SECTOR_SIZE];
+        let list = entries(&buf);
+        assert_eq!(list.len(), 1);
+        assert_eq!((list[0].0, list[0].1), (0, constants::DISK_SECTOR_SIZE));
+        assert!(list[0].2.is_empty());
+    }
+
+    /**
+     * 最长的名称（255个字节）可以完整地写入、读出；放不下的时候插入失败，扇区不变
+     */
+    #[test]
+    fn test_max_name_len() {
+        let long_name = "a".repeat(constant::MAX_FILE_NAME);
+        let other_name = "b".repeat(constant::MAX_FILE_NAME);
+        let mut buf: Sector = [0; constants::DISK_SECTOR_SIZE];
+        assert!(insert_entry(&mut buf, &DirEntry::new(InodeNo::new(7), &long_name, FileType::Regular)));
+        assert_eq!(names(&buf), [long_name.clone()]);
+        assert_eq!(entries(&buf)[0].2.i_no.get_data(), 7);
+
+        // 一个扇区放得下一个最长名称的目录项，放不下两个
+        assert!(entry_rec_len(constant::MAX_FILE_NAME) * 2 > constants::DISK_SECTOR_SIZE);
+        let before = buf;
+        assert!(!insert_entry(&mut buf, &DirEntry::new(InodeNo::new(8), &other_name, FileType::Regular)));
+        assert_eq!(buf, before);
+
+        // 短的名称，还可以放在后面
+        assert!(insert_entry(&mut buf, &DirEntry::new(InodeNo::new(9), "short", FileType::Regular)));
+        assert_eq!(names(&buf), [long_name, "short".to_string()]);
+        assert_well_formed(&buf);
+    }
+
+    /**
+     * 扇区放满之后，再也插入不了；剩余的不足一个目录项的空间，算在最后一个目录项里
+     */
+    #[test]
+    fn test_full_sector() {
+        let mut buf: Sector = [0; constants::DISK_SECTOR_SIZE];
+        let cnt = self::fill_sector(&mut buf);
+        let rec_len = entry_rec_len(file_name(0).len());
+        assert_eq!(cnt, constants::DISK_SECTOR_SIZE / rec_len);
+        assert_eq!(names(&buf), (0..cnt).map(file_name).collect::<Vec<_>>());
+
+        let list = entries(&buf);
+        assert_eq!(list.len(), cnt);
+        assert_eq!(list[cnt - 1].1, rec_len + constants::DISK_SECTOR_SIZE % rec_len);
+        assert_well_formed(&buf);
+
+        // 最短的名称也放不下了
+        let before = buf;
+        assert!(!insert_entry(&mut buf, &DirEntry::new(InodeNo::new(100), "x", FileType::Regular)));
+        assert_eq!(buf, before);
+    }
+
+    /**
+     * 删除目录项之后，空出来的空间可以被新的目录项使用
+     *  - 删除中间的目录项：空间合并到前一个目录项，新目录项放在原来的位置
+     *  - 删除第一个目录项：标记为空闲，新目录项直接使用
+     */
+    #[test]
+    fn test_reuse_after_delete() {
+        let mut buf: Sector = [0; constants::DISK_SECTOR_SIZE];
+        let cnt = self::fill_sector(&mut buf);
+        let rec_len = entry_rec_len(file_name(0).len());
+
+        let removed = remove(&mut buf, &file_name(10)).unwrap();
+        assert_eq!(removed.get_name(), file_name(10));
+        assert_eq!(removed.i_no.get_data(), 11);
+        assert!(remove(&mut buf, &file_name(10)).is_none());
+        assert_eq!(entries(&buf)[9].1, rec_len * 2);
+        assert_eq!(names(&buf).len(), cnt - 1);
+        assert_well_formed(&buf);
+
+        // 比空出来的位置长的名称放不下
+        assert!(!insert_entry(&mut buf, &DirEntry::new(InodeNo::new(200), "longer", FileType::Regular)));
+        assert!(insert_entry(&mut buf, &DirEntry::new(InodeNo::new(200), "new1", FileType::Regular)));
+        let list = entries(&buf);
+        assert_eq!((list[10].0, list[10].1), (10 * rec_len, rec_len));
+        assert_eq!(list[10].2.get_name(), "new1");
+        assert_eq!(list[9].1, rec_len);
+        assert_well_formed(&buf);
+
+        let removed = remove(&mut buf, &file_name(0)).unwrap();
+        assert_eq!(removed.get_name(), file_name(0));
+        let list = entries(&buf);
+        assert_eq!((list[0].0, list[0].1), (0, rec_len));
+        assert!(list[0].2.is_empty());
+        assert!(insert_entry(&mut buf, &DirEntry::new(InodeNo::new(201), "new2", FileType::Directory)));
+        let list = entries(&buf);
+        assert_eq!(list[0].2.get_name(), "new2");
+        assert_eq!(list[0].2.file_type, FileType::Directory);
+        assert_eq!(names(&buf).len(), cnt);
+        assert_well_formed(&buf);
+
+        // 删除最后一个目录项，剩余的空间也合并到前一个目录项
+        let last_rec_len = entries(&buf)[cnt - 1].1;
+        remove(&mut buf, &file_name(cnt - 1)).unwrap();
+        assert_eq!(entries(&buf)[cnt - 2].1, rec_len + last_rec_len);
+        assert_well_formed(&buf);
+    }
+}
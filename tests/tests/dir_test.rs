@@ -1,8 +1,7 @@
 mod test {
-    use core::slice;
     use std::{fs::File, io::{BufReader, Read, Seek}, mem::size_of};
 
-    use kernel::filesystem::{inode::{Inode, OpenedInode}, DirEntry, FileType};
+    use kernel::filesystem::{inode::{Inode, OpenedInode}, DirEntryBlockIter, DirEntryHeader, FileType};
     use os_in_rust_common::{constants, utils};
     use tests::file_system::{self, DISK_FILE_PATH};
 
@@ -19,8 +18,8 @@ mod test {
 
     #[test]
     fn entry_count_in_sector() {
-        println!("{}", constants::DISK_SECTOR_SIZE / size_of::<DirEntry>());
-        println!("{}", utils::div_ceil(constants::DISK_SECTOR_SIZE as u32, size_of::<DirEntry>() as u32) as usize );
+        println!("{}", constants::DISK_SECTOR_SIZE / size_of::<DirEntryHeader>());
+        println!("{}", utils::div_ceil(constants::DISK_SECTOR_SIZE as u32, size_of::<DirEntryHeader>() as u32) as usize );
     }
 
 
@@ -50,10 +49,10 @@ mod test {
             let mut reader = BufReader::new(&mut file);
             let mut buf = [0x0u8; constants::DISK_SECTOR_SIZE];
             reader.read(&mut buf).expect("failed to read file");
-            // 转成目录项的结构
-            let dir_list = unsafe { slice::from_raw_parts(buf.as_ptr() as *const DirEntry, buf.len() / size_of::<DirEntry>()) };
+            // 遍历扇区内的目录项
+            let dir_list = DirEntryBlockIter::new(&buf).map(|(_, _, entry)| entry);
             // 打印一下，看下结果
-            dir_list.iter().filter(|entry| !entry.is_empty() ).for_each(|e| {
+            dir_list.filter(|entry| !entry.is_empty() ).for_each(|e| {
                 println!("entry name: {}\n entry: {:?}\n  inode: {:?}\n", e.get_name(), e, inode_table[usize::from(e.i_no)]);
                 println!("------");
             });
@@ -0,0 +1,219 @@
+#[cfg(test)]
+mod tests {
+    use std::{mem::size_of, slice};
+
+    use kernel::{elf::{Elf32Header, Elf32ProgramHeader, ELF_MAX_PROGRAM_HEADER_CNT, PF_W, PT_LOAD}, exec::{self, ExecError}};
+    use os_in_rust_common::constants;
+
+    /**
+     * 一个合法的ELF头：x86的32位小端可执行文件，有一个程序头
+     */
+    fn valid_header() -> Elf32Header {
+        let mut header = Elf32Header::empty();
+        header.e_ident[..6].copy_from_slice(&[0x7f, b'E', b'L', b'F', 1, 1]);
+        header.e_type = 2;
+        header.e_machine = 3;
+        header.e_version = 1;
+        header.e_entry = constants::USER_PROCESS_ADDR_START as u32;
+        header.e_phoff = size_of::<Elf32Header>() as u32;
+        header.e_ehsize = size_of::<Elf32Header>() as u16;
+        header.e_phentsize = size_of::<Elf32ProgramHeader>() as u16;
+        header.e_phnum = 1;
+        header
+    }
+
+    fn header_bytes(header: &Elf32Header) -> Vec<u8> {
+        unsafe { slice::from_raw_parts(header as *const _ as *const u8, size_of::<Elf32Header>()) }.to_vec()
+    }
+
+    fn assert_bad_header(header: &Elf32Header) {
+        assert!(!header.is_valid());
+        assert!(matches!(exec::parse_header(&header_bytes(header)), Result::Err(ExecError::BadImage)));
+    }
+
+    /**
+     * 一个合法的段：从用户进程的起始地址开始，文件中的部分后面跟着.bss
+     */
+    fn valid_segment() -> Elf32ProgramHeader {
+        let mut ph = Elf32ProgramHeader::empty();
+        ph.p_type = PT_LOAD;
+        ph.p_offset = 0x1000;
+        ph.p_vaddr = constants::USER_PROCESS_ADDR_START as u32;
+        ph.p_filesz = 0x800;
+        ph.p_memsz = 0x2000;
+        ph.p_flags = PF_W;
+        ph
+    }
+
+    fn assert_bad_segment(ph: &Elf32ProgramHeader, file_size: usize) {
+        assert!(matches!(exec::check_segment(ph, file_size), Result::Err(ExecError::BadImage)));
+    }
+
+    #[test]
+    fn test_valid_header() {
+        let header = valid_header();
+        assert!(header.is_valid());
+        let parsed = exec::parse_header(&header_bytes(&header)).unwrap();
+        let entry = parsed.e_entry;
+        assert_eq!(entry as usize, constants::USER_PROCESS_ADDR_START);
+
+        // 文件比ELF头长，只解析开头的部分
+        let mut bytes = header_bytes(&header);
+        bytes.extend_from_slice(&[0xff; 64]);
+        assert!(exec::parse_header(&bytes).is_ok());
+    }
+
+    /**
+     * 魔数不对
+     */
+    #[test]
+    fn test_bad_magic() {
+        for idx in 0..4 {
+            let mut header = valid_header();
+            header.e_ident[idx] ^= 0xff;
+            assert_bad_header(&header);
+        }
+        assert_bad_header(&Elf32Header::empty());
+    }
+
+    /**
+     * 不是32位小端的文件
+     */
+    #[test]
+    fn test_bad_class() {
+        let mut header = valid_header();
+        // ELFCLASS64
+        header.e_ident[4] = 2;
+        assert_bad_header(&header);
+
+        let mut header = valid_header();
+        // ELFDATA2MSB
+        header.e_ident[5] = 2;
+        assert_bad_header(&header);
+    }
+
+    /**
+     * 不是x86的可执行文件
+     */
+    #[test]
+    fn test_bad_machine() {
+        let mut header = valid_header();
+        // EM_X86_64
+        header.e_machine = 0x3e;
+        assert_bad_header(&header);
+
+        let mut header = valid_header();
+        // ET_DYN
+        header.e_type = 3;
+        assert_bad_header(&header);
+    }
+
+    /**
+     * 程序头表项的大小不对，没有程序头，或者程序头太多
+     */
+    #[test]
+    fn test_bad_program_header_table() {
+        let mut header = valid_header();
+        header.e_phentsize = size_of::<Elf32ProgramHeader>() as u16 + 1;
+        assert_bad_header(&header);
+
+        let mut header = valid_header();
+        header.e_phnum = 0;
+        assert_bad_header(&header);
+
+        let mut header = valid_header();
+        header.e_phnum = ELF_MAX_PROGRAM_HEADER_CNT as u16;
+        assert!(header.is_valid());
+        header.e_phnum += 1;
+        assert_bad_header(&header);
+    }
+
+    /**
+     * 文件被截断了，不够一个ELF头
+     */
+    #[test]
+    fn test_truncated_header() {
+        let bytes = header_bytes(&valid_header());
+        for len in [0, 4, 16, bytes.len() - 1] {
+            assert!(matches!(exec::parse_header(&bytes[..len]), Result::Err(ExecError::BadImage)));
+        }
+    }
+
+    #[test]
+    fn test_valid_segment() {
+        let ph = valid_segment();
+        assert!(exec::check_segment(&ph, 0x1800).is_ok());
+
+        // 全是.bss的段，文件中没有数据
+        let mut ph = valid_segment();
+        ph.p_filesz = 0;
+        assert!(exec::check_segment(&ph, 0x1000).is_ok());
+
+        // 紧挨着栈的最大范围
+        let mut ph = valid_segment();
+        ph.p_memsz = 0x1000;
+        ph.p_vaddr = (constants::USER_STACK_BASE_ADDR - constants::USER_STACK_MAX_SIZE - 0x1000) as u32;
+        assert!(exec::check_segment(&ph, 0x1800).is_ok());
+    }
+
+    /**
+     * 段在文件中的结束位置溢出，或者超过了文件大小
+     */
+    #[test]
+    fn test_segment_offset_overflow() {
+        let mut ph = valid_segment();
+        ph.p_offset = 0xffff_f000;
+        ph.p_filesz = 0x2000;
+        ph.p_memsz = 0x2000;
+        assert_bad_segment(&ph, usize::MAX);
+
+        let ph = valid_segment();
+        assert_bad_segment(&ph, 0x17ff);
+    }
+
+    /**
+     * 文件中的部分，比内存中的部分还大；或者段是空的
+     */
+    #[test]
+    fn test_segment_filesz_exceed_memsz() {
+        let mut ph = valid_segment();
+        ph.p_filesz = ph.p_memsz + 1;
+        assert_bad_segment(&ph, usize::MAX);
+
+        let mut ph = valid_segment();
+        ph.p_filesz = 0;
+        ph.p_memsz = 0;
+        assert_bad_segment(&ph, usize::MAX);
+    }
+
+    /**
+     * 段的地址不在用户空间：进入了内核空间、栈的范围，地址溢出，或者在用户进程的起始地址之前
+     */
+    #[test]
+    fn test_segment_overlap_kernel() {
+        let mut ph = valid_segment();
+        ph.p_vaddr = constants::USER_STACK_BASE_ADDR as u32;
+        assert_bad_segment(&ph, usize::MAX);
+
+        let mut ph = valid_segment();
+        ph.p_vaddr = 0xc010_0000;
+        assert_bad_segment(&ph, usize::MAX);
+
+        // 结束地址跨进了栈的范围
+        let mut ph = valid_segment();
+        ph.p_vaddr = (constants::USER_STACK_BASE_ADDR - constants::USER_STACK_MAX_SIZE - 0x1000) as u32;
+        ph.p_memsz = 0x1001;
+        assert_bad_segment(&ph, usize::MAX);
+
+        // 结束地址溢出，绕回到低地址
+        let mut ph = valid_segment();
+        ph.p_vaddr = 0xffff_f000;
+        ph.p_memsz = 0x2000;
+        ph.p_filesz = 0;
+        assert_bad_segment(&ph, usize::MAX);
+
+        let mut ph = valid_segment();
+        ph.p_vaddr = 0;
+        assert_bad_segment(&ph, usize::MAX);
+    }
+}
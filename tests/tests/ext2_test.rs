@@ -0,0 +1,355 @@
+#[cfg(test)]
+mod tests {
+    use kernel::filesystem::{ext2::{self, Ext2Device, Ext2Fs}, FileType};
+    use os_in_rust_common::constants;
+
+    type Sector = [u8; constants::DISK_SECTOR_SIZE];
+
+    const BLOCKS_COUNT: u32 = 64;
+    const INODE_SIZE: u32 = 128;
+    const REGULAR_MODE: u16 = 0o100644;
+
+    /**
+     * 内存中的ext2镜像。超出镜像的扇区读出来都是0
+     */
+    struct MemVolume {
+        bytes: Vec<u8>,
+    }
+
+    impl Ext2Device for MemVolume {
+        fn read_sector(&self, rel_lba: u32, buf: &mut Sector) {
+            let off = rel_lba as usize * constants::DISK_SECTOR_SIZE;
+            *buf = [0; constants::DISK_SECTOR_SIZE];
+            if off + constants::DISK_SECTOR_SIZE <= self.bytes.len() {
+                buf.copy_from_slice(&self.bytes[off..off + constants::DISK_SECTOR_SIZE]);
+            }
+        }
+    }
+
+    /**
+     * 一个BLOCKS_COUNT个块的ext2镜像
+     */
+    struct Image {
+        block_size: u32,
+        first_data_block: u32,
+        bytes: Vec<u8>,
+    }
+
+    impl Image {
+        /**
+         * 每blocks_per_group个块一个块组，每组inodes_per_group个inode。各组的inode表依次放在块组描述符表后面
+         */
+        fn new(log_block_size: u32, blocks_per_group: u32, inodes_per_group: u32) -> Self {
+            let block_size = 1024 << log_block_size;
+            let first_data_block = if block_size == 1024 { 1 } else { 0 };
+            let mut image = Self {
+                block_size,
+                first_data_block,
+                bytes: vec![0; (BLOCKS_COUNT * block_size) as usize],
+            };
+            let group_cnt = (BLOCKS_COUNT - first_data_block).div_ceil(blocks_per_group);
+            image.set_sb_u32(0, inodes_per_group * group_cnt);
+            image.set_sb_u32(4, BLOCKS_COUNT);
+            image.set_sb_u32(12, BLOCKS_COUNT / 2);
+            image.set_sb_u32(16, inodes_per_group);
+            image.set_sb_u32(20, first_data_block);
+            image.set_sb_u32(24, log_block_size);
+            image.set_sb_u32(32, blocks_per_group);
+            image.set_sb_u32(40, inodes_per_group);
+            image.set_sb_u16(56, 0xef53);
+            image.set_sb_u32(76, 1);
+            image.set_sb_u16(88, INODE_SIZE as u16);
+            image.set_sb_u32(96, 0x0002);
+            for group in 0..group_cnt {
+                let table = image.gdt_block() + 1 + group * image.table_blocks(inodes_per_group);
+                image.set_inode_table(group, table);
+            }
+            image
+        }
+
+        fn set_u32(&mut self, off: usize, val: u32) {
+            self.bytes[off..off + 4].copy_from_slice(&val.to_le_bytes());
+        }
+
+        fn set_sb_u32(&mut self, off: usize, val: u32) {
+            self.set_u32(1024 + off, val);
+        }
+
+        fn set_sb_u16(&mut self, off: usize, val: u16) {
+            self.bytes[1024 + off..1024 + off + 2].copy_from_slice(&val.to_le_bytes());
+        }
+
+        fn sb_u32(&self, off: usize) -> u32 {
+            u32::from_le_bytes(self.bytes[1024 + off..1024 + off + 4].try_into().unwrap())
+        }
+
+        fn gdt_block(&self) -> u32 {
+            self.first_data_block + 1
+        }
+
+        fn table_blocks(&self, inodes_per_group: u32) -> u32 {
+            (inodes_per_group * INODE_SIZE).div_ceil(self.block_size)
+        }
+
+        /**
+         * 块组描述符中的inode表的起始块
+         */
+        fn set_inode_table(&mut self, group: u32, block: u32) {
+            let off = (self.gdt_block() * self.block_size + group * 32 + 8) as usize;
+            self.set_u32(off, block);
+        }
+
+        /**
+         * 块block中的第idx个块号
+         */
+        fn set_ptr(&mut self, block: u32, idx: u32, val: u32) {
+            self.set_u32((block * self.block_size + idx * 4) as usize, val);
+        }
+
+        /**
+         * 写入一个普通文件的inode
+         */
+        fn set_inode(&mut self, ino: u32, size: u32, block: &[u32; 15]) {
+            let ipg = self.sb_u32(40);
+            let group = (ino - 1) / ipg;
+            let gdt_off = (self.gdt_block() * self.block_size + group * 32 + 8) as usize;
+            let table = u32::from_le_bytes(self.bytes[gdt_off..gdt_off + 4].try_into().unwrap());
+            let off = (table * self.block_size + (ino - 1) % ipg * INODE_SIZE) as usize;
+            self.bytes[off..off + 2].copy_from_slice(&REGULAR_MODE.to_le_bytes());
+            self.set_u32(off + 4, size);
+            for (idx, ptr) in block.iter().enumerate() {
+                self.set_u32(off + 40 + idx * 4, *ptr);
+            }
+        }
+
+        fn super_block(&self) -> Sector {
+            self.bytes[1024..1024 + constants::DISK_SECTOR_SIZE].try_into().unwrap()
+        }
+
+        /**
+         * 按照分区的扇区数量part_secs解析超级块
+         */
+        fn parse_in(self, part_secs: u32) -> Option<Ext2Fs> {
+            let sb = self.super_block();
+            let dev: &'static MemVolume = Box::leak(Box::new(MemVolume { bytes: self.bytes }));
+            ext2::parse_super_block(dev, part_secs, &sb)
+        }
+
+        fn parse(self) -> Option<Ext2Fs> {
+            let part_secs = self.bytes.len() as u32 / constants::DISK_SECTOR_SIZE as u32;
+            self.parse_in(part_secs)
+        }
+
+        fn mount(self) -> Ext2Fs {
+            self.parse().unwrap()
+        }
+    }
+
+    #[test]
+    fn test_parse_super_block() {
+        for log_block_size in 0..=2 {
+            let fs = Image::new(log_block_size, 8192, 16).mount();
+            assert_eq!(fs.block_size(), 1024 << log_block_size);
+            assert_eq!(fs.group_cnt(), 1);
+        }
+
+        // 最后一个块组不满
+        let fs = Image::new(0, 16, 4).mount();
+        assert_eq!(fs.group_cnt(), 4);
+    }
+
+    /**
+     * 魔数不对，块大小不支持，每组的块数量、inode数量是0，inode数量太少
+     */
+    #[test]
+    fn test_bad_super_block() {
+        let mut image = Image::new(0, 8192, 16);
+        image.set_sb_u16(56, 0xef52);
+        assert!(image.parse().is_none());
+
+        let mut image = Image::new(0, 8192, 16);
+        image.set_sb_u32(24, 3);
+        assert!(image.parse().is_none());
+
+        let mut image = Image::new(0, 8192, 16);
+        image.set_sb_u32(32, 0);
+        assert!(image.parse().is_none());
+
+        let mut image = Image::new(0, 8192, 16);
+        image.set_sb_u32(40, 0);
+        assert!(image.parse().is_none());
+
+        let mut image = Image::new(0, 8192, 16);
+        image.set_sb_u32(0, 1);
+        assert!(image.parse().is_none());
+
+        // 所有块组的inode表，放不下这么多inode
+        let mut image = Image::new(0, 16, 4);
+        image.set_sb_u32(0, 17);
+        assert!(image.parse().is_none());
+    }
+
+    /**
+     * 块的数量超过了分区的大小，或者第一个数据块不在文件系统中
+     */
+    #[test]
+    fn test_blocks_beyond_partition() {
+        let image = Image::new(0, 8192, 16);
+        let part_secs = image.bytes.len() as u32 / constants::DISK_SECTOR_SIZE as u32;
+        assert!(image.parse_in(part_secs - 1).is_none());
+
+        let mut image = Image::new(0, 8192, 16);
+        image.set_sb_u32(20, BLOCKS_COUNT);
+        assert!(image.parse().is_none());
+    }
+
+    /**
+     * inode的大小不是2的幂、小于128，或者比块大；有不支持的不兼容特性
+     */
+    #[test]
+    fn test_bad_inode_size_and_features() {
+        for inode_size in [0u16, 64, 192, 2048] {
+            let mut image = Image::new(0, 8192, 16);
+            image.set_sb_u16(88, inode_size);
+            assert!(image.parse().is_none());
+        }
+        let mut image = Image::new(0, 8192, 16);
+        image.set_sb_u16(88, 256);
+        assert!(image.parse().is_some());
+
+        // 版本0，inode固定是128字节，不读取后面的字段
+        let mut image = Image::new(0, 8192, 16);
+        image.set_sb_u32(76, 0);
+        image.set_sb_u16(88, 0);
+        image.set_sb_u32(96, 0xffff);
+        assert!(image.parse().is_some());
+
+        // extent
+        let mut image = Image::new(0, 8192, 16);
+        image.set_sb_u32(96, 0x0040);
+        assert!(image.parse().is_none());
+
+        // 文件类型 + 灵活块组
+        let mut image = Image::new(0, 8192, 16);
+        image.set_sb_u32(96, 0x0202);
+        assert!(image.parse().is_some());
+    }
+
+    /**
+     * 每个块组的inode表，从块组描述符中读取；块组、inode表不合法的话，读取不到inode
+     */
+    #[test]
+    fn test_group_descriptors() {
+        let mut buf = [0u8; constants::DISK_SECTOR_SIZE];
+        let mut image = Image::new(0, 16, 4);
+        // 第2个块组的第2个inode
+        image.set_inode(10, 1234, &[0; 15]);
+        image.set_inode(13, 5678, &[0; 15]);
+        image.set_inode_table(3, BLOCKS_COUNT - 1);
+        let fs = image.mount();
+        assert_eq!(fs.inode_table(0, &mut buf), Option::Some(3));
+        assert_eq!(fs.inode_table(2, &mut buf), Option::Some(5));
+        assert_eq!(fs.inode_table(3, &mut buf), Option::Some(BLOCKS_COUNT - 1));
+        assert_eq!(fs.inode_table(4, &mut buf), Option::None);
+
+        let node = fs.read_inode(10, &mut buf).unwrap();
+        assert_eq!(node.file_type(), FileType::Regular);
+        assert_eq!(node.size(), 1234);
+        assert_eq!(fs.read_inode(13, &mut buf).unwrap().size(), 0);
+        assert!(fs.read_inode(0, &mut buf).is_none());
+        assert!(fs.read_inode(17, &mut buf).is_none());
+
+        // inode表的块号是0、超出了文件系统，或者inode表的结尾超出了文件系统
+        for table in [0, BLOCKS_COUNT, u32::MAX] {
+            let mut image = Image::new(0, 16, 4);
+            image.set_inode_table(2, table);
+            let fs = image.mount();
+            assert_eq!(fs.inode_table(2, &mut buf), Option::None);
+            assert!(fs.read_inode(10, &mut buf).is_none());
+            assert!(fs.read_inode(1, &mut buf).is_some());
+        }
+        let mut image = Image::new(0, 16, 16);
+        image.set_inode_table(1, BLOCKS_COUNT - 1);
+        assert_eq!(image.mount().inode_table(1, &mut buf), Option::None);
+    }
+
+    /**
+     * 直接块、一级间接块、二级间接块、三级间接块中的块号。块号是0的是空洞
+     */
+    #[test]
+    fn test_indirect_blocks() {
+        let mut buf = [0u8; constants::DISK_SECTOR_SIZE];
+        for log_block_size in 0..=2 {
+            let mut image = Image::new(log_block_size, 8192, 16);
+            let ptrs = image.block_size / 4;
+            let mut block = [0u32; 15];
+            block[0] = 20;
+            block[11] = 21;
+            block[12] = 30;
+            block[13] = 40;
+            block[14] = 50;
+            image.set_ptr(30, 0, 31);
+            image.set_ptr(30, ptrs - 1, 32);
+            image.set_ptr(40, 1, 41);
+            image.set_ptr(41, 3, 42);
+            image.set_ptr(50, 0, 51);
+            image.set_ptr(51, 2, 52);
+            image.set_ptr(52, 1, 53);
+            image.set_inode(12, u32::MAX, &block);
+            let fs = image.mount();
+            let node = fs.read_inode(12, &mut buf).unwrap();
+
+            assert_eq!(fs.map_block(&node, 0, &mut buf), 20);
+            assert_eq!(fs.map_block(&node, 1, &mut buf), 0);
+            assert_eq!(fs.map_block(&node, 11, &mut buf), 21);
+            assert_eq!(fs.map_block(&node, 12, &mut buf), 31);
+            assert_eq!(fs.map_block(&node, 13, &mut buf), 0);
+            assert_eq!(fs.map_block(&node, 12 + ptrs - 1, &mut buf), 32);
+
+            let dind_start = 12 + ptrs;
+            assert_eq!(fs.map_block(&node, dind_start, &mut buf), 0);
+            assert_eq!(fs.map_block(&node, dind_start + ptrs + 3, &mut buf), 42);
+            assert_eq!(fs.map_block(&node, dind_start + ptrs + 4, &mut buf), 0);
+
+            // 三级间接块，只在1KiB的块中测试（更大的块，文件大小超过了u32）
+            if log_block_size == 0 {
+                let tind_start = dind_start + ptrs * ptrs;
+                assert_eq!(fs.map_block(&node, tind_start + 2 * ptrs + 1, &mut buf), 53);
+                assert_eq!(fs.map_block(&node, tind_start + 2 * ptrs, &mut buf), 0);
+            }
+        }
+    }
+
+    /**
+     * 超出文件系统的块号（直接块、间接块本身、间接块中的块号），都当作空洞
+     */
+    #[test]
+    fn test_out_of_range_blocks() {
+        let mut buf = [0u8; constants::DISK_SECTOR_SIZE];
+        let mut image = Image::new(0, 8192, 16);
+        let ptrs = image.block_size / 4;
+        let mut block = [0u32; 15];
+        block[0] = BLOCKS_COUNT - 1;
+        block[1] = BLOCKS_COUNT;
+        block[2] = u32::MAX;
+        block[12] = 30;
+        block[13] = BLOCKS_COUNT + 100;
+        block[14] = 50;
+        image.set_ptr(30, 0, BLOCKS_COUNT);
+        image.set_ptr(30, 1, 0x8000_0000);
+        image.set_ptr(30, 2, 33);
+        image.set_ptr(50, 0, u32::MAX);
+        image.set_inode(12, u32::MAX, &block);
+        let fs = image.mount();
+        let node = fs.read_inode(12, &mut buf).unwrap();
+
+        assert_eq!(fs.map_block(&node, 0, &mut buf), BLOCKS_COUNT - 1);
+        assert_eq!(fs.map_block(&node, 1, &mut buf), 0);
+        assert_eq!(fs.map_block(&node, 2, &mut buf), 0);
+        assert_eq!(fs.map_block(&node, 12, &mut buf), 0);
+        assert_eq!(fs.map_block(&node, 13, &mut buf), 0);
+        assert_eq!(fs.map_block(&node, 14, &mut buf), 33);
+        assert_eq!(fs.map_block(&node, 12 + ptrs, &mut buf), 0);
+        assert_eq!(fs.map_block(&node, 12 + ptrs + ptrs * ptrs, &mut buf), 0);
+    }
+}
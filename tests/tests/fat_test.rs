@@ -0,0 +1,286 @@
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use kernel::filesystem::fat::{self, FatDevice, FatFs, FatType};
+    use os_in_rust_common::constants;
+
+    type Sector = [u8; constants::DISK_SECTOR_SIZE];
+
+    const RSVD_SECS: u32 = 1;
+    const NUM_FATS: u32 = 2;
+    const FAT16_ROOT_ENTRIES: u32 = 512;
+    const FAT32_ROOT_CLUSTER: u32 = 2;
+
+    /**
+     * 内存中的FAT卷。只保存引导扇区和FAT表，数据区的扇区读出来都是0
+     */
+    struct MemVolume {
+        sectors: RefCell<Vec<Sector>>,
+    }
+
+    impl FatDevice for MemVolume {
+        fn read_sector(&self, rel_lba: u32, buf: &mut Sector) {
+            let sectors = self.sectors.borrow();
+            *buf = sectors.get(rel_lba as usize).copied().unwrap_or([0; constants::DISK_SECTOR_SIZE]);
+        }
+    }
+
+    /**
+     * 一个FAT卷的布局
+     */
+    struct Layout {
+        fat_type: FatType,
+        sec_per_clus: u32,
+        fat_sz: u32,
+        tot_sec: u32,
+    }
+
+    impl Layout {
+        /**
+         * cluster_cnt个簇的卷，每个簇1个扇区。FAT表的大小按照FAT表项的宽度计算
+         */
+        fn new(fat_type: FatType, cluster_cnt: u32) -> Self {
+            let entry_size = if fat_type == FatType::Fat16 { 2 } else { 4 };
+            let fat_sz = ((cluster_cnt + 2) * entry_size).div_ceil(constants::DISK_SECTOR_SIZE as u32);
+            let root_dir_secs = if fat_type == FatType::Fat16 { FAT16_ROOT_ENTRIES * 32 / constants::DISK_SECTOR_SIZE as u32 } else { 0 };
+            Self {
+                fat_type,
+                sec_per_clus: 1,
+                fat_sz,
+                tot_sec: RSVD_SECS + NUM_FATS * fat_sz + root_dir_secs + cluster_cnt,
+            }
+        }
+
+        /**
+         * 引导扇区中的BPB
+         */
+        fn boot_sector(&self) -> Sector {
+            let mut buf = [0u8; constants::DISK_SECTOR_SIZE];
+            buf[11..13].copy_from_slice(&(constants::DISK_SECTOR_SIZE as u16).to_le_bytes());
+            buf[13] = self.sec_per_clus as u8;
+            buf[14..16].copy_from_slice(&(RSVD_SECS as u16).to_le_bytes());
+            buf[16] = NUM_FATS as u8;
+            buf[32..36].copy_from_slice(&self.tot_sec.to_le_bytes());
+            if self.fat_type == FatType::Fat16 {
+                buf[17..19].copy_from_slice(&(FAT16_ROOT_ENTRIES as u16).to_le_bytes());
+                buf[22..24].copy_from_slice(&(self.fat_sz as u16).to_le_bytes());
+            } else {
+                buf[36..40].copy_from_slice(&self.fat_sz.to_le_bytes());
+                buf[44..48].copy_from_slice(&FAT32_ROOT_CLUSTER.to_le_bytes());
+            }
+            buf[510] = 0x55;
+            buf[511] = 0xaa;
+            buf
+        }
+
+        /**
+         * 引导扇区 + FAT表，FAT表都是空闲的
+         */
+        fn volume(&self) -> &'static MemVolume {
+            let mut sectors = vec![[0u8; constants::DISK_SECTOR_SIZE]; (RSVD_SECS + NUM_FATS * self.fat_sz) as usize];
+            sectors[0] = self.boot_sector();
+            Box::leak(Box::new(MemVolume { sectors: RefCell::new(sectors) }))
+        }
+
+        fn parse(&self, boot_sector: &Sector) -> Option<FatFs> {
+            fat::parse_boot_sector(self.volume(), self.tot_sec, boot_sector)
+        }
+
+        fn mount(&self) -> (&'static MemVolume, FatFs) {
+            let volume = self.volume();
+            let fs = fat::parse_boot_sector(volume, self.tot_sec, &self.boot_sector()).unwrap();
+            (volume, fs)
+        }
+
+        /**
+         * 设置第一个FAT表中，cluster的表项
+         */
+        fn set_entry(&self, volume: &MemVolume, cluster: u32, next: u32) {
+            let entry_size = if self.fat_type == FatType::Fat16 { 2 } else { 4 };
+            let fat_off = cluster * entry_size;
+            let mut sectors = volume.sectors.borrow_mut();
+            let sector = &mut sectors[(RSVD_SECS + fat_off / constants::DISK_SECTOR_SIZE as u32) as usize];
+            let off = fat_off as usize % constants::DISK_SECTOR_SIZE;
+            if self.fat_type == FatType::Fat16 {
+                sector[off..off + 2].copy_from_slice(&(next as u16).to_le_bytes());
+            } else {
+                sector[off..off + 4].copy_from_slice(&next.to_le_bytes());
+            }
+        }
+
+        /**
+         * 把clusters串成一条簇链，最后一个簇是结束标记
+         */
+        fn set_chain(&self, volume: &MemVolume, clusters: &[u32]) {
+            let eoc = if self.fat_type == FatType::Fat16 { 0xffff } else { 0x0fff_ffff };
+            for (idx, cluster) in clusters.iter().enumerate() {
+                self.set_entry(volume, *cluster, clusters.get(idx + 1).copied().unwrap_or(eoc));
+            }
+        }
+    }
+
+    fn chain_len(fs: &FatFs, first_cluster: u32) -> u32 {
+        let mut fat_sector = [0u8; constants::DISK_SECTOR_SIZE];
+        fs.chain_len(first_cluster, &mut fat_sector)
+    }
+
+    fn next_cluster(fs: &FatFs, cluster: u32) -> Option<u32> {
+        let mut fat_sector = [0u8; constants::DISK_SECTOR_SIZE];
+        fs.next_cluster(cluster, &mut fat_sector)
+    }
+
+    /**
+     * FAT16、FAT32的BPB解析。簇的数量就是数据区的扇区数量除以每簇的扇区数量
+     */
+    #[test]
+    fn test_parse_boot_sector() {
+        let layout = Layout::new(FatType::Fat16, 5000);
+        let fs = layout.parse(&layout.boot_sector()).unwrap();
+        assert_eq!(fs.fat_type(), FatType::Fat16);
+        assert_eq!(fs.cluster_cnt(), 5000);
+
+        let layout = Layout::new(FatType::Fat32, 70000);
+        let fs = layout.parse(&layout.boot_sector()).unwrap();
+        assert_eq!(fs.fat_type(), FatType::Fat32);
+        assert_eq!(fs.cluster_cnt(), 70000);
+
+        // 一个簇8个扇区，簇的数量变成1/8
+        let mut layout = Layout::new(FatType::Fat16, 8 * 5000);
+        layout.sec_per_clus = 8;
+        assert_eq!(layout.parse(&layout.boot_sector()).unwrap().cluster_cnt(), 5000);
+    }
+
+    /**
+     * FAT的类型只由簇的数量决定：少于4085个是FAT12（不支持），少于65525个是FAT16，其他是FAT32
+     */
+    #[test]
+    fn test_fat16_fat32_detection() {
+        let layout = Layout::new(FatType::Fat16, 4084);
+        assert!(layout.parse(&layout.boot_sector()).is_none());
+        for (cluster_cnt, fat_type) in [(4085, FatType::Fat16), (65524, FatType::Fat16), (65525, FatType::Fat32), (100000, FatType::Fat32)] {
+            let layout = Layout::new(fat_type, cluster_cnt);
+            let fs = layout.parse(&layout.boot_sector());
+            assert_eq!(fs.map(|fs| fs.fat_type()), Option::Some(fat_type), "clusters: {}", cluster_cnt);
+        }
+
+        // 簇的数量是FAT32的，但是按照FAT16的格式填写（有根目录区），不是合法的FAT32
+        let layout = Layout::new(FatType::Fat16, 70000);
+        assert!(layout.parse(&layout.boot_sector()).is_none());
+    }
+
+    /**
+     * 不合法的BPB
+     */
+    #[test]
+    fn test_bad_boot_sector() {
+        let layout = Layout::new(FatType::Fat16, 5000);
+        let good = layout.boot_sector();
+        let corrupt = |f: &dyn Fn(&mut Sector)| {
+            let mut buf = good;
+            f(&mut buf);
+            layout.parse(&buf)
+        };
+        // 没有引导扇区的魔数
+        assert!(corrupt(&|buf| buf[510] = 0).is_none());
+        // 扇区不是512字节
+        assert!(corrupt(&|buf| buf[11..13].copy_from_slice(&4096u16.to_le_bytes())).is_none());
+        // 每簇的扇区数量不是2的幂
+        assert!(corrupt(&|buf| buf[13] = 3).is_none());
+        assert!(corrupt(&|buf| buf[13] = 0).is_none());
+        // 没有保留扇区、没有FAT表、FAT表的大小是0
+        assert!(corrupt(&|buf| buf[14..16].fill(0)).is_none());
+        assert!(corrupt(&|buf| buf[16] = 0).is_none());
+        assert!(corrupt(&|buf| buf[22..24].fill(0)).is_none());
+        // 总扇区数量比分区大，或者还没有数据区大
+        assert!(corrupt(&|buf| buf[32..36].copy_from_slice(&(layout.tot_sec + 1).to_le_bytes())).is_none());
+        assert!(corrupt(&|buf| buf[32..36].copy_from_slice(&(RSVD_SECS + NUM_FATS * layout.fat_sz).to_le_bytes())).is_none());
+        // 全是0的扇区
+        assert!(layout.parse(&[0; constants::DISK_SECTOR_SIZE]).is_none());
+        assert!(corrupt(&|_| {}).is_some());
+
+        // FAT32的根目录簇号不在数据区中
+        let layout = Layout::new(FatType::Fat32, 70000);
+        for root_clus in [0, 1, 70002] {
+            let mut buf = layout.boot_sector();
+            buf[44..48].copy_from_slice(&(root_clus as u32).to_le_bytes());
+            assert!(layout.parse(&buf).is_none(), "root cluster: {}", root_clus);
+        }
+    }
+
+    /**
+     * 沿着FAT表遍历簇链，遇到结束标记停止。FAT32的表项只有低28位有效
+     */
+    #[test]
+    fn test_cluster_chain() {
+        let layout = Layout::new(FatType::Fat16, 5000);
+        let (volume, fs) = layout.mount();
+        // 跨过FAT表的扇区边界
+        layout.set_chain(volume, &[2, 3, 300, 4999]);
+        assert_eq!(next_cluster(&fs, 2), Option::Some(3));
+        assert_eq!(next_cluster(&fs, 3), Option::Some(300));
+        assert_eq!(next_cluster(&fs, 300), Option::Some(4999));
+        assert_eq!(next_cluster(&fs, 4999), Option::None);
+        assert_eq!(chain_len(&fs, 2), 4);
+        assert_eq!(chain_len(&fs, 300), 2);
+        // 空闲的簇，当作簇链结束
+        assert_eq!(next_cluster(&fs, 10), Option::None);
+        assert_eq!(chain_len(&fs, 10), 1);
+
+        let layout = Layout::new(FatType::Fat32, 70000);
+        let (volume, fs) = layout.mount();
+        layout.set_chain(volume, &[5, 6, 69000]);
+        // 高4位是保留的
+        layout.set_entry(volume, 6, 0xf000_0000 | 69000);
+        assert_eq!(next_cluster(&fs, 6), Option::Some(69000));
+        assert_eq!(chain_len(&fs, 5), 3);
+    }
+
+    /**
+     * 有环的簇链，最多只走cluster_cnt个簇
+     */
+    #[test]
+    fn test_cyclic_chain() {
+        let layout = Layout::new(FatType::Fat16, 5000);
+        let (volume, fs) = layout.mount();
+        layout.set_entry(volume, 2, 3);
+        layout.set_entry(volume, 3, 4);
+        layout.set_entry(volume, 4, 2);
+        assert_eq!(chain_len(&fs, 2), fs.cluster_cnt());
+
+        // 指向自己
+        layout.set_entry(volume, 100, 100);
+        assert_eq!(chain_len(&fs, 100), fs.cluster_cnt());
+    }
+
+    /**
+     * 簇号超出数据区的范围：第一个簇不合法，簇链的长度是0；中途指向了超出范围的簇、坏簇，簇链在这里结束
+     */
+    #[test]
+    fn test_out_of_range_chain() {
+        let layout = Layout::new(FatType::Fat16, 5000);
+        let (volume, fs) = layout.mount();
+        for first_cluster in [0, 1, 5002, 0xffff, u32::MAX] {
+            assert_eq!(chain_len(&fs, first_cluster), 0, "first cluster: {}", first_cluster);
+        }
+        // 有效的簇号是[2, 5002)
+        layout.set_entry(volume, 2, 5001);
+        assert_eq!(next_cluster(&fs, 2), Option::Some(5001));
+        layout.set_entry(volume, 2, 5002);
+        assert_eq!(next_cluster(&fs, 2), Option::None);
+        assert_eq!(chain_len(&fs, 2), 1);
+        // 1号簇、坏簇
+        layout.set_chain(volume, &[7, 8]);
+        layout.set_entry(volume, 8, 1);
+        assert_eq!(chain_len(&fs, 7), 2);
+        layout.set_entry(volume, 8, 0xfff7);
+        assert_eq!(chain_len(&fs, 7), 2);
+
+        let layout = Layout::new(FatType::Fat32, 70000);
+        let (volume, fs) = layout.mount();
+        layout.set_entry(volume, 2, 70002);
+        assert_eq!(chain_len(&fs, 2), 1);
+        layout.set_entry(volume, 2, 0x0fff_fff7);
+        assert_eq!(next_cluster(&fs, 2), Option::None);
+    }
+}